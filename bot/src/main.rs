@@ -1,44 +1,202 @@
 use anyhow::Result;
 
-use alloy::providers::{Provider, ProviderBuilder};
+use alloy::{
+    providers::{Provider, ProviderBuilder, RootProvider},
+    rpc::client::ClientBuilder,
+};
 use futures_util::StreamExt;
-use kronos_config::Config;
-use kronos_db::DB;
-use kronos_dexes::uniswap_v2::UniswapV2;
-use kronos_executor::Executor;
-use std::sync::Arc;
+use kronos_config::{Config, ExecutionMode, GasStrategyConfig};
+use kronos_db::{Inventory, TokenRegistry, DB};
+use kronos_dexes::registry::DexRegistry;
+use kronos_executor::{
+    bot::bot_address,
+    gas::{GasPricer, MempoolBids},
+    inventory::InventoryManager,
+    nonce::NonceManager,
+    notifier::{AlertDispatcher, BlockWatchdog},
+    relay::BundleRelay,
+    risk::{KillSwitch, RiskManager},
+    wallet::{BalanceWatcher, ExecutorWallet, ReputationKey},
+    Executor, ExecutorParts,
+};
+use kronos_math::oracle::PriceOracle;
+use kronos_metrics::RpcMetricsLayer;
+use std::{sync::Arc, time::Duration};
+
+const HEALTH_INTERVAL: Duration = Duration::from_secs(5);
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    kronos_logger::init_logger(tracing::Level::INFO);
-
     let config = Config::load("./config.yml".into())?;
+    let _logger = kronos_logger::init(&config.logging)?;
+
+    let listen = config.metrics.listen.clone();
+    tokio::spawn(async move {
+        if let Err(err) = kronos_metrics::serve(&listen).await {
+            tracing::error!("metrics server failed: {err}");
+        }
+    });
 
     let database = DB::from_config(&config).await?;
-    let provider = Arc::new(ProviderBuilder::default().connect(&config.rpc_url).await?);
+    // requests are counted by the metrics layer,
+    // subscription needs pubsub transport without layers, so it has its own connection
+    let client = ClientBuilder::default()
+        .layer(RpcMetricsLayer)
+        .connect(&config.rpc_url)
+        .await?;
+    let provider = Arc::new(ProviderBuilder::default().on_client(client));
+    let subscriber: RootProvider = ProviderBuilder::default().connect(&config.rpc_url).await?;
+    let tokens = TokenRegistry::new(database.postgres(), provider.clone()).await?;
+    // pairs stored before the registry existed have no metadata yet
+    let pair_tokens: Vec<_> = database
+        .postgres()
+        .select_pairs()
+        .await?
+        .iter()
+        .flat_map(|pair| [pair.token0, pair.token1])
+        .collect();
+    tokens.ensure_tokens(&pair_tokens).await?;
 
     let (blocks_tx, blocks_rx) = tokio::sync::mpsc::unbounded_channel();
     let (arbitrage_tx, arbitrage_rx) = tokio::sync::mpsc::unbounded_channel();
 
-    let uniswap_v2 =
-        UniswapV2::new(database.clone(), provider.clone(), blocks_rx, arbitrage_tx).await?;
+    // without inventory arbitrages are paid by flash swaps and any token can start a cycle
+    let inventory = match config.executor.inventory.enabled {
+        true => Inventory::new(config.executor.inventory.max_size_percent),
+        false => Inventory::flash_swap(),
+    };
+    let dexes = DexRegistry::from_config(
+        &config,
+        database.clone(),
+        tokens.clone(),
+        inventory.clone(),
+        provider.clone(),
+    )
+    .await?;
+
+    let dispatcher = AlertDispatcher::from_config(&config.alerts)?;
+    if dispatcher.is_empty() {
+        tracing::warn!("no alert sinks are configured");
+    }
+    let notifier = dispatcher.notifier();
+    tokio::spawn(dispatcher.start());
+    tokio::spawn(notifier.clone().watch_rpc(
+        provider.clone(),
+        Duration::from_secs(config.alerts.rpc_check_secs),
+    ));
+    let watchdog = BlockWatchdog::default();
+    tokio::spawn(watchdog.clone().start(
+        notifier.clone(),
+        Duration::from_secs(config.alerts.block_stall_secs),
+    ));
 
-    let executor = Executor::new(database.clone(), provider.clone(), arbitrage_rx);
+    let wallet = ExecutorWallet::from_config(&config.wallet)?;
+    if wallet.is_empty() {
+        tracing::warn!("no executor accounts are configured");
+    }
+    let relay = match &config.wallet.reputation_key {
+        Some(keystore) => {
+            let reputation = ReputationKey::load(keystore, &wallet)?;
+            tracing::info!(
+                "bundles are sent to {} with reputation key {}",
+                config.wallet.relay_url,
+                reputation.address()
+            );
+            Some(BundleRelay::new(&config.wallet.relay_url, reputation))
+        }
+        None => None,
+    };
+    let balances =
+        BalanceWatcher::new(provider.clone(), &wallet, &config.wallet, notifier.clone())?;
+    tokio::spawn(balances.start());
+
+    // nonces are read from the node, so transactions sent before restart are not reused
+    let mut nonces = NonceManager::new(provider.clone(), wallet);
+    if let Some(relay) = relay {
+        nonces = nonces.with_relay(relay);
+    }
+    let nonces = Arc::new(nonces);
+    tokio::spawn(nonces.clone().start(config.executor.stuck_after_blocks));
+
+    let mempool = MempoolBids::default();
+    // paper fills are missed when competitors bid more, so paper mode follows the mempool too
+    if config.executor.mode == ExecutionMode::Paper
+        || matches!(
+            config.executor.gas.strategy,
+            GasStrategyConfig::Competitive { .. }
+        )
+    {
+        tokio::spawn(mempool.clone().watch(subscriber.clone()));
+    }
+    let gas = GasPricer::from_config(&config.executor.gas, mempool);
+    tracing::info!("gas strategy: {}", gas.strategy().name());
+
+    let oracle = PriceOracle::new(database.clone(), tokens.clone(), config.oracle.clone()).await?;
+    if inventory.is_enabled() {
+        let manager = InventoryManager::from_config(
+            &config.executor.inventory,
+            bot_address(&config.executor)?,
+            inventory.clone(),
+            database.clone(),
+            tokens.clone(),
+            oracle.clone(),
+            provider.clone(),
+            nonces.clone(),
+        )
+        .await?;
+        // cycles start only from held tokens, so balances are read before the first block
+        manager.refresh().await?;
+        tokio::spawn(manager.start());
+    }
+    let kill_switch = KillSwitch::default();
+    tokio::spawn(kill_switch.clone().watch(
+        database.redis(),
+        config.executor.risk.kill_switch_file.clone(),
+    ));
+    let risk = RiskManager::new(&config.executor.risk, kill_switch);
+    let parts = ExecutorParts {
+        db: database.clone(),
+        tokens,
+        oracle,
+        provider: provider.clone(),
+        nonces,
+        gas,
+        risk,
+        inventory,
+    };
+    let executor = Executor::new(parts, &config.executor, arbitrage_rx)?;
+    tokio::spawn(notifier.follow(executor.subscribe()));
+
+    // health of adapters is shared with api through redis
+    let health_dexes = dexes.clone();
+    let redis = database.redis();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HEALTH_INTERVAL);
+        loop {
+            interval.tick().await;
+            let statuses: Vec<_> = health_dexes.health().iter().map(|h| h.status()).collect();
+            if let Err(err) = redis.set_health(&statuses).await {
+                tracing::warn!("failed to store dex health: {err}");
+            }
+        }
+    });
 
     // Create handle to start bot
-    let uniswapv2_handle = tokio::spawn(async move { uniswap_v2.start().await.unwrap() });
+    let dexes_handle =
+        tokio::spawn(async move { dexes.start(blocks_rx, arbitrage_tx).await.unwrap() });
 
     let executor_handle = tokio::spawn(async move { executor.start().await.unwrap() });
 
     let blocks_handle = tokio::spawn(async move {
-        let mut stream = provider.subscribe_blocks().await.unwrap().into_stream();
+        let mut stream = subscriber.subscribe_blocks().await.unwrap().into_stream();
         while let Some(block) = stream.next().await {
             tracing::info!("📦 block: {}", block.number);
+            watchdog.block(block.number);
             blocks_tx.send(block).unwrap();
         }
     });
 
-    uniswapv2_handle.await?;
+    dexes_handle.await?;
     executor_handle.await?;
     blocks_handle.await?;
 
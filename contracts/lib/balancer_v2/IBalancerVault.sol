@@ -0,0 +1,43 @@
+pragma solidity >=0.7.0;
+pragma abicoder v2;
+
+import {IERC20} from "./IERC20.sol";
+import {IFlashLoanRecipient} from "./IFlashLoanRecipient.sol";
+
+interface IBalancerVault {
+    enum SwapKind {
+        GIVEN_IN,
+        GIVEN_OUT
+    }
+
+    struct BatchSwapStep {
+        bytes32 poolId;
+        uint assetInIndex;
+        uint assetOutIndex;
+        uint amount;
+        bytes userData;
+    }
+
+    struct FundManagement {
+        address sender;
+        bool fromInternalBalance;
+        address payable recipient;
+        bool toInternalBalance;
+    }
+
+    function batchSwap(
+        SwapKind kind,
+        BatchSwapStep[] memory swaps,
+        address[] memory assets,
+        FundManagement memory funds,
+        int[] memory limits,
+        uint deadline
+    ) external payable returns (int[] memory assetDeltas);
+
+    function flashLoan(
+        IFlashLoanRecipient recipient,
+        IERC20[] memory tokens,
+        uint[] memory amounts,
+        bytes memory userData
+    ) external;
+}
@@ -0,0 +1,7 @@
+pragma solidity >=0.7.0;
+
+interface IERC20 {
+    function balanceOf(address account) external view returns (uint);
+    function transfer(address to, uint value) external returns (bool);
+    function transferFrom(address from, address to, uint value) external returns (bool);
+}
@@ -0,0 +1,12 @@
+pragma solidity >=0.7.0;
+
+import {IERC20} from "./IERC20.sol";
+
+interface IFlashLoanRecipient {
+    function receiveFlashLoan(
+        IERC20[] memory tokens,
+        uint[] memory amounts,
+        uint[] memory feeAmounts,
+        bytes memory userData
+    ) external;
+}
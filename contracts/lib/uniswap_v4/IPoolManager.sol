@@ -0,0 +1,33 @@
+pragma solidity >=0.8.0;
+
+// Part of Uniswap V4 PoolManager which is used by the bot.
+// Currencies are addresses, `BalanceDelta` is `int256` with amount0 in the upper 128 bits
+interface IPoolManager {
+    struct PoolKey {
+        address currency0;
+        address currency1;
+        uint24 fee;
+        int24 tickSpacing;
+        address hooks;
+    }
+
+    struct SwapParams {
+        bool zeroForOne;
+        int256 amountSpecified;
+        uint160 sqrtPriceLimitX96;
+    }
+
+    function unlock(bytes calldata data) external returns (bytes memory);
+
+    function swap(
+        PoolKey memory key,
+        SwapParams memory params,
+        bytes calldata hookData
+    ) external returns (int256 swapDelta);
+
+    function sync(address currency) external;
+
+    function settle() external payable returns (uint256 paid);
+
+    function take(address currency, address to, uint256 amount) external;
+}
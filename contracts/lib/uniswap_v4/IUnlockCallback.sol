@@ -0,0 +1,5 @@
+pragma solidity >=0.8.0;
+
+interface IUnlockCallback {
+    function unlockCallback(bytes calldata data) external returns (bytes memory);
+}
@@ -39,6 +39,344 @@ sol!(
     "../../abi/IUniswapV3Pool.json"
 );
 
+// Multicall3, deployed on the same address on most chains
+sol!(
+    #[allow(missing_docs)]
+    #[sol(rpc)]
+    interface IMulticall3 {
+        struct Call {
+            address target;
+            bytes callData;
+        }
+
+        struct Result {
+            bool success;
+            bytes returnData;
+        }
+
+        function tryAggregate(bool requireSuccess, Call[] calldata calls)
+            external
+            payable
+            returns (Result[] memory returnData);
+    }
+);
+
+// Optional getters which reveal risky ERC20 behaviour: shares of rebasing tokens
+// (Ampleforth, Aave aTokens, Lido stETH) and blacklists of holders (USDC, USDT)
+sol!(
+    #[allow(missing_docs)]
+    interface ITokenRisk {
+        function scaledBalanceOf(address account) external view returns (uint256);
+        function sharesOf(address account) external view returns (uint256);
+        function isBlacklisted(address account) external view returns (bool);
+        function isBlackListed(address account) external view returns (bool);
+    }
+);
+
+// Curve StableSwap pools, events and getters common for legacy and factory templates
+sol!(
+    #[allow(missing_docs)]
+    #[sol(rpc)]
+    interface ICurvePool {
+        event TokenExchange(
+            address indexed buyer,
+            int128 sold_id,
+            uint256 tokens_sold,
+            int128 bought_id,
+            uint256 tokens_bought
+        );
+        event TokenExchangeUnderlying(
+            address indexed buyer,
+            int128 sold_id,
+            uint256 tokens_sold,
+            int128 bought_id,
+            uint256 tokens_bought
+        );
+        event RampA(uint256 old_A, uint256 new_A, uint256 initial_time, uint256 future_time);
+        event StopRampA(uint256 A, uint256 t);
+        event NewFee(uint256 fee, uint256 admin_fee);
+        event ApplyNewFee(uint256 fee);
+
+        function coins(uint256 i) external view returns (address);
+        function balances(uint256 i) external view returns (uint256);
+        function A() external view returns (uint256);
+        function A_precise() external view returns (uint256);
+        function initial_A() external view returns (uint256);
+        function future_A() external view returns (uint256);
+        function initial_A_time() external view returns (uint256);
+        function future_A_time() external view returns (uint256);
+        function fee() external view returns (uint256);
+        function get_dy(int128 i, int128 j, uint256 dx) external view returns (uint256);
+        function get_virtual_price() external view returns (uint256);
+        function base_pool() external view returns (address);
+
+        // legacy pools return nothing
+        function exchange(int128 i, int128 j, uint256 dx, uint256 min_dy) external;
+    }
+);
+
+// Liquidity events of Curve pools depend on number of coins
+sol!(
+    #[allow(missing_docs)]
+    interface ICurvePool2 {
+        event AddLiquidity(
+            address indexed provider,
+            uint256[2] token_amounts,
+            uint256[2] fees,
+            uint256 invariant,
+            uint256 token_supply
+        );
+        event RemoveLiquidity(
+            address indexed provider,
+            uint256[2] token_amounts,
+            uint256[2] fees,
+            uint256 token_supply
+        );
+        event RemoveLiquidityOne(
+            address indexed provider,
+            uint256 token_amount,
+            uint256 coin_amount,
+            uint256 token_supply
+        );
+        event RemoveLiquidityImbalance(
+            address indexed provider,
+            uint256[2] token_amounts,
+            uint256[2] fees,
+            uint256 invariant,
+            uint256 token_supply
+        );
+    }
+);
+
+sol!(
+    #[allow(missing_docs)]
+    interface ICurvePool3 {
+        event AddLiquidity(
+            address indexed provider,
+            uint256[3] token_amounts,
+            uint256[3] fees,
+            uint256 invariant,
+            uint256 token_supply
+        );
+        event RemoveLiquidity(
+            address indexed provider,
+            uint256[3] token_amounts,
+            uint256[3] fees,
+            uint256 token_supply
+        );
+        event RemoveLiquidityOne(address indexed provider, uint256 token_amount, uint256 coin_amount);
+        event RemoveLiquidityImbalance(
+            address indexed provider,
+            uint256[3] token_amounts,
+            uint256[3] fees,
+            uint256 invariant,
+            uint256 token_supply
+        );
+    }
+);
+
+// Curve main registry
+sol!(
+    #[allow(missing_docs)]
+    #[sol(rpc)]
+    interface ICurveRegistry {
+        function pool_count() external view returns (uint256);
+        function pool_list(uint256 i) external view returns (address);
+        function get_n_coins(address pool) external view returns (uint256[2]);
+        function get_coins(address pool) external view returns (address[8]);
+        function get_decimals(address pool) external view returns (uint256[8]);
+        function is_meta(address pool) external view returns (bool);
+    }
+);
+
+// Balancer V2 Vault, holds tokens of all pools
+sol!(
+    #[allow(missing_docs)]
+    #[sol(rpc)]
+    interface IBalancerVault {
+        enum PoolSpecialization {
+            GENERAL,
+            MINIMAL_SWAP_INFO,
+            TWO_TOKEN
+        }
+
+        enum SwapKind {
+            GIVEN_IN,
+            GIVEN_OUT
+        }
+
+        struct BatchSwapStep {
+            bytes32 poolId;
+            uint256 assetInIndex;
+            uint256 assetOutIndex;
+            uint256 amount;
+            bytes userData;
+        }
+
+        struct FundManagement {
+            address sender;
+            bool fromInternalBalance;
+            address recipient;
+            bool toInternalBalance;
+        }
+
+        event PoolRegistered(
+            bytes32 indexed poolId,
+            address indexed poolAddress,
+            PoolSpecialization specialization
+        );
+
+        event Swap(
+            bytes32 indexed poolId,
+            address indexed tokenIn,
+            address indexed tokenOut,
+            uint256 amountIn,
+            uint256 amountOut
+        );
+
+        event PoolBalanceChanged(
+            bytes32 indexed poolId,
+            address indexed liquidityProvider,
+            address[] tokens,
+            int256[] deltas,
+            uint256[] protocolFeeAmounts
+        );
+
+        event PoolBalanceManaged(
+            bytes32 indexed poolId,
+            address indexed assetManager,
+            address indexed token,
+            int256 cashDelta,
+            int256 managedDelta
+        );
+
+        function getPoolTokens(bytes32 poolId)
+            external
+            view
+            returns (address[] memory tokens, uint256[] memory balances, uint256 lastChangeBlock);
+
+        function batchSwap(
+            SwapKind kind,
+            BatchSwapStep[] memory swaps,
+            address[] memory assets,
+            FundManagement memory funds,
+            int256[] memory limits,
+            uint256 deadline
+        ) external payable returns (int256[] memory assetDeltas);
+
+        function queryBatchSwap(
+            SwapKind kind,
+            BatchSwapStep[] memory swaps,
+            address[] memory assets,
+            FundManagement memory funds
+        ) external returns (int256[] memory assetDeltas);
+
+        function flashLoan(
+            address recipient,
+            address[] memory tokens,
+            uint256[] memory amounts,
+            bytes memory userData
+        ) external;
+    }
+);
+
+// Balancer V2 weighted pool
+sol!(
+    #[allow(missing_docs)]
+    #[sol(rpc)]
+    interface IWeightedPool {
+        event SwapFeePercentageChanged(uint256 swapFeePercentage);
+
+        function getPoolId() external view returns (bytes32);
+        function getNormalizedWeights() external view returns (uint256[] memory);
+        function getSwapFeePercentage() external view returns (uint256);
+    }
+);
+
+// Uniswap V4 PoolManager, singleton which holds all pools
+sol!(
+    #[allow(missing_docs)]
+    #[sol(rpc)]
+    interface IPoolManager {
+        event Initialize(
+            bytes32 indexed id,
+            address indexed currency0,
+            address indexed currency1,
+            uint24 fee,
+            int24 tickSpacing,
+            address hooks,
+            uint160 sqrtPriceX96,
+            int24 tick
+        );
+
+        event ModifyLiquidity(
+            bytes32 indexed id,
+            address indexed sender,
+            int24 tickLower,
+            int24 tickUpper,
+            int256 liquidityDelta,
+            bytes32 salt
+        );
+
+        event Swap(
+            bytes32 indexed id,
+            address indexed sender,
+            int128 amount0,
+            int128 amount1,
+            uint160 sqrtPriceX96,
+            uint128 liquidity,
+            int24 tick,
+            uint24 fee
+        );
+
+        event ProtocolFeeUpdated(bytes32 indexed id, uint24 protocolFee);
+
+        function unlock(bytes calldata data) external returns (bytes memory);
+    }
+);
+
+// Uniswap V4 StateView, view functions over PoolManager storage
+sol!(
+    #[allow(missing_docs)]
+    #[sol(rpc)]
+    interface IStateView {
+        function getSlot0(bytes32 poolId)
+            external
+            view
+            returns (uint160 sqrtPriceX96, int24 tick, uint24 protocolFee, uint24 lpFee);
+        function getLiquidity(bytes32 poolId) external view returns (uint128 liquidity);
+        function getTickLiquidity(bytes32 poolId, int24 tick)
+            external
+            view
+            returns (uint128 liquidityGross, int128 liquidityNet);
+    }
+);
+
+// Uniswap V4 Quoter, simulates swaps with hooks through revert
+sol!(
+    #[allow(missing_docs)]
+    #[sol(rpc)]
+    interface IV4Quoter {
+        struct PoolKey {
+            address currency0;
+            address currency1;
+            uint24 fee;
+            int24 tickSpacing;
+            address hooks;
+        }
+
+        struct QuoteExactSingleParams {
+            PoolKey poolKey;
+            bool zeroForOne;
+            uint128 exactAmount;
+            bytes hookData;
+        }
+
+        function quoteExactInputSingle(QuoteExactSingleParams memory params)
+            external
+            returns (uint256 amountOut, uint256 gasEstimate);
+    }
+);
 
 // Router02 Swap Functions
 sol!(
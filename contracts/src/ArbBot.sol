@@ -1,16 +1,184 @@
 // SPDX-License-Identifier: SEE LICENSE IN LICENSE
-pragma solidity >=0.5.0;
+pragma solidity ^0.8.0;
 
 import {IUniswapV2Pair} from "../lib/uniswap_v2/IUniswapV2Pair.sol";
+import {IBalancerVault} from "../lib/balancer_v2/IBalancerVault.sol";
+import {IERC20} from "../lib/balancer_v2/IERC20.sol";
+import {IFlashLoanRecipient} from "../lib/balancer_v2/IFlashLoanRecipient.sol";
+import {IPoolManager} from "../lib/uniswap_v4/IPoolManager.sol";
+import {IUnlockCallback} from "../lib/uniswap_v4/IUnlockCallback.sol";
+
+contract ArbBot is IFlashLoanRecipient, IUnlockCallback {
+    IBalancerVault constant BALANCER_VAULT =
+        IBalancerVault(0xBA12222222228d8Ba445958a75a0704d566BF2C8);
+    IPoolManager constant POOL_MANAGER =
+        IPoolManager(0x000000000004444c5dc75cB358380D2e3dE08A90);
+
+    // TickMath bounds, price limits of V4 swaps which are bounded by `minAmountOut` instead
+    uint160 constant MIN_SQRT_PRICE = 4295128739;
+    uint160 constant MAX_SQRT_PRICE = 1461446703485210103287273052203988822378723970342;
+
+    address public immutable owner;
+
+    // contracts which may be called with the flash loan: tokens and pools of arbitrages
+    mapping(address => bool) public allowedTargets;
+
+    // executor accounts which may start flash loans besides the owner
+    mapping(address => bool) public executors;
+
+    // hash of the flash loan started by `flashLoanBalancer` or of the calls of `swapInventory`,
+    // zero otherwise, so callbacks run only calls which an executor sent
+    bytes32 private flashLoanHash;
+
+    modifier onlyOwner() {
+        require(msg.sender == owner, "ArbBot: not owner");
+        _;
+    }
+
+    modifier onlyExecutor() {
+        require(msg.sender == owner || executors[msg.sender], "ArbBot: not executor");
+        _;
+    }
+
+    constructor() {
+        owner = msg.sender;
+    }
+
+    function setTargets(address[] calldata targets, bool allowed) external onlyOwner {
+        for (uint i = 0; i < targets.length; i++) {
+            allowedTargets[targets[i]] = allowed;
+        }
+    }
+
+    function setExecutors(address[] calldata accounts, bool allowed) external onlyOwner {
+        for (uint i = 0; i < accounts.length; i++) {
+            executors[accounts[i]] = allowed;
+        }
+    }
+
+    // Profit stays in the contract until it is withdrawn
+    function withdraw(IERC20 token, address to, uint amount) external onlyOwner {
+        _transfer(token, to, amount);
+    }
 
-contract ArbBot {
     function swapOnPair(
         address pair_adr,
         uint amount0Out,
         uint amount1Out,
         address to,
         bytes calldata data
-    ) external {
+    ) external onlyOwner {
         IUniswapV2Pair(pair_adr).swap(amount0Out, amount1Out, to, data);
     }
+
+    // Borrows `amounts` of `tokens` from the Balancer Vault without fee.
+    // `data` is abi encoded `(address[] targets, bytes[] calls)` executed with the loan
+    function flashLoanBalancer(
+        IERC20[] calldata tokens,
+        uint[] calldata amounts,
+        bytes calldata data
+    ) external onlyExecutor {
+        flashLoanHash = keccak256(abi.encode(tokens, amounts, data));
+        BALANCER_VAULT.flashLoan(this, tokens, amounts, data);
+        flashLoanHash = bytes32(0);
+    }
+
+    // Swaps `amountIn` of `token` held by the executor, which approves it to the bot.
+    // `data` is abi encoded `(address[] targets, bytes[] calls)` executed with the tokens,
+    // the output returns to the executor
+    function swapInventory(IERC20 token, uint amountIn, bytes calldata data) external onlyExecutor {
+        uint balance = token.balanceOf(address(this));
+        _transferFrom(token, msg.sender, amountIn);
+        flashLoanHash = keccak256(data);
+        _execute(data);
+        flashLoanHash = bytes32(0);
+
+        uint amountOut = token.balanceOf(address(this)) - balance;
+        require(amountOut >= amountIn, "ArbBot: insufficient output");
+        _transfer(token, msg.sender, amountOut);
+    }
+
+    function receiveFlashLoan(
+        IERC20[] memory tokens,
+        uint[] memory amounts,
+        uint[] memory feeAmounts,
+        bytes memory userData
+    ) external override {
+        require(msg.sender == address(BALANCER_VAULT), "ArbBot: not vault");
+        require(
+            flashLoanHash != bytes32(0) &&
+                keccak256(abi.encode(tokens, amounts, userData)) == flashLoanHash,
+            "ArbBot: unexpected flash loan"
+        );
+
+        _execute(userData);
+
+        for (uint i = 0; i < tokens.length; i++) {
+            _transfer(tokens[i], msg.sender, amounts[i] + feeAmounts[i]);
+        }
+    }
+
+    // Uniswap V4 swap of the flash loan calls: `unlock` of the PoolManager calls back
+    // with `(PoolKey key, bool zeroForOne, uint amountIn, uint minAmountOut)`
+    function unlockCallback(bytes calldata data) external override returns (bytes memory) {
+        require(msg.sender == address(POOL_MANAGER), "ArbBot: not pool manager");
+        require(flashLoanHash != bytes32(0), "ArbBot: unexpected unlock");
+
+        (
+            IPoolManager.PoolKey memory key,
+            bool zeroForOne,
+            uint amountIn,
+            uint minAmountOut
+        ) = abi.decode(data, (IPoolManager.PoolKey, bool, uint, uint));
+        int256 delta = POOL_MANAGER.swap(
+            key,
+            IPoolManager.SwapParams({
+                zeroForOne: zeroForOne,
+                amountSpecified: -int256(amountIn),
+                sqrtPriceLimitX96: zeroForOne ? MIN_SQRT_PRICE + 1 : MAX_SQRT_PRICE - 1
+            }),
+            ""
+        );
+        int128 amount0 = int128(delta >> 128);
+        int128 amount1 = int128(delta);
+
+        (address currencyIn, address currencyOut) = zeroForOne
+            ? (key.currency0, key.currency1)
+            : (key.currency1, key.currency0);
+        (int128 paid, int128 received) = zeroForOne ? (amount0, amount1) : (amount1, amount0);
+        require(uint128(received) >= minAmountOut, "ArbBot: insufficient output");
+
+        POOL_MANAGER.sync(currencyIn);
+        _transfer(IERC20(currencyIn), address(POOL_MANAGER), uint128(-paid));
+        POOL_MANAGER.settle();
+        POOL_MANAGER.take(currencyOut, address(this), uint128(received));
+        return "";
+    }
+
+    function _execute(bytes memory data) private {
+        (address[] memory targets, bytes[] memory calls) = abi.decode(data, (address[], bytes[]));
+        require(targets.length == calls.length, "ArbBot: invalid calls");
+        for (uint i = 0; i < targets.length; i++) {
+            require(allowedTargets[targets[i]], "ArbBot: target not allowed");
+            (bool success, ) = targets[i].call(calls[i]);
+            require(success, "ArbBot: call failed");
+        }
+    }
+
+    // Tokens like USDT return nothing from `transfer` and `transferFrom`
+    function _transfer(IERC20 token, address to, uint amount) private {
+        _call(token, abi.encodeCall(IERC20.transfer, (to, amount)));
+    }
+
+    function _transferFrom(IERC20 token, address from, uint amount) private {
+        _call(token, abi.encodeCall(IERC20.transferFrom, (from, address(this), amount)));
+    }
+
+    function _call(IERC20 token, bytes memory call) private {
+        (bool success, bytes memory result) = address(token).call(call);
+        require(
+            success && (result.length == 0 || abi.decode(result, (bool))),
+            "ArbBot: transfer failed"
+        );
+    }
 }
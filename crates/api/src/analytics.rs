@@ -0,0 +1,182 @@
+use crate::{error::ApiError, models::*, routes::clamp_limit, AppState};
+use alloy::primitives::Address;
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use kronos_db::analytics::{StatsBucket, TokenFlow};
+use std::collections::HashMap;
+
+pub const DEFAULT_HOURS: u32 = 24;
+pub const MAX_HOURS: u32 = 24 * 90;
+
+pub const DEFAULT_FLOW_TOKENS: u32 = 10;
+pub const MAX_FLOW_TOKENS: u32 = 50;
+
+fn since(hours: Option<u32>, default: u32) -> DateTime<Utc> {
+    let hours = hours.unwrap_or(default).clamp(1, MAX_HOURS);
+    Utc::now() - TimeDelta::hours(hours as i64)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/analytics/loops",
+    params(LimitQuery),
+    responses((status = 200, body = Vec<LoopView>)),
+)]
+pub async fn loops(
+    State(state): State<AppState>,
+    Query(query): Query<LimitQuery>,
+) -> Result<Json<Vec<LoopView>>, ApiError> {
+    let loops = state.store.loops(clamp_limit(query.limit) as i64).await?;
+    Ok(Json(loops.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/analytics/stats",
+    params(StatsQuery),
+    responses((status = 200, body = Vec<BucketStatsView>)),
+)]
+pub async fn stats(
+    State(state): State<AppState>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<Vec<BucketStatsView>>, ApiError> {
+    let bucket = query.bucket.unwrap_or_default();
+    let default_hours = match bucket {
+        StatsBucket::Hour => DEFAULT_HOURS,
+        StatsBucket::Day => 24 * 30,
+    };
+
+    let stats = state
+        .store
+        .bucket_stats(bucket, since(query.hours, default_hours))
+        .await?;
+    Ok(Json(stats.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/analytics/tokens",
+    params(AnalyticsQuery),
+    responses((status = 200, body = Vec<TokenStatsView>)),
+)]
+pub async fn tokens(
+    State(state): State<AppState>,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<Vec<TokenStatsView>>, ApiError> {
+    let stats = state
+        .store
+        .token_stats(
+            since(query.hours, DEFAULT_HOURS),
+            clamp_limit(query.limit) as i64,
+        )
+        .await?;
+    Ok(Json(stats.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/analytics/dexes",
+    params(AnalyticsQuery),
+    responses((status = 200, body = Vec<DexStatsView>)),
+)]
+pub async fn dexes(
+    State(state): State<AppState>,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<Vec<DexStatsView>>, ApiError> {
+    let stats = state
+        .store
+        .dex_stats(since(query.hours, DEFAULT_HOURS))
+        .await?;
+    Ok(Json(stats.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/analytics/pairs",
+    params(AnalyticsQuery),
+    responses((status = 200, body = Vec<PairCyclesView>)),
+)]
+pub async fn pairs(
+    State(state): State<AppState>,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<Vec<PairCyclesView>>, ApiError> {
+    let pairs = state
+        .store
+        .top_pairs(
+            since(query.hours, DEFAULT_HOURS),
+            clamp_limit(query.limit) as i64,
+        )
+        .await?;
+    Ok(Json(pairs.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/analytics/flows",
+    params(FlowsQuery),
+    responses((status = 200, body = FlowMatrixView)),
+)]
+pub async fn flows(
+    State(state): State<AppState>,
+    Query(query): Query<FlowsQuery>,
+) -> Result<Json<FlowMatrixView>, ApiError> {
+    let flows = state
+        .store
+        .token_flows(since(query.hours, DEFAULT_HOURS))
+        .await?;
+    let tokens = query
+        .tokens
+        .unwrap_or(DEFAULT_FLOW_TOKENS)
+        .clamp(1, MAX_FLOW_TOKENS);
+    Ok(Json(flow_matrix(&flows, tokens as usize)))
+}
+
+/// Builds the square matrix of swaps between `max_tokens` the most traded tokens,
+/// swaps with other tokens are left out
+pub fn flow_matrix(flows: &[TokenFlow], max_tokens: usize) -> FlowMatrixView {
+    let mut volume: HashMap<Address, (i64, String)> = HashMap::new();
+    for flow in flows {
+        for (token, ticker) in [
+            (flow.token_in, &flow.ticker_in),
+            (flow.token_out, &flow.ticker_out),
+        ] {
+            let entry = volume.entry(token).or_insert_with(|| {
+                let ticker = ticker.clone().unwrap_or_else(|| token.to_string());
+                (0, ticker)
+            });
+            entry.0 += flow.swaps;
+        }
+    }
+
+    let mut top: Vec<(Address, i64, String)> = volume
+        .into_iter()
+        .map(|(token, (swaps, ticker))| (token, swaps, ticker))
+        .collect();
+    top.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    top.truncate(max_tokens);
+
+    let index: HashMap<Address, usize> = top
+        .iter()
+        .enumerate()
+        .map(|(index, (token, _, _))| (*token, index))
+        .collect();
+    let mut matrix = vec![vec![0; top.len()]; top.len()];
+    for flow in flows {
+        if let (Some(from), Some(to)) = (index.get(&flow.token_in), index.get(&flow.token_out)) {
+            matrix[*from][*to] += flow.swaps;
+        }
+    }
+
+    let (tokens, tickers) = top
+        .into_iter()
+        .map(|(token, _, ticker)| (token, ticker))
+        .unzip();
+    FlowMatrixView {
+        tokens,
+        tickers,
+        matrix,
+    }
+}
@@ -0,0 +1,44 @@
+use crate::models::ErrorView;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use kronos_db::DbError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("Not found: {0}")]
+    NotFound(String),
+    /// Write endpoint is called without the api token
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("Storage error: {0}")]
+    Db(DbError),
+}
+
+impl From<DbError> for ApiError {
+    fn from(err: DbError) -> Self {
+        match err {
+            DbError::NotFound(what) => Self::NotFound(what),
+            err => Self::Db(err),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Db(err) => {
+                tracing::warn!("api storage error: {err}");
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+        };
+        let body = ErrorView {
+            error: self.to_string(),
+        };
+        (status, Json(body)).into_response()
+    }
+}
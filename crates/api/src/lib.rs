@@ -0,0 +1,91 @@
+use axum::{
+    http::{header, HeaderValue, Method},
+    routing::get,
+    Json, Router,
+};
+use kronos_db::tables::Opportunity;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use utoipa::OpenApi;
+
+pub mod analytics;
+pub mod error;
+pub mod memory;
+pub mod models;
+pub mod openapi;
+pub mod routes;
+pub mod store;
+pub mod ws;
+
+pub use memory::MemoryStore;
+pub use openapi::ApiDoc;
+pub use store::{DbStore, Store};
+
+/// Capacity of the live opportunities channel, slow ws clients skip older messages
+pub const OPPORTUNITIES_CAPACITY: usize = 1024;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub store: Arc<dyn Store>,
+    /// Opportunities to push to ws clients
+    pub opportunities: broadcast::Sender<Opportunity>,
+    /// Bearer token of write endpoints, they are refused if it is not set
+    pub token: Option<String>,
+    /// Origins of browser clients, other origins get no CORS headers
+    pub cors_origins: Vec<HeaderValue>,
+}
+
+impl AppState {
+    pub fn new(store: Arc<dyn Store>) -> Self {
+        let (opportunities, _) = broadcast::channel(OPPORTUNITIES_CAPACITY);
+        Self {
+            store,
+            opportunities,
+            token: None,
+            cors_origins: vec![],
+        }
+    }
+
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn with_cors_origins(mut self, origins: Vec<HeaderValue>) -> Self {
+        self.cors_origins = origins;
+        self
+    }
+}
+
+pub fn router(state: AppState) -> Router {
+    let cors = CorsLayer::new()
+        .allow_origin(AllowOrigin::list(state.cors_origins.clone()))
+        .allow_methods([Method::GET, Method::PUT])
+        .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION]);
+    Router::new()
+        .route("/api/dexes", get(routes::dexes))
+        .route("/api/pairs", get(routes::pairs))
+        .route("/api/pairs/{address}/reserves", get(routes::reserves))
+        .route("/api/tokens/{address}", get(routes::token))
+        .route("/api/opportunities", get(routes::opportunities))
+        .route("/api/executions", get(routes::executions))
+        .route("/api/health", get(routes::health))
+        .route(
+            "/api/kill-switch",
+            get(routes::kill_switch).put(routes::set_kill_switch),
+        )
+        .route("/api/analytics/loops", get(analytics::loops))
+        .route("/api/analytics/stats", get(analytics::stats))
+        .route("/api/analytics/tokens", get(analytics::tokens))
+        .route("/api/analytics/dexes", get(analytics::dexes))
+        .route("/api/analytics/pairs", get(analytics::pairs))
+        .route("/api/analytics/flows", get(analytics::flows))
+        .route("/api/ws/opportunities", get(ws::opportunities))
+        .route(
+            "/api/openapi.json",
+            get(|| async { Json(ApiDoc::openapi()) }),
+        )
+        .layer(cors)
+        .with_state(state)
+}
@@ -1,6 +1,68 @@
 use anyhow::Result;
+use futures::StreamExt;
+use kronos_api::{router, AppState, DbStore};
+use kronos_config::Config;
+use kronos_db::{redis::RedisDB, PostgresDB};
+use std::{sync::Arc, time::Duration};
+use tracing::{info, warn};
+
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(5);
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    kronos_logger::init_logger(tracing::Level::INFO);
+    let config = Config::load("./config.yml".into())?;
+
+    let postgres = PostgresDB::connect(&config.postgres).await?;
+    let redis = RedisDB::connect(&config.redis).await?;
+    let mut state = AppState::new(Arc::new(DbStore::new(postgres.clone(), redis.clone())));
+    match std::env::var(&config.api.token_env) {
+        Ok(token) if !token.is_empty() => state = state.with_token(token),
+        _ => warn!(
+            "{} is not set, the kill switch can't be changed through the api",
+            config.api.token_env
+        ),
+    }
+    let origins = config
+        .api
+        .cors_origins
+        .iter()
+        .map(|origin| origin.parse())
+        .collect::<Result<Vec<_>, _>>()?;
+    let state = state.with_cors_origins(origins);
+
+    let refresh_interval = Duration::from_secs(config.api.aggregates_refresh_secs);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(refresh_interval);
+        loop {
+            interval.tick().await;
+            if let Err(err) = postgres.refresh_aggregates().await {
+                warn!("failed to refresh analytics aggregates: {err}");
+            }
+        }
+    });
+
+    // The bot publishes opportunities to redis, forward them to ws clients
+    let opportunities = state.opportunities.clone();
+    tokio::spawn(async move {
+        loop {
+            match redis.subscribe_opportunities().await {
+                Ok(stream) => {
+                    let mut stream = std::pin::pin!(stream);
+                    while let Some(opportunity) = stream.next().await {
+                        // No receivers is fine, nobody is connected
+                        let _ = opportunities.send(opportunity);
+                    }
+                    warn!("opportunities subscription closed");
+                }
+                Err(err) => warn!("failed to subscribe to opportunities: {err}"),
+            }
+            tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+        }
+    });
+
+    let listener = tokio::net::TcpListener::bind(&config.api.listen).await?;
+    info!("api listening on {}", config.api.listen);
+    axum::serve(listener, router(state)).await?;
     Ok(())
 }
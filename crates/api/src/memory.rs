@@ -0,0 +1,303 @@
+use crate::store::Store;
+use alloy::primitives::Address;
+use chrono::{DateTime, Utc};
+use kronos_common::Reserves;
+use kronos_db::{
+    analytics::{BucketStats, DexStats, Loop, PairCycles, StatsBucket, TokenFlow, TokenStats},
+    tables::{Dex, DexStatus, Execution, KillSwitch, Opportunity, Pair, Token},
+    DbError, PairsFilter, Result,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::RwLock,
+};
+
+/// In-process `Store`, used in tests and to run the api without the bot
+#[derive(Default)]
+pub struct MemoryStore {
+    pub dexes: RwLock<Vec<Dex>>,
+    pub pairs: RwLock<Vec<Pair>>,
+    pub tokens: RwLock<HashMap<Address, Token>>,
+    pub reserves: RwLock<HashMap<Address, Reserves>>,
+    pub opportunities: RwLock<Vec<Opportunity>>,
+    pub executions: RwLock<Vec<Execution>>,
+    pub health: RwLock<Vec<DexStatus>>,
+    pub kill_switch: RwLock<Option<KillSwitch>>,
+}
+
+impl MemoryStore {
+    fn ticker(&self, token: &Address) -> Option<String> {
+        self.tokens
+            .read()
+            .unwrap()
+            .get(token)
+            .map(|token| token.ticker.clone())
+    }
+
+    fn dex_name(&self, dex_id: i32) -> Option<String> {
+        self.dexes
+            .read()
+            .unwrap()
+            .iter()
+            .find(|dex| dex.id == dex_id)
+            .map(|dex| dex.name.clone())
+    }
+
+    fn opportunities_since(&self, since: DateTime<Utc>) -> Vec<Opportunity> {
+        self.opportunities
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|opportunity| opportunity.created_at >= since)
+            .cloned()
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl Store for MemoryStore {
+    async fn dexes(&self) -> Result<Vec<Dex>> {
+        Ok(self.dexes.read().unwrap().clone())
+    }
+
+    async fn pairs(&self, filter: &PairsFilter) -> Result<(Vec<Pair>, i64)> {
+        let mut pairs: Vec<Pair> = self
+            .pairs
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|pair| {
+                filter
+                    .token
+                    .is_none_or(|token| pair.token0 == token || pair.token1 == token)
+                    && filter.dex_id.is_none_or(|dex_id| pair.dex_id == dex_id)
+            })
+            .cloned()
+            .collect();
+        pairs.sort_by_key(|pair| pair.address);
+
+        let total = pairs.len() as i64;
+        let page = pairs
+            .into_iter()
+            .skip(filter.offset as usize)
+            .take(filter.limit as usize)
+            .collect();
+        Ok((page, total))
+    }
+
+    async fn token(&self, token: &Address) -> Result<Token> {
+        self.tokens
+            .read()
+            .unwrap()
+            .get(token)
+            .cloned()
+            .ok_or(DbError::not_found(format!("metadata of token {token:?}")))
+    }
+
+    async fn reserves(&self, pair_adr: &Address) -> Result<(Pair, Reserves)> {
+        let pair = self
+            .pairs
+            .read()
+            .unwrap()
+            .iter()
+            .find(|pair| pair.address == *pair_adr)
+            .cloned()
+            .ok_or(DbError::not_found(format!("pair {pair_adr:?}")))?;
+        let reserves = self
+            .reserves
+            .read()
+            .unwrap()
+            .get(pair_adr)
+            .cloned()
+            .ok_or(DbError::not_found(format!("reserves of pair {pair_adr:?}")))?;
+        Ok((pair, reserves))
+    }
+
+    async fn opportunities(&self, limit: i64) -> Result<Vec<Opportunity>> {
+        let mut opportunities = self.opportunities.read().unwrap().clone();
+        opportunities.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id)));
+        opportunities.truncate(limit as usize);
+        Ok(opportunities)
+    }
+
+    async fn executions(&self, limit: i64) -> Result<Vec<Execution>> {
+        let mut executions = self.executions.read().unwrap().clone();
+        executions.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id)));
+        executions.truncate(limit as usize);
+        Ok(executions)
+    }
+
+    async fn health(&self) -> Result<Vec<DexStatus>> {
+        Ok(self.health.read().unwrap().clone())
+    }
+
+    async fn loops(&self, limit: i64) -> Result<Vec<Loop>> {
+        let opportunities = self.opportunities(limit).await?;
+        Ok(opportunities
+            .into_iter()
+            .filter_map(|opportunity| {
+                let tickers = opportunity
+                    .path
+                    .iter()
+                    .map(|token| self.ticker(token).unwrap_or_else(|| token.to_string()))
+                    .collect();
+                let amount_in_usd = opportunity
+                    .revenue_usd
+                    .filter(|_| !opportunity.revenue.is_zero())
+                    .map(|usd| {
+                        usd * f64::from(opportunity.amount_in) / f64::from(opportunity.revenue)
+                    });
+
+                Some(Loop {
+                    id: opportunity.id,
+                    block_number: opportunity.block_number,
+                    dex: self.dex_name(opportunity.dex_id)?,
+                    path: opportunity.path,
+                    tickers,
+                    amount_in_usd,
+                    revenue_usd: opportunity.revenue_usd,
+                    created_at: opportunity.created_at,
+                })
+            })
+            .collect())
+    }
+
+    async fn bucket_stats(
+        &self,
+        bucket: StatsBucket,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<BucketStats>> {
+        let mut buckets: BTreeMap<DateTime<Utc>, BucketStats> = BTreeMap::new();
+        for opportunity in self.opportunities_since(since) {
+            let start = bucket.truncate(opportunity.created_at);
+            let stats = buckets.entry(start).or_insert(BucketStats {
+                bucket: start,
+                opportunities: 0,
+                revenue_usd: 0.0,
+            });
+            stats.opportunities += 1;
+            stats.revenue_usd += opportunity.revenue_usd.unwrap_or_default();
+        }
+        Ok(buckets.into_values().collect())
+    }
+
+    async fn token_stats(&self, since: DateTime<Utc>, limit: i64) -> Result<Vec<TokenStats>> {
+        let mut tokens: HashMap<Address, TokenStats> = HashMap::new();
+        for opportunity in self.opportunities_since(since) {
+            let Some(token) = opportunity.path.first() else {
+                continue;
+            };
+            let stats = tokens.entry(*token).or_insert(TokenStats {
+                token: *token,
+                ticker: self.ticker(token),
+                opportunities: 0,
+                revenue_usd: 0.0,
+            });
+            stats.opportunities += 1;
+            stats.revenue_usd += opportunity.revenue_usd.unwrap_or_default();
+        }
+
+        let mut tokens: Vec<TokenStats> = tokens.into_values().collect();
+        tokens.sort_by(|a, b| {
+            b.opportunities
+                .cmp(&a.opportunities)
+                .then(a.token.cmp(&b.token))
+        });
+        tokens.truncate(limit as usize);
+        Ok(tokens)
+    }
+
+    async fn dex_stats(&self, since: DateTime<Utc>) -> Result<Vec<DexStats>> {
+        let mut dexes: HashMap<i32, DexStats> = HashMap::new();
+        for opportunity in self.opportunities_since(since) {
+            let Some(name) = self.dex_name(opportunity.dex_id) else {
+                continue;
+            };
+            let stats = dexes.entry(opportunity.dex_id).or_insert(DexStats {
+                dex_id: opportunity.dex_id,
+                name,
+                opportunities: 0,
+                revenue_usd: 0.0,
+            });
+            stats.opportunities += 1;
+            stats.revenue_usd += opportunity.revenue_usd.unwrap_or_default();
+        }
+
+        let mut dexes: Vec<DexStats> = dexes.into_values().collect();
+        dexes.sort_by(|a, b| {
+            b.opportunities
+                .cmp(&a.opportunities)
+                .then(a.dex_id.cmp(&b.dex_id))
+        });
+        Ok(dexes)
+    }
+
+    async fn token_flows(&self, since: DateTime<Utc>) -> Result<Vec<TokenFlow>> {
+        let mut swaps: HashMap<(Address, Address), i64> = HashMap::new();
+        for opportunity in self.opportunities_since(since) {
+            for hop in opportunity.path.windows(2) {
+                *swaps.entry((hop[0], hop[1])).or_default() += 1;
+            }
+        }
+
+        Ok(swaps
+            .into_iter()
+            .map(|((token_in, token_out), swaps)| TokenFlow {
+                token_in,
+                token_out,
+                ticker_in: self.ticker(&token_in),
+                ticker_out: self.ticker(&token_out),
+                swaps,
+            })
+            .collect())
+    }
+
+    async fn top_pairs(&self, since: DateTime<Utc>, limit: i64) -> Result<Vec<PairCycles>> {
+        let mut cycles: HashMap<(i32, Address, Address), i64> = HashMap::new();
+        for opportunity in self.opportunities_since(since) {
+            for hop in opportunity.path.windows(2) {
+                let key = (opportunity.dex_id, hop[0].min(hop[1]), hop[0].max(hop[1]));
+                *cycles.entry(key).or_default() += 1;
+            }
+        }
+
+        let pairs = self.pairs.read().unwrap();
+        let mut top: Vec<PairCycles> = cycles
+            .into_iter()
+            .map(|((dex_id, token0, token1), cycles)| PairCycles {
+                dex_id,
+                token0,
+                token1,
+                address: pairs
+                    .iter()
+                    .find(|pair| {
+                        pair.dex_id == dex_id
+                            && pair.token0.min(pair.token1) == token0
+                            && pair.token0.max(pair.token1) == token1
+                    })
+                    .map(|pair| pair.address),
+                cycles,
+            })
+            .collect();
+        top.sort_by(|a, b| {
+            b.cycles
+                .cmp(&a.cycles)
+                .then((a.dex_id, a.token0, a.token1).cmp(&(b.dex_id, b.token0, b.token1)))
+        });
+        top.truncate(limit as usize);
+        Ok(top)
+    }
+
+    async fn kill_switch(&self) -> Result<Option<KillSwitch>> {
+        Ok(self.kill_switch.read().unwrap().clone())
+    }
+
+    async fn set_kill_switch(&self, kill_switch: &KillSwitch) -> Result<()> {
+        *self.kill_switch.write().unwrap() = Some(kill_switch.clone());
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<()> {
+        Ok(())
+    }
+}
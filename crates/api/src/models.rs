@@ -0,0 +1,444 @@
+use alloy::primitives::{Address, B256, U256};
+use chrono::{DateTime, Utc};
+use kronos_common::Reserves;
+use kronos_db::{
+    analytics::{BucketStats, DexStats, Loop, PairCycles, StatsBucket, TokenStats},
+    tables::{
+        Dex, DexStatus, Execution, ExecutionMode, ExecutionStatus, GasBid, KillSwitch, Opportunity,
+        Pair, Token,
+    },
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+// Addresses, hashes and raw amounts are serialized as hex strings
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct DexView {
+    pub id: i32,
+    pub name: String,
+}
+
+impl From<Dex> for DexView {
+    fn from(dex: Dex) -> Self {
+        Self {
+            id: dex.id,
+            name: dex.name,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct PairView {
+    #[schema(value_type = String)]
+    pub address: Address,
+    pub dex_id: i32,
+    #[schema(value_type = String)]
+    pub token0: Address,
+    #[schema(value_type = String)]
+    pub token1: Address,
+}
+
+impl From<Pair> for PairView {
+    fn from(pair: Pair) -> Self {
+        Self {
+            address: pair.address,
+            dex_id: pair.dex_id,
+            token0: pair.token0,
+            token1: pair.token1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub page: u32,
+    pub limit: u32,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PairsQuery {
+    /// Only pairs with this token
+    #[param(value_type = Option<String>)]
+    pub token: Option<Address>,
+    pub dex_id: Option<i32>,
+    /// Starts from 1
+    pub page: Option<u32>,
+    /// Pairs per page, at most 500
+    pub limit: Option<u32>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LimitQuery {
+    /// Number of the latest records, at most 500
+    pub limit: Option<u32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TokenView {
+    #[schema(value_type = String)]
+    pub token: Address,
+    pub ticker: String,
+    pub name: String,
+    pub decimals: Option<u8>,
+    pub fee_on_transfer: bool,
+    pub rebasing: bool,
+    pub blacklist: bool,
+    pub unverified: bool,
+    pub honeypot: bool,
+    pub transfer_tax_bps: Option<u16>,
+}
+
+impl From<Token> for TokenView {
+    fn from(token: Token) -> Self {
+        Self {
+            token: token.token,
+            ticker: token.ticker,
+            name: token.name,
+            decimals: token.decimals,
+            fee_on_transfer: token.flags.fee_on_transfer,
+            rebasing: token.flags.rebasing,
+            blacklist: token.flags.blacklist,
+            unverified: token.flags.unverified,
+            honeypot: token.flags.honeypot,
+            transfer_tax_bps: token.transfer_tax_bps,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReservesView {
+    pub pair: PairView,
+    /// Raw amount of `token0`
+    #[schema(value_type = String)]
+    pub reserve0: U256,
+    /// Raw amount of `token1`
+    #[schema(value_type = String)]
+    pub reserve1: U256,
+}
+
+impl ReservesView {
+    pub fn new(pair: Pair, reserves: Reserves) -> Self {
+        Self {
+            pair: pair.into(),
+            reserve0: U256::from(reserves.0),
+            reserve1: U256::from(reserves.1),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct OpportunityView {
+    pub id: i64,
+    pub dex_id: i32,
+    pub block_number: u64,
+    /// Tokens of the cycle, the first and the last are the same
+    #[schema(value_type = Vec<String>)]
+    pub path: Vec<Address>,
+    #[schema(value_type = String)]
+    pub amount_in: U256,
+    #[schema(value_type = String)]
+    pub revenue: U256,
+    pub revenue_usd: Option<f64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<Opportunity> for OpportunityView {
+    fn from(opportunity: Opportunity) -> Self {
+        Self {
+            id: opportunity.id,
+            dex_id: opportunity.dex_id,
+            block_number: opportunity.block_number,
+            path: opportunity.path,
+            amount_in: opportunity.amount_in,
+            revenue: opportunity.revenue,
+            revenue_usd: opportunity.revenue_usd,
+            created_at: opportunity.created_at,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ExecutionView {
+    pub id: i64,
+    pub opportunity_id: Option<i64>,
+    pub block_number: u64,
+    #[schema(value_type = Option<String>)]
+    pub tx_hash: Option<B256>,
+    /// `pending`, `included`, `reverted` or `failed`
+    #[schema(value_type = String)]
+    pub status: ExecutionStatus,
+    /// `live` or `paper`
+    #[schema(value_type = String)]
+    pub mode: ExecutionMode,
+    pub gas_used: Option<u64>,
+    pub profit_usd: Option<f64>,
+    pub gas: Option<GasBidView>,
+    /// Predicted minus simulated output in basis points
+    pub slippage_bps: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct GasBidView {
+    /// `fixed_tip`, `profit_share` or `competitive`
+    pub strategy: String,
+    /// Parameters of the strategy as JSON
+    pub params: String,
+    pub max_fee_per_gas: u128,
+    pub max_priority_fee_per_gas: u128,
+}
+
+impl From<GasBid> for GasBidView {
+    fn from(bid: GasBid) -> Self {
+        Self {
+            strategy: bid.strategy,
+            params: bid.params,
+            max_fee_per_gas: bid.max_fee_per_gas,
+            max_priority_fee_per_gas: bid.max_priority_fee_per_gas,
+        }
+    }
+}
+
+impl From<Execution> for ExecutionView {
+    fn from(execution: Execution) -> Self {
+        Self {
+            id: execution.id,
+            opportunity_id: execution.opportunity_id,
+            block_number: execution.block_number,
+            tx_hash: execution.tx_hash,
+            status: execution.status,
+            mode: execution.mode,
+            gas_used: execution.gas_used,
+            profit_usd: execution.profit_usd,
+            gas: execution.gas.map(Into::into),
+            slippage_bps: execution.slippage_bps,
+            created_at: execution.created_at,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct DexStatusView {
+    pub name: String,
+    pub dex_id: i32,
+    pub last_block: u64,
+    pub last_success_block: u64,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<DexStatus> for DexStatusView {
+    fn from(status: DexStatus) -> Self {
+        Self {
+            name: status.name,
+            dex_id: status.dex_id,
+            last_block: status.last_block,
+            last_success_block: status.last_success_block,
+            consecutive_failures: status.consecutive_failures,
+            last_error: status.last_error,
+            updated_at: status.updated_at,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct HealthView {
+    /// `ok` if storage is reachable and all adapters are healthy and alive
+    pub status: String,
+    pub storage: bool,
+    pub dexes: Vec<DexStatusView>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct KillSwitchView {
+    /// Execution is paused while the kill switch is engaged, detection keeps running
+    pub engaged: bool,
+    pub reason: Option<String>,
+    /// `None` if the kill switch was never set
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl From<Option<KillSwitch>> for KillSwitchView {
+    fn from(kill_switch: Option<KillSwitch>) -> Self {
+        match kill_switch {
+            Some(kill_switch) => Self {
+                engaged: kill_switch.engaged,
+                reason: kill_switch.reason,
+                updated_at: Some(kill_switch.updated_at),
+            },
+            None => Self {
+                engaged: false,
+                reason: None,
+                updated_at: None,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct KillSwitchRequest {
+    pub engaged: bool,
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AnalyticsQuery {
+    /// Time window in hours, 24 by default
+    pub hours: Option<u32>,
+    /// Number of returned rows, at most 500
+    pub limit: Option<u32>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatsQuery {
+    /// `hour` or `day`
+    #[param(value_type = Option<String>)]
+    pub bucket: Option<StatsBucket>,
+    /// Time window in hours, 24 for hourly and 720 for daily buckets by default
+    pub hours: Option<u32>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FlowsQuery {
+    /// Time window in hours, 24 by default
+    pub hours: Option<u32>,
+    /// Number of the most traded tokens in the matrix, at most 50
+    pub tokens: Option<u32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct LoopView {
+    pub id: i64,
+    pub block_number: u64,
+    pub dex: String,
+    #[schema(value_type = Vec<String>)]
+    pub path: Vec<Address>,
+    /// Ticker of every token of `path`, address if the ticker is unknown
+    pub tickers: Vec<String>,
+    pub amount_in_usd: Option<f64>,
+    pub revenue_usd: Option<f64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<Loop> for LoopView {
+    fn from(cycle: Loop) -> Self {
+        Self {
+            id: cycle.id,
+            block_number: cycle.block_number,
+            dex: cycle.dex,
+            path: cycle.path,
+            tickers: cycle.tickers,
+            amount_in_usd: cycle.amount_in_usd,
+            revenue_usd: cycle.revenue_usd,
+            created_at: cycle.created_at,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct BucketStatsView {
+    /// Start of the bucket
+    pub bucket: DateTime<Utc>,
+    pub opportunities: i64,
+    pub revenue_usd: f64,
+}
+
+impl From<BucketStats> for BucketStatsView {
+    fn from(stats: BucketStats) -> Self {
+        Self {
+            bucket: stats.bucket,
+            opportunities: stats.opportunities,
+            revenue_usd: stats.revenue_usd,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TokenStatsView {
+    /// Start token of the cycles
+    #[schema(value_type = String)]
+    pub token: Address,
+    pub ticker: Option<String>,
+    pub opportunities: i64,
+    pub revenue_usd: f64,
+}
+
+impl From<TokenStats> for TokenStatsView {
+    fn from(stats: TokenStats) -> Self {
+        Self {
+            token: stats.token,
+            ticker: stats.ticker,
+            opportunities: stats.opportunities,
+            revenue_usd: stats.revenue_usd,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct DexStatsView {
+    pub dex_id: i32,
+    pub name: String,
+    pub opportunities: i64,
+    pub revenue_usd: f64,
+}
+
+impl From<DexStats> for DexStatsView {
+    fn from(stats: DexStats) -> Self {
+        Self {
+            dex_id: stats.dex_id,
+            name: stats.name,
+            opportunities: stats.opportunities,
+            revenue_usd: stats.revenue_usd,
+        }
+    }
+}
+
+/// Input of the chord chart, `matrix[i][j]` is the number of swaps from `tokens[i]` to `tokens[j]`
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct FlowMatrixView {
+    #[schema(value_type = Vec<String>)]
+    pub tokens: Vec<Address>,
+    /// Ticker of every token of `tokens`, address if the ticker is unknown
+    pub tickers: Vec<String>,
+    pub matrix: Vec<Vec<i64>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct PairCyclesView {
+    pub dex_id: i32,
+    #[schema(value_type = String)]
+    pub token0: Address,
+    #[schema(value_type = String)]
+    pub token1: Address,
+    /// Known pool of the dex with these tokens
+    #[schema(value_type = Option<String>)]
+    pub address: Option<Address>,
+    /// Number of hops of cycles through the pair
+    pub cycles: i64,
+}
+
+impl From<PairCycles> for PairCyclesView {
+    fn from(pair: PairCycles) -> Self {
+        Self {
+            dex_id: pair.dex_id,
+            token0: pair.token0,
+            token1: pair.token1,
+            address: pair.address,
+            cycles: pair.cycles,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorView {
+    pub error: String,
+}
@@ -0,0 +1,50 @@
+use crate::{analytics, models::*, routes, ws};
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Kronos api",
+        description = "Api over the data collected by the bot and the kill switch of its executor"
+    ),
+    paths(
+        routes::dexes,
+        routes::pairs,
+        routes::token,
+        routes::reserves,
+        routes::opportunities,
+        routes::executions,
+        routes::health,
+        routes::kill_switch,
+        routes::set_kill_switch,
+        analytics::loops,
+        analytics::stats,
+        analytics::tokens,
+        analytics::dexes,
+        analytics::pairs,
+        analytics::flows,
+        ws::opportunities,
+    ),
+    components(schemas(
+        DexView,
+        PairView,
+        Page<PairView>,
+        TokenView,
+        ReservesView,
+        OpportunityView,
+        ExecutionView,
+        GasBidView,
+        DexStatusView,
+        HealthView,
+        KillSwitchView,
+        KillSwitchRequest,
+        LoopView,
+        BucketStatsView,
+        TokenStatsView,
+        DexStatsView,
+        FlowMatrixView,
+        PairCyclesView,
+        ErrorView,
+    ))
+)]
+pub struct ApiDoc;
@@ -0,0 +1,222 @@
+use crate::{error::ApiError, models::*, AppState};
+use alloy::primitives::Address;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap},
+    Json,
+};
+use chrono::Utc;
+use kronos_db::{tables::KillSwitch, PairsFilter};
+
+pub const DEFAULT_LIMIT: u32 = 50;
+pub const MAX_LIMIT: u32 = 500;
+
+/// Health status becomes `degraded` if an adapter has not reported for this long
+pub const STALE_AFTER_SECS: i64 = 60;
+
+pub(crate) fn clamp_limit(limit: Option<u32>) -> u32 {
+    limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+}
+
+/// Write endpoints take `Authorization: Bearer <token>`, they are refused
+/// if the api has no token
+fn authorize(state: &AppState, headers: &HeaderMap) -> Result<(), ApiError> {
+    let token = state
+        .token
+        .as_deref()
+        .ok_or(ApiError::Unauthorized("api token is not configured".into()))?;
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    // compared in constant time, so the token can't be guessed byte by byte
+    let equal = given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0;
+    match equal {
+        true => Ok(()),
+        false => Err(ApiError::Unauthorized("invalid api token".into())),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/dexes",
+    responses((status = 200, body = Vec<DexView>)),
+)]
+pub async fn dexes(State(state): State<AppState>) -> Result<Json<Vec<DexView>>, ApiError> {
+    let dexes = state.store.dexes().await?;
+    Ok(Json(dexes.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/pairs",
+    params(PairsQuery),
+    responses((status = 200, body = Page<PairView>)),
+)]
+pub async fn pairs(
+    State(state): State<AppState>,
+    Query(query): Query<PairsQuery>,
+) -> Result<Json<Page<PairView>>, ApiError> {
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = clamp_limit(query.limit);
+    let filter = PairsFilter {
+        token: query.token,
+        dex_id: query.dex_id,
+        limit: limit as i64,
+        offset: (page as i64 - 1) * limit as i64,
+    };
+
+    let (pairs, total) = state.store.pairs(&filter).await?;
+    Ok(Json(Page {
+        items: pairs.into_iter().map(Into::into).collect(),
+        total,
+        page,
+        limit,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/tokens/{address}",
+    params(("address" = String, Path, description = "Token address")),
+    responses(
+        (status = 200, body = TokenView),
+        (status = 404, body = ErrorView),
+    ),
+)]
+pub async fn token(
+    State(state): State<AppState>,
+    Path(address): Path<Address>,
+) -> Result<Json<TokenView>, ApiError> {
+    let token = state.store.token(&address).await?;
+    Ok(Json(token.into()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/pairs/{address}/reserves",
+    params(("address" = String, Path, description = "Pair address")),
+    responses(
+        (status = 200, body = ReservesView),
+        (status = 404, body = ErrorView),
+    ),
+)]
+pub async fn reserves(
+    State(state): State<AppState>,
+    Path(address): Path<Address>,
+) -> Result<Json<ReservesView>, ApiError> {
+    let (pair, reserves) = state.store.reserves(&address).await?;
+    Ok(Json(ReservesView::new(pair, reserves)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/opportunities",
+    params(LimitQuery),
+    responses((status = 200, body = Vec<OpportunityView>)),
+)]
+pub async fn opportunities(
+    State(state): State<AppState>,
+    Query(query): Query<LimitQuery>,
+) -> Result<Json<Vec<OpportunityView>>, ApiError> {
+    let limit = clamp_limit(query.limit);
+    let opportunities = state.store.opportunities(limit as i64).await?;
+    Ok(Json(opportunities.into_iter().map(Into::into).collect()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/executions",
+    params(LimitQuery),
+    responses((status = 200, body = Vec<ExecutionView>)),
+)]
+pub async fn executions(
+    State(state): State<AppState>,
+    Query(query): Query<LimitQuery>,
+) -> Result<Json<Vec<ExecutionView>>, ApiError> {
+    let limit = clamp_limit(query.limit);
+    let executions = state.store.executions(limit as i64).await?;
+    Ok(Json(executions.into_iter().map(Into::into).collect()))
+}
+
+/// Always answers 200 so that the dashboard can show what exactly is wrong
+#[utoipa::path(
+    get,
+    path = "/api/health",
+    responses((status = 200, body = HealthView)),
+)]
+pub async fn health(State(state): State<AppState>) -> Json<HealthView> {
+    let storage = state.store.ping().await.is_ok();
+    let dexes = if storage {
+        state.store.health().await.unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    let now = Utc::now();
+    let dexes_ok = !dexes.is_empty()
+        && dexes.iter().all(|status| {
+            status.consecutive_failures == 0
+                && (now - status.updated_at).num_seconds() < STALE_AFTER_SECS
+        });
+    let status = if storage && dexes_ok {
+        "ok"
+    } else {
+        "degraded"
+    };
+
+    Json(HealthView {
+        status: status.to_string(),
+        storage,
+        dexes: dexes.into_iter().map(Into::into).collect(),
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/kill-switch",
+    responses((status = 200, body = KillSwitchView)),
+)]
+pub async fn kill_switch(State(state): State<AppState>) -> Result<Json<KillSwitchView>, ApiError> {
+    let kill_switch = state.store.kill_switch().await?;
+    Ok(Json(kill_switch.into()))
+}
+
+/// Engaged kill switch pauses execution of the bot within a second.
+/// Requires `Authorization: Bearer <token>`
+#[utoipa::path(
+    put,
+    path = "/api/kill-switch",
+    request_body = KillSwitchRequest,
+    responses(
+        (status = 200, body = KillSwitchView),
+        (status = 401, body = ErrorView),
+    ),
+)]
+pub async fn set_kill_switch(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<KillSwitchRequest>,
+) -> Result<Json<KillSwitchView>, ApiError> {
+    authorize(&state, &headers)?;
+    let kill_switch = KillSwitch {
+        engaged: request.engaged,
+        reason: request.reason,
+        updated_at: Utc::now(),
+    };
+    state.store.set_kill_switch(&kill_switch).await?;
+    tracing::warn!(
+        "kill switch is {} through api",
+        match kill_switch.engaged {
+            true => "engaged",
+            false => "released",
+        }
+    );
+    Ok(Json(Some(kill_switch).into()))
+}
@@ -0,0 +1,150 @@
+use alloy::primitives::Address;
+use chrono::{DateTime, Utc};
+use kronos_common::Reserves;
+use kronos_db::{
+    analytics::{BucketStats, DexStats, Loop, PairCycles, StatsBucket, TokenFlow, TokenStats},
+    redis::RedisDB,
+    tables::{Dex, DexStatus, Execution, KillSwitch, Opportunity, Pair, Token},
+    PairsFilter, PostgresDB, Result,
+};
+
+/// Storage used by the api, everything but the kill switch is read only
+#[async_trait::async_trait]
+pub trait Store: Send + Sync {
+    async fn dexes(&self) -> Result<Vec<Dex>>;
+
+    /// Returns pairs of the page and total number of matched pairs
+    async fn pairs(&self, filter: &PairsFilter) -> Result<(Vec<Pair>, i64)>;
+
+    async fn token(&self, token: &Address) -> Result<Token>;
+
+    /// Returns the pair and its cached reserves in order of pair tokens
+    async fn reserves(&self, pair_adr: &Address) -> Result<(Pair, Reserves)>;
+
+    /// Newest first
+    async fn opportunities(&self, limit: i64) -> Result<Vec<Opportunity>>;
+
+    /// Newest first
+    async fn executions(&self, limit: i64) -> Result<Vec<Execution>>;
+
+    async fn health(&self) -> Result<Vec<DexStatus>>;
+
+    /// Newest first
+    async fn loops(&self, limit: i64) -> Result<Vec<Loop>>;
+
+    /// Oldest bucket first
+    async fn bucket_stats(
+        &self,
+        bucket: StatsBucket,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<BucketStats>>;
+
+    /// The most active start tokens first
+    async fn token_stats(&self, since: DateTime<Utc>, limit: i64) -> Result<Vec<TokenStats>>;
+
+    /// The most active dexes first
+    async fn dex_stats(&self, since: DateTime<Utc>) -> Result<Vec<DexStats>>;
+
+    async fn token_flows(&self, since: DateTime<Utc>) -> Result<Vec<TokenFlow>>;
+
+    /// Pairs which appear in cycles most often first
+    async fn top_pairs(&self, since: DateTime<Utc>, limit: i64) -> Result<Vec<PairCycles>>;
+
+    /// `None` if the kill switch was never set
+    async fn kill_switch(&self) -> Result<Option<KillSwitch>>;
+
+    async fn set_kill_switch(&self, kill_switch: &KillSwitch) -> Result<()>;
+
+    /// Checks that storage is reachable
+    async fn ping(&self) -> Result<()>;
+}
+
+/// `Store` over postgres and redis which are filled by the bot
+#[derive(Clone)]
+pub struct DbStore {
+    postgres: PostgresDB,
+    redis: RedisDB,
+}
+
+impl DbStore {
+    pub fn new(postgres: PostgresDB, redis: RedisDB) -> Self {
+        Self { postgres, redis }
+    }
+}
+
+#[async_trait::async_trait]
+impl Store for DbStore {
+    async fn dexes(&self) -> Result<Vec<Dex>> {
+        self.postgres.select_dexes().await
+    }
+
+    async fn pairs(&self, filter: &PairsFilter) -> Result<(Vec<Pair>, i64)> {
+        self.postgres.select_pairs_page(filter).await
+    }
+
+    async fn token(&self, token: &Address) -> Result<Token> {
+        self.postgres.get_token(token).await
+    }
+
+    async fn reserves(&self, pair_adr: &Address) -> Result<(Pair, Reserves)> {
+        let pair = self.postgres.get_pair(pair_adr).await?;
+        let reserves = self
+            .redis
+            .reserves(pair.dex_id, &pair.token0, &pair.token1)
+            .await?;
+        Ok((pair, reserves))
+    }
+
+    async fn opportunities(&self, limit: i64) -> Result<Vec<Opportunity>> {
+        self.postgres.select_opportunities(limit).await
+    }
+
+    async fn executions(&self, limit: i64) -> Result<Vec<Execution>> {
+        self.postgres.select_executions(limit).await
+    }
+
+    async fn health(&self) -> Result<Vec<DexStatus>> {
+        self.redis.health().await
+    }
+
+    async fn loops(&self, limit: i64) -> Result<Vec<Loop>> {
+        self.postgres.select_loops(limit).await
+    }
+
+    async fn bucket_stats(
+        &self,
+        bucket: StatsBucket,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<BucketStats>> {
+        self.postgres.select_bucket_stats(bucket, since).await
+    }
+
+    async fn token_stats(&self, since: DateTime<Utc>, limit: i64) -> Result<Vec<TokenStats>> {
+        self.postgres.select_token_stats(since, limit).await
+    }
+
+    async fn dex_stats(&self, since: DateTime<Utc>) -> Result<Vec<DexStats>> {
+        self.postgres.select_dex_stats(since).await
+    }
+
+    async fn token_flows(&self, since: DateTime<Utc>) -> Result<Vec<TokenFlow>> {
+        self.postgres.select_token_flows(since).await
+    }
+
+    async fn top_pairs(&self, since: DateTime<Utc>, limit: i64) -> Result<Vec<PairCycles>> {
+        self.postgres.select_top_pairs(since, limit).await
+    }
+
+    async fn kill_switch(&self) -> Result<Option<KillSwitch>> {
+        self.redis.kill_switch().await
+    }
+
+    async fn set_kill_switch(&self, kill_switch: &KillSwitch) -> Result<()> {
+        self.redis.set_kill_switch(kill_switch).await
+    }
+
+    async fn ping(&self) -> Result<()> {
+        self.postgres.ping().await?;
+        self.redis.ping().await
+    }
+}
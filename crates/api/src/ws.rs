@@ -0,0 +1,56 @@
+use crate::{models::OpportunityView, AppState};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        State, WebSocketUpgrade,
+    },
+    response::Response,
+};
+use kronos_db::tables::Opportunity;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tracing::{debug, warn};
+
+/// Pushes every new opportunity as a JSON text message
+#[utoipa::path(
+    get,
+    path = "/api/ws/opportunities",
+    responses((status = 101, description = "Stream of OpportunityView messages")),
+)]
+pub async fn opportunities(ws: WebSocketUpgrade, State(state): State<AppState>) -> Response {
+    let rx = state.opportunities.subscribe();
+    ws.on_upgrade(move |socket| stream_opportunities(socket, rx))
+}
+
+async fn stream_opportunities(mut socket: WebSocket, mut rx: Receiver<Opportunity>) {
+    loop {
+        tokio::select! {
+            received = rx.recv() => {
+                let opportunity = match received {
+                    Ok(opportunity) => opportunity,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("ws client lagged, skipped {skipped} opportunities");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+
+                let payload = match serde_json::to_string(&OpportunityView::from(opportunity)) {
+                    Ok(payload) => payload,
+                    Err(err) => {
+                        warn!("failed to encode opportunity: {err}");
+                        continue;
+                    }
+                };
+                if socket.send(Message::Text(payload.into())).await.is_err() {
+                    break;
+                }
+            }
+            // Client messages are ignored, only close is handled
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    debug!("ws client disconnected");
+}
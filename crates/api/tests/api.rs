@@ -0,0 +1,429 @@
+use alloy::primitives::{address, Address, U256};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use chrono::{Duration, Utc};
+use futures::StreamExt;
+use http_body_util::BodyExt;
+use kronos_api::{router, AppState, MemoryStore};
+use kronos_common::Reserves;
+use kronos_db::tables::{
+    Dex, DexStatus, Execution, ExecutionMode, ExecutionStatus, GasBid, Opportunity, Pair, Token,
+    TokenFlags,
+};
+use serde_json::Value;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const WETH: Address = address!("c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2");
+const USDC: Address = address!("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48");
+const DAI: Address = address!("6b175474e89094c44da98b954eedeac495271d0f");
+
+fn pair(byte: u8, dex_id: i32, token0: Address, token1: Address) -> Pair {
+    Pair {
+        address: Address::repeat_byte(byte),
+        dex_id,
+        token0,
+        token1,
+    }
+}
+
+fn opportunity(id: i64, minutes_ago: i64) -> Opportunity {
+    Opportunity {
+        id,
+        dex_id: 1,
+        block_number: 100 + id as u64,
+        path: vec![WETH, USDC, WETH],
+        amount_in: U256::from(10u64.pow(18)),
+        revenue: U256::from(10u64.pow(15)),
+        revenue_usd: Some(2.5),
+        created_at: Utc::now() - Duration::minutes(minutes_ago),
+    }
+}
+
+fn dex_status(dex_id: i32, consecutive_failures: u32) -> DexStatus {
+    DexStatus {
+        name: format!("dex{dex_id}"),
+        dex_id,
+        last_block: 100,
+        last_success_block: 100,
+        consecutive_failures,
+        last_error: None,
+        updated_at: Utc::now(),
+    }
+}
+
+fn store() -> Arc<MemoryStore> {
+    let store = MemoryStore::default();
+    *store.dexes.write().unwrap() = vec![
+        Dex {
+            id: 1,
+            name: "uniswap_v2".to_string(),
+        },
+        Dex {
+            id: 2,
+            name: "sushiswap".to_string(),
+        },
+    ];
+    *store.pairs.write().unwrap() = vec![
+        pair(1, 1, USDC, WETH),
+        pair(2, 1, DAI, WETH),
+        pair(3, 2, USDC, WETH),
+        pair(4, 2, DAI, USDC),
+    ];
+    store.tokens.write().unwrap().insert(
+        WETH,
+        Token {
+            token: WETH,
+            ticker: "WETH".to_string(),
+            name: "Wrapped Ether".to_string(),
+            decimals: Some(18),
+            flags: TokenFlags::default(),
+            transfer_tax_bps: Some(0),
+        },
+    );
+    store.reserves.write().unwrap().insert(
+        Address::repeat_byte(1),
+        Reserves(U256::from(5_000_000u64).to(), U256::from(2_000u64).to()),
+    );
+    *store.opportunities.write().unwrap() = vec![opportunity(1, 10), opportunity(2, 5)];
+    *store.executions.write().unwrap() = vec![Execution {
+        id: 1,
+        opportunity_id: Some(2),
+        block_number: 102,
+        tx_hash: None,
+        status: ExecutionStatus::Reverted,
+        mode: ExecutionMode::Paper,
+        gas_used: Some(180_000),
+        profit_usd: None,
+        gas: Some(GasBid {
+            strategy: "fixed_tip".to_string(),
+            params: r#"{"tip_gwei":1.0}"#.to_string(),
+            max_fee_per_gas: 30_000_000_000,
+            max_priority_fee_per_gas: 1_000_000_000,
+        }),
+        slippage_bps: Some(12),
+        created_at: Utc::now(),
+    }];
+    *store.health.write().unwrap() = vec![dex_status(1, 0), dex_status(2, 0)];
+    Arc::new(store)
+}
+
+const TOKEN: &str = "secret";
+const ORIGIN: &str = "http://localhost:3000";
+
+fn app(store: Arc<MemoryStore>) -> Router {
+    let state = AppState::new(store)
+        .with_token(TOKEN)
+        .with_cors_origins(vec![ORIGIN.parse().unwrap()]);
+    router(state)
+}
+
+fn put_kill_switch(token: Option<&str>) -> Request<Body> {
+    let mut request = Request::put("/api/kill-switch").header("content-type", "application/json");
+    if let Some(token) = token {
+        request = request.header("authorization", format!("Bearer {token}"));
+    }
+    request
+        .body(Body::from(r#"{"engaged":true,"reason":"incident"}"#))
+        .unwrap()
+}
+
+async fn get_status(app: Router, uri: &str) -> StatusCode {
+    let response = app
+        .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    response.status()
+}
+
+async fn get(app: Router, uri: &str) -> (StatusCode, Value) {
+    let response = app
+        .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn lists_dexes() {
+    let (status, body) = get(app(store()), "/api/dexes").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 2);
+    assert_eq!(body[0]["name"], "uniswap_v2");
+}
+
+#[tokio::test]
+async fn paginates_and_filters_pairs() {
+    let (status, body) = get(app(store()), "/api/pairs?limit=3").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["total"], 4);
+    assert_eq!(body["items"].as_array().unwrap().len(), 3);
+
+    let (_, body) = get(app(store()), "/api/pairs?limit=3&page=2").await;
+    assert_eq!(body["page"], 2);
+    assert_eq!(body["items"].as_array().unwrap().len(), 1);
+
+    let uri = format!("/api/pairs?token={DAI}");
+    let (_, body) = get(app(store()), &uri).await;
+    assert_eq!(body["total"], 2);
+
+    let uri = format!("/api/pairs?token={DAI}&dex_id=2");
+    let (_, body) = get(app(store()), &uri).await;
+    assert_eq!(body["total"], 1);
+    assert_eq!(
+        body["items"][0]["address"],
+        Address::repeat_byte(4).to_string()
+    );
+}
+
+#[tokio::test]
+async fn clamps_page_limit() {
+    let (_, body) = get(app(store()), "/api/pairs?limit=100000").await;
+    assert_eq!(body["limit"], kronos_api::routes::MAX_LIMIT);
+}
+
+#[tokio::test]
+async fn rejects_bad_address() {
+    let status = get_status(app(store()), "/api/pairs?token=nope").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let status = get_status(app(store()), "/api/tokens/nope").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn returns_token_metadata() {
+    let (status, body) = get(app(store()), &format!("/api/tokens/{WETH}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["ticker"], "WETH");
+    assert_eq!(body["decimals"], 18);
+
+    let (status, body) = get(app(store()), &format!("/api/tokens/{DAI}")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body["error"].is_string());
+}
+
+#[tokio::test]
+async fn returns_reserves() {
+    let uri = format!("/api/pairs/{}/reserves", Address::repeat_byte(1));
+    let (status, body) = get(app(store()), &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["reserve0"], "0x4c4b40");
+    assert_eq!(body["pair"]["token1"], format!("{WETH:#x}"));
+
+    // pair without cached reserves
+    let uri = format!("/api/pairs/{}/reserves", Address::repeat_byte(2));
+    let (status, _) = get(app(store()), &uri).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn returns_newest_opportunities_and_executions() {
+    let (status, body) = get(app(store()), "/api/opportunities?limit=1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["id"], 2);
+
+    let (status, body) = get(app(store()), "/api/executions").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body[0]["status"], "reverted");
+    assert_eq!(body[0]["gas"]["strategy"], "fixed_tip");
+    assert_eq!(body[0]["mode"], "paper");
+}
+
+#[tokio::test]
+async fn reports_health() {
+    let store = store();
+    let (_, body) = get(app(store.clone()), "/api/health").await;
+    assert_eq!(body["status"], "ok");
+    assert_eq!(body["storage"], true);
+
+    store.health.write().unwrap()[1] = dex_status(2, 3);
+    let (_, body) = get(app(store.clone()), "/api/health").await;
+    assert_eq!(body["status"], "degraded");
+
+    let mut stale = dex_status(2, 0);
+    stale.updated_at = Utc::now() - Duration::minutes(5);
+    store.health.write().unwrap()[1] = stale;
+    let (_, body) = get(app(store), "/api/health").await;
+    assert_eq!(body["status"], "degraded");
+}
+
+#[tokio::test]
+async fn toggles_kill_switch() {
+    let store = store();
+    let (_, body) = get(app(store.clone()), "/api/kill-switch").await;
+    assert_eq!(body["engaged"], false);
+    assert!(body["updated_at"].is_null());
+
+    let response = app(store.clone())
+        .oneshot(put_kill_switch(Some(TOKEN)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let kill_switch = store.kill_switch.read().unwrap().clone().unwrap();
+    assert!(kill_switch.engaged);
+    assert_eq!(kill_switch.reason.as_deref(), Some("incident"));
+
+    let (_, body) = get(app(store), "/api/kill-switch").await;
+    assert_eq!(body["engaged"], true);
+    assert_eq!(body["reason"], "incident");
+}
+
+#[tokio::test]
+async fn kill_switch_requires_token() {
+    let store = store();
+    for token in [None, Some("wrong"), Some("secre")] {
+        let response = app(store.clone())
+            .oneshot(put_kill_switch(token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    // without a configured token nobody can change the kill switch
+    let response = router(AppState::new(store.clone()))
+        .oneshot(put_kill_switch(Some(TOKEN)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(store.kill_switch.read().unwrap().is_none());
+}
+
+#[tokio::test]
+async fn allows_only_configured_origins() {
+    let preflight = |origin: &str| {
+        Request::options("/api/kill-switch")
+            .header("origin", origin)
+            .header("access-control-request-method", "PUT")
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = app(store()).oneshot(preflight(ORIGIN)).await.unwrap();
+    assert_eq!(response.headers()["access-control-allow-origin"], ORIGIN);
+
+    let response = app(store())
+        .oneshot(preflight("https://evil.example"))
+        .await
+        .unwrap();
+    assert!(!response
+        .headers()
+        .contains_key("access-control-allow-origin"));
+}
+
+#[tokio::test]
+async fn serves_openapi() {
+    let (status, body) = get(app(store()), "/api/openapi.json").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["paths"]["/api/pairs"].is_object());
+    assert!(body["paths"]["/api/ws/opportunities"].is_object());
+}
+
+#[tokio::test]
+async fn pushes_opportunities_over_ws() {
+    let state = AppState::new(store());
+    let opportunities = state.opportunities.clone();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router(state)).await });
+
+    let url = format!("ws://{addr}/api/ws/opportunities");
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+    // the receiver is subscribed before the upgrade completes
+    opportunities.send(opportunity(7, 0)).unwrap();
+
+    let message = tokio::time::timeout(std::time::Duration::from_secs(5), socket.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let body: Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+    assert_eq!(body["id"], 7);
+    assert_eq!(body["path"][0], format!("{WETH:#x}"));
+}
+
+#[tokio::test]
+async fn returns_latest_loops_with_tickers() {
+    let (status, body) = get(app(store()), "/api/analytics/loops?limit=1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body[0]["id"], 2);
+    assert_eq!(body[0]["dex"], "uniswap_v2");
+    // USDC has no metadata in the store
+    assert_eq!(body[0]["tickers"][0], "WETH");
+    assert_eq!(body[0]["tickers"][1], USDC.to_string());
+    assert_eq!(body[0]["amount_in_usd"], 2500.0);
+}
+
+#[tokio::test]
+async fn aggregates_opportunities() {
+    let (status, body) = get(app(store()), "/api/analytics/stats?bucket=day").await;
+    assert_eq!(status, StatusCode::OK);
+    let total: i64 = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|bucket| bucket["opportunities"].as_i64().unwrap())
+        .sum();
+    assert_eq!(total, 2);
+
+    let (_, body) = get(app(store()), "/api/analytics/tokens").await;
+    assert_eq!(body[0]["ticker"], "WETH");
+    assert_eq!(body[0]["opportunities"], 2);
+    assert_eq!(body[0]["revenue_usd"], 5.0);
+
+    let (_, body) = get(app(store()), "/api/analytics/dexes").await;
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["name"], "uniswap_v2");
+
+    let store = store();
+    store.opportunities.write().unwrap()[0].created_at = Utc::now() - Duration::days(3);
+    let (_, body) = get(app(store), "/api/analytics/tokens?hours=48").await;
+    assert_eq!(body[0]["opportunities"], 1);
+}
+
+#[tokio::test]
+async fn returns_top_pairs() {
+    let (status, body) = get(app(store()), "/api/analytics/pairs").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["address"], Address::repeat_byte(1).to_string());
+    // every opportunity goes through the pair twice
+    assert_eq!(body[0]["cycles"], 4);
+}
+
+#[tokio::test]
+async fn builds_flow_matrix() {
+    let store = store();
+    let mut dai_loop = opportunity(3, 1);
+    dai_loop.path = vec![WETH, DAI, USDC, WETH];
+    store.opportunities.write().unwrap().push(dai_loop);
+
+    let (status, body) = get(app(store.clone()), "/api/analytics/flows").await;
+    assert_eq!(status, StatusCode::OK);
+    let position = |token: Address| {
+        body["tokens"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .position(|item| *item == format!("{token:#x}"))
+            .unwrap()
+    };
+    let (weth, usdc) = (position(WETH), position(USDC));
+    assert_eq!(body["tickers"][weth], "WETH");
+    // WETH -> USDC in two loops, USDC -> WETH in three
+    assert_eq!(body["matrix"][weth][usdc], 2);
+    assert_eq!(body["matrix"][usdc][weth], 3);
+
+    // DAI has the least swaps and is left out
+    let (_, body) = get(app(store), "/api/analytics/flows?tokens=2").await;
+    assert_eq!(body["matrix"].as_array().unwrap().len(), 2);
+    assert_eq!(body["matrix"][0].as_array().unwrap().len(), 2);
+}
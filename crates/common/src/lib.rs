@@ -1,10 +1,98 @@
-use alloy::primitives::Uint;
+use alloy::{
+    primitives::Uint,
+    transports::{RpcError as AlloyRpcError, TransportError, TransportErrorKind},
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Reserves(pub Uint<112, 2>, pub Uint<112, 2>);
 
+/// What caller should do with the failed unit of work (block, pair, arbitrage)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorAction {
+    /// Failure is temporary, the same work can be repeated
+    Retry,
+    /// Work can't be done, but the rest of the stream is fine
+    Skip,
+    /// State is broken, continuing makes no sense
+    Abort,
+}
+
+/// JSON-RPC codes which providers use for request limits
+pub const RATE_LIMIT_CODES: [i64; 2] = [429, -32005];
+
+/// Node request errors split by the way they should be handled
 #[derive(Debug, thiserror::Error)]
-pub enum DexError {
+pub enum RpcError {
+    #[error("Rate limited by rpc: {0}")]
+    RateLimited(TransportError),
+    #[error("Transient rpc error: {0}")]
+    Transient(TransportError),
+    /// Node answered with an error, e.g. reverted call or unknown block
+    #[error("Rpc request rejected: {0}")]
+    Rejected(TransportError),
     #[error("Max rpc request per block")]
-    BlockRpcLimitExceed,
+    BlockLimitExceeded,
+    #[error("Failed to decode rpc response: {0}")]
+    Decode(String),
+}
+
+impl RpcError {
+    pub fn action(&self) -> ErrorAction {
+        match self {
+            Self::RateLimited(_) | Self::Transient(_) => ErrorAction::Retry,
+            Self::Rejected(_) | Self::BlockLimitExceeded | Self::Decode(_) => ErrorAction::Skip,
+        }
+    }
+}
+
+impl From<TransportError> for RpcError {
+    fn from(err: TransportError) -> Self {
+        let rate_limited = match &err {
+            AlloyRpcError::ErrorResp(payload) => RATE_LIMIT_CODES.contains(&payload.code),
+            AlloyRpcError::Transport(TransportErrorKind::HttpError(http)) => {
+                http.is_rate_limit_err()
+            }
+            _ => false,
+        };
+
+        match &err {
+            _ if rate_limited => Self::RateLimited(err),
+            AlloyRpcError::ErrorResp(_) => Self::Rejected(err),
+            AlloyRpcError::SerError(_)
+            | AlloyRpcError::DeserError { .. }
+            | AlloyRpcError::UnsupportedFeature(_)
+            | AlloyRpcError::LocalUsageError(_) => Self::Decode(err.to_string()),
+            _ => Self::Transient(err),
+        }
+    }
+}
+
+impl From<alloy::contract::Error> for RpcError {
+    fn from(err: alloy::contract::Error) -> Self {
+        match err {
+            alloy::contract::Error::TransportError(err) => err.into(),
+            err => Self::Decode(err.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_transport_errors() {
+        let err: RpcError = TransportErrorKind::http_error(429, String::new()).into();
+        assert!(matches!(err, RpcError::RateLimited(_)));
+        assert_eq!(err.action(), ErrorAction::Retry);
+
+        let err: RpcError = TransportErrorKind::http_error(500, String::new()).into();
+        assert!(matches!(err, RpcError::Transient(_)));
+
+        let err: RpcError = TransportErrorKind::backend_gone().into();
+        assert!(matches!(err, RpcError::Transient(_)));
+        assert_eq!(err.action(), ErrorAction::Retry);
+
+        assert_eq!(RpcError::BlockLimitExceeded.action(), ErrorAction::Skip);
+    }
 }
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PostgresConfig {
@@ -40,12 +40,435 @@ impl RedisConfig {
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DexConfig {
+    pub name: String,
+    /// Type of adapter which is built for the dex, `name` is used if not set
+    #[serde(default)]
+    pub kind: Option<String>,
+    /// Factory of uniswap v2 compatible pairs, only its pairs belong to the dex
+    #[serde(default)]
+    pub factory: Option<String>,
+}
+
+impl DexConfig {
+    pub fn kind(&self) -> &str {
+        self.kind.as_deref().unwrap_or(&self.name)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OracleConfig {
+    /// Pools with less liquidity (in USD) are ignored by price oracle
+    pub min_liquidity_usd: f64,
+}
+
+impl Default for OracleConfig {
+    fn default() -> Self {
+        Self {
+            min_liquidity_usd: 10_000.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    /// Address of `/metrics` endpoint for prometheus
+    pub listen: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            listen: "0.0.0.0:9100".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiConfig {
+    /// Address of REST and WebSocket api
+    pub listen: String,
+    /// Interval of recomputing analytics aggregates in postgres
+    pub aggregates_refresh_secs: u64,
+    /// Environment variable with the bearer token of write endpoints,
+    /// the kill switch can't be changed through the api without it
+    pub token_env: String,
+    /// Origins of browser clients which may call the api
+    pub cors_origins: Vec<String>,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            listen: "0.0.0.0:8080".to_string(),
+            aggregates_refresh_secs: 60,
+            token_env: "KRONOS_API_TOKEN".to_string(),
+            cors_origins: vec!["http://localhost:3000".to_string()],
+        }
+    }
+}
+
+/// Key kept in an encrypted JSON keystore, password is read from the environment
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeystoreConfig {
+    pub path: PathBuf,
+    /// Environment variable with the keystore password
+    pub password_env: String,
+}
+
+/// Source of the key of an executor account
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SignerConfig {
+    Keystore(KeystoreConfig),
+    /// Remote signer which implements `eth_signTransaction`, e.g. Web3Signer
+    Remote {
+        url: String,
+        address: String,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WalletConfig {
+    /// Accounts which send arbitrage transactions in turn
+    pub executors: Vec<SignerConfig>,
+    /// Key which signs Flashbots bundles to build searcher reputation, never holds funds.
+    /// If it is set, transactions are sent to `relay_url` as bundles instead of the mempool
+    pub reputation_key: Option<KeystoreConfig>,
+    pub relay_url: String,
+    /// Alert is raised when an executor has less ETH
+    pub min_balance_eth: f64,
+    pub balance_check_secs: u64,
+}
+
+impl Default for WalletConfig {
+    fn default() -> Self {
+        Self {
+            executors: vec![],
+            reputation_key: None,
+            relay_url: "https://relay.flashbots.net".to_string(),
+            min_balance_eth: 0.05,
+            balance_check_secs: 60,
+        }
+    }
+}
+
+/// How the priority fee of arbitrage transactions is chosen
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GasStrategyConfig {
+    FixedTip {
+        tip_gwei: f64,
+    },
+    /// Tip is the share of expected profit
+    ProfitShare {
+        profit_percent: f64,
+    },
+    /// Outbids pending transactions which touch the same pairs
+    Competitive {
+        min_tip_gwei: f64,
+        outbid_percent: f64,
+        /// Tip never takes more of expected profit
+        max_profit_percent: f64,
+    },
+}
+
+impl Default for GasStrategyConfig {
+    fn default() -> Self {
+        Self::FixedTip { tip_gwei: 1.0 }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GasConfig {
+    pub strategy: GasStrategyConfig,
+    /// Max fee is the next base fee times this multiplier plus the tip,
+    /// so the transaction stays valid while base fee grows
+    pub base_fee_multiplier: f64,
+}
+
+impl Default for GasConfig {
+    fn default() -> Self {
+        Self {
+            strategy: GasStrategyConfig::default(),
+            base_fee_multiplier: 2.0,
+        }
+    }
+}
+
+/// What the executor does with found arbitrages
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionMode {
+    /// Transactions are signed and sent
+    #[default]
+    Live,
+    /// Arbitrages are simulated and filled in a virtual ledger, nothing is sent
+    Paper,
+}
+
+impl ExecutionMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Live => "live",
+            Self::Paper => "paper",
+        }
+    }
+
+    pub fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "live" => Some(Self::Live),
+            "paper" => Some(Self::Paper),
+            _ => None,
+        }
+    }
+}
+
+/// Start token held by executor accounts
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InventoryTokenConfig {
+    pub token: String,
+    /// Share of the value of the account which is kept in the token
+    pub target_percent: f64,
+}
+
+/// Arbitrages are paid from tokens held by executor accounts instead of flash swaps
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InventoryConfig {
+    pub enabled: bool,
+    pub tokens: Vec<InventoryTokenConfig>,
+    /// Amount in is at most this share of the balance of one account
+    pub max_size_percent: f64,
+    /// Token is rebalanced when its share is further from the target
+    pub rebalance_drift_percent: f64,
+    /// Smaller rebalancing swaps are not worth gas
+    pub min_rebalance_usd: f64,
+    pub max_slippage_bps: u32,
+    pub check_secs: u64,
+    /// Uniswap V2 compatible routers by dex name, rebalancing routes go only through them
+    pub routers: BTreeMap<String, String>,
+}
+
+impl Default for InventoryConfig {
+    fn default() -> Self {
+        let token = |token: &str, target_percent| InventoryTokenConfig {
+            token: token.to_string(),
+            target_percent,
+        };
+        Self {
+            enabled: false,
+            tokens: vec![
+                token("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", 40.0),
+                token("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", 20.0),
+                token("0xdAC17F958D2ee523a2206206994597C13D831ec7", 20.0),
+                token("0x6B175474E89094C44Da98b954EedeAC495271d0F", 20.0),
+            ],
+            max_size_percent: 50.0,
+            rebalance_drift_percent: 10.0,
+            min_rebalance_usd: 100.0,
+            max_slippage_bps: 50,
+            check_secs: 300,
+            routers: BTreeMap::from([(
+                "uniswap_v2".to_string(),
+                "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D".to_string(),
+            )]),
+        }
+    }
+}
+
+/// Guardrails of the executor, a limit is not checked if it is not set
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiskConfig {
+    /// Amount in of one arbitrage in USD
+    pub max_trade_usd: Option<f64>,
+    /// Amount in of all arbitrages of one block in USD
+    pub max_block_usd: Option<f64>,
+    /// Execution is paused until the next UTC day when the loss is reached
+    pub max_daily_loss_usd: Option<f64>,
+    /// Execution is paused after this number of reverted transactions in a row
+    pub max_consecutive_reverts: Option<u32>,
+    /// Amount in of open arbitrages through one token in USD
+    pub max_token_exposure_usd: Option<f64>,
+    /// Amount in of open arbitrages through one pair in USD
+    pub max_pair_exposure_usd: Option<f64>,
+    /// Kill switch is engaged while this file exists
+    pub kill_switch_file: Option<PathBuf>,
+}
+
+impl Default for RiskConfig {
+    fn default() -> Self {
+        Self {
+            max_trade_usd: Some(50_000.0),
+            max_block_usd: Some(100_000.0),
+            max_daily_loss_usd: Some(1_000.0),
+            max_consecutive_reverts: Some(3),
+            max_token_exposure_usd: Some(100_000.0),
+            max_pair_exposure_usd: Some(50_000.0),
+            kill_switch_file: Some(PathBuf::from("./KILL_SWITCH")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExecutorConfig {
+    pub mode: ExecutionMode,
+    /// Address of the deployed ArbBot contract which swaps with flash loans,
+    /// paper mode simulates its transactions
+    pub bot: Option<String>,
+    /// Pending transaction is cancelled if it is not mined for this number of blocks
+    pub stuck_after_blocks: u64,
+    pub gas: GasConfig,
+    pub inventory: InventoryConfig,
+    pub risk: RiskConfig,
+}
+
+impl Default for ExecutorConfig {
+    fn default() -> Self {
+        Self {
+            mode: ExecutionMode::default(),
+            bot: None,
+            stuck_after_blocks: 5,
+            gas: GasConfig::default(),
+            inventory: InventoryConfig::default(),
+            risk: RiskConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// `EnvFilter` directives, e.g. `info,kronos_dexes=debug`, `RUST_LOG` takes precedence
+    pub filter: String,
+    /// Logs are written as JSON lines with fields of their spans
+    pub json: bool,
+    /// Directory of daily rolling files, logs are written only to stdout if not set
+    pub dir: Option<PathBuf>,
+    /// Files are named `<file_prefix>.<date>`
+    pub file_prefix: String,
+    /// The oldest files are removed over this number, all files are kept if not set
+    pub max_files: Option<usize>,
+    /// Spans are exported as OpenTelemetry traces if set
+    pub otlp: Option<OtlpConfig>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            filter: "info".to_string(),
+            json: false,
+            dir: None,
+            file_prefix: "kronos.log".to_string(),
+            max_files: None,
+            otlp: None,
+        }
+    }
+}
+
+/// Collector which receives traces over OTLP/HTTP, e.g. Jaeger
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OtlpConfig {
+    /// Traces endpoint of the collector
+    pub endpoint: String,
+    pub service_name: String,
+    /// Share of blocks whose traces are exported
+    pub sample_ratio: f64,
+}
+
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://localhost:4318/v1/traces".to_string(),
+            service_name: "kronos".to_string(),
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+/// Where alerts are delivered
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertSinkConfig {
+    Telegram {
+        /// Environment variable with the bot token
+        bot_token_env: String,
+        chat_id: String,
+        #[serde(default = "default_telegram_api")]
+        api_url: String,
+    },
+    /// Slack incoming webhook
+    Slack {
+        /// Environment variable with the webhook url, the url is a secret
+        webhook_url_env: String,
+    },
+    /// JSON alert is posted to the url
+    Webhook { url: String },
+}
+
+fn default_telegram_api() -> String {
+    "https://api.telegram.org".to_string()
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertsConfig {
+    pub sinks: Vec<AlertSinkConfig>,
+    /// Message templates by alert kind which replace defaults, fields are put in `{field}`
+    pub templates: BTreeMap<String, String>,
+    /// Alert with the same key is sent once in this interval
+    pub dedup_secs: u64,
+    /// Info and warning alerts over this number per minute are dropped, critical are always sent
+    pub max_per_minute: u32,
+    /// Alert is raised when no block is received for this time
+    pub block_stall_secs: u64,
+    /// Interval of checking that the node answers
+    pub rpc_check_secs: u64,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self {
+            sinks: vec![],
+            templates: BTreeMap::new(),
+            dedup_secs: 600,
+            max_per_minute: 20,
+            block_stall_secs: 60,
+            rpc_check_secs: 15,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub bot_name: String,
     pub rpc_url: String,
     pub postgres: PostgresConfig,
     pub redis: RedisConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    /// Dexes are seeded into postgres `dexes` table on startup
+    #[serde(default)]
+    pub dexes: Vec<DexConfig>,
+    #[serde(default)]
+    pub oracle: OracleConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub api: ApiConfig,
+    #[serde(default)]
+    pub wallet: WalletConfig,
+    #[serde(default)]
+    pub executor: ExecutorConfig,
+    #[serde(default)]
+    pub alerts: AlertsConfig,
 }
 
 impl Config {
@@ -0,0 +1,4 @@
+// Rebuild the crate when migrations change, because they are embedded by `sqlx::migrate!`
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
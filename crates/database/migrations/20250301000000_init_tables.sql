@@ -1,10 +1,10 @@
-CREATE TABLE
+CREATE TABLE IF NOT EXISTS
     dexes (
         id SERIAL PRIMARY KEY,
         name VARCHAR(255) NOT NULL UNIQUE
     );
 
-CREATE TABLE
+CREATE TABLE IF NOT EXISTS
     trading_pairs (
         address BYTEA PRIMARY KEY,
         dex_id INT NOT NULL,
@@ -13,13 +13,5 @@ CREATE TABLE
         FOREIGN KEY (dex_id) REFERENCES dexes (id) ON DELETE CASCADE
     );
 
-CREATE TABLE
+CREATE TABLE IF NOT EXISTS
     token_tickers (token BYTEA PRIMARY KEY, ticker TEXT NOT NULL);
-
-INSERT INTO dexes (name) VALUES ('uniswap_v2');
-
--- Migrate table
--- INSERT INTO trading_pairs (address, dex_id, token0, token1)
--- SELECT p.address, e.id, p.token0, p.token1
--- FROM pairs_v2 p
--- JOIN dexes e ON e.name = 'uniswap_v2';
\ No newline at end of file
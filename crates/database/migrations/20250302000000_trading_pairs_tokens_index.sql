@@ -0,0 +1,2 @@
+CREATE INDEX IF NOT EXISTS trading_pairs_dex_tokens_idx
+    ON trading_pairs (dex_id, token0, token1);
@@ -0,0 +1,5 @@
+-- Effective transfer tax in basis points, NULL until the token is classified.
+-- Honeypots can be bought but not sold, `blacklist` means the token can freeze holders
+ALTER TABLE token_tickers
+    ADD COLUMN IF NOT EXISTS transfer_tax_bps INTEGER,
+    ADD COLUMN IF NOT EXISTS honeypot BOOLEAN NOT NULL DEFAULT FALSE;
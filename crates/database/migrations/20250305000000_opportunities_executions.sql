@@ -0,0 +1,31 @@
+-- Arbitrages found by dex adapters, path is the sequence of tokens of the cycle
+CREATE TABLE IF NOT EXISTS
+    opportunities (
+        id BIGSERIAL PRIMARY KEY,
+        dex_id INT NOT NULL,
+        block_number BIGINT NOT NULL,
+        path BYTEA[] NOT NULL,
+        amount_in NUMERIC(78, 0) NOT NULL,
+        revenue NUMERIC(78, 0) NOT NULL,
+        revenue_usd DOUBLE PRECISION,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        FOREIGN KEY (dex_id) REFERENCES dexes (id) ON DELETE CASCADE
+    );
+CREATE INDEX IF NOT EXISTS opportunities_created_at_idx
+    ON opportunities (created_at DESC);
+
+-- Transactions sent for opportunities
+CREATE TABLE IF NOT EXISTS
+    executions (
+        id BIGSERIAL PRIMARY KEY,
+        opportunity_id BIGINT,
+        block_number BIGINT NOT NULL,
+        tx_hash BYTEA,
+        status TEXT NOT NULL,
+        gas_used BIGINT,
+        profit_usd DOUBLE PRECISION,
+        created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        FOREIGN KEY (opportunity_id) REFERENCES opportunities (id) ON DELETE SET NULL
+    );
+CREATE INDEX IF NOT EXISTS executions_created_at_idx
+    ON executions (created_at DESC);
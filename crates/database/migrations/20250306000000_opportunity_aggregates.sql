@@ -0,0 +1,45 @@
+-- Aggregates of opportunities for the analytics api, refreshed by the api process.
+-- Unique indexes are required by REFRESH MATERIALIZED VIEW CONCURRENTLY.
+
+-- Opportunities per hour, dex and start token of the cycle
+CREATE MATERIALIZED VIEW IF NOT EXISTS
+    opportunity_stats_hourly AS
+SELECT
+    date_trunc('hour', created_at) AS bucket,
+    dex_id,
+    path[1] AS token,
+    COUNT(*) AS opportunities,
+    COALESCE(SUM(revenue_usd), 0) AS revenue_usd
+FROM opportunities
+GROUP BY 1, 2, 3;
+CREATE UNIQUE INDEX IF NOT EXISTS opportunity_stats_hourly_idx
+    ON opportunity_stats_hourly (bucket, dex_id, token);
+
+-- Swaps from one token to another per hour
+CREATE MATERIALIZED VIEW IF NOT EXISTS
+    token_flows_hourly AS
+SELECT
+    date_trunc('hour', o.created_at) AS bucket,
+    o.path[i] AS token_in,
+    o.path[i + 1] AS token_out,
+    COUNT(*) AS swaps
+FROM opportunities o
+CROSS JOIN LATERAL generate_series(1, array_length(o.path, 1) - 1) AS i
+GROUP BY 1, 2, 3;
+CREATE UNIQUE INDEX IF NOT EXISTS token_flows_hourly_idx
+    ON token_flows_hourly (bucket, token_in, token_out);
+
+-- Cycles going through the pair of tokens on the dex per hour, tokens are sorted
+CREATE MATERIALIZED VIEW IF NOT EXISTS
+    pair_cycles_hourly AS
+SELECT
+    date_trunc('hour', o.created_at) AS bucket,
+    o.dex_id,
+    LEAST(o.path[i], o.path[i + 1]) AS token0,
+    GREATEST(o.path[i], o.path[i + 1]) AS token1,
+    COUNT(*) AS cycles
+FROM opportunities o
+CROSS JOIN LATERAL generate_series(1, array_length(o.path, 1) - 1) AS i
+GROUP BY 1, 2, 3, 4;
+CREATE UNIQUE INDEX IF NOT EXISTS pair_cycles_hourly_idx
+    ON pair_cycles_hourly (bucket, dex_id, token0, token1);
@@ -0,0 +1,6 @@
+-- Gas strategy which priced the execution, its parameters (JSON) and chosen fees in wei
+ALTER TABLE executions
+    ADD COLUMN IF NOT EXISTS gas_strategy TEXT,
+    ADD COLUMN IF NOT EXISTS gas_params TEXT,
+    ADD COLUMN IF NOT EXISTS max_fee_per_gas NUMERIC(39, 0),
+    ADD COLUMN IF NOT EXISTS max_priority_fee_per_gas NUMERIC(39, 0);
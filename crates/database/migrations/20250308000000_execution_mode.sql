@@ -0,0 +1,4 @@
+-- Paper fills of the paper-trading mode are stored next to live executions
+ALTER TABLE executions
+    ADD COLUMN IF NOT EXISTS mode TEXT NOT NULL DEFAULT 'live',
+    ADD COLUMN IF NOT EXISTS slippage_bps INTEGER;
@@ -0,0 +1,201 @@
+use alloy::primitives::Address;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+pub const STATS_VIEW: &str = "opportunity_stats_hourly";
+pub const FLOWS_VIEW: &str = "token_flows_hourly";
+pub const PAIR_CYCLES_VIEW: &str = "pair_cycles_hourly";
+
+/// Materialized views of opportunities which are refreshed together
+pub const AGGREGATE_VIEWS: [&str; 3] = [STATS_VIEW, FLOWS_VIEW, PAIR_CYCLES_VIEW];
+
+/// Width of the time bucket of opportunity stats
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsBucket {
+    #[default]
+    Hour,
+    Day,
+}
+
+impl StatsBucket {
+    /// Field of postgres `date_trunc`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Hour => "hour",
+            Self::Day => "day",
+        }
+    }
+
+    pub fn truncate(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        use chrono::{DurationRound, TimeDelta};
+        let width = match self {
+            Self::Hour => TimeDelta::hours(1),
+            Self::Day => TimeDelta::days(1),
+        };
+        time.duration_trunc(width).unwrap_or(time)
+    }
+}
+
+/// Opportunity with tickers of its path and amounts in USD
+#[derive(Clone, Debug)]
+pub struct Loop {
+    pub id: i64,
+    pub block_number: u64,
+    pub dex: String,
+    pub path: Vec<Address>,
+    /// Ticker or address of every token of `path`
+    pub tickers: Vec<String>,
+    pub amount_in_usd: Option<f64>,
+    pub revenue_usd: Option<f64>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Number of opportunities and their revenue in the time bucket
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct BucketStats {
+    pub bucket: DateTime<Utc>,
+    pub opportunities: i64,
+    pub revenue_usd: f64,
+}
+
+/// Number of opportunities which start with the token and their revenue
+#[derive(Clone, Debug)]
+pub struct TokenStats {
+    pub token: Address,
+    pub ticker: Option<String>,
+    pub opportunities: i64,
+    pub revenue_usd: f64,
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct DexStats {
+    pub dex_id: i32,
+    pub name: String,
+    pub opportunities: i64,
+    pub revenue_usd: f64,
+}
+
+/// Number of swaps from `token_in` to `token_out` in cycles
+#[derive(Clone, Debug)]
+pub struct TokenFlow {
+    pub token_in: Address,
+    pub token_out: Address,
+    pub ticker_in: Option<String>,
+    pub ticker_out: Option<String>,
+    pub swaps: i64,
+}
+
+/// Number of cycles going through the pair, `token0 < token1`
+#[derive(Clone, Debug)]
+pub struct PairCycles {
+    pub dex_id: i32,
+    pub token0: Address,
+    pub token1: Address,
+    /// Any known pool of the dex with these tokens
+    pub address: Option<Address>,
+    pub cycles: i64,
+}
+
+// These structs are needed for sqlx::query_as
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct LoopRaw {
+    pub id: i64,
+    pub block_number: i64,
+    pub dex: String,
+    pub path: Vec<Vec<u8>>,
+    pub tickers: Vec<Option<String>>,
+    pub amount_in_usd: Option<f64>,
+    pub revenue_usd: Option<f64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl LoopRaw {
+    pub fn into_loop(self) -> Option<Loop> {
+        let path = self
+            .path
+            .iter()
+            .map(|token| Address::try_from(token.as_slice()).ok())
+            .collect::<Option<Vec<Address>>>()?;
+        let tickers = path
+            .iter()
+            .zip(self.tickers)
+            .map(|(token, ticker)| ticker.unwrap_or_else(|| token.to_string()))
+            .collect();
+
+        Some(Loop {
+            id: self.id,
+            block_number: u64::try_from(self.block_number).ok()?,
+            dex: self.dex,
+            path,
+            tickers,
+            amount_in_usd: self.amount_in_usd,
+            revenue_usd: self.revenue_usd,
+            created_at: self.created_at,
+        })
+    }
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct TokenStatsRaw {
+    pub token: Vec<u8>,
+    pub ticker: Option<String>,
+    pub opportunities: i64,
+    pub revenue_usd: f64,
+}
+
+impl TokenStatsRaw {
+    pub fn into_stats(self) -> Option<TokenStats> {
+        Some(TokenStats {
+            token: Address::try_from(self.token.as_slice()).ok()?,
+            ticker: self.ticker,
+            opportunities: self.opportunities,
+            revenue_usd: self.revenue_usd,
+        })
+    }
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct TokenFlowRaw {
+    pub token_in: Vec<u8>,
+    pub token_out: Vec<u8>,
+    pub ticker_in: Option<String>,
+    pub ticker_out: Option<String>,
+    pub swaps: i64,
+}
+
+impl TokenFlowRaw {
+    pub fn into_flow(self) -> Option<TokenFlow> {
+        Some(TokenFlow {
+            token_in: Address::try_from(self.token_in.as_slice()).ok()?,
+            token_out: Address::try_from(self.token_out.as_slice()).ok()?,
+            ticker_in: self.ticker_in,
+            ticker_out: self.ticker_out,
+            swaps: self.swaps,
+        })
+    }
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct PairCyclesRaw {
+    pub dex_id: i32,
+    pub token0: Vec<u8>,
+    pub token1: Vec<u8>,
+    pub address: Option<Vec<u8>>,
+    pub cycles: i64,
+}
+
+impl PairCyclesRaw {
+    pub fn into_pair_cycles(self) -> Option<PairCycles> {
+        Some(PairCycles {
+            dex_id: self.dex_id,
+            token0: Address::try_from(self.token0.as_slice()).ok()?,
+            token1: Address::try_from(self.token1.as_slice()).ok()?,
+            address: match self.address {
+                Some(address) => Some(Address::try_from(address.as_slice()).ok()?),
+                None => None,
+            },
+            cycles: self.cycles,
+        })
+    }
+}
@@ -0,0 +1,36 @@
+use anyhow::{bail, Result};
+use kronos_config::Config;
+use kronos_db::PostgresDB;
+
+// Usage: migrations [status|run]
+// `status` (default) prints every known migration and whether it was applied, writes nothing
+// `run` applies pending migrations and seeds configured dexes
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    kronos_logger::init_logger(tracing::Level::INFO);
+
+    let config = Config::load("../config.yml".into())?;
+    let command = std::env::args().nth(1).unwrap_or("status".into());
+
+    let postgres = PostgresDB::connect_without_migrations(&config.postgres).await?;
+    match command.as_str() {
+        "status" => {}
+        "run" => {
+            let dexes: Vec<String> = config.dexes.iter().map(|dex| dex.name.clone()).collect();
+            postgres.migrate(&dexes).await?;
+        }
+        _ => bail!("unknown command: {command}, expected `status` or `run`"),
+    }
+
+    for migration in postgres.migrations_status().await? {
+        let state = if migration.applied {
+            "applied"
+        } else {
+            "pending"
+        };
+        tracing::info!("{} {} [{state}]", migration.version, migration.description);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use kronos_common::{ErrorAction, RpcError};
+
+pub type Result<T, E = DbError> = std::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DbError {
+    /// Data is not stored (yet), e.g. pair or reserves were never cached
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Redis error: {0}")]
+    Redis(#[from] redis::RedisError),
+    #[error("Redis pool error: {0}")]
+    RedisPool(#[from] bb8::RunError<redis::RedisError>),
+    #[error("Postgres error: {0}")]
+    Postgres(sqlx::Error),
+    #[error("Migration error: {0}")]
+    Migration(#[from] sqlx::migrate::MigrateError),
+    /// Stored value has unexpected format
+    #[error("Failed to decode stored value: {0}")]
+    Decode(String),
+    #[error(transparent)]
+    Rpc(#[from] RpcError),
+}
+
+impl DbError {
+    pub fn not_found(what: impl Into<String>) -> Self {
+        Self::NotFound(what.into())
+    }
+
+    pub fn action(&self) -> ErrorAction {
+        match self {
+            Self::NotFound(_) | Self::Decode(_) => ErrorAction::Skip,
+            Self::Redis(err) if err.is_connection_dropped() || err.is_timeout() => {
+                ErrorAction::Retry
+            }
+            Self::RedisPool(_) => ErrorAction::Retry,
+            Self::Postgres(sqlx::Error::PoolTimedOut | sqlx::Error::Io(_)) => ErrorAction::Retry,
+            Self::Rpc(err) => err.action(),
+            Self::Redis(_) | Self::Postgres(_) | Self::Migration(_) => ErrorAction::Abort,
+        }
+    }
+}
+
+impl From<sqlx::Error> for DbError {
+    fn from(err: sqlx::Error) -> Self {
+        match err {
+            sqlx::Error::RowNotFound => Self::NotFound("postgres row".to_string()),
+            err => Self::Postgres(err),
+        }
+    }
+}
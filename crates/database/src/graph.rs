@@ -0,0 +1,454 @@
+use crate::{tables::Pair, UpdateReservesData};
+use alloy::primitives::Address;
+use arc_swap::ArcSwap;
+use hashbrown::HashMap;
+use kronos_common::Reserves;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Reserves are stored in chunks, a batch copies only chunks it writes
+const RESERVES_PER_CHUNK: usize = 4096;
+
+type ReservesChunk = Arc<Vec<Option<Reserves>>>;
+
+/// `Edge` is a pair of tokens of one pool. Tokens are indices in `Topology`,
+/// `token0` is the token with the lower address as in uniswap pair.
+/// Pool of several tokens has an edge per pair of its tokens
+#[derive(Clone, Debug)]
+pub struct Edge {
+    pub dex_id: i32,
+    pub pair: Address,
+    pub token0: u32,
+    pub token1: u32,
+}
+
+/// Tokens and pairs of all dexes, changes only when a new pair is added
+#[derive(Clone, Debug, Default)]
+pub struct Topology {
+    tokens: Vec<Address>,
+    token_index: HashMap<Address, u32>,
+    // token index -> edges with this token
+    adjacency: Vec<Vec<u32>>,
+    edges: Vec<Edge>,
+    // pool -> its edges, the only identity of the edge
+    pool_index: HashMap<Address, Vec<u32>>,
+    // (dex_id, token0, token1) -> parallel edges of pools with both tokens
+    tokens_index: HashMap<(i32, u32, u32), Vec<u32>>,
+}
+
+impl Topology {
+    fn token_or_insert(&mut self, token: Address) -> u32 {
+        if let Some(index) = self.token_index.get(&token) {
+            return *index;
+        }
+        let index = self.tokens.len() as u32;
+        self.tokens.push(token);
+        self.token_index.insert(token, index);
+        self.adjacency.push(vec![]);
+        index
+    }
+
+    fn contains(&self, pair: &Pair) -> bool {
+        self.pool_edge(&pair.address, &pair.token0, &pair.token1)
+            .is_some()
+    }
+
+    // Returns `false` if pair is already known
+    fn add_pair(&mut self, pair: &Pair) -> bool {
+        if self.contains(pair) {
+            return false;
+        }
+
+        let (token0, token1) = sorted(pair.token0, pair.token1);
+        let token0 = self.token_or_insert(token0);
+        let token1 = self.token_or_insert(token1);
+
+        let edge = self.edges.len() as u32;
+        self.edges.push(Edge {
+            dex_id: pair.dex_id,
+            pair: pair.address,
+            token0,
+            token1,
+        });
+        self.adjacency[token0 as usize].push(edge);
+        self.adjacency[token1 as usize].push(edge);
+        self.pool_index.entry(pair.address).or_default().push(edge);
+        self.tokens_index
+            .entry((pair.dex_id, token0, token1))
+            .or_default()
+            .push(edge);
+        true
+    }
+
+    pub fn token_index(&self, token: &Address) -> Option<u32> {
+        self.token_index.get(token).copied()
+    }
+
+    pub fn token(&self, index: u32) -> Address {
+        self.tokens[index as usize]
+    }
+
+    pub fn tokens_len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// The first edge of the tokens on the dex, uniswap v2 has only one
+    pub fn edge(&self, dex_id: i32, token0: &Address, token1: &Address) -> Option<u32> {
+        self.edge_between(dex_id, self.token_index(token0)?, self.token_index(token1)?)
+    }
+
+    /// The first edge between two token indices on the dex
+    pub fn edge_between(&self, dex_id: i32, token0: u32, token1: u32) -> Option<u32> {
+        self.edges_between(dex_id, token0, token1).first().copied()
+    }
+
+    /// Edges of all pools of the dex which trade two token indices
+    pub fn edges_between(&self, dex_id: i32, token0: u32, token1: u32) -> &[u32] {
+        let key = match self.tokens[token0 as usize] < self.tokens[token1 as usize] {
+            true => (dex_id, token0, token1),
+            false => (dex_id, token1, token0),
+        };
+        self.tokens_index
+            .get(&key)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Edges of the pool, one per pair of its tokens
+    pub fn pool_edges(&self, pool: &Address) -> &[u32] {
+        self.pool_index
+            .get(pool)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Edge of two tokens of the pool
+    pub fn pool_edge(&self, pool: &Address, token0: &Address, token1: &Address) -> Option<u32> {
+        let (token0, token1) = sorted(*token0, *token1);
+        let (token0, token1) = (self.token_index(&token0)?, self.token_index(&token1)?);
+        self.pool_edges(pool).iter().copied().find(|edge| {
+            let edge = &self.edges[*edge as usize];
+            edge.token0 == token0 && edge.token1 == token1
+        })
+    }
+
+    /// Edge of the two token pool of the dex
+    pub fn edge_by_pair(&self, dex_id: i32, pair: &Address) -> Option<u32> {
+        self.pool_edges(pair)
+            .iter()
+            .copied()
+            .find(|edge| self.edges[*edge as usize].dex_id == dex_id)
+    }
+
+    /// Number of pairs of the token on all dexes
+    pub fn degree(&self, token: u32) -> usize {
+        self.adjacency[token as usize].len()
+    }
+
+    /// Edges of the token on the dex
+    pub fn token_edges(&self, dex_id: i32, token: u32) -> impl Iterator<Item = u32> + '_ {
+        self.adjacency[token as usize]
+            .iter()
+            .copied()
+            .filter(move |edge| self.edges[*edge as usize].dex_id == dex_id)
+    }
+
+    /// Token on the other side of the edge
+    pub fn other(&self, edge: u32, token: u32) -> u32 {
+        let edge = &self.edges[edge as usize];
+        match edge.token0 == token {
+            true => edge.token1,
+            false => edge.token0,
+        }
+    }
+}
+
+/// Immutable state of the graph after the block was processed
+#[derive(Clone, Debug, Default)]
+pub struct GraphSnapshot {
+    pub block_number: u64,
+    pub topology: Arc<Topology>,
+    // edge -> reserves of (edge.token0, edge.token1) by chunks, `None` until first update
+    reserves: Arc<Vec<ReservesChunk>>,
+}
+
+impl GraphSnapshot {
+    fn stored_reserves(&self, edge: u32) -> Option<&Reserves> {
+        let edge = edge as usize;
+        self.reserves
+            .get(edge / RESERVES_PER_CHUNK)?
+            .get(edge % RESERVES_PER_CHUNK)?
+            .as_ref()
+    }
+
+    /// Reserves of edge in order (token_in, token_out), where `token_in` is token index
+    pub fn edge_reserves(&self, edge: u32, token_in: u32) -> Option<Reserves> {
+        let reserves = self.stored_reserves(edge)?.clone();
+        match self.topology.edges[edge as usize].token0 == token_in {
+            true => Some(reserves),
+            false => Some(Reserves(reserves.1, reserves.0)),
+        }
+    }
+
+    /// Reserves in order of requested tokens
+    pub fn reserves(&self, dex_id: i32, token0: &Address, token1: &Address) -> Option<Reserves> {
+        let edge = self.topology.edge(dex_id, token0, token1)?;
+        self.edge_reserves(edge, self.topology.token_index(token0)?)
+    }
+
+    pub fn adjacent(&self, dex_id: i32, token: &Address) -> Vec<Address> {
+        let Some(index) = self.topology.token_index(token) else {
+            return vec![];
+        };
+        self.topology
+            .token_edges(dex_id, index)
+            .map(|edge| self.topology.token(self.topology.other(edge, index)))
+            .collect()
+    }
+
+    /// Returns (token0, token1) of the pair
+    pub fn pair_tokens(&self, dex_id: i32, pair: &Address) -> Option<(Address, Address)> {
+        let edge = &self.topology.edges[self.topology.edge_by_pair(dex_id, pair)? as usize];
+        Some((
+            self.topology.token(edge.token0),
+            self.topology.token(edge.token1),
+        ))
+    }
+
+    pub fn pair_adr(&self, dex_id: i32, token0: &Address, token1: &Address) -> Option<Address> {
+        let edge = self.topology.edge(dex_id, token0, token1)?;
+        Some(self.topology.edges[edge as usize].pair)
+    }
+}
+
+/// `TokenGraph` is in-memory graph of tokens and reserves of all dexes.
+/// Writers build new snapshot in a `GraphBatch` and publish it atomically,
+/// readers take `snapshot()` once per block and never lock
+#[derive(Clone, Default)]
+pub struct TokenGraph {
+    current: Arc<ArcSwap<GraphSnapshot>>,
+    // serializes writers, readers are not affected
+    writer: Arc<Mutex<()>>,
+}
+
+impl TokenGraph {
+    pub fn snapshot(&self) -> Arc<GraphSnapshot> {
+        self.current.load_full()
+    }
+
+    /// Starts a write, changes are published by `GraphBatch::commit`
+    pub fn batch(&self) -> GraphBatch<'_> {
+        let guard = self.writer.lock().unwrap();
+        let base = self.current.load_full();
+        GraphBatch {
+            graph: self,
+            _guard: guard,
+            block_number: base.block_number,
+            base,
+            topology: None,
+            reserves: None,
+        }
+    }
+
+    pub fn add_pairs(&self, pairs: &[Pair]) {
+        let mut batch = self.batch();
+        batch.add_pairs(pairs);
+        batch.commit();
+    }
+
+    /// Updates reserves of known pairs, reserves of unknown pairs are ignored
+    pub fn update_reserves(&self, data: &[UpdateReservesData]) {
+        let mut batch = self.batch();
+        batch.update_reserves(data);
+        batch.commit();
+    }
+}
+
+/// Pending changes of the graph. Topology is copied on the first new pair
+/// and reserves only by touched chunks, so the cost of a batch
+/// doesn't grow with the size of the graph
+pub struct GraphBatch<'a> {
+    graph: &'a TokenGraph,
+    _guard: MutexGuard<'a, ()>,
+    base: Arc<GraphSnapshot>,
+    block_number: u64,
+    topology: Option<Topology>,
+    reserves: Option<Vec<ReservesChunk>>,
+}
+
+impl GraphBatch<'_> {
+    fn topology(&self) -> &Topology {
+        self.topology.as_ref().unwrap_or(&self.base.topology)
+    }
+
+    /// Returns number of pairs which were not known
+    pub fn add_pairs(&mut self, pairs: &[Pair]) -> usize {
+        let mut added = 0;
+        for pair in pairs {
+            if self.topology().contains(pair) {
+                continue;
+            }
+            let topology = self
+                .topology
+                .get_or_insert_with(|| (*self.base.topology).clone());
+            topology.add_pair(pair);
+            added += 1;
+        }
+        added
+    }
+
+    /// Updates reserves of known pairs, reserves of unknown pairs are ignored
+    pub fn update_reserves(&mut self, data: &[UpdateReservesData]) {
+        for update in data {
+            let Some(edge) =
+                self.topology()
+                    .pool_edge(&update.pair, &update.token0, &update.token1)
+            else {
+                continue;
+            };
+            // store in edge order
+            let reserves = match update.token0 < update.token1 {
+                true => update.reserves.clone(),
+                false => Reserves(update.reserves.1, update.reserves.0),
+            };
+
+            let chunks = self
+                .reserves
+                .get_or_insert_with(|| (*self.base.reserves).clone());
+            let (chunk, offset) = (
+                edge as usize / RESERVES_PER_CHUNK,
+                edge as usize % RESERVES_PER_CHUNK,
+            );
+            if chunks.len() <= chunk {
+                chunks.resize_with(chunk + 1, Default::default);
+            }
+            let chunk = Arc::make_mut(&mut chunks[chunk]);
+            if chunk.len() <= offset {
+                chunk.resize(offset + 1, None);
+            }
+            chunk[offset] = Some(reserves);
+            self.block_number = self.block_number.max(update.block_number);
+        }
+    }
+
+    /// Publishes the new snapshot if anything was changed
+    pub fn commit(self) {
+        if self.topology.is_none() && self.reserves.is_none() {
+            return;
+        }
+        let topology = match self.topology {
+            Some(topology) => Arc::new(topology),
+            None => self.base.topology.clone(),
+        };
+        let reserves = match self.reserves {
+            Some(reserves) => Arc::new(reserves),
+            None => self.base.reserves.clone(),
+        };
+        self.graph.current.store(Arc::new(GraphSnapshot {
+            block_number: self.block_number,
+            topology,
+            reserves,
+        }));
+    }
+}
+
+fn sorted(token0: Address, token1: Address) -> (Address, Address) {
+    match token0 < token1 {
+        true => (token0, token1),
+        false => (token1, token0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::Uint;
+
+    const TOKEN0: Address = Address::repeat_byte(1);
+    const TOKEN1: Address = Address::repeat_byte(2);
+    const TOKEN2: Address = Address::repeat_byte(3);
+
+    fn pair(address: u8, token0: Address, token1: Address) -> Pair {
+        Pair {
+            address: Address::repeat_byte(address),
+            dex_id: 1,
+            token0,
+            token1,
+        }
+    }
+
+    fn update(pair: &Pair, r0: u64, r1: u64) -> UpdateReservesData {
+        UpdateReservesData {
+            pair: pair.address,
+            token0: pair.token0,
+            token1: pair.token1,
+            reserves: Reserves(Uint::from(r0), Uint::from(r1)),
+            block_number: 7,
+        }
+    }
+
+    #[test]
+    fn keys_edges_by_pool() {
+        let graph = TokenGraph::default();
+        let (first, second) = (pair(10, TOKEN1, TOKEN0), pair(11, TOKEN0, TOKEN1));
+        graph.add_pairs(&[first.clone(), second.clone(), first.clone()]);
+        graph.update_reserves(&[update(&first, 100, 200), update(&second, 300, 400)]);
+
+        let snapshot = graph.snapshot();
+        let topology = &snapshot.topology;
+        assert_eq!(topology.edges().len(), 2);
+        let (token0, token1) = (
+            topology.token_index(&TOKEN0).unwrap(),
+            topology.token_index(&TOKEN1).unwrap(),
+        );
+        // parallel pools of the same tokens are different edges
+        assert_eq!(topology.edges_between(1, token1, token0), [0, 1]);
+        assert_eq!(
+            topology.pool_edge(&second.address, &TOKEN1, &TOKEN0),
+            Some(1)
+        );
+
+        // first pair was updated in order (TOKEN1, TOKEN0)
+        let reserves = snapshot.edge_reserves(0, token0).unwrap();
+        assert_eq!(reserves, Reserves(Uint::from(200), Uint::from(100)));
+        let reserves = snapshot.edge_reserves(1, token0).unwrap();
+        assert_eq!(reserves, Reserves(Uint::from(300), Uint::from(400)));
+        assert_eq!(snapshot.block_number, 7);
+    }
+
+    #[test]
+    fn batch_is_published_on_commit() {
+        let graph = TokenGraph::default();
+        let pairs: Vec<Pair> = (0..RESERVES_PER_CHUNK + 1)
+            .map(|index| {
+                let pool = Address::left_padding_from(&(index as u64).to_be_bytes());
+                Pair {
+                    address: pool,
+                    ..pair(0, TOKEN0, TOKEN1)
+                }
+            })
+            .collect();
+        graph.add_pairs(&pairs);
+        graph.update_reserves(&[update(&pairs[0], 1, 1)]);
+        let before = graph.snapshot();
+
+        let mut batch = graph.batch();
+        assert_eq!(
+            batch.add_pairs(&[pairs[0].clone(), pair(1, TOKEN1, TOKEN2)]),
+            1
+        );
+        batch.update_reserves(&[update(&pairs[RESERVES_PER_CHUNK], 5, 6)]);
+        // readers see the old snapshot until commit
+        assert_eq!(graph.snapshot().topology.edges().len(), pairs.len());
+        batch.commit();
+
+        let after = graph.snapshot();
+        assert_eq!(after.topology.edges().len(), pairs.len() + 1);
+        // untouched chunk is shared with the previous snapshot
+        assert!(Arc::ptr_eq(&before.reserves[0], &after.reserves[0]));
+        assert!(after.stored_reserves(RESERVES_PER_CHUNK as u32).is_some());
+    }
+}
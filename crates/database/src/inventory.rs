@@ -0,0 +1,106 @@
+use alloy::primitives::{Address, U256};
+use hashbrown::HashMap;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug)]
+struct Holdings {
+    max_size_percent: f64,
+    // token -> account -> balance
+    balances: RwLock<HashMap<Address, HashMap<Address, U256>>>,
+}
+
+/// `Inventory` keeps balances of start tokens held by executor accounts.
+/// It is shared by dex adapters, which only start cycles from held tokens,
+/// and the executor, which refreshes balances. Without holdings arbitrages are paid
+/// by flash swaps, so any token can start a cycle and size is not limited
+#[derive(Clone, Debug, Default)]
+pub struct Inventory {
+    holdings: Option<Arc<Holdings>>,
+}
+
+impl Inventory {
+    /// Inventory mode, amount in is at most `max_size_percent` of the balance of one account
+    pub fn new(max_size_percent: f64) -> Self {
+        Self {
+            holdings: Some(Arc::new(Holdings {
+                max_size_percent,
+                balances: RwLock::new(HashMap::new()),
+            })),
+        }
+    }
+
+    /// Arbitrages are paid by flash swaps
+    pub fn flash_swap() -> Self {
+        Self::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.holdings.is_some()
+    }
+
+    pub fn set_balance(&self, account: Address, token: Address, balance: U256) {
+        if let Some(holdings) = &self.holdings {
+            let mut balances = holdings.balances.write().unwrap();
+            balances.entry(token).or_default().insert(account, balance);
+        }
+    }
+
+    pub fn balance(&self, account: &Address, token: &Address) -> U256 {
+        let Some(holdings) = &self.holdings else {
+            return U256::ZERO;
+        };
+        let balances = holdings.balances.read().unwrap();
+        balances
+            .get(token)
+            .and_then(|accounts| accounts.get(account))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Balance of the token over all accounts
+    pub fn total(&self, token: &Address) -> U256 {
+        let Some(holdings) = &self.holdings else {
+            return U256::ZERO;
+        };
+        let balances = holdings.balances.read().unwrap();
+        balances
+            .get(token)
+            .map(|accounts| accounts.values().copied().sum())
+            .unwrap_or_default()
+    }
+
+    /// Whether a cycle can start from the token
+    pub fn holds(&self, token: &Address) -> bool {
+        match &self.holdings {
+            Some(_) => !self.total(token).is_zero(),
+            None => true,
+        }
+    }
+
+    /// The largest amount in of a cycle starting from the token, `None` if not limited.
+    /// Transaction is sent from one account, so the limit is by the richest one
+    pub fn max_amount_in(&self, token: &Address) -> Option<U256> {
+        let holdings = self.holdings.as_ref()?;
+        let (_, balance) = self.richest(token)?;
+        let percent = (holdings.max_size_percent * 100.0) as u64;
+        Some(balance * U256::from(percent) / U256::from(10_000))
+    }
+
+    /// Account which sends the arbitrage of `amount` of the token: the richest one,
+    /// which the amount is sized by. `None` if it doesn't hold the amount
+    pub fn holder(&self, token: &Address, amount: U256) -> Option<Address> {
+        self.richest(token)
+            .filter(|(_, balance)| *balance >= amount)
+            .map(|(account, _)| account)
+    }
+
+    fn richest(&self, token: &Address) -> Option<(Address, U256)> {
+        let holdings = self.holdings.as_ref()?;
+        let balances = holdings.balances.read().unwrap();
+        balances
+            .get(token)?
+            .iter()
+            .max_by_key(|(_, balance)| **balance)
+            .map(|(account, balance)| (*account, *balance))
+    }
+}
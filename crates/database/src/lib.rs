@@ -1,21 +1,33 @@
 use crate::tables::Pair;
+use hashbrown::HashMap;
 use std::collections::HashSet;
 
 use alloy::primitives::Address;
-use anyhow::Result;
+
 use kronos_common::Reserves;
 use kronos_config::Config;
 
+pub mod analytics;
+pub mod error;
+pub mod graph;
+pub mod inventory;
 pub mod postgres;
 pub mod redis;
 pub mod tables;
+pub mod tokens;
 
+pub use error::{DbError, Result};
+pub use graph::{GraphBatch, GraphSnapshot, TokenGraph};
+pub use inventory::Inventory;
 pub use postgres::*;
+pub use tokens::TokenRegistry;
 
 pub struct UpdateReservesData {
+    pub pair: Address,
     pub token0: Address,
     pub token1: Address,
     pub reserves: Reserves,
+    pub block_number: u64,
 }
 
 // Traits
@@ -23,13 +35,23 @@ pub struct UpdateReservesData {
 pub trait PricesStorage {
     async fn reserves(&self, dex_id: i32, token0: &Address, token1: &Address) -> Result<Reserves>;
 
-    async fn update_reserves(&self, dex_id: i32, data: UpdateReservesData) -> Result<()>;
+    // `None` for pairs without cached reserves
+    async fn reserves_batch(
+        &self,
+        dex_id: i32,
+        tokens: &[(Address, Address)],
+    ) -> Result<Vec<Option<Reserves>>>;
+
+    async fn update_reserves(&self, dex_id: i32, data: &[UpdateReservesData]) -> Result<()>;
 }
 
 #[async_trait::async_trait]
 pub trait TokensGraphStorage {
     async fn add_pair(&self, pair: Pair) -> Result<()>;
 
+    /// Pairs are published to the graph in one batch
+    async fn add_pairs(&self, pairs: &[Pair]) -> Result<()>;
+
     async fn adjacent_tokens(&self, dex_id: i32, token: &Address) -> Result<HashSet<Address>>;
 
     async fn pair_by_tokens(&self, dex_id: i32, pair_adr: &Address) -> Result<(Address, Address)>;
@@ -37,70 +59,155 @@ pub trait TokensGraphStorage {
     async fn pair_adr(&self, dex_id: i32, token0: &Address, token1: &Address) -> Result<Address>;
 }
 
-/// `DB`
+// Reserves are loaded from redis with one pipeline per chunk on warm start
+const RESERVES_PER_PIPELINE: usize = 10_000;
+
+/// `DB` serves reads from in-memory `TokenGraph`,
+/// redis and postgres are used as persistence and for warm start
 #[derive(Clone)]
 pub struct DB {
+    graph: TokenGraph,
     redis: redis::RedisDB,
     postgres: postgres::PostgresDB,
 }
 
 impl DB {
     pub async fn from_config(config: &Config) -> Result<DB> {
-        let postgres = postgres::PostgresDB::connect(&config.postgres).await?;
+        let postgres = postgres::PostgresDB::connect_without_migrations(&config.postgres).await?;
+        let dexes: Vec<String> = config.dexes.iter().map(|dex| dex.name.clone()).collect();
+        postgres.migrate(&dexes).await?;
+
         let redis = redis::RedisDB::connect(&config.redis).await?;
+        redis.migrate().await?;
 
         // pre initialization
         let pairs = postgres.select_pairs().await?;
         tracing::info!("📦 Load {} pairs from Postgres", pairs.len());
 
-        for pair in pairs {
-            redis.add_pair(pair).await?;
+        redis.add_pairs(&pairs).await?;
+
+        // warm start of reserves from redis
+        let mut reserves = Vec::with_capacity(pairs.len());
+        let mut by_dex: HashMap<i32, Vec<&Pair>> = HashMap::new();
+        for pair in pairs.iter() {
+            by_dex.entry(pair.dex_id).or_default().push(pair);
+        }
+        for (dex_id, pairs) in by_dex {
+            for chunk in pairs.chunks(RESERVES_PER_PIPELINE) {
+                let tokens: Vec<(Address, Address)> = chunk
+                    .iter()
+                    .map(|pair| (pair.token0, pair.token1))
+                    .collect();
+                let cached = redis.reserves_batch(dex_id, &tokens).await?;
+                reserves.extend(cached.into_iter().zip(chunk).filter_map(|(cached, pair)| {
+                    Some(UpdateReservesData {
+                        pair: pair.address,
+                        token0: pair.token0,
+                        token1: pair.token1,
+                        reserves: cached?,
+                        block_number: 0,
+                    })
+                }));
+            }
         }
 
-        Ok(Self { redis, postgres })
+        // pairs and their reserves are published as one snapshot
+        let graph = TokenGraph::default();
+        let mut batch = graph.batch();
+        batch.add_pairs(&pairs);
+        batch.update_reserves(&reserves);
+        batch.commit();
+        tracing::info!("🕸️ Token graph is built");
+
+        Ok(Self {
+            graph,
+            redis,
+            postgres,
+        })
     }
 
     pub fn postgres(&self) -> PostgresDB {
         self.postgres.clone()
     }
+
+    pub fn redis(&self) -> redis::RedisDB {
+        self.redis.clone()
+    }
+
+    pub fn graph(&self) -> TokenGraph {
+        self.graph.clone()
+    }
 }
 
 // Impl DB traits
 #[async_trait::async_trait]
 impl PricesStorage for DB {
     async fn reserves(&self, dex_id: i32, token0: &Address, token1: &Address) -> Result<Reserves> {
-        self.redis.reserves(dex_id, token0, token1).await
+        self.graph
+            .snapshot()
+            .reserves(dex_id, token0, token1)
+            .ok_or(DbError::not_found(format!(
+                "reserves for {token0:?}, {token1:?}"
+            )))
+    }
+
+    async fn reserves_batch(
+        &self,
+        dex_id: i32,
+        tokens: &[(Address, Address)],
+    ) -> Result<Vec<Option<Reserves>>> {
+        let snapshot = self.graph.snapshot();
+        Ok(tokens
+            .iter()
+            .map(|(token0, token1)| snapshot.reserves(dex_id, token0, token1))
+            .collect())
     }
 
-    async fn update_reserves(&self, dex_id: i32, data: UpdateReservesData) -> Result<()> {
-        self.redis
-            .update_reserves(
-                dex_id,
-                &data.token0,
-                &data.token1,
-                data.reserves.0,
-                data.reserves.1,
-            )
-            .await
+    async fn update_reserves(&self, dex_id: i32, data: &[UpdateReservesData]) -> Result<()> {
+        self.graph.update_reserves(data);
+        self.redis.update_reserves(dex_id, data).await
     }
 }
 
 #[async_trait::async_trait]
 impl TokensGraphStorage for DB {
     async fn add_pair(&self, pair: Pair) -> Result<()> {
-        self.redis.add_pair(pair.clone()).await?;
-        self.postgres.insert_pair(pair).await
+        self.add_pairs(std::slice::from_ref(&pair)).await
+    }
+
+    async fn add_pairs(&self, pairs: &[Pair]) -> Result<()> {
+        self.graph.add_pairs(pairs);
+        self.redis.add_pairs(pairs).await?;
+        for pair in pairs {
+            self.postgres.insert_pair(pair.clone()).await?;
+        }
+        Ok(())
     }
 
     async fn adjacent_tokens(&self, dex_id: i32, token: &Address) -> Result<HashSet<Address>> {
-        self.redis.adjacent(dex_id, token).await
+        Ok(self
+            .graph
+            .snapshot()
+            .adjacent(dex_id, token)
+            .into_iter()
+            .collect())
     }
 
     async fn pair_by_tokens(&self, dex_id: i32, pair_adr: &Address) -> Result<(Address, Address)> {
-        self.redis.pair_by_tokens(dex_id, pair_adr).await
+        self.graph
+            .snapshot()
+            .pair_tokens(dex_id, pair_adr)
+            .ok_or(DbError::not_found(format!(
+                "tokens of pair {pair_adr:?} on dex={dex_id}"
+            )))
     }
 
     async fn pair_adr(&self, dex_id: i32, token0: &Address, token1: &Address) -> Result<Address> {
-        self.redis.pair_adr(dex_id, token0, token1).await
+        self.graph
+            .snapshot()
+            .pair_adr(dex_id, token0, token1)
+            .ok_or(DbError::not_found(format!(
+                "pair for {token0:?}, {token1:?}"
+            )))
     }
 }
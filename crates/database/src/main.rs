@@ -1,10 +1,10 @@
-use alloy::providers::{Provider, ProviderBuilder, RootProvider};
+use alloy::providers::{ProviderBuilder, RootProvider};
 use anyhow::Result;
 use kronos_config::Config;
-use kronos_db::{tables::Ticker, DB};
+use kronos_db::{TokenRegistry, DB};
 use std::sync::Arc;
 
-// This script is needed to load the tickers for already exist tokens in pairs
+// This script is needed to load the metadata for already exist tokens in pairs
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -16,24 +16,14 @@ async fn main() -> Result<()> {
 
     let db = DB::from_config(&config).await?;
     let pairs = db.postgres().select_pairs().await?;
+    let registry = TokenRegistry::new(db.postgres(), provider).await?;
 
-    for pair in pairs.iter() {
-        for token in [pair.token0, pair.token1] {
-            if db.postgres().get_token_ticker(&token).await.is_err() {
-                let instance = ethereum_abi::IERC20::new(token, provider.clone());
-                let ticker = instance.symbol().call().await?._0;
+    let tokens: Vec<_> = pairs
+        .iter()
+        .flat_map(|pair| [pair.token0, pair.token1])
+        .collect();
+    registry.ensure_tokens(&tokens).await?;
 
-                db.postgres()
-                    .insert_ticker(Ticker {
-                        token,
-                        ticker: ticker.clone(),
-                    })
-                    .await?;
-                tracing::trace!("insert ticker: {ticker}");
-            }
-        }
-    }
-
-    tracing::info!("All tickers check!");
+    tracing::info!("All tokens check!");
     Ok(())
 }
@@ -1,17 +1,63 @@
+use crate::analytics::{
+    BucketStats, DexStats, Loop, LoopRaw, PairCycles, PairCyclesRaw, StatsBucket, TokenFlow,
+    TokenFlowRaw, TokenStats, TokenStatsRaw, AGGREGATE_VIEWS, FLOWS_VIEW, PAIR_CYCLES_VIEW,
+    STATS_VIEW,
+};
+use crate::error::{DbError, Result};
 use crate::tables::{
-    Dex, Pair, PairRaw, Ticker, TickerRaw, DEXES_TABLE, PAIRS_TABLE, TICKERS_TABLE,
+    Dex, Execution, ExecutionMode, ExecutionRaw, ExecutionStatus, Opportunity, OpportunityRaw,
+    Pair, PairRaw, Token, TokenFlags, TokenRaw, DEXES_TABLE, EXECUTIONS_TABLE, OPPORTUNITIES_TABLE,
+    PAIRS_TABLE, TICKERS_TABLE,
 };
 use alloy::primitives::Address;
-use anyhow::Result;
+use chrono::{DateTime, Utc};
 use kronos_config::PostgresConfig;
-use sqlx::{Pool, Postgres};
+use kronos_metrics as metrics;
+use sqlx::{migrate::Migrator, Pool, Postgres};
+use std::collections::HashSet;
+
+/// Versioned schema migrations from `crates/database/migrations`
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Status of the single migration from `MIGRATOR`
+#[derive(Clone, Debug)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
+/// Page of `trading_pairs`, optionally only pairs with `token` or on `dex_id`
+#[derive(Clone, Debug, Default)]
+pub struct PairsFilter {
+    pub token: Option<Address>,
+    pub dex_id: Option<i32>,
+    pub limit: i64,
+    pub offset: i64,
+}
 
 #[derive(Clone)]
 pub struct PostgresDB {
     pool: Pool<Postgres>,
 }
 impl PostgresDB {
+    /// Connects to postgres and applies all pending migrations
     pub async fn connect(config: &PostgresConfig) -> Result<Self> {
+        let db = Self::connect_without_migrations(config).await?;
+        db.migrate(&[]).await?;
+        Ok(db)
+    }
+
+    /// Applies pending migrations and seeds `dexes` with the configured names,
+    /// so ids of the dexes exist before any adapter is built
+    pub async fn migrate(&self, dexes: &[String]) -> Result<()> {
+        MIGRATOR.run(&self.pool).await?;
+        self.seed_dexes(dexes).await?;
+        tracing::info!("🐘 Postgres migrations applied");
+        Ok(())
+    }
+
+    pub async fn connect_without_migrations(config: &PostgresConfig) -> Result<Self> {
         let conn_data = config.sqlx_connection();
 
         let pool = sqlx::PgPool::connect(&conn_data).await?;
@@ -20,21 +66,100 @@ impl PostgresDB {
         Ok(Self { pool })
     }
 
-    pub async fn select_pairs(&self) -> Result<Vec<Pair>> {
-        let query = format!("SELECT * FROM {PAIRS_TABLE}");
-        let pairs_v2: Vec<PairRaw> = sqlx::query_as(&query).fetch_all(&self.pool).await?;
-        Ok(pairs_v2
+    /// Read only, a database without the migrations table has every migration pending
+    pub async fn migrations_status(&self) -> Result<Vec<MigrationStatus>> {
+        let (exists,): (bool,) =
+            sqlx::query_as("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+                .fetch_one(&self.pool)
+                .await?;
+        let applied: HashSet<i64> = match exists {
+            true => sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+                .fetch_all(&self.pool)
+                .await?
+                .into_iter()
+                .collect(),
+            false => HashSet::new(),
+        };
+
+        Ok(MIGRATOR
             .iter()
-            .map(|pair_raw| Pair {
-                address: Address::from_slice(&pair_raw.address),
-                token0: Address::from_slice(&pair_raw.token0),
-                token1: Address::from_slice(&pair_raw.token1),
-                dex_id: pair_raw.dex_id,
+            .map(|migration| MigrationStatus {
+                version: migration.version,
+                description: migration.description.to_string(),
+                applied: applied.contains(&migration.version),
             })
             .collect())
     }
 
+    /// Inserts dexes which are not exist yet
+    async fn seed_dexes(&self, names: &[String]) -> Result<()> {
+        let _timer = metrics::db_timer("postgres", "seed_dexes");
+        let query =
+            format!("INSERT INTO {DEXES_TABLE} (name) VALUES ($1) ON CONFLICT (name) DO NOTHING");
+
+        for name in names {
+            sqlx::query(&query).bind(name).execute(&self.pool).await?;
+        }
+
+        tracing::trace!("seeded dexes: {names:?}");
+        Ok(())
+    }
+
+    pub async fn select_pairs(&self) -> Result<Vec<Pair>> {
+        let _timer = metrics::db_timer("postgres", "select_pairs");
+        let query = format!("SELECT * FROM {PAIRS_TABLE}");
+        let pairs_v2: Vec<PairRaw> = sqlx::query_as(&query).fetch_all(&self.pool).await?;
+        Ok(pairs_v2.iter().map(Pair::from).collect())
+    }
+
+    /// Returns pairs of the page and total number of pairs matched by filter
+    pub async fn select_pairs_page(&self, filter: &PairsFilter) -> Result<(Vec<Pair>, i64)> {
+        let _timer = metrics::db_timer("postgres", "select_pairs_page");
+        let condition = "($1::BYTEA IS NULL OR token0 = $1 OR token1 = $1) \
+            AND ($2::INT IS NULL OR dex_id = $2)";
+        let token = filter.token.map(|token| token.to_vec());
+
+        let query = format!(
+            "SELECT * FROM {PAIRS_TABLE} WHERE {condition} ORDER BY address LIMIT $3 OFFSET $4"
+        );
+        let pairs: Vec<PairRaw> = sqlx::query_as(&query)
+            .bind(&token)
+            .bind(filter.dex_id)
+            .bind(filter.limit)
+            .bind(filter.offset)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let query = format!("SELECT COUNT(*) FROM {PAIRS_TABLE} WHERE {condition}");
+        let total: i64 = sqlx::query_scalar(&query)
+            .bind(&token)
+            .bind(filter.dex_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok((pairs.iter().map(Pair::from).collect(), total))
+    }
+
+    pub async fn get_pair(&self, pair_adr: &Address) -> Result<Pair> {
+        let _timer = metrics::db_timer("postgres", "get_pair");
+        let query = format!("SELECT * FROM {PAIRS_TABLE} WHERE address = $1");
+
+        let pair: PairRaw = sqlx::query_as(&query)
+            .bind(pair_adr.as_slice())
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(Pair::from(&pair))
+    }
+
+    pub async fn select_dexes(&self) -> Result<Vec<Dex>> {
+        let _timer = metrics::db_timer("postgres", "select_dexes");
+        let query = format!("SELECT * FROM {DEXES_TABLE}");
+        Ok(sqlx::query_as(&query).fetch_all(&self.pool).await?)
+    }
+
     pub async fn insert_pair(&self, pair: Pair) -> Result<()> {
+        let _timer = metrics::db_timer("postgres", "insert_pair");
         let query = format!(
             "INSERT INTO {PAIRS_TABLE} (address, dex_id, token0, token1) VALUES ($1, $2, $3, $4)"
         );
@@ -59,6 +184,7 @@ impl PostgresDB {
     }
 
     pub async fn get_pair_dex_id(&self, pair_adr: &Address) -> Result<i32> {
+        let _timer = metrics::db_timer("postgres", "get_pair_dex_id");
         let query = format!("SELECT * FROM {PAIRS_TABLE} WHERE address = $1");
 
         let pair: PairRaw = sqlx::query_as(&query)
@@ -70,6 +196,7 @@ impl PostgresDB {
     }
 
     pub async fn get_dex_id(&self, dex_name: &str) -> Result<i32> {
+        let _timer = metrics::db_timer("postgres", "get_dex_id");
         let query = format!("SELECT * FROM {DEXES_TABLE} WHERE name = $1");
 
         let dex: Dex = sqlx::query_as(&query)
@@ -79,30 +206,382 @@ impl PostgresDB {
         Ok(dex.id)
     }
 
-    pub async fn insert_ticker(&self, ticker: Ticker) -> Result<()> {
-        let query = format!("INSERT INTO {TICKERS_TABLE} (token, ticker) VALUES ($1, $2)");
+    pub async fn upsert_token(&self, token: &Token) -> Result<()> {
+        let _timer = metrics::db_timer("postgres", "upsert_token");
+        let query = format!(
+            "INSERT INTO {TICKERS_TABLE} \
+                (token, ticker, name, decimals, fee_on_transfer, rebasing, blacklist, unverified, \
+                honeypot, transfer_tax_bps) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) \
+            ON CONFLICT (token) DO UPDATE SET \
+                ticker = EXCLUDED.ticker, \
+                name = EXCLUDED.name, \
+                decimals = EXCLUDED.decimals, \
+                fee_on_transfer = EXCLUDED.fee_on_transfer, \
+                rebasing = EXCLUDED.rebasing, \
+                blacklist = EXCLUDED.blacklist, \
+                unverified = EXCLUDED.unverified, \
+                honeypot = EXCLUDED.honeypot, \
+                transfer_tax_bps = EXCLUDED.transfer_tax_bps"
+        );
         let rows_affected = sqlx::query(&query)
-            .bind(ticker.token.as_slice())
-            .bind(ticker.ticker)
+            .bind(token.token.as_slice())
+            .bind(&token.ticker)
+            .bind(&token.name)
+            .bind(token.decimals.map(i16::from))
+            .bind(token.flags.fee_on_transfer)
+            .bind(token.flags.rebasing)
+            .bind(token.flags.blacklist)
+            .bind(token.flags.unverified)
+            .bind(token.flags.honeypot)
+            .bind(token.transfer_tax_bps.map(i32::from))
             .execute(&self.pool)
             .await?
             .rows_affected();
 
-        debug_assert!(rows_affected == 1, "Insert token rows affected not equal 1");
+        debug_assert!(rows_affected == 1, "Upsert token rows affected not equal 1");
+        Ok(())
+    }
+
+    pub async fn update_token_flags(&self, token: &Address, flags: TokenFlags) -> Result<()> {
+        let _timer = metrics::db_timer("postgres", "update_token_flags");
+        let query = format!(
+            "UPDATE {TICKERS_TABLE} \
+            SET fee_on_transfer = $2, rebasing = $3, blacklist = $4, unverified = $5, \
+                honeypot = $6 \
+            WHERE token = $1"
+        );
+        sqlx::query(&query)
+            .bind(token.as_slice())
+            .bind(flags.fee_on_transfer)
+            .bind(flags.rebasing)
+            .bind(flags.blacklist)
+            .bind(flags.unverified)
+            .bind(flags.honeypot)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update_token_transfer_tax(
+        &self,
+        token: &Address,
+        transfer_tax_bps: Option<u16>,
+    ) -> Result<()> {
+        let _timer = metrics::db_timer("postgres", "update_token_transfer_tax");
+        let query = format!("UPDATE {TICKERS_TABLE} SET transfer_tax_bps = $2 WHERE token = $1");
+        sqlx::query(&query)
+            .bind(token.as_slice())
+            .bind(transfer_tax_bps.map(i32::from))
+            .execute(&self.pool)
+            .await?;
         Ok(())
     }
 
-    pub async fn get_token_ticker(&self, token: &Address) -> Result<Ticker> {
+    /// Returns tokens with complete metadata, rows without decimals are skipped
+    pub async fn select_tokens(&self) -> Result<Vec<Token>> {
+        let _timer = metrics::db_timer("postgres", "select_tokens");
+        let query = format!("SELECT * FROM {TICKERS_TABLE}");
+        let tokens: Vec<TokenRaw> = sqlx::query_as(&query).fetch_all(&self.pool).await?;
+        Ok(tokens
+            .into_iter()
+            .filter_map(TokenRaw::into_token)
+            .collect())
+    }
+
+    pub async fn get_token(&self, token: &Address) -> Result<Token> {
+        let _timer = metrics::db_timer("postgres", "get_token");
         let query = format!("SELECT * FROM {TICKERS_TABLE} WHERE token = $1");
 
-        let ticker: TickerRaw = sqlx::query_as(&query)
+        let raw: TokenRaw = sqlx::query_as(&query)
             .bind(token.as_slice())
             .fetch_one(&self.pool)
             .await?;
 
-        Ok(Ticker {
-            token: Address::from_slice(&ticker.token),
-            ticker: ticker.ticker,
-        })
+        raw.into_token()
+            .ok_or(DbError::not_found(format!("metadata of token {token:?}")))
+    }
+
+    /// Returns id of inserted opportunity
+    pub async fn insert_opportunity(&self, opportunity: &Opportunity) -> Result<i64> {
+        let _timer = metrics::db_timer("postgres", "insert_opportunity");
+        let query = format!(
+            "INSERT INTO {OPPORTUNITIES_TABLE} \
+                (dex_id, block_number, path, amount_in, revenue, revenue_usd, created_at) \
+            VALUES ($1, $2, $3, $4::NUMERIC, $5::NUMERIC, $6, $7) RETURNING id"
+        );
+        let path: Vec<Vec<u8>> = opportunity
+            .path
+            .iter()
+            .map(|token| token.to_vec())
+            .collect();
+
+        Ok(sqlx::query_scalar(&query)
+            .bind(opportunity.dex_id)
+            .bind(opportunity.block_number as i64)
+            .bind(path)
+            .bind(opportunity.amount_in.to_string())
+            .bind(opportunity.revenue.to_string())
+            .bind(opportunity.revenue_usd)
+            .bind(opportunity.created_at)
+            .fetch_one(&self.pool)
+            .await?)
+    }
+
+    /// Returns the latest opportunities, newest first
+    pub async fn select_opportunities(&self, limit: i64) -> Result<Vec<Opportunity>> {
+        let _timer = metrics::db_timer("postgres", "select_opportunities");
+        let query = format!(
+            "SELECT id, dex_id, block_number, path, amount_in::TEXT AS amount_in, \
+                revenue::TEXT AS revenue, revenue_usd, created_at \
+            FROM {OPPORTUNITIES_TABLE} ORDER BY created_at DESC, id DESC LIMIT $1"
+        );
+        let rows: Vec<OpportunityRaw> = sqlx::query_as(&query)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(OpportunityRaw::into_opportunity)
+            .collect())
+    }
+
+    /// Returns id of inserted execution
+    pub async fn insert_execution(&self, execution: &Execution) -> Result<i64> {
+        let _timer = metrics::db_timer("postgres", "insert_execution");
+        let query = format!(
+            "INSERT INTO {EXECUTIONS_TABLE} \
+                (opportunity_id, block_number, tx_hash, status, mode, gas_used, profit_usd, \
+                gas_strategy, gas_params, max_fee_per_gas, max_priority_fee_per_gas, \
+                slippage_bps, created_at) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10::NUMERIC, $11::NUMERIC, $12, $13) \
+            RETURNING id"
+        );
+        let gas = execution.gas.as_ref();
+
+        Ok(sqlx::query_scalar(&query)
+            .bind(execution.opportunity_id)
+            .bind(execution.block_number as i64)
+            .bind(execution.tx_hash.map(|hash| hash.to_vec()))
+            .bind(execution.status.as_str())
+            .bind(execution.mode.as_str())
+            .bind(execution.gas_used.map(|gas| gas as i64))
+            .bind(execution.profit_usd)
+            .bind(gas.map(|gas| gas.strategy.as_str()))
+            .bind(gas.map(|gas| gas.params.as_str()))
+            .bind(gas.map(|gas| gas.max_fee_per_gas.to_string()))
+            .bind(gas.map(|gas| gas.max_priority_fee_per_gas.to_string()))
+            .bind(execution.slippage_bps)
+            .bind(execution.created_at)
+            .fetch_one(&self.pool)
+            .await?)
+    }
+
+    /// Stores the result of the mined transaction of the execution
+    pub async fn update_execution(
+        &self,
+        id: i64,
+        status: ExecutionStatus,
+        gas_used: Option<u64>,
+        profit_usd: Option<f64>,
+    ) -> Result<()> {
+        let _timer = metrics::db_timer("postgres", "update_execution");
+        let query = format!(
+            "UPDATE {EXECUTIONS_TABLE} SET status = $2, gas_used = $3, profit_usd = $4 \
+            WHERE id = $1"
+        );
+        sqlx::query(&query)
+            .bind(id)
+            .bind(status.as_str())
+            .bind(gas_used.map(|gas| gas as i64))
+            .bind(profit_usd)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Profit net of gas of all executions of the mode, in USD
+    pub async fn realized_pnl_usd(&self, mode: ExecutionMode) -> Result<f64> {
+        let _timer = metrics::db_timer("postgres", "realized_pnl_usd");
+        let query =
+            format!("SELECT COALESCE(SUM(profit_usd), 0) FROM {EXECUTIONS_TABLE} WHERE mode = $1");
+        Ok(sqlx::query_scalar(&query)
+            .bind(mode.as_str())
+            .fetch_one(&self.pool)
+            .await?)
+    }
+
+    /// Returns the latest executions, newest first
+    pub async fn select_executions(&self, limit: i64) -> Result<Vec<Execution>> {
+        let _timer = metrics::db_timer("postgres", "select_executions");
+        let query = format!(
+            "SELECT id, opportunity_id, block_number, tx_hash, status, mode, gas_used, \
+                profit_usd, gas_strategy, gas_params, max_fee_per_gas::TEXT AS max_fee_per_gas, \
+                max_priority_fee_per_gas::TEXT AS max_priority_fee_per_gas, slippage_bps, \
+                created_at \
+            FROM {EXECUTIONS_TABLE} ORDER BY created_at DESC, id DESC LIMIT $1"
+        );
+        let rows: Vec<ExecutionRaw> = sqlx::query_as(&query)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(ExecutionRaw::into_execution)
+            .collect())
+    }
+
+    /// Recomputes materialized views of opportunities used by analytics
+    pub async fn refresh_aggregates(&self) -> Result<()> {
+        let _timer = metrics::db_timer("postgres", "refresh_aggregates");
+        for view in AGGREGATE_VIEWS {
+            let query = format!("REFRESH MATERIALIZED VIEW CONCURRENTLY {view}");
+            sqlx::query(&query).execute(&self.pool).await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the latest opportunities with tickers and USD amounts, newest first
+    pub async fn select_loops(&self, limit: i64) -> Result<Vec<Loop>> {
+        let _timer = metrics::db_timer("postgres", "select_loops");
+        // amount_in and revenue are in the start token, so revenue_usd gives its price
+        let query = format!(
+            "SELECT o.id, o.block_number, d.name AS dex, o.path, \
+                ARRAY( \
+                    SELECT t.ticker FROM unnest(o.path) WITH ORDINALITY AS p(token, i) \
+                    LEFT JOIN {TICKERS_TABLE} t ON t.token = p.token ORDER BY p.i \
+                ) AS tickers, \
+                o.revenue_usd * (o.amount_in / NULLIF(o.revenue, 0))::FLOAT8 AS amount_in_usd, \
+                o.revenue_usd, o.created_at \
+            FROM {OPPORTUNITIES_TABLE} o JOIN {DEXES_TABLE} d ON d.id = o.dex_id \
+            ORDER BY o.created_at DESC, o.id DESC LIMIT $1"
+        );
+        let rows: Vec<LoopRaw> = sqlx::query_as(&query)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().filter_map(LoopRaw::into_loop).collect())
+    }
+
+    /// Returns opportunities per bucket since `since`, oldest first
+    pub async fn select_bucket_stats(
+        &self,
+        bucket: StatsBucket,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<BucketStats>> {
+        let _timer = metrics::db_timer("postgres", "select_bucket_stats");
+        let query = format!(
+            "SELECT date_trunc('{}', bucket) AS bucket, \
+                SUM(opportunities)::BIGINT AS opportunities, SUM(revenue_usd) AS revenue_usd \
+            FROM {STATS_VIEW} WHERE bucket >= $1 GROUP BY 1 ORDER BY 1",
+            bucket.as_str()
+        );
+        Ok(sqlx::query_as(&query)
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    /// Returns start tokens with the most opportunities since `since`
+    pub async fn select_token_stats(
+        &self,
+        since: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<TokenStats>> {
+        let _timer = metrics::db_timer("postgres", "select_token_stats");
+        let query = format!(
+            "SELECT s.token, t.ticker, \
+                SUM(s.opportunities)::BIGINT AS opportunities, SUM(s.revenue_usd) AS revenue_usd \
+            FROM {STATS_VIEW} s LEFT JOIN {TICKERS_TABLE} t ON t.token = s.token \
+            WHERE s.bucket >= $1 GROUP BY s.token, t.ticker \
+            ORDER BY opportunities DESC, s.token LIMIT $2"
+        );
+        let rows: Vec<TokenStatsRaw> = sqlx::query_as(&query)
+            .bind(since)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(TokenStatsRaw::into_stats)
+            .collect())
+    }
+
+    /// Returns opportunities of every dex since `since`, the most active first
+    pub async fn select_dex_stats(&self, since: DateTime<Utc>) -> Result<Vec<DexStats>> {
+        let _timer = metrics::db_timer("postgres", "select_dex_stats");
+        let query = format!(
+            "SELECT d.id AS dex_id, d.name, \
+                SUM(s.opportunities)::BIGINT AS opportunities, SUM(s.revenue_usd) AS revenue_usd \
+            FROM {STATS_VIEW} s JOIN {DEXES_TABLE} d ON d.id = s.dex_id \
+            WHERE s.bucket >= $1 GROUP BY d.id, d.name ORDER BY opportunities DESC, d.id"
+        );
+        Ok(sqlx::query_as(&query)
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    /// Returns swaps between every two tokens since `since`
+    pub async fn select_token_flows(&self, since: DateTime<Utc>) -> Result<Vec<TokenFlow>> {
+        let _timer = metrics::db_timer("postgres", "select_token_flows");
+        let query = format!(
+            "SELECT f.token_in, f.token_out, t_in.ticker AS ticker_in, t_out.ticker AS ticker_out, \
+                SUM(f.swaps)::BIGINT AS swaps \
+            FROM {FLOWS_VIEW} f \
+            LEFT JOIN {TICKERS_TABLE} t_in ON t_in.token = f.token_in \
+            LEFT JOIN {TICKERS_TABLE} t_out ON t_out.token = f.token_out \
+            WHERE f.bucket >= $1 GROUP BY 1, 2, 3, 4"
+        );
+        let rows: Vec<TokenFlowRaw> = sqlx::query_as(&query)
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(TokenFlowRaw::into_flow)
+            .collect())
+    }
+
+    /// Returns pairs which appear in cycles most often since `since`
+    pub async fn select_top_pairs(
+        &self,
+        since: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<PairCycles>> {
+        let _timer = metrics::db_timer("postgres", "select_top_pairs");
+        let query = format!(
+            "SELECT c.dex_id, c.token0, c.token1, \
+                (SELECT p.address FROM {PAIRS_TABLE} p WHERE p.dex_id = c.dex_id \
+                    AND ((p.token0 = c.token0 AND p.token1 = c.token1) \
+                        OR (p.token0 = c.token1 AND p.token1 = c.token0)) \
+                    LIMIT 1) AS address, \
+                c.cycles \
+            FROM ( \
+                SELECT dex_id, token0, token1, SUM(cycles)::BIGINT AS cycles \
+                FROM {PAIR_CYCLES_VIEW} WHERE bucket >= $1 GROUP BY 1, 2, 3 \
+            ) c \
+            ORDER BY c.cycles DESC, c.dex_id, c.token0, c.token1 LIMIT $2"
+        );
+        let rows: Vec<PairCyclesRaw> = sqlx::query_as(&query)
+            .bind(since)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(PairCyclesRaw::into_pair_cycles)
+            .collect())
+    }
+
+    pub async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
     }
 }
@@ -1,54 +1,146 @@
-use crate::{tables::Pair, Reserves};
+use crate::error::{DbError, Result};
+use crate::{
+    tables::{DexStatus, KillSwitch, Opportunity, Pair},
+    Reserves, UpdateReservesData,
+};
 use alloy::primitives::{Address, Uint};
-use anyhow::{anyhow, Result};
 use bb8_redis::RedisConnectionManager;
+use futures::{Stream, StreamExt};
 use kronos_config::RedisConfig;
+use kronos_metrics as metrics;
 use redis::AsyncCommands;
+use serde::{de::DeserializeOwned, Serialize};
 use std::collections::HashSet;
 
 const BYTES_U112: usize = Uint::<112, 2>::BYTES;
-const BYTES_U256: usize = Uint::<256, 4>::BYTES;
+
+// Pairs are loaded to redis with one pipeline per chunk
+const PAIRS_PER_PIPELINE: usize = 10_000;
+
+// Key prefixes, the rest of the key is `dex_id` (big-endian) and raw addresses
+const PREFIX_ADJACENT: u8 = b'a';
+const PREFIX_RESERVES: u8 = b'r';
+const PREFIX_TOKENS: u8 = b't';
+const PREFIX_PAIR: u8 = b'p';
+
+// Hash with JSON encoded `DexStatus` by `dex_id`, written by the bot
+const KEY_HEALTH: &[u8] = b"h";
+
+// JSON encoded `KillSwitch`, written by the api
+const KEY_KILL_SWITCH: &[u8] = b"k";
+
+// JSON encoded ledger of paper trading, written by the bot after every fill
+const KEY_PAPER_LEDGER: &[u8] = b"l";
+
+/// Pub/sub channel with JSON encoded opportunities
+pub const CHANNEL_OPPORTUNITIES: &str = "opportunities";
+
+// Version of the key layout, written by `migrate`
+const KEY_LAYOUT: &[u8] = b"v";
+const LAYOUT_VERSION: u8 = 2;
+
+// Text keys of the first layout, e.g. "reserves:{dex_id}:{token0:?}:{token1:?}"
+const LEGACY_PATTERNS: [&str; 4] = ["adjacent:*", "reserves:*", "tokens:*", "pair:*"];
+const KEYS_PER_SCAN: usize = 10_000;
+
+// Fields of reserves hash
+const FIELD_RESERVE0: &[u8] = b"0";
+const FIELD_RESERVE1: &[u8] = b"1";
+const FIELD_BLOCK: &[u8] = b"b";
+
+// (reserve0, reserve1) fields as they are returned by HMGET
+type ReservesFields = (Option<Vec<u8>>, Option<Vec<u8>>);
 
 #[derive(Clone, Debug)]
 pub struct RedisDB {
     pool: bb8::Pool<RedisConnectionManager>,
+    // pub/sub needs dedicated connection, it is not taken from the pool
+    client: redis::Client,
 }
 
 impl RedisDB {
     pub async fn connect(config: &RedisConfig) -> Result<Self> {
         let manager = RedisConnectionManager::new(config.into_connection())?;
         let pool = bb8::Pool::builder().build(manager).await?;
+        let client = redis::Client::open(config.into_connection())?;
 
-        Ok(Self { pool })
+        Ok(Self { pool, client })
+    }
+
+    /// Brings keys to the current layout. Keys of the text layout are deleted,
+    /// pairs are loaded again from postgres and reserves are refilled by the next blocks
+    pub async fn migrate(&self) -> Result<()> {
+        let mut conn = self.pool.get().await?;
+        let version: Option<u8> = conn.get(KEY_LAYOUT).await?;
+        if version.is_some_and(|version| version >= LAYOUT_VERSION) {
+            return Ok(());
+        }
+
+        let mut deleted = 0;
+        for pattern in LEGACY_PATTERNS {
+            let mut cursor = 0u64;
+            loop {
+                let (next, keys): (u64, Vec<Vec<u8>>) = redis::cmd("SCAN")
+                    .arg(cursor)
+                    .arg("MATCH")
+                    .arg(pattern)
+                    .arg("COUNT")
+                    .arg(KEYS_PER_SCAN)
+                    .query_async(&mut *conn)
+                    .await?;
+                if !keys.is_empty() {
+                    deleted += keys.len();
+                    let _: () = conn.unlink(keys).await?;
+                }
+                if next == 0 {
+                    break;
+                }
+                cursor = next;
+            }
+        }
+
+        let _: () = conn.set(KEY_LAYOUT, LAYOUT_VERSION).await?;
+        tracing::info!(
+            "(redis 🛡️): migrated to layout v{LAYOUT_VERSION}, deleted {deleted} legacy keys"
+        );
+        Ok(())
     }
 }
 
-/// Implementation for key functions
+/// Implementation for key functions.
+/// Keys are binary: `{prefix}{dex_id}{address}..`
 impl RedisDB {
-    /// key: "adjacent:{dex_id}:{token}"
-    pub fn key_adjacent_tokens(dex_id: i32, token: &Address) -> String {
-        format!("adjacent:{dex_id}:{token:?}")
+    fn key(prefix: u8, dex_id: i32, addresses: &[&Address]) -> Vec<u8> {
+        let mut key = Vec::with_capacity(5 + 20 * addresses.len());
+        key.push(prefix);
+        key.extend_from_slice(&dex_id.to_be_bytes());
+        for address in addresses {
+            key.extend_from_slice(address.as_slice());
+        }
+        key
     }
 
-    /// key : "reserves:{dex_id}:{token0}:{token1}"
-    /// Returns reserves for token0 in pair with token1
-    pub fn key_token_reserves(dex_id: i32, token0: &Address, token1: &Address) -> String {
-        format!("reserves:{dex_id}:{token0:?}:{token1:?}")
+    /// key: "a{dex_id}{token}"
+    pub fn key_adjacent_tokens(dex_id: i32, token: &Address) -> Vec<u8> {
+        Self::key(PREFIX_ADJACENT, dex_id, &[token])
     }
 
-    /// key: "tokens:{dex_id}:{pair_address}"
-    pub fn key_tokens(dex_id: i32, pair_address: &Address) -> String {
-        format!("tokens:{dex_id}:{pair_address}")
+    /// key: "r{dex_id}{token0}{token1}", tokens are sorted
+    /// Hash with reserves of both tokens and block number of the last update
+    pub fn key_reserves(dex_id: i32, token0: &Address, token1: &Address) -> Vec<u8> {
+        let (token0, token1) = sorted(token0, token1);
+        Self::key(PREFIX_RESERVES, dex_id, &[token0, token1])
     }
 
-    /// key: "pair:{dex_id}:{token0}:{token1}"
-    pub fn key_pair(dex_id: i32, token0: &Address, token1: &Address) -> String {
-        // Change to correct order
-        if *token0 < *token1 {
-            format!("pair:{dex_id}:{token0}:{token1}")
-        } else {
-            format!("pair:{dex_id}:{token1}:{token0}")
-        }
+    /// key: "t{dex_id}{pair_address}"
+    pub fn key_tokens(dex_id: i32, pair_address: &Address) -> Vec<u8> {
+        Self::key(PREFIX_TOKENS, dex_id, &[pair_address])
+    }
+
+    /// key: "p{dex_id}{token0}{token1}", tokens are sorted
+    pub fn key_pair(dex_id: i32, token0: &Address, token1: &Address) -> Vec<u8> {
+        let (token0, token1) = sorted(token0, token1);
+        Self::key(PREFIX_PAIR, dex_id, &[token0, token1])
     }
 }
 
@@ -58,29 +150,12 @@ impl RedisDB {
     /// 2. mapping from tokens to pair address
     /// 3. setting adjacent tokens
     pub async fn add_pair(&self, pair: Pair) -> Result<()> {
+        let _timer = metrics::db_timer("redis", "add_pair");
         let mut conn = self.pool.get().await?;
 
-        // mapping from `pair`: `token0+token1`
-        let key_tokens = Self::key_tokens(pair.dex_id, &pair.address);
-        let mut addresses = [0u8; 40];
-        addresses[0..20].copy_from_slice(pair.token0.as_slice());
-        addresses[20..40].copy_from_slice(pair.token1.as_slice());
-        let _: () = conn.set(key_tokens, &addresses).await?;
-
-        // mapping from `tokens` to `pair` address
-        let key_pair = Self::key_pair(pair.dex_id, &pair.token0, &pair.token1);
-        let _: () = conn.set(key_pair, pair.address.as_slice()).await?;
-
-        // addind adjacent tokens
-        let key_token0_adjacent = Self::key_adjacent_tokens(pair.dex_id, &pair.token0);
-        let key_token1_adjacent = Self::key_adjacent_tokens(pair.dex_id, &pair.token1);
-
-        let _: () = conn
-            .sadd(key_token0_adjacent, pair.token1.as_slice())
-            .await?;
-        let _: () = conn
-            .sadd(key_token1_adjacent, pair.token0.as_slice())
-            .await?;
+        let mut pipe = redis::pipe();
+        Self::pipe_add_pair(&mut pipe, &pair);
+        let _: () = pipe.query_async(&mut *conn).await?;
 
         tracing::trace!(
             "(redis 🛡️): add on dex={} new pair: {}",
@@ -91,21 +166,68 @@ impl RedisDB {
         Ok(())
     }
 
+    /// Same as `add_pair`, but commands are sent in pipelines by chunks
+    pub async fn add_pairs(&self, pairs: &[Pair]) -> Result<()> {
+        let _timer = metrics::db_timer("redis", "add_pairs");
+        let mut conn = self.pool.get().await?;
+
+        for chunk in pairs.chunks(PAIRS_PER_PIPELINE) {
+            let mut pipe = redis::pipe();
+            for pair in chunk {
+                Self::pipe_add_pair(&mut pipe, pair);
+            }
+            let _: () = pipe.query_async(&mut *conn).await?;
+        }
+
+        tracing::trace!("(redis 🛡️): add {} pairs", pairs.len());
+        Ok(())
+    }
+
+    fn pipe_add_pair(pipe: &mut redis::Pipeline, pair: &Pair) {
+        // mapping from `pair`: `token0+token1`
+        let mut addresses = [0u8; 40];
+        addresses[0..20].copy_from_slice(pair.token0.as_slice());
+        addresses[20..40].copy_from_slice(pair.token1.as_slice());
+
+        pipe.set(Self::key_tokens(pair.dex_id, &pair.address), &addresses)
+            .ignore()
+            // mapping from `tokens` to `pair` address
+            .set(
+                Self::key_pair(pair.dex_id, &pair.token0, &pair.token1),
+                pair.address.as_slice(),
+            )
+            .ignore()
+            // addind adjacent tokens
+            .sadd(
+                Self::key_adjacent_tokens(pair.dex_id, &pair.token0),
+                pair.token1.as_slice(),
+            )
+            .ignore()
+            .sadd(
+                Self::key_adjacent_tokens(pair.dex_id, &pair.token1),
+                pair.token0.as_slice(),
+            )
+            .ignore();
+    }
+
     /// Returns (token0, token1) addresses for concrete pair
     pub async fn pair_by_tokens(
         &self,
         dex_id: i32,
         pair_adr: &Address,
     ) -> Result<(Address, Address)> {
+        let _timer = metrics::db_timer("redis", "pair_by_tokens");
         let mut conn = self.pool.get().await?;
         let key = Self::key_tokens(dex_id, pair_adr);
 
-        match conn.get::<String, [u8; 40]>(key).await {
-            Ok(addresses) => Ok((
+        match conn.get::<_, Option<Vec<u8>>>(key).await? {
+            Some(addresses) if addresses.len() == 40 => Ok((
                 Address::from_slice(&addresses[0..20]),
                 Address::from_slice(&addresses[20..40]),
             )),
-            Err(_) => Err(anyhow!("think about fetching data")),
+            _ => Err(DbError::not_found(format!(
+                "tokens of pair {pair_adr:?} on dex={dex_id}"
+            ))),
         }
     }
 
@@ -115,6 +237,7 @@ impl RedisDB {
         token0: &Address,
         token1: &Address,
     ) -> Result<Address> {
+        let _timer = metrics::db_timer("redis", "pair_adr");
         let mut conn = self.pool.get().await?;
         let key = Self::key_pair(dex_id, token0, token1);
 
@@ -123,6 +246,7 @@ impl RedisDB {
     }
 
     pub async fn adjacent(&self, dex_id: i32, token: &Address) -> Result<HashSet<Address>> {
+        let _timer = metrics::db_timer("redis", "adjacent");
         let mut conn = self.pool.get().await?;
         let key = Self::key_adjacent_tokens(dex_id, token);
 
@@ -134,25 +258,33 @@ impl RedisDB {
     }
 
     /// NOTE: reserves convert to big-endian bytes
-    pub async fn update_reserves(
-        &self,
-        dex_id: i32,
-        token0: &Address,
-        token1: &Address,
-        reserve0: Uint<112, 2>,
-        reserve1: Uint<112, 2>,
-    ) -> Result<()> {
+    pub async fn update_reserves(&self, dex_id: i32, data: &[UpdateReservesData]) -> Result<()> {
+        let _timer = metrics::db_timer("redis", "update_reserves");
         let mut conn = self.pool.get().await?;
-        let key_token0 = Self::key_token_reserves(dex_id, token0, token1);
-        let key_token1 = Self::key_token_reserves(dex_id, token1, token0);
 
-        let reserve0_be: [u8; BYTES_U112] = reserve0.to_be_bytes();
-        let reserve1_be: [u8; BYTES_U112] = reserve1.to_be_bytes();
+        let mut pipe = redis::pipe();
+        for update in data {
+            // reserves are stored in order of sorted tokens
+            let (reserve0, reserve1) = match update.token0 < update.token1 {
+                true => (update.reserves.0, update.reserves.1),
+                false => (update.reserves.1, update.reserves.0),
+            };
+            let reserve0_be: [u8; BYTES_U112] = reserve0.to_be_bytes();
+            let reserve1_be: [u8; BYTES_U112] = reserve1.to_be_bytes();
 
-        let _: () = conn.set(key_token0, &reserve0_be).await?;
-        let _: () = conn.set(key_token1, &reserve1_be).await?;
+            pipe.hset_multiple(
+                Self::key_reserves(dex_id, &update.token0, &update.token1),
+                &[
+                    (FIELD_RESERVE0, reserve0_be.as_slice()),
+                    (FIELD_RESERVE1, reserve1_be.as_slice()),
+                    (FIELD_BLOCK, update.block_number.to_be_bytes().as_slice()),
+                ],
+            )
+            .ignore();
+        }
+        let _: () = pipe.query_async(&mut *conn).await?;
 
-        tracing::trace!("update reserves on dex={dex_id} for token0: {token0}, token1: {token1}");
+        tracing::trace!("update {} reserves on dex={dex_id}", data.len());
         Ok(())
     }
 
@@ -162,16 +294,161 @@ impl RedisDB {
         token0: &Address,
         token1: &Address,
     ) -> Result<Reserves> {
+        self.reserves_batch(dex_id, &[(*token0, *token1)])
+            .await?
+            .remove(0)
+            .ok_or(DbError::not_found(format!(
+                "cached reserves for {token0:?}, {token1:?}"
+            )))
+    }
+
+    /// Returns reserves for every pair of tokens in one pipeline,
+    /// `None` if reserves are not cached
+    pub async fn reserves_batch(
+        &self,
+        dex_id: i32,
+        tokens: &[(Address, Address)],
+    ) -> Result<Vec<Option<Reserves>>> {
+        let _timer = metrics::db_timer("redis", "reserves_batch");
         let mut conn = self.pool.get().await?;
-        let key_token0 = Self::key_token_reserves(dex_id, token0, token1);
-        let key_token1 = Self::key_token_reserves(dex_id, token1, token0);
 
-        let reserve0_bytes: [u8; BYTES_U112] = conn.get(key_token0).await?;
-        let reserve1_bytes: [u8; BYTES_U112] = conn.get(key_token1).await?;
+        let mut pipe = redis::pipe();
+        for (token0, token1) in tokens {
+            pipe.hget(
+                Self::key_reserves(dex_id, token0, token1),
+                &[FIELD_RESERVE0, FIELD_RESERVE1],
+            );
+        }
+        let values: Vec<ReservesFields> = pipe.query_async(&mut *conn).await?;
+
+        Ok(tokens
+            .iter()
+            .zip(values)
+            .map(|((token0, token1), value)| {
+                let reserve0 = decode_reserve(value.0?)?;
+                let reserve1 = decode_reserve(value.1?)?;
+
+                // return reserves in order of requested tokens
+                Some(match token0 < token1 {
+                    true => Reserves(reserve0, reserve1),
+                    false => Reserves(reserve1, reserve0),
+                })
+            })
+            .collect())
+    }
+}
+
+/// Live data shared between the bot and api
+impl RedisDB {
+    pub async fn publish_opportunity(&self, opportunity: &Opportunity) -> Result<()> {
+        let _timer = metrics::db_timer("redis", "publish_opportunity");
+        let mut conn = self.pool.get().await?;
+        let payload =
+            serde_json::to_string(opportunity).map_err(|err| DbError::Decode(err.to_string()))?;
+
+        let _: () = conn.publish(CHANNEL_OPPORTUNITIES, payload).await?;
+        Ok(())
+    }
+
+    /// Stream of opportunities published after the subscription, invalid messages are skipped
+    pub async fn subscribe_opportunities(&self) -> Result<impl Stream<Item = Opportunity>> {
+        let mut pubsub = self.client.get_async_pubsub().await?;
+        pubsub.subscribe(CHANNEL_OPPORTUNITIES).await?;
+
+        Ok(pubsub.into_on_message().filter_map(|message| async move {
+            let payload: String = message.get_payload().ok()?;
+            serde_json::from_str(&payload).ok()
+        }))
+    }
+
+    pub async fn set_health(&self, statuses: &[DexStatus]) -> Result<()> {
+        let _timer = metrics::db_timer("redis", "set_health");
+        let mut conn = self.pool.get().await?;
+
+        let mut pipe = redis::pipe();
+        for status in statuses {
+            let payload =
+                serde_json::to_vec(status).map_err(|err| DbError::Decode(err.to_string()))?;
+            pipe.hset(KEY_HEALTH, status.dex_id.to_be_bytes().as_slice(), payload)
+                .ignore();
+        }
+        let _: () = pipe.query_async(&mut *conn).await?;
+        Ok(())
+    }
+
+    /// Returns the last status of every dex adapter sorted by `dex_id`
+    pub async fn health(&self) -> Result<Vec<DexStatus>> {
+        let _timer = metrics::db_timer("redis", "health");
+        let mut conn = self.pool.get().await?;
+
+        let values: Vec<Vec<u8>> = conn.hvals(KEY_HEALTH).await?;
+        let mut statuses = values
+            .iter()
+            .map(|value| serde_json::from_slice(value))
+            .collect::<Result<Vec<DexStatus>, _>>()
+            .map_err(|err| DbError::Decode(err.to_string()))?;
+        statuses.sort_by_key(|status| status.dex_id);
+        Ok(statuses)
+    }
+
+    pub async fn set_kill_switch(&self, kill_switch: &KillSwitch) -> Result<()> {
+        let _timer = metrics::db_timer("redis", "set_kill_switch");
+        let mut conn = self.pool.get().await?;
+        let payload =
+            serde_json::to_vec(kill_switch).map_err(|err| DbError::Decode(err.to_string()))?;
+
+        let _: () = conn.set(KEY_KILL_SWITCH, payload).await?;
+        Ok(())
+    }
+
+    /// `None` if the kill switch was never set
+    pub async fn kill_switch(&self) -> Result<Option<KillSwitch>> {
+        let _timer = metrics::db_timer("redis", "kill_switch");
+        let mut conn = self.pool.get().await?;
+
+        let value: Option<Vec<u8>> = conn.get(KEY_KILL_SWITCH).await?;
+        value
+            .map(|value| serde_json::from_slice(&value))
+            .transpose()
+            .map_err(|err| DbError::Decode(err.to_string()))
+    }
+
+    pub async fn set_paper_ledger(&self, ledger: &impl Serialize) -> Result<()> {
+        let _timer = metrics::db_timer("redis", "set_paper_ledger");
+        let mut conn = self.pool.get().await?;
+        let payload = serde_json::to_vec(ledger).map_err(|err| DbError::Decode(err.to_string()))?;
+
+        let _: () = conn.set(KEY_PAPER_LEDGER, payload).await?;
+        Ok(())
+    }
+
+    /// `None` if nothing was filled on paper yet
+    pub async fn paper_ledger<T: DeserializeOwned>(&self) -> Result<Option<T>> {
+        let _timer = metrics::db_timer("redis", "paper_ledger");
+        let mut conn = self.pool.get().await?;
 
-        Ok(Reserves(
-            Uint::<112, 2>::from_be_bytes(reserve0_bytes),
-            Uint::<112, 2>::from_be_bytes(reserve1_bytes),
-        ))
+        let value: Option<Vec<u8>> = conn.get(KEY_PAPER_LEDGER).await?;
+        value
+            .map(|value| serde_json::from_slice(&value))
+            .transpose()
+            .map_err(|err| DbError::Decode(err.to_string()))
     }
+
+    pub async fn ping(&self) -> Result<()> {
+        let mut conn = self.pool.get().await?;
+        let _: String = redis::cmd("PING").query_async(&mut *conn).await?;
+        Ok(())
+    }
+}
+
+fn sorted<'a>(token0: &'a Address, token1: &'a Address) -> (&'a Address, &'a Address) {
+    match token0 < token1 {
+        true => (token0, token1),
+        false => (token1, token0),
+    }
+}
+
+fn decode_reserve(bytes: Vec<u8>) -> Option<Uint<112, 2>> {
+    let bytes: [u8; BYTES_U112] = bytes.try_into().ok()?;
+    Some(Uint::<112, 2>::from_be_bytes(bytes))
 }
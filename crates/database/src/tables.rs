@@ -1,8 +1,14 @@
-use alloy::primitives::Address;
+use alloy::primitives::{Address, B256, U256};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+pub use kronos_config::ExecutionMode;
 
 pub const PAIRS_TABLE: &str = "trading_pairs";
 pub const DEXES_TABLE: &str = "dexes";
 pub const TICKERS_TABLE: &str = "token_tickers";
+pub const OPPORTUNITIES_TABLE: &str = "opportunities";
+pub const EXECUTIONS_TABLE: &str = "executions";
 
 /// `Pair` represents the trading pair in DEX
 #[derive(Debug, Clone)]
@@ -13,17 +19,42 @@ pub struct Pair {
     pub token1: Address,
 }
 
-
 #[derive(Clone, Debug, sqlx::FromRow)]
 pub struct Dex {
     pub id: i32,
     pub name: String,
 }
 
+/// Risk flags of the token
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TokenFlags {
+    pub fee_on_transfer: bool,
+    pub rebasing: bool,
+    pub blacklist: bool,
+    pub unverified: bool,
+    /// Transfer out of the pair or back into it reverts
+    pub honeypot: bool,
+}
+
+impl TokenFlags {
+    /// Blocked tokens are never traded. Fee-on-transfer tokens are allowed
+    /// while their transfer tax is measured, blacklist only tells that holders can be frozen
+    pub fn blocked(&self) -> bool {
+        self.rebasing || self.unverified || self.honeypot
+    }
+}
+
+/// `Token` represents ERC20 metadata stored in `token_tickers` table
 #[derive(Clone, Debug)]
-pub struct Ticker {
+pub struct Token {
     pub token: Address,
     pub ticker: String,
+    pub name: String,
+    /// `None` if `decimals()` failed, such token is unverified
+    pub decimals: Option<u8>,
+    pub flags: TokenFlags,
+    /// Measured transfer tax in basis points, `None` if token is not classified yet
+    pub transfer_tax_bps: Option<u16>,
 }
 
 // These structs are needed for sqlx::query_as
@@ -35,8 +66,231 @@ pub struct PairRaw {
     pub token1: [u8; 20],
 }
 
+impl From<&PairRaw> for Pair {
+    fn from(raw: &PairRaw) -> Self {
+        Self {
+            address: Address::from_slice(&raw.address),
+            dex_id: raw.dex_id,
+            token0: Address::from_slice(&raw.token0),
+            token1: Address::from_slice(&raw.token1),
+        }
+    }
+}
+
 #[derive(Clone, Debug, sqlx::FromRow)]
-pub struct TickerRaw {
+pub struct TokenRaw {
     pub token: [u8; 20],
     pub ticker: String,
+    pub name: Option<String>,
+    pub decimals: Option<i16>,
+    pub fee_on_transfer: bool,
+    pub rebasing: bool,
+    pub blacklist: bool,
+    pub unverified: bool,
+    pub honeypot: bool,
+    pub transfer_tax_bps: Option<i32>,
+}
+
+impl TokenRaw {
+    /// Returns `None` for rows inserted before metadata was collected
+    pub fn into_token(self) -> Option<Token> {
+        Some(Token {
+            token: Address::from_slice(&self.token),
+            ticker: self.ticker,
+            name: self.name?,
+            decimals: self
+                .decimals
+                .and_then(|decimals| u8::try_from(decimals).ok()),
+            flags: TokenFlags {
+                fee_on_transfer: self.fee_on_transfer,
+                rebasing: self.rebasing,
+                blacklist: self.blacklist,
+                unverified: self.unverified,
+                honeypot: self.honeypot,
+            },
+            transfer_tax_bps: self
+                .transfer_tax_bps
+                .and_then(|bps| u16::try_from(bps).ok()),
+        })
+    }
+}
+
+/// Arbitrage found by dex adapter
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Opportunity {
+    /// 0 until the opportunity is inserted
+    pub id: i64,
+    pub dex_id: i32,
+    pub block_number: u64,
+    /// Tokens of the cycle, the first and the last are the same
+    pub path: Vec<Address>,
+    pub amount_in: U256,
+    pub revenue: U256,
+    pub revenue_usd: Option<f64>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionStatus {
+    Pending,
+    Included,
+    Reverted,
+    Failed,
+}
+
+impl ExecutionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Included => "included",
+            Self::Reverted => "reverted",
+            Self::Failed => "failed",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<Self> {
+        match status {
+            "pending" => Some(Self::Pending),
+            "included" => Some(Self::Included),
+            "reverted" => Some(Self::Reverted),
+            "failed" => Some(Self::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// Transaction sent for the opportunity
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Execution {
+    /// 0 until the execution is inserted
+    pub id: i64,
+    pub opportunity_id: Option<i64>,
+    pub block_number: u64,
+    pub tx_hash: Option<B256>,
+    pub status: ExecutionStatus,
+    /// Paper fills are simulated, `tx_hash` is never set for them
+    pub mode: ExecutionMode,
+    pub gas_used: Option<u64>,
+    pub profit_usd: Option<f64>,
+    /// Fees of the transaction, `None` if nothing was sent
+    pub gas: Option<GasBid>,
+    /// Difference between predicted and simulated output in basis points,
+    /// positive if the output is worse than predicted
+    pub slippage_bps: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Fees of the transaction and the gas strategy which chose them
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GasBid {
+    pub strategy: String,
+    /// Parameters of the strategy as JSON
+    pub params: String,
+    pub max_fee_per_gas: u128,
+    pub max_priority_fee_per_gas: u128,
+}
+
+/// State of the dex adapter which is shared by the bot with other services
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DexStatus {
+    pub name: String,
+    pub dex_id: i32,
+    pub last_block: u64,
+    pub last_success_block: u64,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    /// When the adapter processed its last block, the adapter is stale if it stops
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Kill switch of the executor, set through the api and read by the bot
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KillSwitch {
+    pub engaged: bool,
+    pub reason: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// Amounts are selected as text, NUMERIC(78, 0) has no native decoding
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct OpportunityRaw {
+    pub id: i64,
+    pub dex_id: i32,
+    pub block_number: i64,
+    pub path: Vec<Vec<u8>>,
+    pub amount_in: String,
+    pub revenue: String,
+    pub revenue_usd: Option<f64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl OpportunityRaw {
+    pub fn into_opportunity(self) -> Option<Opportunity> {
+        Some(Opportunity {
+            id: self.id,
+            dex_id: self.dex_id,
+            block_number: u64::try_from(self.block_number).ok()?,
+            path: self
+                .path
+                .iter()
+                .map(|token| Address::try_from(token.as_slice()).ok())
+                .collect::<Option<Vec<Address>>>()?,
+            amount_in: self.amount_in.parse().ok()?,
+            revenue: self.revenue.parse().ok()?,
+            revenue_usd: self.revenue_usd,
+            created_at: self.created_at,
+        })
+    }
+}
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct ExecutionRaw {
+    pub id: i64,
+    pub opportunity_id: Option<i64>,
+    pub block_number: i64,
+    pub tx_hash: Option<Vec<u8>>,
+    pub status: String,
+    pub mode: String,
+    pub gas_used: Option<i64>,
+    pub profit_usd: Option<f64>,
+    pub gas_strategy: Option<String>,
+    pub gas_params: Option<String>,
+    pub max_fee_per_gas: Option<String>,
+    pub max_priority_fee_per_gas: Option<String>,
+    pub slippage_bps: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ExecutionRaw {
+    pub fn into_execution(self) -> Option<Execution> {
+        Some(Execution {
+            id: self.id,
+            opportunity_id: self.opportunity_id,
+            block_number: u64::try_from(self.block_number).ok()?,
+            tx_hash: match self.tx_hash {
+                Some(hash) => Some(B256::try_from(hash.as_slice()).ok()?),
+                None => None,
+            },
+            status: ExecutionStatus::parse(&self.status)?,
+            mode: ExecutionMode::parse(&self.mode)?,
+            gas_used: self.gas_used.and_then(|gas| u64::try_from(gas).ok()),
+            profit_usd: self.profit_usd,
+            gas: match (
+                self.gas_strategy,
+                self.max_fee_per_gas,
+                self.max_priority_fee_per_gas,
+            ) {
+                (Some(strategy), Some(max_fee), Some(max_priority_fee)) => Some(GasBid {
+                    strategy,
+                    params: self.gas_params.unwrap_or_default(),
+                    max_fee_per_gas: max_fee.parse().ok()?,
+                    max_priority_fee_per_gas: max_priority_fee.parse().ok()?,
+                }),
+                _ => None,
+            },
+            slippage_bps: self.slippage_bps,
+            created_at: self.created_at,
+        })
+    }
 }
@@ -0,0 +1,328 @@
+use crate::error::{DbError, Result};
+use crate::{
+    tables::{Token, TokenFlags},
+    PostgresDB,
+};
+use alloy::{
+    primitives::{address, utils::format_units, Address, Bytes, Uint},
+    providers::RootProvider,
+    sol_types::{SolCall, SolValue},
+};
+use ethereum_abi::{
+    IMulticall3::{self, Call},
+    ITokenRisk, IERC20,
+};
+use hashbrown::HashMap;
+use std::sync::{Arc, RwLock};
+
+const MULTICALL3: Address = address!("0xcA11bde05977b3631167028862bE2a173976CA11");
+
+// symbol, name, decimals and 4 risk getters
+const CALLS_PER_TOKEN: usize = 7;
+const TOKENS_PER_MULTICALL: usize = 100;
+
+/// `TokenRegistry` keeps ERC20 metadata of all known tokens in memory.
+/// Missing tokens are fetched with batched multicall and stored to postgres
+#[derive(Clone)]
+pub struct TokenRegistry {
+    postgres: PostgresDB,
+    provider: Arc<RootProvider>,
+    tokens: Arc<RwLock<HashMap<Address, Token>>>,
+}
+
+impl TokenRegistry {
+    pub async fn new(postgres: PostgresDB, provider: Arc<RootProvider>) -> Result<Self> {
+        let tokens: HashMap<Address, Token> = postgres
+            .select_tokens()
+            .await?
+            .into_iter()
+            .map(|token| (token.token, token))
+            .collect();
+        tracing::info!("🪙 Load {} tokens from Postgres", tokens.len());
+
+        Ok(Self {
+            postgres,
+            provider,
+            tokens: Arc::new(RwLock::new(tokens)),
+        })
+    }
+
+    pub fn get(&self, token: &Address) -> Option<Token> {
+        self.tokens.read().unwrap().get(token).cloned()
+    }
+
+    pub fn contains(&self, token: &Address) -> bool {
+        self.tokens.read().unwrap().contains_key(token)
+    }
+
+    pub fn decimals(&self, token: &Address) -> Option<u8> {
+        self.tokens
+            .read()
+            .unwrap()
+            .get(token)
+            .and_then(|t| t.decimals)
+    }
+
+    /// Returns ticker or address for unknown tokens
+    pub fn ticker(&self, token: &Address) -> String {
+        match self.tokens.read().unwrap().get(token) {
+            Some(t) if !t.ticker.is_empty() => t.ticker.clone(),
+            _ => token.to_string(),
+        }
+    }
+
+    pub fn flags(&self, token: &Address) -> Option<TokenFlags> {
+        self.tokens.read().unwrap().get(token).map(|t| t.flags)
+    }
+
+    pub fn transfer_tax_bps(&self, token: &Address) -> Option<u16> {
+        self.tokens
+            .read()
+            .unwrap()
+            .get(token)
+            .and_then(|t| t.transfer_tax_bps)
+    }
+
+    /// Token is tradable only if its metadata is known, it is not blocked
+    /// and transfer tax is measured for fee-on-transfer token
+    pub fn is_tradable(&self, token: &Address) -> bool {
+        match self.tokens.read().unwrap().get(token) {
+            Some(t) => {
+                !t.flags.blocked() && (!t.flags.fee_on_transfer || t.transfer_tax_bps.is_some())
+            }
+            None => false,
+        }
+    }
+
+    /// Formats raw `amount` with token decimals, e.g. "1.5 WETH"
+    pub fn format_amount(&self, token: &Address, amount: Uint<256, 4>) -> String {
+        let decimals = self.decimals(token).unwrap_or(18);
+        let value = format_units(amount, decimals).unwrap_or(amount.to_string());
+        format!("{value} {}", self.ticker(token))
+    }
+
+    pub async fn set_flags(&self, token: &Address, flags: TokenFlags) -> Result<()> {
+        self.postgres.update_token_flags(token, flags).await?;
+        if let Some(t) = self.tokens.write().unwrap().get_mut(token) {
+            t.flags = flags;
+        }
+        tracing::debug!("token {token} flags updated: {flags:?}");
+        Ok(())
+    }
+
+    /// Stores result of transfer tax classification
+    pub async fn set_transfer_tax(
+        &self,
+        token: &Address,
+        flags: TokenFlags,
+        transfer_tax_bps: Option<u16>,
+    ) -> Result<()> {
+        self.postgres.update_token_flags(token, flags).await?;
+        self.postgres
+            .update_token_transfer_tax(token, transfer_tax_bps)
+            .await?;
+        if let Some(t) = self.tokens.write().unwrap().get_mut(token) {
+            t.flags = flags;
+            t.transfer_tax_bps = transfer_tax_bps;
+        }
+        tracing::debug!("token {token} transfer tax: {transfer_tax_bps:?} bps, flags: {flags:?}");
+        Ok(())
+    }
+
+    /// Fetches metadata for tokens which are not in registry yet
+    pub async fn ensure_tokens(&self, tokens: &[Address]) -> Result<()> {
+        let mut missing: Vec<Address> = tokens
+            .iter()
+            .filter(|token| !self.contains(token))
+            .copied()
+            .collect();
+        missing.sort();
+        missing.dedup();
+
+        for chunk in missing.chunks(TOKENS_PER_MULTICALL) {
+            for token in self.fetch_metadata(chunk).await? {
+                self.postgres.upsert_token(&token).await?;
+                tracing::trace!("insert token: {} ({})", token.ticker, token.token);
+                self.tokens.write().unwrap().insert(token.token, token);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Requests metadata and risk getters of every token in one multicall
+    async fn fetch_metadata(&self, tokens: &[Address]) -> Result<Vec<Token>> {
+        let calls: Vec<Call> = tokens
+            .iter()
+            .flat_map(|token| {
+                metadata_calls().into_iter().map(|call_data| Call {
+                    target: *token,
+                    callData: call_data.into(),
+                })
+            })
+            .collect();
+
+        let multicall = IMulticall3::new(MULTICALL3, self.provider.clone());
+        let results = multicall
+            .tryAggregate(false, calls)
+            .call()
+            .await
+            .map_err(|err| DbError::Rpc(err.into()))?
+            .returnData;
+
+        Ok(tokens
+            .iter()
+            .zip(results.chunks(CALLS_PER_TOKEN))
+            .map(|(token, results)| decode_metadata(*token, results))
+            .collect())
+    }
+}
+
+fn metadata_calls() -> [Vec<u8>; CALLS_PER_TOKEN] {
+    [
+        IERC20::symbolCall {}.abi_encode(),
+        IERC20::nameCall {}.abi_encode(),
+        IERC20::decimalsCall {}.abi_encode(),
+        ITokenRisk::scaledBalanceOfCall {
+            account: Address::ZERO,
+        }
+        .abi_encode(),
+        ITokenRisk::sharesOfCall {
+            account: Address::ZERO,
+        }
+        .abi_encode(),
+        ITokenRisk::isBlacklistedCall {
+            account: Address::ZERO,
+        }
+        .abi_encode(),
+        ITokenRisk::isBlackListedCall {
+            account: Address::ZERO,
+        }
+        .abi_encode(),
+    ]
+}
+
+/// Builds token from results of `metadata_calls`.
+/// Tokens without symbol or decimals are unverified and keep unknown decimals.
+/// Getter which is answered with a single word exists, calls to missing functions revert
+/// or hit a fallback which returns nothing
+fn decode_metadata(token: Address, results: &[IMulticall3::Result]) -> Token {
+    let data = |index: usize| -> Option<&Bytes> {
+        let result = results.get(index)?;
+        result.success.then_some(&result.returnData)
+    };
+    let exists = |index: usize| data(index).is_some_and(|data| data.len() == 32);
+
+    let symbol = data(0).and_then(|data| decode_string(data));
+    let name = data(1).and_then(|data| decode_string(data));
+    let decimals = data(2)
+        .and_then(|data| IERC20::decimalsCall::abi_decode_returns(data, false).ok())
+        .map(|r| r._0);
+
+    Token {
+        token,
+        flags: TokenFlags {
+            rebasing: exists(3) || exists(4),
+            blacklist: exists(5) || exists(6),
+            unverified: symbol.is_none() || decimals.is_none(),
+            ..Default::default()
+        },
+        ticker: symbol.unwrap_or_default(),
+        name: name.unwrap_or_default(),
+        decimals,
+        transfer_tax_bps: None,
+    }
+}
+
+/// Decodes `string` or `bytes32` returned by early tokens like MKR
+fn decode_string(data: &[u8]) -> Option<String> {
+    if data.len() == 32 {
+        let end = data.iter().position(|byte| *byte == 0).unwrap_or(32);
+        return String::from_utf8(data[..end].to_vec()).ok();
+    }
+    String::abi_decode(data, false).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::{
+        primitives::{address, Bytes, FixedBytes, U256},
+        sol_types::SolValue,
+    };
+
+    const TOKEN: Address = address!("0x9f8F72aA9304c8B593d555F12eF6589cC3A579A2");
+
+    fn ok(data: Vec<u8>) -> IMulticall3::Result {
+        IMulticall3::Result {
+            success: true,
+            returnData: Bytes::from(data),
+        }
+    }
+
+    fn reverted() -> IMulticall3::Result {
+        IMulticall3::Result {
+            success: false,
+            returnData: Bytes::new(),
+        }
+    }
+
+    fn results(
+        symbol: IMulticall3::Result,
+        decimals: IMulticall3::Result,
+    ) -> Vec<IMulticall3::Result> {
+        let mut results = vec![symbol, ok("Token".abi_encode()), decimals];
+        results.extend((3..CALLS_PER_TOKEN).map(|_| reverted()));
+        results
+    }
+
+    #[test]
+    fn decodes_string_and_bytes32_symbols() {
+        let token = decode_metadata(
+            TOKEN,
+            &results(ok("WETH".abi_encode()), ok(U256::from(18).abi_encode())),
+        );
+        assert_eq!(token.ticker, "WETH");
+        assert_eq!(token.name, "Token");
+        assert_eq!(token.decimals, Some(18));
+        assert!(!token.flags.blocked());
+
+        // MKR returns symbol as bytes32
+        let mkr = FixedBytes::<32>::right_padding_from(b"MKR");
+        let token = decode_metadata(
+            TOKEN,
+            &results(ok(mkr.to_vec()), ok(U256::from(18).abi_encode())),
+        );
+        assert_eq!(token.ticker, "MKR");
+        assert!(!token.flags.unverified);
+    }
+
+    #[test]
+    fn token_without_decimals_is_unverified() {
+        let token = decode_metadata(TOKEN, &results(ok("ABC".abi_encode()), reverted()));
+        assert_eq!(token.decimals, None);
+        assert!(token.flags.unverified);
+        assert!(token.flags.blocked());
+
+        // fallback which returns nothing is not a symbol
+        let token = decode_metadata(TOKEN, &results(ok(vec![]), ok(U256::from(6).abi_encode())));
+        assert!(token.flags.unverified);
+    }
+
+    #[test]
+    fn flags_rebasing_and_blacklist_by_existing_getters() {
+        let mut results = results(ok("stETH".abi_encode()), ok(U256::from(18).abi_encode()));
+        results[4] = ok(U256::ZERO.abi_encode());
+        let token = decode_metadata(TOKEN, &results);
+        assert!(token.flags.rebasing);
+        assert!(!token.flags.blacklist);
+
+        results[4] = reverted();
+        results[6] = ok(false.abi_encode());
+        let token = decode_metadata(TOKEN, &results);
+        assert!(!token.flags.rebasing);
+        assert!(token.flags.blacklist);
+        // tokens which can freeze holders, like USDT, are still traded
+        assert!(!token.flags.blocked());
+    }
+}
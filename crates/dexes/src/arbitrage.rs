@@ -0,0 +1,223 @@
+use crate::{
+    common::{spawn_cpu, Arbitrage, Hop},
+    error::Result,
+};
+use alloy::primitives::{Address, U256};
+use hashbrown::{hash_map::Entry, HashMap};
+use kronos_db::{
+    graph::{Edge, Topology},
+    tables::Pair,
+    Inventory, TokenRegistry, DB,
+};
+use kronos_math::cycles::{cycle_paths, optimal_amount_in, CycleIndex, Path};
+use kronos_metrics::{self as metrics, prometheus::IntCounter};
+use rayon::prelude::*;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+use tracing::Span;
+
+/// Cycles of the dex whose pools are quoted by the adapter itself.
+/// Pools are only in memory, so their edges are added to the graph but not stored
+pub struct CycleSearch {
+    name: String,
+    dex_id: i32,
+    db: DB,
+    tokens: TokenRegistry,
+    inventory: Inventory,
+    cycles: Arc<Mutex<CycleIndex>>,
+}
+
+impl CycleSearch {
+    pub fn new(
+        name: &str,
+        dex_id: i32,
+        db: DB,
+        tokens: TokenRegistry,
+        inventory: Inventory,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            dex_id,
+            db,
+            tokens,
+            inventory,
+            cycles: Arc::new(Mutex::new(CycleIndex::new(dex_id))),
+        }
+    }
+
+    /// Adds edges of pools to the graph and indexes their cycles, returns number of all cycles
+    pub fn add_pairs(&self, pairs: &[Pair]) -> usize {
+        self.db.graph().add_pairs(pairs);
+        let mut cycles = self.cycles.lock().unwrap();
+        cycles.sync(&self.db.graph().snapshot().topology);
+        cycles.len()
+    }
+
+    /// Sizes cycles of `changed` pools on rayon pool. `quote` is built for pools of the cycles
+    /// by the adapter and owns copies of their states, so the adapter isn't locked while
+    /// cycles are sized
+    pub async fn find<Q>(
+        &self,
+        block_number: u64,
+        changed: &[Address],
+        quote: impl FnOnce(&HashSet<Address>) -> Q,
+    ) -> Result<Vec<Arbitrage>>
+    where
+        Q: Fn(&Edge, &Address, &Address, U256) -> Option<Hop> + Send + Sync + 'static,
+    {
+        if changed.is_empty() {
+            return Ok(vec![]);
+        }
+        let snapshot = self.db.graph().snapshot();
+        let cycles = self
+            .cycles
+            .lock()
+            .unwrap()
+            .affected(changed, &snapshot.topology);
+        let pools: HashSet<Address> = cycles
+            .iter()
+            .flat_map(|cycle| cycle.edges().to_vec())
+            .map(|edge| snapshot.topology.edges()[edge as usize].pair)
+            .collect();
+        let quote = quote(&pools);
+
+        let tokens = self.tokens.clone();
+        let inventory = self.inventory.clone();
+        let dex_id = self.dex_id;
+        let found = metrics::CANDIDATES_FOUND.with_label_values(&[&self.name]);
+        let sized = metrics::CANDIDATES_SIZED.with_label_values(&[&self.name]);
+        // rayon threads don't see the span of the block, so it is passed explicitly
+        let span = Span::current();
+        let best_arbitrages = spawn_cpu(move || {
+            let _span = tracing::info_span!(parent: &span, "size", cycles = cycles.len()).entered();
+            let paths = cycle_paths(cycles, &snapshot.topology, |token| {
+                tokens.is_tradable(token)
+            });
+            found.inc_by(paths.len() as u64);
+
+            let sizing = Sizing {
+                topology: &snapshot.topology,
+                tokens: &tokens,
+                inventory: &inventory,
+                dex_id,
+                block_number,
+            };
+            sizing.best_arbitrages(paths, &sized, quote)
+        })
+        .await?;
+
+        Ok(best_arbitrages.into_values().collect())
+    }
+}
+
+/// State of the block which cycles of the adapter are sized in
+pub struct Sizing<'a> {
+    pub topology: &'a Topology,
+    pub tokens: &'a TokenRegistry,
+    pub inventory: &'a Inventory,
+    pub dex_id: i32,
+    pub block_number: u64,
+}
+
+impl Sizing<'_> {
+    /// Sizes `paths` of pools which are quoted by the adapter and keeps the most profitable
+    /// cycle per start token. `quote` is the hop through the pool of the edge for the amount in,
+    /// `None` if the pool would revert. Quotes don't model transfer taxes, so paths with
+    /// taxed tokens are skipped. In inventory mode amount in is capped by the balance held
+    /// in the start token
+    pub fn best_arbitrages<Q>(
+        &self,
+        paths: Vec<Path>,
+        sized: &IntCounter,
+        quote: Q,
+    ) -> HashMap<Address, Arbitrage>
+    where
+        Q: Fn(&Edge, &Address, &Address, U256) -> Option<Hop> + Sync,
+    {
+        paths
+            .into_par_iter()
+            .filter_map(|path| {
+                let arbitrage = self.size(&path, &quote)?;
+                sized.inc();
+                Some(arbitrage)
+            })
+            .fold(HashMap::new, |mut best, arbitrage| {
+                keep_best(&mut best, arbitrage);
+                best
+            })
+            .reduce(HashMap::new, |mut best, other| {
+                for arbitrage in other.into_values() {
+                    keep_best(&mut best, arbitrage);
+                }
+                best
+            })
+    }
+
+    fn size<Q>(&self, path: &Path, quote: &Q) -> Option<Arbitrage>
+    where
+        Q: Fn(&Edge, &Address, &Address, U256) -> Option<Hop>,
+    {
+        let topology = self.topology;
+        let path: Vec<(&Edge, Address, Address)> = (0..path.len())
+            .map(|i| {
+                let (token_in, edge) = path[i];
+                let token_out = path[(i + 1) % path.len()].0;
+                (
+                    &topology.edges()[edge as usize],
+                    topology.token(token_in),
+                    topology.token(token_out),
+                )
+            })
+            .collect();
+        let start = path[0].1;
+        let taxed = path
+            .iter()
+            .any(|(_, token, _)| self.tokens.transfer_tax_bps(token).unwrap_or(0) > 0);
+        if taxed || !self.inventory.holds(&start) {
+            return None;
+        }
+
+        let swap = |amount_in: U256| {
+            let mut hops = Vec::with_capacity(path.len());
+            let mut amount = amount_in;
+            for (edge, token_in, token_out) in path.iter() {
+                let hop = quote(edge, token_in, token_out, amount)?;
+                amount = hop.amount_out;
+                hops.push(hop);
+            }
+            Some(hops)
+        };
+        let amount_out = |amount_in| Some(swap(amount_in)?.last()?.amount_out);
+        let (amount_in, revenue) =
+            optimal_amount_in(amount_out, self.inventory.max_amount_in(&start))?;
+
+        Some(Arbitrage {
+            dex_id: self.dex_id,
+            block_number: self.block_number,
+            amount_in,
+            revenue,
+            path: path
+                .iter()
+                .map(|(_, token_in, token_out)| (*token_in, *token_out))
+                .collect(),
+            hops: swap(amount_in)?,
+            span: Span::none(),
+        })
+    }
+}
+
+/// Keeps the arbitrage with the highest revenue per start token
+pub fn keep_best(best: &mut HashMap<Address, Arbitrage>, arbitrage: Arbitrage) {
+    match best.entry(arbitrage.path[0].0) {
+        Entry::Occupied(mut entry) => {
+            if entry.get().revenue < arbitrage.revenue {
+                *entry.get_mut() = arbitrage;
+            }
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(arbitrage);
+        }
+    }
+}
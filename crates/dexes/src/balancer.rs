@@ -0,0 +1,538 @@
+use crate::{
+    arbitrage::CycleSearch,
+    common::{call, decode, multicall, Arbitrage, DexHealth, Hop, Venue, DEX},
+    error::{DexError, Result},
+};
+use alloy::{
+    primitives::{address, Address, Bytes, Uint, B256, I256, U256},
+    providers::{Provider, RootProvider},
+    rpc::types::{Filter, Header, Log},
+    sol_types::SolEvent,
+};
+
+use ethereum_abi::{IBalancerVault, IMulticall3::Call, IWeightedPool};
+use hashbrown::HashMap;
+use kronos_common::Reserves;
+use kronos_config::DexConfig;
+use kronos_db::{tables::Pair, Inventory, TokenRegistry, DB};
+use kronos_math::weighted::WeightedPool;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, RwLock},
+};
+
+pub const KIND: &str = "balancer_v2";
+
+pub const VAULT: Address = address!("0xBA12222222228d8Ba445958a75a0704d566BF2C8");
+const VAULT_DEPLOY_BLOCK: u64 = 12272146;
+
+// Blocks per `eth_getLogs` request when pools are discovered
+const LOGS_CHUNK: u64 = 50_000;
+// Calls per pool: getPoolTokens, getNormalizedWeights, getSwapFeePercentage
+const POOL_CALLS: usize = 3;
+
+/// Weighted pool registered in the Vault, balances are stored in the Vault
+#[derive(Clone, Debug)]
+pub struct BalancerPool {
+    pub id: B256,
+    pub address: Address,
+    pub tokens: Vec<Address>,
+    pub state: WeightedPool,
+}
+
+impl BalancerPool {
+    pub fn token_index(&self, token: &Address) -> Option<usize> {
+        self.tokens.iter().position(|t| t == token)
+    }
+
+    pub fn swap_given_in(
+        &self,
+        token_in: &Address,
+        token_out: &Address,
+        amount_in: U256,
+    ) -> Option<U256> {
+        self.state.swap_given_in(
+            self.token_index(token_in)?,
+            self.token_index(token_out)?,
+            amount_in,
+        )
+    }
+
+    /// Swap of `amount_in` of `token_in` in the pool
+    pub fn hop(&self, token_in: &Address, token_out: &Address, amount_in: U256) -> Option<Hop> {
+        Some(Hop {
+            pool: self.address,
+            token_in: *token_in,
+            token_out: *token_out,
+            venue: Venue::Balancer { pool_id: self.id },
+            amount_out: self.swap_given_in(token_in, token_out, amount_in)?,
+        })
+    }
+
+    /// Edge of the graph for every pair of tokens
+    fn pairs(&self, dex_id: i32) -> Vec<Pair> {
+        let mut pairs = vec![];
+        for (i, token0) in self.tokens.iter().enumerate() {
+            for token1 in self.tokens[i + 1..].iter() {
+                pairs.push(Pair {
+                    address: self.address,
+                    dex_id,
+                    token0: *token0,
+                    token1: *token1,
+                });
+            }
+        }
+        pairs
+    }
+}
+
+/// `BalancerV2` indexes weighted pools of the Vault.
+/// Pools are discovered from `PoolRegistered` events, pools without normalized weights
+/// (stable, linear, composable) are skipped. Balances follow Vault `Swap`,
+/// `PoolBalanceChanged` and `PoolBalanceManaged` deltas and are re-read for pools touched
+/// in the block, so drift after a skipped block or the startup gap is corrected.
+/// Pool address is the first 20 bytes of its id, it is the pool of edges in the graph
+pub struct BalancerV2 {
+    name: String,
+    dex_id: i32,
+    tokens: TokenRegistry,
+    provider: Arc<RootProvider>,
+    pools: RwLock<HashMap<B256, BalancerPool>>,
+    search: CycleSearch,
+    events: Vec<B256>,
+    health: Mutex<DexHealth>,
+}
+
+impl BalancerV2 {
+    pub async fn new(
+        config: &DexConfig,
+        db: DB,
+        tokens: TokenRegistry,
+        inventory: Inventory,
+        provider: Arc<RootProvider>,
+    ) -> Result<Self> {
+        let dex_id = db.postgres().get_dex_id(&config.name).await?;
+
+        let balancer = Self {
+            name: config.name.clone(),
+            dex_id,
+            search: CycleSearch::new(&config.name, dex_id, db, tokens.clone(), inventory),
+            tokens,
+            provider,
+            pools: RwLock::new(HashMap::new()),
+            events: vec![
+                IBalancerVault::PoolRegistered::SIGNATURE_HASH,
+                IBalancerVault::Swap::SIGNATURE_HASH,
+                IBalancerVault::PoolBalanceChanged::SIGNATURE_HASH,
+                IBalancerVault::PoolBalanceManaged::SIGNATURE_HASH,
+            ],
+            health: Mutex::new(DexHealth::new(&config.name, dex_id)),
+        };
+        balancer.load_pools().await?;
+
+        Ok(balancer)
+    }
+
+    pub fn pool(&self, pool_id: &B256) -> Option<BalancerPool> {
+        self.pools.read().unwrap().get(pool_id).cloned()
+    }
+
+    /// Best output over all pools with both tokens: (pool id, amount_out)
+    pub fn quote(
+        &self,
+        token_in: &Address,
+        token_out: &Address,
+        amount_in: U256,
+    ) -> Option<(B256, U256)> {
+        self.pools
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(|pool| Some((pool.id, pool.swap_given_in(token_in, token_out, amount_in)?)))
+            .max_by_key(|(_, amount_out)| *amount_out)
+    }
+
+    async fn load_pools(&self) -> Result<()> {
+        let latest = self.provider.get_block_number().await?;
+
+        let mut registered = vec![];
+        let mut from = VAULT_DEPLOY_BLOCK;
+        while from <= latest {
+            let to = (from + LOGS_CHUNK - 1).min(latest);
+            let filter = Filter::new()
+                .address(VAULT)
+                .event_signature(IBalancerVault::PoolRegistered::SIGNATURE_HASH)
+                .from_block(from)
+                .to_block(to);
+            for log in self.provider.get_logs(&filter).await? {
+                let event = IBalancerVault::PoolRegistered::decode_log(&log.inner, false)?;
+                registered.push((event.poolId, event.poolAddress));
+            }
+            from = to + 1;
+        }
+
+        let pools = self.fetch_pools(&registered).await?;
+        let cycles = self.add_pools(&pools);
+        tracing::info!(
+            "⚖️ {} indexed {} of {} pools, {cycles} cycles",
+            self.name,
+            pools.len(),
+            registered.len()
+        );
+        self.pools
+            .write()
+            .unwrap()
+            .extend(pools.into_iter().map(|pool| (pool.id, pool)));
+        Ok(())
+    }
+
+    /// Reads tokens, balances, weights and fee of pools, non-weighted pools are dropped
+    async fn fetch_pools(&self, registered: &[(B256, Address)]) -> Result<Vec<BalancerPool>> {
+        let mut calls = Vec::with_capacity(registered.len() * POOL_CALLS);
+        for (pool_id, address) in registered {
+            calls.push(call(
+                VAULT,
+                IBalancerVault::getPoolTokensCall { poolId: *pool_id },
+            ));
+            calls.push(call(*address, IWeightedPool::getNormalizedWeightsCall {}));
+            calls.push(call(*address, IWeightedPool::getSwapFeePercentageCall {}));
+        }
+        let results = self.multicall(calls).await?;
+
+        let mut pools: Vec<BalancerPool> = registered
+            .iter()
+            .zip(results.chunks(POOL_CALLS))
+            .filter_map(|((id, address), data)| {
+                let pool_tokens = decode::<IBalancerVault::getPoolTokensCall>(&data[0])?;
+                let weights = decode::<IWeightedPool::getNormalizedWeightsCall>(&data[1])?._0;
+                let swap_fee = decode::<IWeightedPool::getSwapFeePercentageCall>(&data[2])?._0;
+                // pools which hold their own BPT are not plain weighted pools
+                if pool_tokens.tokens.len() != weights.len() || pool_tokens.tokens.contains(address)
+                {
+                    return None;
+                }
+
+                Some(BalancerPool {
+                    id: *id,
+                    address: *address,
+                    tokens: pool_tokens.tokens,
+                    state: WeightedPool {
+                        balances: pool_tokens.balances,
+                        weights,
+                        scaling_factors: vec![],
+                        swap_fee,
+                    },
+                })
+            })
+            .collect();
+
+        let tokens: Vec<Address> = pools.iter().flat_map(|pool| pool.tokens.clone()).collect();
+        self.tokens.ensure_tokens(&tokens).await?;
+
+        pools.retain_mut(|pool| {
+            let Some(scaling_factors) = pool
+                .tokens
+                .iter()
+                .map(|token| {
+                    let decimals = self.tokens.decimals(token)?;
+                    Some(U256::from(10).pow(U256::from(18u8.checked_sub(decimals)?)))
+                })
+                .collect::<Option<Vec<U256>>>()
+            else {
+                return false;
+            };
+            pool.state.scaling_factors = scaling_factors;
+            true
+        });
+
+        Ok(pools)
+    }
+
+    fn add_pools(&self, pools: &[BalancerPool]) -> usize {
+        let pairs: Vec<Pair> = pools
+            .iter()
+            .flat_map(|pool| pool.pairs(self.dex_id))
+            .collect();
+        self.search.add_pairs(&pairs)
+    }
+
+    async fn handle_block(&self, block: Header) -> Result<Vec<Arbitrage>> {
+        // balances are changed only by events of the Vault, fees by events of pools.
+        // Fee changes are rare, so they are requested by the event for all contracts
+        // instead of the growing list of pool addresses
+        let vault_filter = Filter::new()
+            .address(VAULT)
+            .event_signature(self.events.clone())
+            .from_block(block.number)
+            .to_block(block.number);
+        let fee_filter = Filter::new()
+            .event_signature(IWeightedPool::SwapFeePercentageChanged::SIGNATURE_HASH)
+            .from_block(block.number)
+            .to_block(block.number);
+
+        let mut logs = self.provider.get_logs(&vault_filter).await?;
+        logs.extend(self.provider.get_logs(&fee_filter).await?);
+
+        let mut registered = vec![];
+        let mut touched = HashSet::new();
+        for log in logs.iter() {
+            if log.topic0() == Some(&IBalancerVault::PoolRegistered::SIGNATURE_HASH) {
+                let event = IBalancerVault::PoolRegistered::decode_log(&log.inner, false)?;
+                registered.push((event.poolId, event.poolAddress));
+            } else if log.address() == VAULT {
+                // pool id is the first indexed topic of every balance event
+                touched.extend(log.topics().get(1).copied());
+            }
+        }
+
+        // new pools and balances are read after the block, so events of this block are
+        // already included. All requests are done before deltas are applied,
+        // so the failed block can be retried
+        let new_pools = match registered.is_empty() {
+            true => vec![],
+            false => self.fetch_pools(&registered).await?,
+        };
+        let balances = self.fetch_balances(&touched).await?;
+
+        let mut changed = HashSet::new();
+        for log in logs.iter() {
+            if log.topic0() != Some(&IBalancerVault::PoolRegistered::SIGNATURE_HASH) {
+                changed.extend(self.apply_log(log)?);
+            }
+        }
+        self.refresh_balances(balances);
+        if !new_pools.is_empty() {
+            self.add_pools(&new_pools);
+            changed.extend(new_pools.iter().map(|pool| pool.address));
+        }
+        self.pools
+            .write()
+            .unwrap()
+            .extend(new_pools.into_iter().map(|pool| (pool.id, pool)));
+
+        let changed: Vec<Address> = changed.into_iter().collect();
+        self.search
+            .find(block.number, &changed, |pools| {
+                let pools: HashMap<Address, BalancerPool> = self
+                    .pools
+                    .read()
+                    .unwrap()
+                    .values()
+                    .filter(|pool| pools.contains(&pool.address))
+                    .map(|pool| (pool.address, pool.clone()))
+                    .collect();
+                move |edge, token_in, token_out, amount_in| {
+                    pools.get(&edge.pair)?.hop(token_in, token_out, amount_in)
+                }
+            })
+            .await
+    }
+
+    /// Reads balances of indexed pools from the Vault, `None` if the read failed
+    async fn fetch_balances(
+        &self,
+        pool_ids: &HashSet<B256>,
+    ) -> Result<Vec<(B256, Option<Vec<U256>>)>> {
+        let pool_ids: Vec<B256> = {
+            let pools = self.pools.read().unwrap();
+            pool_ids
+                .iter()
+                .filter(|id| pools.contains_key(*id))
+                .copied()
+                .collect()
+        };
+        if pool_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let calls = pool_ids
+            .iter()
+            .map(|pool_id| {
+                call(
+                    VAULT,
+                    IBalancerVault::getPoolTokensCall { poolId: *pool_id },
+                )
+            })
+            .collect();
+        let results = self.multicall(calls).await?;
+        Ok(pool_ids
+            .into_iter()
+            .zip(results.iter())
+            .map(|(pool_id, data)| {
+                let balances = decode::<IBalancerVault::getPoolTokensCall>(data)
+                    .map(|pool_tokens| pool_tokens.balances);
+                (pool_id, balances)
+            })
+            .collect())
+    }
+
+    /// Replaces balances which were tracked by deltas with the read ones.
+    /// Pool keeps its tracked balances if the read failed
+    fn refresh_balances(&self, balances: Vec<(B256, Option<Vec<U256>>)>) {
+        let mut pools = self.pools.write().unwrap();
+        for (pool_id, balances) in balances {
+            let Some(pool) = pools.get_mut(&pool_id) else {
+                continue;
+            };
+            match balances {
+                Some(balances) if balances.len() == pool.tokens.len() => {
+                    pool.state.balances = balances
+                }
+                _ => tracing::warn!("failed to fetch balances of {}", pool.address),
+            }
+        }
+    }
+
+    /// Applies the event to the pool, returns address of the changed pool
+    fn apply_log(&self, log: &Log) -> Result<Option<Address>> {
+        let mut pools = self.pools.write().unwrap();
+        match log.topic0() {
+            Some(&IBalancerVault::Swap::SIGNATURE_HASH) => {
+                let swap = IBalancerVault::Swap::decode_log(&log.inner, false)?;
+                let Some(pool) = pools.get_mut(&swap.poolId) else {
+                    return Ok(None);
+                };
+                if let Some(i) = pool.token_index(&swap.tokenIn) {
+                    pool.state.balances[i] += swap.amountIn;
+                }
+                if let Some(j) = pool.token_index(&swap.tokenOut) {
+                    pool.state.balances[j] = pool.state.balances[j].saturating_sub(swap.amountOut);
+                }
+                Ok(Some(pool.address))
+            }
+            Some(&IBalancerVault::PoolBalanceChanged::SIGNATURE_HASH) => {
+                let change = IBalancerVault::PoolBalanceChanged::decode_log(&log.inner, false)?;
+                let Some(pool) = pools.get_mut(&change.poolId) else {
+                    return Ok(None);
+                };
+                // joins have positive deltas, exits negative, protocol fees leave the pool
+                for ((token, delta), fee) in change
+                    .tokens
+                    .iter()
+                    .zip(change.deltas.iter())
+                    .zip(change.protocolFeeAmounts.iter())
+                {
+                    if let Some(i) = pool.token_index(token) {
+                        let balance = apply_delta(pool.state.balances[i], *delta);
+                        pool.state.balances[i] = balance.saturating_sub(*fee);
+                    }
+                }
+                Ok(Some(pool.address))
+            }
+            Some(&IBalancerVault::PoolBalanceManaged::SIGNATURE_HASH) => {
+                let managed = IBalancerVault::PoolBalanceManaged::decode_log(&log.inner, false)?;
+                let Some(pool) = pools.get_mut(&managed.poolId) else {
+                    return Ok(None);
+                };
+                // total balance is cash + managed
+                if let Some(i) = pool.token_index(&managed.token) {
+                    let balance = apply_delta(pool.state.balances[i], managed.cashDelta);
+                    pool.state.balances[i] = apply_delta(balance, managed.managedDelta);
+                }
+                Ok(Some(pool.address))
+            }
+            Some(&IWeightedPool::SwapFeePercentageChanged::SIGNATURE_HASH) => {
+                let fee = IWeightedPool::SwapFeePercentageChanged::decode_log(&log.inner, false)?;
+                let Some(pool) = pools
+                    .values_mut()
+                    .find(|pool| pool.address == log.address())
+                else {
+                    return Ok(None);
+                };
+                pool.state.swap_fee = fee.swapFeePercentage;
+                Ok(Some(pool.address))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn multicall(&self, calls: Vec<Call>) -> Result<Vec<Option<Bytes>>> {
+        multicall(&self.provider, calls).await
+    }
+}
+
+fn apply_delta(balance: U256, delta: I256) -> U256 {
+    match delta.is_negative() {
+        true => balance.saturating_sub(delta.unsigned_abs()),
+        false => balance.saturating_add(delta.into_raw()),
+    }
+}
+
+#[async_trait::async_trait]
+impl DEX for BalancerV2 {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn dex_id(&self) -> i32 {
+        self.dex_id
+    }
+
+    fn health(&self) -> DexHealth {
+        self.health.lock().unwrap().clone()
+    }
+
+    async fn process_block(&self, block: Header) -> Result<Vec<Arbitrage>> {
+        let block_number = block.number;
+        let result = self.handle_block(block).await;
+        self.health.lock().unwrap().record(block_number, &result);
+        result
+    }
+
+    async fn adjacent(&self, token: &Address) -> Result<HashSet<Address>> {
+        Ok(self
+            .pools
+            .read()
+            .unwrap()
+            .values()
+            .filter(|pool| pool.token_index(token).is_some())
+            .flat_map(|pool| pool.tokens.iter().copied())
+            .filter(|t| t != token)
+            .collect())
+    }
+
+    /// Balances of the first two tokens of the pool with this address
+    async fn fetch_reserves(&self, pair_adr: &Address) -> Result<Reserves> {
+        let pools = self.pools.read().unwrap();
+        let pool = pools
+            .values()
+            .find(|pool| pool.address == *pair_adr)
+            .ok_or(DexError::not_found(format!("balancer pool {pair_adr:?}")))?;
+        Ok(Reserves(
+            Uint::saturating_from(pool.state.balances[0]),
+            Uint::saturating_from(pool.state.balances[1]),
+        ))
+    }
+
+    async fn owns_pair(&self, pair_adr: &Address) -> Result<bool> {
+        Ok(self
+            .pools
+            .read()
+            .unwrap()
+            .values()
+            .any(|pool| pool.address == *pair_adr))
+    }
+
+    /// Balances of tokens in the deepest pool which has both of them
+    async fn token_reserves(&self, token0: &Address, token1: &Address) -> Result<Reserves> {
+        self.pools
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(|pool| {
+                let balance0 = pool.state.balances[pool.token_index(token0)?];
+                let balance1 = pool.state.balances[pool.token_index(token1)?];
+                Some((balance0, balance1))
+            })
+            .max_by_key(|(balance0, balance1)| balance0.saturating_add(*balance1))
+            .map(|(balance0, balance1)| {
+                Reserves(
+                    Uint::saturating_from(balance0),
+                    Uint::saturating_from(balance1),
+                )
+            })
+            .ok_or(DexError::not_found(format!(
+                "balancer pool for {token0:?}, {token1:?}"
+            )))
+    }
+}
@@ -1,17 +1,27 @@
+use crate::error::{DexError, Result};
 use alloy::{
-    primitives::{Address, Uint},
+    eips::BlockId,
+    primitives::{address, keccak256, Address, Bytes, Uint, B256, U256},
+    providers::RootProvider,
     rpc::types::Header,
+    sol_types::SolCall,
 };
-use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ethereum_abi::IMulticall3::{self, Call};
 use kronos_common::Reserves;
-use std::collections::HashSet;
+use kronos_db::tables::DexStatus;
+use std::{collections::HashSet, sync::Arc};
 
 #[async_trait::async_trait]
 pub trait DEX: Send + Sync {
-    // DEX logic for new block
-    // async fn on_block(&self, header: Header) -> Result<()>;
+    fn name(&self) -> &str;
 
-    async fn process_block(&self, block: Header) -> Result<()>;
+    fn dex_id(&self) -> i32;
+
+    fn health(&self) -> DexHealth;
+
+    // DEX logic for new block: updates reserves and returns found arbitrages
+    async fn process_block(&self, block: Header) -> Result<Vec<Arbitrage>>;
 
     async fn fetch_reserves(&self, pair_adr: &Address) -> Result<Reserves>;
 
@@ -26,16 +36,204 @@ pub trait DEX: Send + Sync {
     async fn token_reserves(&self, token0: &Address, token1: &Address) -> Result<Reserves>;
 }
 
+/// Runs CPU bound work on rayon pool and awaits its result,
+/// so tokio workers are not blocked by arbitrage search
+pub async fn spawn_cpu<F, T>(f: F) -> Result<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    rayon::spawn(move || {
+        let _ = tx.send(f());
+    });
+    rx.await
+        .map_err(|_| DexError::invariant("cpu task is dropped without result"))
+}
+
+pub const MULTICALL3: Address = address!("0xcA11bde05977b3631167028862bE2a173976CA11");
+
+const CALLS_PER_MULTICALL: usize = 500;
+
+/// Executes calls with `tryAggregate` in chunks, failed calls are `None`
+pub async fn multicall(
+    provider: &Arc<RootProvider>,
+    calls: Vec<Call>,
+) -> Result<Vec<Option<Bytes>>> {
+    multicall_at(provider, calls, BlockId::latest()).await
+}
+
+/// `multicall` on the state of `block`, all chunks read the same state
+pub async fn multicall_at(
+    provider: &Arc<RootProvider>,
+    calls: Vec<Call>,
+    block: BlockId,
+) -> Result<Vec<Option<Bytes>>> {
+    let multicall = IMulticall3::new(MULTICALL3, provider.clone());
+
+    let mut results = Vec::with_capacity(calls.len());
+    for chunk in calls.chunks(CALLS_PER_MULTICALL) {
+        let data = multicall
+            .tryAggregate(false, chunk.to_vec())
+            .block(block)
+            .call()
+            .await?
+            .returnData;
+        results.extend(
+            data.into_iter()
+                .map(|result| result.success.then_some(result.returnData)),
+        );
+    }
+
+    Ok(results)
+}
+
+pub fn call<C: SolCall>(target: Address, call: C) -> Call {
+    Call {
+        target,
+        callData: call.abi_encode().into(),
+    }
+}
+
+pub fn decode<C: SolCall>(data: &Option<Bytes>) -> Option<C::Return> {
+    C::abi_decode_returns(data.as_ref()?, false).ok()
+}
+
+/// State of the dex adapter after the last processed block
+#[derive(Clone, Debug)]
+pub struct DexHealth {
+    pub name: String,
+    pub dex_id: i32,
+    pub last_block: u64,
+    /// Last block which was processed without error
+    pub last_success_block: u64,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    /// When the last block was processed, creation of the adapter before the first block
+    pub processed_at: DateTime<Utc>,
+}
+
+impl DexHealth {
+    pub fn new(name: &str, dex_id: i32) -> Self {
+        Self {
+            name: name.to_string(),
+            dex_id,
+            last_block: 0,
+            last_success_block: 0,
+            consecutive_failures: 0,
+            last_error: None,
+            processed_at: Utc::now(),
+        }
+    }
+
+    /// Snapshot which is shared with other services through redis
+    pub fn status(&self) -> DexStatus {
+        DexStatus {
+            name: self.name.clone(),
+            dex_id: self.dex_id,
+            last_block: self.last_block,
+            last_success_block: self.last_success_block,
+            consecutive_failures: self.consecutive_failures,
+            last_error: self.last_error.clone(),
+            updated_at: self.processed_at,
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.consecutive_failures == 0
+    }
+
+    pub fn record<T, E: std::fmt::Display>(&mut self, block_number: u64, result: &Result<T, E>) {
+        self.last_block = block_number;
+        self.processed_at = Utc::now();
+        match result {
+            Ok(_) => {
+                self.last_success_block = block_number;
+                self.consecutive_failures = 0;
+            }
+            Err(err) => {
+                self.consecutive_failures += 1;
+                self.last_error = Some(err.to_string());
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct AddressBook {
     pub factory: Address,
-    pub router: Address,
+}
+
+/// How the hop is executed on its pool
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Venue {
+    /// Uniswap V2 compatible pair, input is transferred to the pair before `swap`
+    UniswapV2,
+    /// `exchange` of coin `i` to coin `j` of Curve pool
+    Curve { i: i128, j: i128 },
+    /// Swap of Balancer V2 pool in the Vault
+    Balancer { pool_id: B256 },
+    /// Swap of Uniswap V4 pool with the key, settled in `unlockCallback` of the bot
+    UniswapV4 {
+        currency0: Address,
+        currency1: Address,
+        fee: u32,
+        tick_spacing: i32,
+        hooks: Address,
+    },
+}
+
+/// One swap of the arbitrage with the output expected in the block of the arbitrage
+#[derive(Clone, Debug)]
+pub struct Hop {
+    pub pool: Address,
+    pub token_in: Address,
+    pub token_out: Address,
+    pub venue: Venue,
+    pub amount_out: U256,
 }
 
 #[derive(Debug)]
 pub struct Arbitrage {
     pub dex_id: i32,
+    pub block_number: u64,
     pub amount_in: Uint<256, 4>,
     pub revenue: Uint<256, 4>,
     pub path: Vec<(Address, Address)>,
+    /// Swaps of `path` for `amount_in`, hop `i` trades `path[i]`
+    pub hops: Vec<Hop>,
+    /// Span of the block processing by the adapter, set by the registry.
+    /// Spans of the executor are its children, so the block is traced end to end
+    pub span: tracing::Span,
+}
+
+impl Arbitrage {
+    /// Short id of the cycle on the dex, it is the same in logs of the adapter and the executor
+    pub fn path_id(&self) -> String {
+        let mut data = self.dex_id.to_be_bytes().to_vec();
+        for (token, _) in self.path.iter() {
+            data.extend_from_slice(token.as_slice());
+        }
+        alloy::hex::encode(&keccak256(data)[..4])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn status_reports_time_of_the_processed_block() {
+        let mut health = DexHealth::new("dex", 1);
+        let processed_at = Utc::now() - Duration::minutes(5);
+        health.processed_at = processed_at;
+        // adapter which stopped processing blocks stays stale in every snapshot
+        assert_eq!(health.status().updated_at, processed_at);
+
+        health.record::<(), String>(10, &Err("failed".to_string()));
+        let status = health.status();
+        assert!(status.updated_at > processed_at);
+        assert_eq!((status.last_block, status.consecutive_failures), (10, 1));
+    }
 }
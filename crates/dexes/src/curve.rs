@@ -0,0 +1,599 @@
+use crate::{
+    arbitrage::CycleSearch,
+    common::{call, decode, multicall, Arbitrage, DexHealth, Hop, Venue, DEX},
+    error::{DexError, Result},
+};
+use alloy::{
+    primitives::{address, Address, Bytes, Uint, B256, U256},
+    providers::{Provider, RootProvider},
+    rpc::types::{Filter, Header},
+    sol_types::SolEvent,
+};
+
+use ethereum_abi::{ICurvePool, ICurvePool2, ICurvePool3, ICurveRegistry, IMulticall3::Call};
+use hashbrown::HashMap;
+use kronos_common::Reserves;
+use kronos_config::DexConfig;
+use kronos_db::{tables::Pair, Inventory, TokenRegistry, DB};
+use kronos_math::stableswap::{AmpRamp, StableSwapPool, Template};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, RwLock},
+};
+
+pub const KIND: &str = "curve";
+
+const MAIN_REGISTRY: Address = address!("0x90E00ACe148ca3b23Ac1bC8C240C2a7Dd9c2d7f5");
+
+// Calls per pool for registry data: n_coins, coins, decimals, is_meta, base_pool
+const INFO_CALLS: usize = 5;
+// Calls per pool after balances: A_precise, initial_A, future_A, initial_A_time, future_A_time, fee
+const PARAMS_CALLS: usize = 6;
+
+/// Curve pool indexed from the registry. For metapool the last coin is LP token of base pool
+#[derive(Clone, Debug)]
+pub struct CurvePool {
+    pub address: Address,
+    pub coins: Vec<Address>,
+    pub base_pool: Option<Address>,
+    pub ramp: AmpRamp,
+    pub state: StableSwapPool,
+}
+
+impl CurvePool {
+    pub fn coin_index(&self, token: &Address) -> Option<usize> {
+        self.coins.iter().position(|coin| coin == token)
+    }
+
+    pub fn get_dy(&self, token_in: &Address, token_out: &Address, dx: U256) -> Option<U256> {
+        self.state
+            .get_dy(self.coin_index(token_in)?, self.coin_index(token_out)?, dx)
+    }
+
+    /// `exchange` of `dx` of `token_in` in the pool
+    pub fn hop(&self, token_in: &Address, token_out: &Address, dx: U256) -> Option<Hop> {
+        let (i, j) = (self.coin_index(token_in)?, self.coin_index(token_out)?);
+        Some(Hop {
+            pool: self.address,
+            token_in: *token_in,
+            token_out: *token_out,
+            venue: Venue::Curve {
+                i: i as i128,
+                j: j as i128,
+            },
+            amount_out: self.state.get_dy(i, j, dx)?,
+        })
+    }
+
+    /// Edge of the graph for every pair of coins
+    fn pairs(&self, dex_id: i32) -> Vec<Pair> {
+        let mut pairs = vec![];
+        for (i, token0) in self.coins.iter().enumerate() {
+            for token1 in self.coins[i + 1..].iter() {
+                pairs.push(Pair {
+                    address: self.address,
+                    dex_id,
+                    token0: *token0,
+                    token1: *token1,
+                });
+            }
+        }
+        pairs
+    }
+}
+
+// Pool data from the registry
+struct PoolInfo {
+    address: Address,
+    coins: Vec<Address>,
+    decimals: Vec<u8>,
+    base_pool: Option<Address>,
+}
+
+/// `Curve` indexes plain and meta pools of the main registry.
+/// Balances are re-read for pools which emitted exchange or liquidity events in the block,
+/// `A` ramps and fees are tracked from events, quotes use exact StableSwap math.
+/// Pools whose `get_dy` does not match on-chain quote on load (lending pools) are skipped.
+/// Every pair of coins of the pool is an edge of the graph, so cycles go through two pools
+/// of the same pair or three pools of the dex
+pub struct Curve {
+    name: String,
+    dex_id: i32,
+    tokens: TokenRegistry,
+    provider: Arc<RootProvider>,
+    pools: RwLock<HashMap<Address, CurvePool>>,
+    search: CycleSearch,
+    events: Vec<B256>,
+    health: Mutex<DexHealth>,
+}
+
+impl Curve {
+    pub async fn new(
+        config: &DexConfig,
+        db: DB,
+        tokens: TokenRegistry,
+        inventory: Inventory,
+        provider: Arc<RootProvider>,
+    ) -> Result<Self> {
+        let dex_id = db.postgres().get_dex_id(&config.name).await?;
+
+        let curve = Self {
+            name: config.name.clone(),
+            dex_id,
+            search: CycleSearch::new(&config.name, dex_id, db, tokens.clone(), inventory),
+            tokens,
+            provider,
+            pools: RwLock::new(HashMap::new()),
+            events: vec![
+                ICurvePool::TokenExchange::SIGNATURE_HASH,
+                ICurvePool::TokenExchangeUnderlying::SIGNATURE_HASH,
+                ICurvePool::RampA::SIGNATURE_HASH,
+                ICurvePool::StopRampA::SIGNATURE_HASH,
+                ICurvePool::NewFee::SIGNATURE_HASH,
+                ICurvePool::ApplyNewFee::SIGNATURE_HASH,
+                ICurvePool2::AddLiquidity::SIGNATURE_HASH,
+                ICurvePool2::RemoveLiquidity::SIGNATURE_HASH,
+                ICurvePool2::RemoveLiquidityOne::SIGNATURE_HASH,
+                ICurvePool2::RemoveLiquidityImbalance::SIGNATURE_HASH,
+                ICurvePool3::AddLiquidity::SIGNATURE_HASH,
+                ICurvePool3::RemoveLiquidity::SIGNATURE_HASH,
+                ICurvePool3::RemoveLiquidityOne::SIGNATURE_HASH,
+                ICurvePool3::RemoveLiquidityImbalance::SIGNATURE_HASH,
+            ],
+            health: Mutex::new(DexHealth::new(&config.name, dex_id)),
+        };
+        curve.load_pools().await?;
+
+        Ok(curve)
+    }
+
+    pub fn pool(&self, pool: &Address) -> Option<CurvePool> {
+        self.pools.read().unwrap().get(pool).cloned()
+    }
+
+    /// Best output over all pools with both tokens: (pool, amount_out)
+    pub fn quote(
+        &self,
+        token_in: &Address,
+        token_out: &Address,
+        amount_in: U256,
+    ) -> Option<(Address, U256)> {
+        self.pools
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(|pool| Some((pool.address, pool.get_dy(token_in, token_out, amount_in)?)))
+            .max_by_key(|(_, amount_out)| *amount_out)
+    }
+
+    async fn load_pools(&self) -> Result<()> {
+        let registry = ICurveRegistry::new(MAIN_REGISTRY, self.provider.clone());
+        let pool_count: usize = registry.pool_count().call().await?._0.to();
+
+        let calls = (0..pool_count)
+            .map(|i| {
+                call(
+                    MAIN_REGISTRY,
+                    ICurveRegistry::pool_listCall { i: U256::from(i) },
+                )
+            })
+            .collect();
+        let addresses: Vec<Address> = self
+            .multicall(calls)
+            .await?
+            .iter()
+            .filter_map(|data| decode::<ICurveRegistry::pool_listCall>(data).map(|r| r._0))
+            .collect();
+
+        let infos = self.fetch_infos(&addresses).await?;
+        let coins: Vec<Address> = infos.iter().flat_map(|info| info.coins.clone()).collect();
+        self.tokens.ensure_tokens(&coins).await?;
+
+        let mut pools = self.fetch_pools(infos).await?;
+        let verified = self.verify(&pools).await?;
+        pools.retain(|pool| verified.contains(&pool.address));
+
+        let pairs: Vec<Pair> = pools
+            .iter()
+            .flat_map(|pool| pool.pairs(self.dex_id))
+            .collect();
+        let cycles = self.search.add_pairs(&pairs);
+
+        tracing::info!(
+            "🌀 {} indexed {} of {pool_count} pools, {cycles} cycles",
+            self.name,
+            pools.len(),
+        );
+        *self.pools.write().unwrap() = pools.into_iter().map(|pool| (pool.address, pool)).collect();
+        Ok(())
+    }
+
+    async fn fetch_infos(&self, addresses: &[Address]) -> Result<Vec<PoolInfo>> {
+        let mut calls = Vec::with_capacity(addresses.len() * INFO_CALLS);
+        for pool in addresses {
+            let pool = *pool;
+            calls.push(call(
+                MAIN_REGISTRY,
+                ICurveRegistry::get_n_coinsCall { pool },
+            ));
+            calls.push(call(MAIN_REGISTRY, ICurveRegistry::get_coinsCall { pool }));
+            calls.push(call(
+                MAIN_REGISTRY,
+                ICurveRegistry::get_decimalsCall { pool },
+            ));
+            calls.push(call(MAIN_REGISTRY, ICurveRegistry::is_metaCall { pool }));
+            calls.push(call(pool, ICurvePool::base_poolCall {}));
+        }
+        let results = self.multicall(calls).await?;
+
+        Ok(addresses
+            .iter()
+            .zip(results.chunks(INFO_CALLS))
+            .filter_map(|(address, data)| {
+                let n: usize = decode::<ICurveRegistry::get_n_coinsCall>(&data[0])?._0[0].to();
+                if !(2..=8).contains(&n) {
+                    return None;
+                }
+                let coins = decode::<ICurveRegistry::get_coinsCall>(&data[1])?._0;
+                let decimals = decode::<ICurveRegistry::get_decimalsCall>(&data[2])?._0;
+                let is_meta = decode::<ICurveRegistry::is_metaCall>(&data[3])?._0;
+                let base_pool = match is_meta {
+                    true => Some(decode::<ICurvePool::base_poolCall>(&data[4])?._0),
+                    false => None,
+                };
+
+                Some(PoolInfo {
+                    address: *address,
+                    coins: coins[..n].to_vec(),
+                    decimals: decimals[..n].iter().map(|d| d.to()).collect(),
+                    base_pool,
+                })
+            })
+            .collect())
+    }
+
+    async fn fetch_pools(&self, infos: Vec<PoolInfo>) -> Result<Vec<CurvePool>> {
+        let mut calls = vec![];
+        for info in infos.iter() {
+            let pool = info.address;
+            calls.extend(balances_calls(&pool, info.coins.len()));
+            calls.push(call(pool, ICurvePool::A_preciseCall {}));
+            calls.push(call(pool, ICurvePool::initial_ACall {}));
+            calls.push(call(pool, ICurvePool::future_ACall {}));
+            calls.push(call(pool, ICurvePool::initial_A_timeCall {}));
+            calls.push(call(pool, ICurvePool::future_A_timeCall {}));
+            calls.push(call(pool, ICurvePool::feeCall {}));
+            if let Some(base_pool) = info.base_pool {
+                calls.push(call(base_pool, ICurvePool::get_virtual_priceCall {}));
+            }
+        }
+        let results = self.multicall(calls).await?;
+
+        let mut pools = vec![];
+        let mut offset = 0;
+        for info in infos {
+            let n = info.coins.len();
+            let len = n + PARAMS_CALLS + info.base_pool.is_some() as usize;
+            let data = &results[offset..offset + len];
+            offset += len;
+
+            let Some(pool) = parse_pool(info, data) else {
+                continue;
+            };
+            pools.push(pool);
+        }
+
+        Ok(pools)
+    }
+
+    /// Compares `get_dy` of one whole coin 0 -> 1 with on-chain quote,
+    /// returns pools where they are equal
+    async fn verify(&self, pools: &[CurvePool]) -> Result<HashSet<Address>> {
+        let amounts: Vec<U256> = pools
+            .iter()
+            .map(|pool| {
+                let decimals = self.tokens.decimals(&pool.coins[0]).unwrap_or(18);
+                U256::from(10).pow(U256::from(decimals))
+            })
+            .collect();
+        let calls = pools
+            .iter()
+            .zip(amounts.iter())
+            .map(|(pool, dx)| {
+                call(
+                    pool.address,
+                    ICurvePool::get_dyCall {
+                        i: 0,
+                        j: 1,
+                        dx: *dx,
+                    },
+                )
+            })
+            .collect();
+        let results = self.multicall(calls).await?;
+
+        Ok(pools
+            .iter()
+            .zip(amounts)
+            .zip(results)
+            .filter(|((pool, dx), data)| {
+                let onchain = decode::<ICurvePool::get_dyCall>(data).map(|r| r._0);
+                onchain.is_some() && onchain == pool.state.get_dy(0, 1, *dx)
+            })
+            .map(|((pool, _), _)| pool.address)
+            .collect())
+    }
+
+    async fn handle_block(&self, block: Header) -> Result<Vec<Arbitrage>> {
+        let addresses: Vec<Address> = self.pools.read().unwrap().keys().copied().collect();
+        let filter = Filter::new()
+            .address(addresses)
+            .event_signature(self.events.clone())
+            .from_block(block.number)
+            .to_block(block.number);
+
+        let mut dirty = HashSet::new();
+        // pools whose quotes changed without balances
+        let mut changed = HashSet::new();
+        for log in self.provider.get_logs(&filter).await? {
+            let pool = log.address();
+            let mut pools = self.pools.write().unwrap();
+            let Some(state) = pools.get_mut(&pool) else {
+                continue;
+            };
+
+            match log.topic0() {
+                Some(&ICurvePool::RampA::SIGNATURE_HASH) => {
+                    let ramp = ICurvePool::RampA::decode_log(&log.inner, false)?;
+                    state.ramp = AmpRamp {
+                        initial_a: ramp.old_A,
+                        future_a: ramp.new_A,
+                        initial_time: ramp.initial_time.to(),
+                        future_time: ramp.future_time.to(),
+                    };
+                }
+                Some(&ICurvePool::StopRampA::SIGNATURE_HASH) => {
+                    let stop = ICurvePool::StopRampA::decode_log(&log.inner, false)?;
+                    state.ramp = AmpRamp {
+                        initial_a: stop.A,
+                        future_a: stop.A,
+                        initial_time: stop.t.to(),
+                        future_time: stop.t.to(),
+                    };
+                }
+                Some(&ICurvePool::NewFee::SIGNATURE_HASH) => {
+                    state.state.fee = ICurvePool::NewFee::decode_log(&log.inner, false)?.fee;
+                }
+                Some(&ICurvePool::ApplyNewFee::SIGNATURE_HASH) => {
+                    state.state.fee = ICurvePool::ApplyNewFee::decode_log(&log.inner, false)?.fee;
+                }
+                // exchanges and liquidity changes
+                _ => {
+                    dirty.insert(pool);
+                    continue;
+                }
+            }
+            changed.insert(pool);
+        }
+
+        self.refresh_balances(&dirty).await?;
+
+        // `A` changes every block while it is ramped
+        for pool in self.pools.write().unwrap().values_mut() {
+            let amp = pool.ramp.a(block.timestamp);
+            if amp != pool.state.amp {
+                pool.state.amp = amp;
+                changed.insert(pool.address);
+            }
+            // metapools are repriced by virtual price of the base pool
+            if dirty.contains(&pool.address)
+                || pool.base_pool.is_some_and(|base| dirty.contains(&base))
+            {
+                changed.insert(pool.address);
+            }
+        }
+
+        let changed: Vec<Address> = changed.into_iter().collect();
+        self.search
+            .find(block.number, &changed, |pools| {
+                let state = self.pools.read().unwrap();
+                let pools: HashMap<Address, CurvePool> = pools
+                    .iter()
+                    .filter_map(|pool| Some((*pool, state.get(pool)?.clone())))
+                    .collect();
+                move |edge, token_in, token_out, amount_in| {
+                    pools.get(&edge.pair)?.hop(token_in, token_out, amount_in)
+                }
+            })
+            .await
+    }
+
+    /// Re-reads balances of changed pools and virtual price for their metapools
+    async fn refresh_balances(&self, dirty: &HashSet<Address>) -> Result<()> {
+        let pools: Vec<CurvePool> = self
+            .pools
+            .read()
+            .unwrap()
+            .values()
+            .filter(|pool| {
+                dirty.contains(&pool.address)
+                    || pool.base_pool.is_some_and(|base| dirty.contains(&base))
+            })
+            .cloned()
+            .collect();
+        if pools.is_empty() {
+            return Ok(());
+        }
+
+        let mut calls = vec![];
+        for pool in pools.iter() {
+            calls.extend(balances_calls(&pool.address, pool.coins.len()));
+            if let Some(base_pool) = pool.base_pool {
+                calls.push(call(base_pool, ICurvePool::get_virtual_priceCall {}));
+            }
+        }
+        let results = self.multicall(calls).await?;
+
+        let mut state = self.pools.write().unwrap();
+        let mut offset = 0;
+        for pool in pools.iter() {
+            let n = pool.coins.len();
+            let len = n + pool.base_pool.is_some() as usize;
+            let data = &results[offset..offset + len];
+            offset += len;
+
+            let Some(stored) = state.get_mut(&pool.address) else {
+                continue;
+            };
+            for (i, data) in data[..n].iter().enumerate() {
+                match decode::<ICurvePool::balancesCall>(data) {
+                    Some(balance) => stored.state.balances[i] = balance._0,
+                    None => tracing::warn!("failed to fetch balances of {}", pool.address),
+                }
+            }
+            if pool.base_pool.is_some() {
+                if let Some(price) = decode::<ICurvePool::get_virtual_priceCall>(&data[n]) {
+                    stored.state.rates[n - 1] = price._0;
+                }
+            }
+        }
+
+        tracing::trace!("update {} curve pools", pools.len());
+        Ok(())
+    }
+
+    async fn multicall(&self, calls: Vec<Call>) -> Result<Vec<Option<Bytes>>> {
+        multicall(&self.provider, calls).await
+    }
+}
+
+fn balances_calls(pool: &Address, n: usize) -> Vec<Call> {
+    (0..n)
+        .map(|i| call(*pool, ICurvePool::balancesCall { i: U256::from(i) }))
+        .collect()
+}
+
+// `data` are results of `balances`, params and virtual price calls of `fetch_pools`
+fn parse_pool(info: PoolInfo, data: &[Option<Bytes>]) -> Option<CurvePool> {
+    let n = info.coins.len();
+    let balances = data[..n]
+        .iter()
+        .map(|data| decode::<ICurvePool::balancesCall>(data).map(|r| r._0))
+        .collect::<Option<Vec<U256>>>()?;
+
+    let params = &data[n..n + PARAMS_CALLS];
+    // `A_precise` exists only in pools with `A_PRECISION`
+    let template = match decode::<ICurvePool::A_preciseCall>(&params[0]) {
+        Some(_) => Template::Factory,
+        None => Template::Legacy,
+    };
+    let ramp = AmpRamp {
+        initial_a: decode::<ICurvePool::initial_ACall>(&params[1])?._0,
+        future_a: decode::<ICurvePool::future_ACall>(&params[2])?._0,
+        initial_time: decode::<ICurvePool::initial_A_timeCall>(&params[3])?
+            ._0
+            .to(),
+        future_time: decode::<ICurvePool::future_A_timeCall>(&params[4])?._0.to(),
+    };
+    let fee = decode::<ICurvePool::feeCall>(&params[5])?._0;
+
+    let mut rates = info
+        .decimals
+        .iter()
+        .map(|decimals| Some(U256::from(10).pow(U256::from(36u8.checked_sub(*decimals)?))))
+        .collect::<Option<Vec<U256>>>()?;
+    if info.base_pool.is_some() {
+        rates[n - 1] = decode::<ICurvePool::get_virtual_priceCall>(&data[n + PARAMS_CALLS])?._0;
+    }
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+
+    Some(CurvePool {
+        address: info.address,
+        coins: info.coins,
+        base_pool: info.base_pool,
+        state: StableSwapPool {
+            template,
+            balances,
+            rates,
+            amp: ramp.a(now),
+            fee,
+        },
+        ramp,
+    })
+}
+
+#[async_trait::async_trait]
+impl DEX for Curve {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn dex_id(&self) -> i32 {
+        self.dex_id
+    }
+
+    fn health(&self) -> DexHealth {
+        self.health.lock().unwrap().clone()
+    }
+
+    async fn process_block(&self, block: Header) -> Result<Vec<Arbitrage>> {
+        let block_number = block.number;
+        let result = self.handle_block(block).await;
+        self.health.lock().unwrap().record(block_number, &result);
+        result
+    }
+
+    async fn adjacent(&self, token: &Address) -> Result<HashSet<Address>> {
+        Ok(self
+            .pools
+            .read()
+            .unwrap()
+            .values()
+            .filter(|pool| pool.coin_index(token).is_some())
+            .flat_map(|pool| pool.coins.iter().copied())
+            .filter(|coin| coin != token)
+            .collect())
+    }
+
+    /// Balances of the first two coins
+    async fn fetch_reserves(&self, pair_adr: &Address) -> Result<Reserves> {
+        let pool = self
+            .pool(pair_adr)
+            .ok_or(DexError::not_found(format!("curve pool {pair_adr:?}")))?;
+        Ok(Reserves(
+            Uint::saturating_from(pool.state.balances[0]),
+            Uint::saturating_from(pool.state.balances[1]),
+        ))
+    }
+
+    async fn owns_pair(&self, pair_adr: &Address) -> Result<bool> {
+        Ok(self.pools.read().unwrap().contains_key(pair_adr))
+    }
+
+    /// Balances of tokens in the deepest pool which has both of them
+    async fn token_reserves(&self, token0: &Address, token1: &Address) -> Result<Reserves> {
+        self.pools
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(|pool| {
+                let balance0 = pool.state.balances[pool.coin_index(token0)?];
+                let balance1 = pool.state.balances[pool.coin_index(token1)?];
+                Some((balance0, balance1))
+            })
+            .max_by_key(|(balance0, balance1)| balance0.saturating_add(*balance1))
+            .map(|(balance0, balance1)| {
+                Reserves(
+                    Uint::saturating_from(balance0),
+                    Uint::saturating_from(balance1),
+                )
+            })
+            .ok_or(DexError::not_found(format!(
+                "curve pool for {token0:?}, {token1:?}"
+            )))
+    }
+}
@@ -0,0 +1,60 @@
+use alloy::transports::TransportError;
+use kronos_common::{ErrorAction, RpcError};
+use kronos_db::DbError;
+
+pub type Result<T, E = DexError> = std::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DexError {
+    #[error(transparent)]
+    Rpc(#[from] RpcError),
+    #[error(transparent)]
+    Db(#[from] DbError),
+    /// Log or call result doesn't match the abi
+    #[error("Failed to decode: {0}")]
+    Decode(#[from] alloy::sol_types::Error),
+    /// Pool or pair is not tracked by the adapter
+    #[error("Not found: {0}")]
+    NotFound(String),
+    /// Local state contradicts itself, e.g. pool math or background task failed
+    #[error("Invariant violation: {0}")]
+    Invariant(String),
+    /// Dex config is missing or has invalid values
+    #[error("Invalid config: {0}")]
+    Config(String),
+}
+
+impl DexError {
+    pub fn not_found(what: impl Into<String>) -> Self {
+        Self::NotFound(what.into())
+    }
+
+    pub fn invariant(what: impl Into<String>) -> Self {
+        Self::Invariant(what.into())
+    }
+
+    pub fn config(what: impl Into<String>) -> Self {
+        Self::Config(what.into())
+    }
+
+    pub fn action(&self) -> ErrorAction {
+        match self {
+            Self::Rpc(err) => err.action(),
+            Self::Db(err) => err.action(),
+            Self::Decode(_) | Self::NotFound(_) => ErrorAction::Skip,
+            Self::Invariant(_) | Self::Config(_) => ErrorAction::Abort,
+        }
+    }
+}
+
+impl From<TransportError> for DexError {
+    fn from(err: TransportError) -> Self {
+        Self::Rpc(err.into())
+    }
+}
+
+impl From<alloy::contract::Error> for DexError {
+    fn from(err: alloy::contract::Error) -> Self {
+        Self::Rpc(err.into())
+    }
+}
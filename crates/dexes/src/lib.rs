@@ -1,2 +1,9 @@
+pub mod arbitrage;
+pub mod balancer;
 pub mod common;
+pub mod curve;
+pub mod error;
+pub mod registry;
+pub mod tax;
 pub mod uniswap_v2;
+pub mod uniswap_v4;
@@ -0,0 +1,325 @@
+use crate::{
+    balancer::{self, BalancerV2},
+    common::{Arbitrage, DexHealth, DEX},
+    curve::{self, Curve},
+    error::DexError,
+    uniswap_v2::{self, UniswapV2},
+    uniswap_v4::{self, UniswapV4},
+};
+use alloy::{providers::RootProvider, rpc::types::Header};
+use anyhow::{anyhow, Result};
+use kronos_common::ErrorAction;
+use kronos_config::{Config, DexConfig};
+use kronos_db::{Inventory, TokenRegistry, DB};
+use kronos_metrics as metrics;
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    task::JoinSet,
+};
+use tracing::{Instrument, Span};
+
+// Block is repeated on transient rpc errors, delay grows with every attempt
+const BLOCK_RETRIES: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// `DexRegistry` owns adapters of all configured dexes.
+/// Every block is sent to all adapters, found arbitrages are merged into one stream
+#[derive(Clone)]
+pub struct DexRegistry {
+    adapters: Vec<Arc<dyn DEX>>,
+}
+
+impl DexRegistry {
+    pub async fn from_config(
+        config: &Config,
+        db: DB,
+        tokens: TokenRegistry,
+        inventory: Inventory,
+        provider: Arc<RootProvider>,
+    ) -> Result<Self> {
+        let mut adapters = vec![];
+        for dex in config.dexes.iter() {
+            let adapter = Self::build(
+                dex,
+                db.clone(),
+                tokens.clone(),
+                inventory.clone(),
+                provider.clone(),
+            )
+            .await?;
+            adapters.push(adapter);
+            tracing::info!("🏦 {} adapter is built", dex.name);
+        }
+
+        Ok(Self { adapters })
+    }
+
+    /// Builds adapter by `kind` of the dex. New venue types are added here
+    async fn build(
+        config: &DexConfig,
+        db: DB,
+        tokens: TokenRegistry,
+        inventory: Inventory,
+        provider: Arc<RootProvider>,
+    ) -> Result<Arc<dyn DEX>> {
+        match config.kind() {
+            uniswap_v2::KIND => Ok(Arc::new(
+                UniswapV2::new(config, db, tokens, inventory, provider).await?,
+            )),
+            curve::KIND => Ok(Arc::new(
+                Curve::new(config, db, tokens, inventory, provider).await?,
+            )),
+            balancer::KIND => Ok(Arc::new(
+                BalancerV2::new(config, db, tokens, inventory, provider).await?,
+            )),
+            uniswap_v4::KIND => Ok(Arc::new(
+                UniswapV4::new(config, db, tokens, inventory, provider).await?,
+            )),
+            kind => Err(anyhow!("Unknown dex kind {kind} of {}", config.name)),
+        }
+    }
+
+    pub fn adapters(&self) -> &[Arc<dyn DEX>] {
+        &self.adapters
+    }
+
+    pub fn health(&self) -> Vec<DexHealth> {
+        self.adapters.iter().map(|dex| dex.health()).collect()
+    }
+
+    /// Fans out blocks to all adapters. Every adapter processes blocks in its own task,
+    /// so a slow dex does not delay the others. Errors are recorded in adapter health:
+    /// transient errors are retried, skippable ones drop the block, and the adapter
+    /// is stopped on errors which break its state while the others keep running. Adapter tasks are joined,
+    /// so a panic of an adapter stops the registry instead of being lost
+    pub async fn start(
+        &self,
+        mut blocks: UnboundedReceiver<Header>,
+        arbitrage_tx: UnboundedSender<Arbitrage>,
+    ) -> Result<()> {
+        let mut senders = vec![];
+        let mut tasks = JoinSet::new();
+
+        for dex in self.adapters.iter() {
+            let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<(Header, Span)>();
+            senders.push((dex.name(), tx));
+
+            let dex = dex.clone();
+            let arbitrage_tx = arbitrage_tx.clone();
+            tasks.spawn(async move {
+                tracing::info!("🚀 {} started", dex.name());
+                let channel = format!("blocks_{}", dex.name());
+
+                while let Some((block, block_span)) = rx.recv().await {
+                    metrics::set_channel_depth(&channel, rx.len());
+                    let block_number = block.number;
+                    let block_timestamp = block.timestamp;
+                    // every log of the block carries its number and the dex
+                    let span =
+                        tracing::info_span!(parent: &block_span, "process_block", dex = dex.name());
+                    let stop = async {
+                        let result = Self::process_with_retry(dex.as_ref(), block).await;
+                        record_block(dex.name(), block_timestamp, &result);
+
+                        match result {
+                            Ok(arbitrages) => {
+                                for mut arbitrage in arbitrages {
+                                    tracing::debug!(path_id = %arbitrage.path_id(), "arbitrage found");
+                                    arbitrage.span = Span::current();
+                                    if arbitrage_tx.send(arbitrage).is_err() {
+                                        return true;
+                                    }
+                                }
+                                false
+                            }
+                            Err(err) if err.action() == ErrorAction::Abort => {
+                                tracing::error!(
+                                    "{} stopped on block {block_number}: {err}",
+                                    dex.name()
+                                );
+                                true
+                            }
+                            Err(err) => {
+                                tracing::warn!("{} skip block {block_number}: {err}", dex.name());
+                                false
+                            }
+                        }
+                    }
+                    .instrument(span)
+                    .await;
+                    if stop {
+                        return;
+                    }
+                }
+            });
+        }
+
+        loop {
+            tokio::select! {
+                block = blocks.recv() => {
+                    let Some(block) = block else {
+                        break;
+                    };
+                    // root of the trace of the block, stages of all adapters and the executor are its children
+                    let span = tracing::info_span!(parent: None, "block", block_number = block.number);
+                    // adapter which stopped on an abort has dropped its receiver,
+                    // the other adapters keep receiving blocks
+                    senders.retain(|(name, tx)| {
+                        let sent = tx.send((block.clone(), span.clone())).is_ok();
+                        if !sent {
+                            tracing::error!("{name} is stopped, block {} is not processed by it", block.number);
+                        }
+                        sent
+                    });
+                }
+                Some(joined) = tasks.join_next() => joined?,
+            }
+        }
+
+        // adapters finish blocks which are already queued
+        drop(senders);
+        while let Some(joined) = tasks.join_next().await {
+            joined?;
+        }
+        Ok(())
+    }
+
+    /// Repeats the block while the error is transient, at most `BLOCK_RETRIES` times
+    async fn process_with_retry(dex: &dyn DEX, block: Header) -> Result<Vec<Arbitrage>, DexError> {
+        let mut attempt = 0;
+        loop {
+            match dex.process_block(block.clone()).await {
+                Err(err) if err.action() == ErrorAction::Retry && attempt < BLOCK_RETRIES => {
+                    attempt += 1;
+                    tracing::debug!(
+                        "{} retry block {} ({attempt}/{BLOCK_RETRIES}): {err}",
+                        dex.name(),
+                        block.number
+                    );
+                    tokio::time::sleep(RETRY_DELAY * attempt).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+fn record_block(dex: &str, block_timestamp: u64, result: &Result<Vec<Arbitrage>, DexError>) {
+    let status = match result {
+        Ok(_) => "ok",
+        Err(err) if err.action() == ErrorAction::Abort => "aborted",
+        Err(_) => "skipped",
+    };
+    metrics::BLOCKS_PROCESSED
+        .with_label_values(&[dex, status])
+        .inc();
+
+    if result.is_ok() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        metrics::DETECTION_LATENCY
+            .with_label_values(&[dex])
+            .observe((now - block_timestamp as f64).max(0.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Result;
+    use alloy::primitives::Address;
+    use kronos_common::Reserves;
+    use std::{collections::HashSet, sync::Mutex};
+
+    /// Adapter which aborts on every block or records the blocks it processed
+    struct Stub {
+        name: &'static str,
+        abort: bool,
+        blocks: Mutex<Vec<u64>>,
+    }
+
+    #[async_trait::async_trait]
+    impl DEX for Stub {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn dex_id(&self) -> i32 {
+            0
+        }
+
+        fn health(&self) -> DexHealth {
+            DexHealth::new(self.name, 0)
+        }
+
+        async fn process_block(&self, block: Header) -> Result<Vec<Arbitrage>> {
+            if self.abort {
+                return Err(DexError::invariant("state is broken"));
+            }
+            self.blocks.lock().unwrap().push(block.number);
+            Ok(vec![])
+        }
+
+        async fn fetch_reserves(&self, _: &Address) -> Result<Reserves> {
+            unimplemented!()
+        }
+
+        async fn owns_pair(&self, _: &Address) -> Result<bool> {
+            unimplemented!()
+        }
+
+        async fn adjacent(&self, _: &Address) -> Result<HashSet<Address>> {
+            unimplemented!()
+        }
+
+        async fn token_reserves(&self, _: &Address, _: &Address) -> Result<Reserves> {
+            unimplemented!()
+        }
+    }
+
+    fn header(number: u64) -> Header {
+        Header {
+            inner: alloy::consensus::Header {
+                number,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn aborted_adapter_does_not_stop_the_others() {
+        let stub = |name, abort| {
+            Arc::new(Stub {
+                name,
+                abort,
+                blocks: Mutex::new(vec![]),
+            })
+        };
+        let aborting = stub("aborting", true);
+        let healthy = stub("healthy", false);
+        let registry = DexRegistry {
+            adapters: vec![aborting, healthy.clone()],
+        };
+
+        let (blocks_tx, blocks_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (arbitrage_tx, _arbitrage_rx) = tokio::sync::mpsc::unbounded_channel();
+        let feed = tokio::spawn(async move {
+            for number in 1..=3 {
+                blocks_tx.send(header(number)).unwrap();
+                // aborting adapter drops its receiver before the next block
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        });
+
+        registry.start(blocks_rx, arbitrage_tx).await.unwrap();
+        feed.await.unwrap();
+        assert_eq!(*healthy.blocks.lock().unwrap(), vec![1, 2, 3]);
+    }
+}
@@ -0,0 +1,238 @@
+use alloy::{
+    network::TransactionBuilder,
+    primitives::{Address, Uint},
+    providers::{Provider, RootProvider},
+    rpc::types::{
+        simulate::{SimBlock, SimulatePayload},
+        TransactionRequest,
+    },
+    sol_types::SolCall,
+};
+use anyhow::{anyhow, Result};
+use ethereum_abi::IERC20;
+use kronos_db::{tables::TokenFlags, TokenRegistry};
+use std::sync::Arc;
+
+// Recipient of simulated buy, must not be excluded from fees by the token
+const SIMULATION_RECIPIENT: Address = Address::repeat_byte(0x42);
+
+// 1% of the pair balance is moved by simulation
+const SIMULATION_SHARE: u64 = 100;
+
+const BPS: u64 = 10_000;
+
+/// Result of buy-then-sell simulation for the single token
+#[derive(Clone, Debug)]
+pub enum TaxReport {
+    /// Both transfers succeed, effective tax is the max of buy and sell tax
+    Taxed { transfer_tax_bps: u16 },
+    /// Transfer out of the pair or back into it reverts
+    Honeypot,
+}
+
+impl TaxReport {
+    /// Flags and transfer tax of the token with this report
+    pub fn apply(&self, flags: TokenFlags) -> (TokenFlags, Option<u16>) {
+        match *self {
+            Self::Taxed { transfer_tax_bps } => (
+                TokenFlags {
+                    fee_on_transfer: transfer_tax_bps > 0,
+                    ..flags
+                },
+                Some(transfer_tax_bps),
+            ),
+            Self::Honeypot => (
+                TokenFlags {
+                    honeypot: true,
+                    ..flags
+                },
+                None,
+            ),
+        }
+    }
+}
+
+/// `TaxClassifier` measures effective transfer tax of the token with `eth_simulateV1`.
+///
+/// Buy is simulated as `transfer` from the pair to `SIMULATION_RECIPIENT`,
+/// sell as `transfer` of the received amount back to the pair.
+/// Tax is measured by `balanceOf` deltas between the calls
+#[derive(Clone)]
+pub struct TaxClassifier {
+    provider: Arc<RootProvider>,
+    tokens: TokenRegistry,
+}
+
+impl TaxClassifier {
+    pub fn new(provider: Arc<RootProvider>, tokens: TokenRegistry) -> Self {
+        Self { provider, tokens }
+    }
+
+    /// Classifies tokens of the pair which are not classified yet and stores result to registry
+    pub async fn classify_pair(&self, pair_adr: Address, tokens: [Address; 2]) -> Result<()> {
+        for token in tokens {
+            let Some(flags) = self.tokens.flags(&token) else {
+                continue;
+            };
+            if flags.blocked() || self.tokens.transfer_tax_bps(&token).is_some() {
+                continue;
+            }
+
+            let report = self.simulate(pair_adr, token).await?;
+            let (flags, transfer_tax_bps) = report.apply(flags);
+            self.tokens
+                .set_transfer_tax(&token, flags, transfer_tax_bps)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn simulate(&self, pair_adr: Address, token: Address) -> Result<TaxReport> {
+        let instance = IERC20::new(token, self.provider.clone());
+        let pair_balance = instance.balanceOf(pair_adr).call().await?.balance;
+        let amount = pair_balance / Uint::from(SIMULATION_SHARE);
+        if amount.is_zero() {
+            return Err(anyhow!("Pair {pair_adr} has no balance of {token}"));
+        }
+
+        let call = |from: Address, input: Vec<u8>| {
+            TransactionRequest::default()
+                .with_from(from)
+                .with_to(token)
+                .with_input(input)
+        };
+        let balance_of = |owner: Address| IERC20::balanceOfCall { _owner: owner }.abi_encode();
+
+        let block = SimBlock::default().extend_calls([
+            // buy
+            call(
+                pair_adr,
+                IERC20::transferCall {
+                    _to: SIMULATION_RECIPIENT,
+                    _value: amount,
+                }
+                .abi_encode(),
+            ),
+            call(SIMULATION_RECIPIENT, balance_of(SIMULATION_RECIPIENT)),
+            call(pair_adr, balance_of(pair_adr)),
+        ]);
+        let payload = SimulatePayload {
+            block_state_calls: vec![block],
+            ..Default::default()
+        };
+
+        let simulated = self.provider.simulate(&payload).await?;
+        let calls = &simulated
+            .first()
+            .ok_or(anyhow!("Empty simulation result"))?
+            .calls;
+        if !calls[0].status {
+            // pair itself can't transfer the token
+            return Ok(TaxReport::Honeypot);
+        }
+
+        let received =
+            IERC20::balanceOfCall::abi_decode_returns(&calls[1].return_data, false)?.balance;
+        let pair_after_buy =
+            IERC20::balanceOfCall::abi_decode_returns(&calls[2].return_data, false)?.balance;
+
+        // sell received amount back on top of the buy state
+        let block = payload.block_state_calls[0].clone().extend_calls([
+            call(
+                SIMULATION_RECIPIENT,
+                IERC20::transferCall {
+                    _to: pair_adr,
+                    _value: received,
+                }
+                .abi_encode(),
+            ),
+            call(pair_adr, balance_of(pair_adr)),
+        ]);
+        let payload = SimulatePayload {
+            block_state_calls: vec![block],
+            ..Default::default()
+        };
+
+        let simulated = self.provider.simulate(&payload).await?;
+        let calls = &simulated
+            .first()
+            .ok_or(anyhow!("Empty simulation result"))?
+            .calls;
+        if !calls[3].status {
+            return Ok(TaxReport::Honeypot);
+        }
+
+        let pair_after_sell =
+            IERC20::balanceOfCall::abi_decode_returns(&calls[4].return_data, false)?.balance;
+        let sold = pair_after_sell.saturating_sub(pair_after_buy);
+
+        let buy_tax_bps = tax_bps(amount, received);
+        let sell_tax_bps = tax_bps(received, sold);
+
+        tracing::debug!("token {token} buy tax: {buy_tax_bps} bps, sell tax: {sell_tax_bps} bps");
+        Ok(TaxReport::Taxed {
+            transfer_tax_bps: buy_tax_bps.max(sell_tax_bps),
+        })
+    }
+}
+
+// Share of `sent` which is lost on transfer, in basis points
+fn tax_bps(sent: Uint<256, 4>, received: Uint<256, 4>) -> u16 {
+    if sent.is_zero() {
+        return BPS as u16;
+    }
+
+    let lost = sent.saturating_sub(received);
+    let bps = lost * Uint::from(BPS) / sent;
+    bps.to::<u16>().min(BPS as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_lost_share_in_bps() {
+        let sent = Uint::from(1_000_000);
+        assert_eq!(tax_bps(sent, sent), 0);
+        assert_eq!(tax_bps(sent, Uint::from(950_000)), 500);
+        // rounding is in favour of the tax
+        assert_eq!(tax_bps(sent, Uint::from(999_999)), 0);
+        assert_eq!(tax_bps(sent, Uint::ZERO), 10_000);
+        assert_eq!(tax_bps(Uint::ZERO, Uint::ZERO), 10_000);
+        // rebase during the transfer is not a negative tax
+        assert_eq!(tax_bps(sent, Uint::from(1_100_000)), 0);
+    }
+
+    #[test]
+    fn honeypot_is_blocked_without_blacklist() {
+        let (flags, transfer_tax_bps) = TaxReport::Honeypot.apply(TokenFlags::default());
+        assert!(flags.honeypot);
+        assert!(!flags.blacklist);
+        assert!(flags.blocked());
+        assert_eq!(transfer_tax_bps, None);
+    }
+
+    #[test]
+    fn taxed_token_stays_tradable() {
+        let flags = TokenFlags {
+            blacklist: true,
+            ..Default::default()
+        };
+        let (flags, transfer_tax_bps) = TaxReport::Taxed {
+            transfer_tax_bps: 300,
+        }
+        .apply(flags);
+        assert!(flags.fee_on_transfer);
+        assert!(flags.blacklist);
+        assert!(!flags.blocked());
+        assert_eq!(transfer_tax_bps, Some(300));
+
+        let (flags, _) = TaxReport::Taxed {
+            transfer_tax_bps: 0,
+        }
+        .apply(TokenFlags::default());
+        assert!(!flags.fee_on_transfer);
+    }
+}
@@ -1,26 +1,37 @@
-use crate::common::{AddressBook, Arbitrage, DEX};
+use crate::{
+    arbitrage::keep_best,
+    common::{spawn_cpu, AddressBook, Arbitrage, DexHealth, Hop, Venue, DEX},
+    error::{DexError, Result},
+    tax::TaxClassifier,
+};
 use alloy::{
     primitives::{address, Address, Uint},
     providers::{Provider, RootProvider},
     rpc::types::{Filter, Header},
     sol_types::SolEvent,
 };
-use anyhow::Result;
-use ethereum_abi::{IUniswapV2Pair, IERC20};
-use hashbrown::{hash_map::Entry, HashMap};
-use kronos_common::{DexError, Reserves};
+use ethereum_abi::IUniswapV2Pair;
+use hashbrown::HashMap;
+use kronos_common::{ErrorAction, Reserves, RpcError};
+use kronos_config::DexConfig;
 use kronos_db::{
-    tables::{Pair, Ticker},
-    PricesStorage, TokensGraphStorage, UpdateReservesData, DB,
+    tables::Pair, DbError, GraphSnapshot, Inventory, PricesStorage, TokenRegistry,
+    TokensGraphStorage, UpdateReservesData, DB,
+};
+use kronos_math::{
+    cpmm::{calculate_dy, path_amount_out, path_taxes, ArbitrageData},
+    cycles::{optimal_amount_in, profitable_paths, CycleIndex, Path},
 };
-use kronos_math::cpmm::{find_profit, find_triangular_arbitrage, ArbitrageData};
+use kronos_metrics::{self as metrics, prometheus::IntCounter};
+use rayon::prelude::*;
 use std::{
     collections::HashSet,
     sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
     },
 };
+use tracing::{Instrument, Span};
 
 const USDT: Address = address!("0xdAC17F958D2ee523a2206206994597C13D831ec7");
 const USDC: Address = address!("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
@@ -28,58 +39,86 @@ const DAI: Address = address!("0x6B175474E89094C44Da98b954EedeAC495271d0F");
 const WETH: Address = address!("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2");
 const WBTC: Address = address!("0x2260FAC5E5542a773Aa44fBCfeDf7C193bc2C599");
 
-const DEX_NAME: &str = "uniswap_v2";
+pub const KIND: &str = "uniswap_v2";
 
 const MAX_REQUESTS_PER_BLOCK: usize = 50;
-static REQUESTS_PER_BLOCK: AtomicUsize = AtomicUsize::new(0);
-
-pub fn request_wrapper(inc: usize) -> Result<()> {
-    if REQUESTS_PER_BLOCK.load(Ordering::Relaxed) >= MAX_REQUESTS_PER_BLOCK {
-        tracing::info!("reach requests limit per block");
-        return Err(DexError::BlockRpcLimitExceed.into());
-    }
-
-    REQUESTS_PER_BLOCK.fetch_add(inc, Ordering::Relaxed);
-    Ok(())
-}
 
 pub struct UniswapV2 {
+    name: String,
     db: DB,
+    tokens: TokenRegistry,
+    classifier: TaxClassifier,
     dex_id: i32,
     address_book: AddressBook,
     provider: Arc<RootProvider>,
+    // number of the block which is processed now
+    last_block: AtomicU64,
+    // rpc requests of the adapter in the current block
+    requests_per_block: AtomicUsize,
+    cycles: Arc<Mutex<CycleIndex>>,
+    health: Mutex<DexHealth>,
+    // start tokens and size limits of arbitrages
+    inventory: Inventory,
 
     whitelisted_tokens: HashSet<Address>,
-
-    rx: tokio::sync::mpsc::UnboundedReceiver<Header>,
-    tx: tokio::sync::mpsc::UnboundedSender<Arbitrage>,
 }
 
 impl UniswapV2 {
     pub async fn new(
+        config: &DexConfig,
         db: DB,
+        tokens: TokenRegistry,
+        inventory: Inventory,
         provider: Arc<RootProvider>,
-        rx: tokio::sync::mpsc::UnboundedReceiver<Header>,
-        tx: tokio::sync::mpsc::UnboundedSender<Arbitrage>,
     ) -> Result<Self> {
-        let dex_id = db.postgres().get_dex_id(DEX_NAME).await?;
-
-        Ok(Self {
+        let factory = config.factory.as_deref().ok_or(DexError::config(format!(
+            "factory of {} is not set",
+            config.name
+        )))?;
+        let factory: Address = factory.parse().map_err(|err| {
+            DexError::config(format!("factory {factory} of {}: {err}", config.name))
+        })?;
+        let dex_id = db.postgres().get_dex_id(&config.name).await?;
+
+        let uniswap_v2 = Self {
+            name: config.name.clone(),
             dex_id,
-            address_book: AddressBook {
-                factory: address!("0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f"),
-                router: address!("0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D"),
-            },
+            address_book: AddressBook { factory },
             whitelisted_tokens: HashSet::from([USDC, USDT, DAI]),
             db,
+            classifier: TaxClassifier::new(provider.clone(), tokens.clone()),
+            tokens,
             provider,
-            rx,
-            tx,
-        })
+            last_block: AtomicU64::new(0),
+            requests_per_block: AtomicUsize::new(0),
+            cycles: Arc::new(Mutex::new(CycleIndex::new(dex_id))),
+            health: Mutex::new(DexHealth::new(&config.name, dex_id)),
+            inventory,
+        };
+
+        // cycles of known pairs are indexed once before the first block
+        {
+            let mut cycles = uniswap_v2.cycles.lock().unwrap();
+            cycles.sync(&uniswap_v2.db.graph().snapshot().topology);
+            tracing::info!("🦄 Uniswap-V2 indexed {} cycles", cycles.len());
+        }
+
+        Ok(uniswap_v2)
+    }
+
+    /// Counts `inc` rpc requests of the block, fails once the limit of the block is reached
+    fn request_wrapper(&self, inc: usize) -> Result<()> {
+        if self.requests_per_block.load(Ordering::Relaxed) >= MAX_REQUESTS_PER_BLOCK {
+            tracing::info!("reach requests limit per block");
+            return Err(RpcError::BlockLimitExceeded.into());
+        }
+
+        self.requests_per_block.fetch_add(inc, Ordering::Relaxed);
+        Ok(())
     }
 
     pub async fn fetch_pair(&self, pair_adr: Address) -> Result<Pair> {
-        request_wrapper(1usize)?;
+        self.request_wrapper(1usize)?;
 
         let instance = IUniswapV2Pair::new(pair_adr, self.provider.clone());
         let token0 = instance.token0().call().await?._0;
@@ -95,142 +134,285 @@ impl UniswapV2 {
         })
     }
 
-    async fn add_pair(&self, pair: Pair) -> Result<()> {
-        self.db.add_pair(pair.clone()).await?;
-
-        for token in [pair.token0, pair.token1] {
-            if self.db.postgres().get_token_ticker(&token).await.is_err() {
-                let instance = IERC20::new(token, self.provider.clone());
-                let ticker = Ticker {
-                    token,
-                    ticker: instance.symbol().call().await?._0,
-                };
-                self.db.postgres().insert_ticker(ticker).await?;
-            }
-        }
-
-        Ok(())
-    }
-
-    async fn best_arbitrages(
+    /// Returns (token0, token1) of the pair, `None` if the pair is from another dex.
+    /// Unknown pairs are fetched from the node and collected to `new_pairs`,
+    /// which are stored in one batch by the caller
+    async fn resolve_pair(
         &self,
-        paths: Vec<Vec<(Address, Address)>>,
-    ) -> Result<HashMap<Address, Arbitrage>> {
-        let mut best_arbitrages: HashMap<Address, Arbitrage> = HashMap::new();
-
-        for path in paths.into_iter() {
-            // if !self.whitelisted_tokens.contains(&path.last().unwrap().1) {
-            //     continue;
-            // }
-
-            let mut data = Vec::new();
-            for tokens in &path {
-                data.push(ArbitrageData {
-                    reserves: self.token_reserves(&tokens.0, &tokens.1).await?,
-                    fee: Uint::from(3),
-                });
-            }
+        pair_adr: &Address,
+        new_pairs: &mut Vec<Pair>,
+    ) -> Result<Option<(Address, Address)>> {
+        if let Some(pair) = new_pairs.iter().find(|pair| pair.address == *pair_adr) {
+            return Ok(Some((pair.token0, pair.token1)));
+        }
+        if !self.owns_pair(pair_adr).await? {
+            return Ok(None);
+        }
 
-            if let Some(profit) = find_profit(&data) {
-                match best_arbitrages.entry(path[0].0) {
-                    Entry::Occupied(mut entry) => {
-                        let arbitrage = entry.get_mut();
-
-                        if arbitrage.revenue < profit.1 - profit.0 {
-                            *arbitrage = Arbitrage {
-                                dex_id: self.dex_id,
-                                amount_in: profit.0,
-                                revenue: profit.1 - profit.0,
-                                path: path.clone(),
-                            }
-                        }
-                    }
-                    Entry::Vacant(entry) => {
-                        entry.insert(Arbitrage {
-                            dex_id: self.dex_id,
-                            amount_in: profit.0,
-                            revenue: profit.1 - profit.0,
-                            path: path.clone(),
-                        });
-                    }
-                }
+        match self.db.pair_by_tokens(self.dex_id, pair_adr).await {
+            Ok(tokens) => Ok(Some(tokens)),
+            Err(DbError::NotFound(_)) => {
+                let pair = self.fetch_pair(*pair_adr).await?;
+                let tokens = (pair.token0, pair.token1);
+                new_pairs.push(pair);
+                Ok(Some(tokens))
             }
+            Err(err) => Err(err.into()),
         }
-
-        Ok(best_arbitrages)
     }
 
-    async fn collect_updated_tokens(&self, block: Header) -> Result<Vec<Address>> {
+    /// Writes reserves from `Sync` logs of the block and returns addresses of updated pairs
+    async fn collect_updated_pairs(&self, block: Header) -> Result<Vec<Address>> {
         let filter = Filter::new()
             .event_signature(IUniswapV2Pair::Sync::SIGNATURE_HASH)
             .from_block(block.number);
 
-        let mut updated_tokens = vec![];
+        let mut updated_pairs = vec![];
+        let mut updated_reserves = vec![];
+        let mut new_pairs = vec![];
 
-        for log in self.provider.get_logs(&filter).await? {
+        let logs = self
+            .provider
+            .get_logs(&filter)
+            .instrument(tracing::info_span!("fetch_logs"))
+            .await?;
+        for log in logs {
             let sync = IUniswapV2Pair::Sync::decode_log(&log.inner, false)?;
 
-            if !self.owns_pair(&sync.address).await? {
-                continue;
-            }
-
-            let (token0, token1) = match self.db.pair_by_tokens(self.dex_id, &sync.address).await {
-                Ok(r) => (r.0, r.1),
-                Err(_) => {
-                    let pair = self.fetch_pair(sync.address).await?;
-                    self.add_pair(pair.clone()).await?;
-                    (pair.token0, pair.token1)
+            // pair which can't be resolved is skipped, the rest of the block is still processed
+            let (token0, token1) = match self.resolve_pair(&sync.address, &mut new_pairs).await {
+                Ok(Some(tokens)) => tokens,
+                Ok(None) => continue,
+                Err(err) if err.action() == ErrorAction::Skip => {
+                    tracing::debug!("skip sync of pair {}: {err}", sync.address);
+                    continue;
                 }
+                Err(err) => return Err(err),
             };
 
-            let data = UpdateReservesData {
+            updated_reserves.push(UpdateReservesData {
+                pair: sync.address,
                 token0,
                 token1,
                 reserves: Reserves(sync.reserve0, sync.reserve1),
-            };
-            self.db.update_reserves(self.dex_id, data).await?;
+                block_number: log.block_number.unwrap_or(block.number),
+            });
 
-            updated_tokens.push(token0);
-            updated_tokens.push(token1);
+            updated_pairs.push(sync.address);
         }
 
-        Ok(updated_tokens)
+        // pairs discovered in the block are added to the graph at once before their reserves
+        self.db
+            .add_pairs(&new_pairs)
+            .instrument(tracing::info_span!("add_pairs", pairs = new_pairs.len()))
+            .await?;
+
+        // all reserves of the block are written in one pipeline
+        self.db
+            .update_reserves(self.dex_id, &updated_reserves)
+            .instrument(tracing::info_span!(
+                "update_reserves",
+                pairs = updated_reserves.len()
+            ))
+            .await?;
+
+        // metadata for tokens of discovered pairs is fetched in one batch
+        let new_tokens: Vec<Address> = new_pairs
+            .iter()
+            .flat_map(|pair| [pair.token0, pair.token1])
+            .collect();
+        self.tokens.ensure_tokens(&new_tokens).await?;
+
+        for pair in new_pairs {
+            if let Err(err) = self
+                .classifier
+                .classify_pair(pair.address, [pair.token0, pair.token1])
+                .await
+            {
+                tracing::warn!("failed to classify tokens of pair {}: {err}", pair.address);
+            }
+        }
+
+        Ok(updated_pairs)
     }
 
-    pub async fn start(mut self) -> Result<()> {
-        tracing::info!("🦄 Uniswap-V2 started");
+    async fn handle_block(&self, block: Header) -> Result<Vec<Arbitrage>> {
+        self.requests_per_block.store(0usize, Ordering::Relaxed);
+        let block_number = block.number;
+        self.last_block.store(block_number, Ordering::Relaxed);
+        let updated_pairs = self.collect_updated_pairs(block).await?;
+
+        // reserves of the block are already in the graph,
+        // only cycles of updated pairs are scored over one immutable snapshot
+        // on rayon pool without any IO
+        let snapshot = self.db.graph().snapshot();
+        let tokens = self.tokens.clone();
+        let cycles = self.cycles.clone();
+        let dex_id = self.dex_id;
+        let inventory = self.inventory.clone();
+        let found = metrics::CANDIDATES_FOUND.with_label_values(&[&self.name]);
+        let sized = metrics::CANDIDATES_SIZED.with_label_values(&[&self.name]);
+        // rayon threads don't see the span of the block, so it is passed explicitly
+        let span = Span::current();
+        let best_arbitrages = spawn_cpu(move || {
+            let paths = {
+                let _span = tracing::info_span!(parent: &span, "find_cycles").entered();
+                // new pairs of the block are indexed before scoring,
+                // the index is locked only to copy out affected cycles
+                let cycles = {
+                    let mut cycles = cycles.lock().unwrap();
+                    cycles.sync(&snapshot.topology);
+                    cycles.affected(&updated_pairs, &snapshot.topology)
+                };
+                let paths = profitable_paths(cycles, &snapshot, |token| tokens.is_tradable(token));
+                found.inc_by(paths.len() as u64);
+                paths
+            };
+            let paths: Vec<_> = paths
+                .into_iter()
+                .filter(|path| inventory.holds(&snapshot.topology.token(path[0].0)))
+                .collect();
+            let _span = tracing::info_span!(parent: &span, "size", paths = paths.len()).entered();
+            best_arbitrages(
+                &snapshot,
+                &tokens,
+                &inventory,
+                dex_id,
+                block_number,
+                paths,
+                &sized,
+            )
+        })
+        .await?;
 
-        while let Some(block) = self.rx.recv().await {
-            self.handle_block(block).await?;
-        }
-        Ok(())
+        Ok(best_arbitrages.into_values().collect())
     }
+}
 
-    async fn handle_block(&self, block: Header) -> Result<()> {
-        REQUESTS_PER_BLOCK.store(0usize, Ordering::Relaxed);
-        let updated_tokens = self.collect_updated_tokens(block).await?;
+/// Sizes all paths in parallel and keeps the most profitable cycle per start token.
+/// In inventory mode amount in is capped by the balance held in the start token
+fn best_arbitrages(
+    snapshot: &GraphSnapshot,
+    tokens: &TokenRegistry,
+    inventory: &Inventory,
+    dex_id: i32,
+    block_number: u64,
+    paths: Vec<Path>,
+    sized: &IntCounter,
+) -> HashMap<Address, Arbitrage> {
+    let topology = &snapshot.topology;
+    paths
+        .into_par_iter()
+        .filter_map(|hops| {
+            let pairs: Vec<Address> = hops
+                .iter()
+                .map(|(_, edge)| topology.edges()[*edge as usize].pair)
+                .collect();
+            let path: Vec<(Address, Address)> = (0..hops.len())
+                .map(|i| {
+                    let token_out = hops[(i + 1) % hops.len()].0;
+                    (topology.token(hops[i].0), topology.token(token_out))
+                })
+                .collect();
+
+            // paths are found in the same snapshot, so reserves are known
+            let data = hops
+                .iter()
+                .zip(path_taxes(tokens, &path))
+                .map(|((token_in, edge), (transfer_tax_bps, output_tax_bps))| {
+                    Some(ArbitrageData {
+                        reserves: snapshot.edge_reserves(*edge, *token_in)?,
+                        fee: Uint::from(3),
+                        transfer_tax_bps,
+                        output_tax_bps,
+                    })
+                })
+                .collect::<Option<Vec<ArbitrageData>>>()?;
+
+            let (amount_in, revenue) = optimal_amount_in(
+                |amount_in| Some(path_amount_out(&data, amount_in)),
+                inventory.max_amount_in(&path[0].0),
+            )?;
+            sized.inc();
+            Some(Arbitrage {
+                dex_id,
+                block_number,
+                amount_in,
+                revenue,
+                hops: swap_hops(&path, &pairs, &data, amount_in),
+                path,
+                span: Span::none(),
+            })
+        })
+        .fold(HashMap::new, |mut best, arbitrage| {
+            keep_best(&mut best, arbitrage);
+            best
+        })
+        .reduce(HashMap::new, |mut best, other| {
+            for arbitrage in other.into_values() {
+                keep_best(&mut best, arbitrage);
+            }
+            best
+        })
+}
+
+/// Hops with amounts sent by the pairs, output transfer tax is taken from them
+/// on the way to the next pair
+fn swap_hops(
+    path: &[(Address, Address)],
+    pairs: &[Address],
+    data: &[ArbitrageData],
+    amount_in: Uint<256, 4>,
+) -> Vec<Hop> {
+    let mut amount = amount_in;
+    path.iter()
+        .zip(pairs)
+        .zip(data)
+        .map(|(((token_in, token_out), pair), hop)| {
+            let untaxed = ArbitrageData {
+                output_tax_bps: 0,
+                ..hop.clone()
+            };
+            let amount_out = calculate_dy(&untaxed, amount);
+            amount = calculate_dy(hop, amount);
+            Hop {
+                pool: *pair,
+                token_in: *token_in,
+                token_out: *token_out,
+                venue: Venue::UniswapV2,
+                amount_out,
+            }
+        })
+        .collect()
+}
 
-        let paths =
-            find_triangular_arbitrage(&updated_tokens, self.db.clone(), self.dex_id).await?;
+#[async_trait::async_trait]
+impl DEX for UniswapV2 {
+    fn name(&self) -> &str {
+        &self.name
+    }
 
-        let best_arbitrages = self.best_arbitrages(paths).await?;
+    fn dex_id(&self) -> i32 {
+        self.dex_id
+    }
 
-        for arbitrage in best_arbitrages.into_values() {
-            self.tx.send(arbitrage)?;
-        }
+    fn health(&self) -> DexHealth {
+        self.health.lock().unwrap().clone()
+    }
 
-        Ok(())
+    async fn process_block(&self, block: Header) -> Result<Vec<Arbitrage>> {
+        let block_number = block.number;
+        let result = self.handle_block(block).await;
+        self.health.lock().unwrap().record(block_number, &result);
+        result
     }
-}
 
-#[async_trait::async_trait]
-impl DEX for UniswapV2 {
     async fn adjacent(&self, token: &Address) -> Result<HashSet<Address>> {
-        self.db.adjacent_tokens(self.dex_id, token).await
+        Ok(self.db.adjacent_tokens(self.dex_id, token).await?)
     }
 
     async fn fetch_reserves(&self, pair_adr: &Address) -> Result<Reserves> {
-        request_wrapper(1usize)?;
+        self.request_wrapper(1usize)?;
         let instance = IUniswapV2Pair::new(*pair_adr, self.provider.clone());
         let reserves = instance.getReserves().call().await?;
         Ok(Reserves(reserves.reserve0, reserves.reserve1))
@@ -240,21 +422,17 @@ impl DEX for UniswapV2 {
     async fn owns_pair(&self, pair_adr: &Address) -> Result<bool> {
         match self.db.postgres().get_pair_dex_id(pair_adr).await {
             Ok(pair_dex_id) => Ok(pair_dex_id == self.dex_id),
-            Err(err) => {
-                if let Some(sqlx_err) = err.downcast_ref::<sqlx::Error>() {
-                    if matches!(sqlx_err, sqlx::Error::RowNotFound) {
-                        let instance = IUniswapV2Pair::new(*pair_adr, self.provider.clone());
-                        return Ok(instance.factory().call().await?._0 == self.address_book.factory);
-                    }
-                }
-                return Err(err);
+            Err(DbError::NotFound(_)) => {
+                let instance = IUniswapV2Pair::new(*pair_adr, self.provider.clone());
+                Ok(instance.factory().call().await?._0 == self.address_book.factory)
             }
+            Err(err) => Err(err.into()),
         }
     }
 
     async fn token_reserves(&self, token0: &Address, token1: &Address) -> Result<Reserves> {
         match self.db.reserves(self.dex_id, token0, token1).await {
-            // reserves are cached in redis
+            // reserves are known in the graph
             Ok(reserves) => Ok(reserves),
             Err(_) => {
                 let pair_adr = self.db.pair_adr(self.dex_id, token0, token1).await?;
@@ -267,18 +445,16 @@ impl DEX for UniswapV2 {
                 };
 
                 let data = UpdateReservesData {
+                    pair: pair_adr,
                     token0: *token0,
                     token1: *token1,
                     reserves: Reserves(r0, r1),
+                    block_number: self.last_block.load(Ordering::Relaxed),
                 };
-                self.db.update_reserves(self.dex_id, data).await?;
+                self.db.update_reserves(self.dex_id, &[data]).await?;
 
                 Ok(Reserves(r0, r1))
             }
         }
     }
-
-    async fn process_block(&self, block: Header) -> Result<()> {
-        todo!()
-    }
 }
@@ -0,0 +1,614 @@
+use crate::{
+    arbitrage::CycleSearch,
+    common::{call, decode, multicall_at, Arbitrage, DexHealth, Hop, Venue, DEX},
+    error::{DexError, Result},
+};
+use alloy::{
+    eips::BlockId,
+    primitives::{address, Address, Uint, B256, U256},
+    providers::{Provider, RootProvider},
+    rpc::types::{Filter, Header, Log},
+    sol_types::SolEvent,
+};
+
+use ethereum_abi::{IPoolManager, IStateView};
+use hashbrown::HashMap;
+use kronos_common::Reserves;
+use kronos_config::DexConfig;
+use kronos_db::{tables::Pair, Inventory, TokenRegistry, DB};
+use kronos_math::concentrated::ConcentratedPool;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, RwLock},
+};
+
+pub const KIND: &str = "uniswap_v4";
+
+pub const POOL_MANAGER: Address = address!("0x000000000004444c5dc75cB358380D2e3dE08A90");
+const STATE_VIEW: Address = address!("0x7fFE42C4a5DEeA5b0feC41C94C136Cf115597227");
+const POOL_MANAGER_DEPLOY_BLOCK: u64 = 21688329;
+
+// Blocks per `eth_getLogs` request when pools are discovered
+const LOGS_CHUNK: u64 = 10_000;
+// Calls per pool: getSlot0, getLiquidity
+const STATE_CALLS: usize = 2;
+
+/// Fee of the pool is set by its hook
+const DYNAMIC_FEE_FLAG: u32 = 0x800000;
+
+/// Hook permissions are encoded in the lowest 14 bits of the hooks address
+pub mod hook_flags {
+    pub const BEFORE_INITIALIZE: u16 = 1 << 13;
+    pub const AFTER_INITIALIZE: u16 = 1 << 12;
+    pub const BEFORE_ADD_LIQUIDITY: u16 = 1 << 11;
+    pub const AFTER_ADD_LIQUIDITY: u16 = 1 << 10;
+    pub const BEFORE_REMOVE_LIQUIDITY: u16 = 1 << 9;
+    pub const AFTER_REMOVE_LIQUIDITY: u16 = 1 << 8;
+    pub const BEFORE_SWAP: u16 = 1 << 7;
+    pub const AFTER_SWAP: u16 = 1 << 6;
+    pub const BEFORE_DONATE: u16 = 1 << 5;
+    pub const AFTER_DONATE: u16 = 1 << 4;
+    pub const BEFORE_SWAP_RETURNS_DELTA: u16 = 1 << 3;
+    pub const AFTER_SWAP_RETURNS_DELTA: u16 = 1 << 2;
+    pub const AFTER_ADD_LIQUIDITY_RETURNS_DELTA: u16 = 1 << 1;
+    pub const AFTER_REMOVE_LIQUIDITY_RETURNS_DELTA: u16 = 1;
+
+    pub const ALL: u16 = (1 << 14) - 1;
+
+    /// Hooks which can change amounts or fee of a swap: `beforeSwap` may override dynamic fee,
+    /// delta returning hooks take or give tokens
+    pub const SWAP_ALTERING: u16 =
+        BEFORE_SWAP | BEFORE_SWAP_RETURNS_DELTA | AFTER_SWAP_RETURNS_DELTA;
+}
+
+/// How the pool can be quoted depending on its hooks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookClass {
+    /// Pool without hooks
+    NoHooks,
+    /// Hooks do not change swaps, pool is quoted with V3 math
+    Passive,
+    /// Hooks or dynamic fee change swaps, pool math can't quote it and the pool is not indexed
+    SwapAltering,
+}
+
+impl HookClass {
+    pub fn classify(hooks: &Address, fee: u32) -> Self {
+        let flags = u16::from_be_bytes([hooks[18], hooks[19]]) & hook_flags::ALL;
+        if fee & DYNAMIC_FEE_FLAG != 0 || flags & hook_flags::SWAP_ALTERING != 0 {
+            HookClass::SwapAltering
+        } else if *hooks == Address::ZERO {
+            HookClass::NoHooks
+        } else {
+            HookClass::Passive
+        }
+    }
+}
+
+/// Pool of the PoolManager, identified by `keccak256(PoolKey)`.
+/// Native ETH is `Address::ZERO` currency
+#[derive(Clone, Debug)]
+pub struct V4Pool {
+    pub id: B256,
+    pub currency0: Address,
+    pub currency1: Address,
+    /// Fee from the pool key, may have dynamic fee flag
+    pub fee: u32,
+    pub tick_spacing: i32,
+    pub hooks: Address,
+    pub hook_class: HookClass,
+    pub state: ConcentratedPool,
+}
+
+impl V4Pool {
+    pub fn has_currency(&self, token: &Address) -> bool {
+        self.currency0 == *token || self.currency1 == *token
+    }
+
+    // `Some(zero_for_one)` if the pool swaps `token_in` to `token_out`
+    fn direction(&self, token_in: &Address, token_out: &Address) -> Option<bool> {
+        match (*token_in, *token_out) {
+            (t0, t1) if t0 == self.currency0 && t1 == self.currency1 => Some(true),
+            (t1, t0) if t0 == self.currency0 && t1 == self.currency1 => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Pool of the edge in the graph: the first 20 bytes of the pool id
+    pub fn address(&self) -> Address {
+        Address::from_slice(&self.id[..20])
+    }
+
+    /// Swap of `amount_in` of `token_in` with V3 math, `None` for pools with swap-altering hooks
+    pub fn hop(&self, token_in: &Address, token_out: &Address, amount_in: U256) -> Option<Hop> {
+        if self.hook_class == HookClass::SwapAltering {
+            return None;
+        }
+        let zero_for_one = self.direction(token_in, token_out)?;
+        Some(Hop {
+            pool: self.address(),
+            token_in: *token_in,
+            token_out: *token_out,
+            venue: Venue::UniswapV4 {
+                currency0: self.currency0,
+                currency1: self.currency1,
+                fee: self.fee,
+                tick_spacing: self.tick_spacing,
+                hooks: self.hooks,
+            },
+            amount_out: self.state.swap_exact_in(zero_for_one, amount_in)?,
+        })
+    }
+
+    /// Edge of the graph if cycles of the pool can be sized: pools with swap-altering hooks
+    /// are not quoted, native ETH is not settled by the bot
+    fn pair(&self, dex_id: i32) -> Option<Pair> {
+        if self.hook_class == HookClass::SwapAltering || self.currency0 == Address::ZERO {
+            return None;
+        }
+        Some(Pair {
+            address: self.address(),
+            dex_id,
+            token0: self.currency0,
+            token1: self.currency1,
+        })
+    }
+
+    /// Virtual reserves of the active range: `L / sqrt(P)` and `L * sqrt(P)`
+    pub fn virtual_reserves(&self) -> (U256, U256) {
+        let liquidity = U256::from(self.state.liquidity);
+        let sqrt_price = self.state.sqrt_price_x96;
+        if sqrt_price.is_zero() {
+            return (U256::ZERO, U256::ZERO);
+        }
+        (
+            (liquidity << 96usize) / sqrt_price,
+            (liquidity * sqrt_price) >> 96usize,
+        )
+    }
+}
+
+/// `UniswapV4` indexes pools of the singleton PoolManager.
+/// Pools are discovered from `Initialize` events and their ticks are rebuilt from
+/// `ModifyLiquidity` history, price and active liquidity are taken from `Swap` events,
+/// protocol fee from `ProtocolFeeUpdated`.
+/// Pools are classified by hook flags: pools whose hooks alter swaps can't be quoted
+/// with V3 math, so they are not indexed. Cycles are searched over the other pools
+pub struct UniswapV4 {
+    name: String,
+    dex_id: i32,
+    tokens: TokenRegistry,
+    provider: Arc<RootProvider>,
+    pools: RwLock<HashMap<B256, V4Pool>>,
+    search: CycleSearch,
+    // last block whose events are applied
+    synced_block: Mutex<u64>,
+    health: Mutex<DexHealth>,
+}
+
+impl UniswapV4 {
+    pub async fn new(
+        config: &DexConfig,
+        db: DB,
+        tokens: TokenRegistry,
+        inventory: Inventory,
+        provider: Arc<RootProvider>,
+    ) -> Result<Self> {
+        let dex_id = db.postgres().get_dex_id(&config.name).await?;
+
+        let uniswap = Self {
+            name: config.name.clone(),
+            dex_id,
+            search: CycleSearch::new(&config.name, dex_id, db, tokens.clone(), inventory),
+            tokens,
+            provider,
+            pools: RwLock::new(HashMap::new()),
+            synced_block: Mutex::new(0),
+            health: Mutex::new(DexHealth::new(&config.name, dex_id)),
+        };
+        uniswap.load_pools().await?;
+
+        Ok(uniswap)
+    }
+
+    pub fn pool(&self, pool_id: &B256) -> Option<V4Pool> {
+        self.pools.read().unwrap().get(pool_id).cloned()
+    }
+
+    /// Best output over all pools with both tokens: (pool id, amount_out)
+    pub fn quote(
+        &self,
+        token_in: &Address,
+        token_out: &Address,
+        amount_in: U256,
+    ) -> Option<(B256, U256)> {
+        self.pools
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(|pool| {
+                Some((
+                    pool.id,
+                    pool.hop(token_in, token_out, amount_in)?.amount_out,
+                ))
+            })
+            .max_by_key(|(_, amount_out)| *amount_out)
+    }
+
+    async fn load_pools(&self) -> Result<()> {
+        // logs and state are read up to the same block, later blocks are applied by events
+        let latest = self.provider.get_block_number().await?;
+
+        let mut from = POOL_MANAGER_DEPLOY_BLOCK;
+        while from <= latest {
+            let to = (from + LOGS_CHUNK - 1).min(latest);
+            let filter = Filter::new()
+                .address(POOL_MANAGER)
+                .event_signature(vec![
+                    IPoolManager::Initialize::SIGNATURE_HASH,
+                    IPoolManager::ModifyLiquidity::SIGNATURE_HASH,
+                ])
+                .from_block(from)
+                .to_block(to);
+            for log in self.provider.get_logs(&filter).await? {
+                self.apply_log(&log)?;
+            }
+            from = to + 1;
+        }
+
+        // ticks are complete, price, active liquidity and fees are read at the same block
+        self.fetch_state(BlockId::number(latest)).await?;
+        *self.synced_block.lock().unwrap() = latest;
+
+        let pools: Vec<V4Pool> = self.pools.read().unwrap().values().cloned().collect();
+        let cycles = self.add_pools(&pools).await?;
+        let hooked = pools
+            .iter()
+            .filter(|pool| pool.hook_class != HookClass::NoHooks)
+            .count();
+        tracing::info!(
+            "🦄 {} indexed {} pools, {hooked} with hooks, {cycles} cycles",
+            self.name,
+            pools.len()
+        );
+        Ok(())
+    }
+
+    /// Registers currencies of pools and adds their edges to the graph,
+    /// returns number of all cycles
+    async fn add_pools(&self, pools: &[V4Pool]) -> Result<usize> {
+        let currencies: Vec<Address> = pools
+            .iter()
+            .flat_map(|pool| [pool.currency0, pool.currency1])
+            .filter(|currency| *currency != Address::ZERO)
+            .collect();
+        self.tokens.ensure_tokens(&currencies).await?;
+
+        let pairs: Vec<Pair> = pools
+            .iter()
+            .filter_map(|pool| pool.pair(self.dex_id))
+            .collect();
+        Ok(self.search.add_pairs(&pairs))
+    }
+
+    async fn fetch_state(&self, block: BlockId) -> Result<()> {
+        let ids: Vec<B256> = self.pools.read().unwrap().keys().copied().collect();
+
+        let mut calls = Vec::with_capacity(ids.len() * STATE_CALLS);
+        for pool_id in ids.iter() {
+            calls.push(call(
+                STATE_VIEW,
+                IStateView::getSlot0Call { poolId: *pool_id },
+            ));
+            calls.push(call(
+                STATE_VIEW,
+                IStateView::getLiquidityCall { poolId: *pool_id },
+            ));
+        }
+        let results = multicall_at(&self.provider, calls, block).await?;
+
+        let mut pools = self.pools.write().unwrap();
+        for (pool_id, data) in ids.iter().zip(results.chunks(STATE_CALLS)) {
+            let Some(pool) = pools.get_mut(pool_id) else {
+                continue;
+            };
+            let (Some(slot0), Some(liquidity)) = (
+                decode::<IStateView::getSlot0Call>(&data[0]),
+                decode::<IStateView::getLiquidityCall>(&data[1]),
+            ) else {
+                tracing::warn!("failed to fetch state of v4 pool {pool_id}");
+                continue;
+            };
+            pool.state.sqrt_price_x96 = U256::from(slot0.sqrtPriceX96);
+            pool.state.tick = slot0.tick.as_i32();
+            pool.state.fee = slot0.lpFee.to();
+            pool.state.protocol_fee = slot0.protocolFee.to();
+            pool.state.liquidity = liquidity.liquidity;
+        }
+
+        Ok(())
+    }
+
+    async fn handle_block(&self, block: Header) -> Result<Vec<Arbitrage>> {
+        let synced_block = *self.synced_block.lock().unwrap();
+        if block.number <= synced_block {
+            return Ok(vec![]);
+        }
+
+        // ticks are rebuilt from events, so events of blocks after the loaded state
+        // and of skipped blocks are applied together with the block
+        let mut logs = vec![];
+        let mut from = synced_block + 1;
+        while from <= block.number {
+            let to = (from + LOGS_CHUNK - 1).min(block.number);
+            let filter = Filter::new()
+                .address(POOL_MANAGER)
+                .event_signature(vec![
+                    IPoolManager::Initialize::SIGNATURE_HASH,
+                    IPoolManager::ModifyLiquidity::SIGNATURE_HASH,
+                    IPoolManager::Swap::SIGNATURE_HASH,
+                    IPoolManager::ProtocolFeeUpdated::SIGNATURE_HASH,
+                ])
+                .from_block(from)
+                .to_block(to);
+            logs.extend(self.provider.get_logs(&filter).await?);
+            from = to + 1;
+        }
+
+        // currencies of new pools are registered before events are applied,
+        // so the failed block can be retried without applying liquidity twice
+        let mut initialized = vec![];
+        for log in logs.iter() {
+            if log.topic0() == Some(&IPoolManager::Initialize::SIGNATURE_HASH) {
+                let init = IPoolManager::Initialize::decode_log(&log.inner, false)?;
+                initialized.extend([init.currency0, init.currency1]);
+            }
+        }
+        initialized.retain(|currency| *currency != Address::ZERO);
+        self.tokens.ensure_tokens(&initialized).await?;
+
+        let mut changed = HashSet::new();
+        let mut new_pools = vec![];
+        for log in logs.iter() {
+            let Some(pool_id) = self.apply_log(log)? else {
+                continue;
+            };
+            if log.topic0() == Some(&IPoolManager::Initialize::SIGNATURE_HASH) {
+                new_pools.push(pool_id);
+            }
+            changed.insert(Address::from_slice(&pool_id[..20]));
+        }
+
+        if !new_pools.is_empty() {
+            let pools: Vec<V4Pool> = new_pools.iter().filter_map(|id| self.pool(id)).collect();
+            self.add_pools(&pools).await?;
+        }
+        *self.synced_block.lock().unwrap() = block.number;
+
+        let changed: Vec<Address> = changed.into_iter().collect();
+        self.search
+            .find(block.number, &changed, |pools| {
+                let pools: HashMap<Address, V4Pool> = self
+                    .pools
+                    .read()
+                    .unwrap()
+                    .values()
+                    .filter(|pool| pools.contains(&pool.address()))
+                    .map(|pool| (pool.address(), pool.clone()))
+                    .collect();
+                move |edge, token_in, token_out, amount_in| {
+                    pools.get(&edge.pair)?.hop(token_in, token_out, amount_in)
+                }
+            })
+            .await
+    }
+
+    /// Applies the event to the pool, returns id of the changed pool
+    fn apply_log(&self, log: &Log) -> Result<Option<B256>> {
+        let mut pools = self.pools.write().unwrap();
+
+        match log.topic0() {
+            Some(&IPoolManager::Initialize::SIGNATURE_HASH) => {
+                let init = IPoolManager::Initialize::decode_log(&log.inner, false)?;
+                let fee: u32 = init.fee.to();
+                let hook_class = HookClass::classify(&init.hooks, fee);
+                if hook_class == HookClass::SwapAltering {
+                    return Ok(None);
+                }
+
+                let tick_spacing = init.tickSpacing.as_i32();
+                let pool = V4Pool {
+                    id: init.id,
+                    currency0: init.currency0,
+                    currency1: init.currency1,
+                    fee,
+                    tick_spacing,
+                    hooks: init.hooks,
+                    hook_class,
+                    state: ConcentratedPool::new(
+                        U256::from(init.sqrtPriceX96),
+                        init.tick.as_i32(),
+                        fee & !DYNAMIC_FEE_FLAG,
+                        tick_spacing,
+                    ),
+                };
+                pools.insert(init.id, pool);
+                Ok(Some(init.id))
+            }
+            Some(&IPoolManager::ModifyLiquidity::SIGNATURE_HASH) => {
+                let modify = IPoolManager::ModifyLiquidity::decode_log(&log.inner, false)?;
+                let Some(pool) = pools.get_mut(&modify.id) else {
+                    return Ok(None);
+                };
+                pool.state.modify_liquidity(
+                    modify.tickLower.as_i32(),
+                    modify.tickUpper.as_i32(),
+                    modify.liquidityDelta.try_into().map_err(|_| {
+                        alloy::sol_types::Error::custom("liquidity delta exceeds i128")
+                    })?,
+                );
+                Ok(Some(modify.id))
+            }
+            Some(&IPoolManager::Swap::SIGNATURE_HASH) => {
+                let swap = IPoolManager::Swap::decode_log(&log.inner, false)?;
+                let Some(pool) = pools.get_mut(&swap.id) else {
+                    return Ok(None);
+                };
+                pool.state.sqrt_price_x96 = U256::from(swap.sqrtPriceX96);
+                pool.state.tick = swap.tick.as_i32();
+                pool.state.liquidity = swap.liquidity;
+                Ok(Some(swap.id))
+            }
+            Some(&IPoolManager::ProtocolFeeUpdated::SIGNATURE_HASH) => {
+                let update = IPoolManager::ProtocolFeeUpdated::decode_log(&log.inner, false)?;
+                let Some(pool) = pools.get_mut(&update.id) else {
+                    return Ok(None);
+                };
+                pool.state.protocol_fee = update.protocolFee.to();
+                Ok(Some(update.id))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl DEX for UniswapV4 {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn dex_id(&self) -> i32 {
+        self.dex_id
+    }
+
+    fn health(&self) -> DexHealth {
+        self.health.lock().unwrap().clone()
+    }
+
+    async fn process_block(&self, block: Header) -> Result<Vec<Arbitrage>> {
+        let block_number = block.number;
+        let result = self.handle_block(block).await;
+        self.health.lock().unwrap().record(block_number, &result);
+        result
+    }
+
+    async fn adjacent(&self, token: &Address) -> Result<HashSet<Address>> {
+        Ok(self
+            .pools
+            .read()
+            .unwrap()
+            .values()
+            .filter(|pool| pool.has_currency(token))
+            .flat_map(|pool| [pool.currency0, pool.currency1])
+            .filter(|currency| currency != token)
+            .collect())
+    }
+
+    /// V4 pools do not have addresses, they are identified by pool id
+    async fn fetch_reserves(&self, pair_adr: &Address) -> Result<Reserves> {
+        Err(DexError::not_found(format!(
+            "v4 pool with address {pair_adr:?}"
+        )))
+    }
+
+    async fn owns_pair(&self, _pair_adr: &Address) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Virtual reserves of the pool with the most active liquidity
+    async fn token_reserves(&self, token0: &Address, token1: &Address) -> Result<Reserves> {
+        self.pools
+            .read()
+            .unwrap()
+            .values()
+            .filter(|pool| pool.direction(token0, token1).is_some())
+            .max_by_key(|pool| pool.state.liquidity)
+            .map(|pool| {
+                let (reserve0, reserve1) = pool.virtual_reserves();
+                match pool.currency0 == *token0 {
+                    true => Reserves(
+                        Uint::saturating_from(reserve0),
+                        Uint::saturating_from(reserve1),
+                    ),
+                    false => Reserves(
+                        Uint::saturating_from(reserve1),
+                        Uint::saturating_from(reserve0),
+                    ),
+                }
+            })
+            .ok_or(DexError::not_found(format!(
+                "v4 pool for {token0:?}, {token1:?}"
+            )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(currency0: Address, fee: u32) -> V4Pool {
+        let mut state = ConcentratedPool::new(U256::from(1) << 96usize, 0, fee, 10);
+        state.modify_liquidity(-600, 600, 1_000_000_000_000_000_000_000);
+        V4Pool {
+            id: B256::repeat_byte(0x44),
+            currency0,
+            currency1: Address::repeat_byte(2),
+            fee,
+            tick_spacing: 10,
+            hooks: Address::ZERO,
+            hook_class: HookClass::classify(&Address::ZERO, fee),
+            state,
+        }
+    }
+
+    #[test]
+    fn only_pools_quoted_with_v3_math_are_edges() {
+        let token = Address::repeat_byte(1);
+        let pair = pool(token, 500).pair(7).unwrap();
+        assert_eq!(pair.address, Address::repeat_byte(0x44));
+        assert_eq!((pair.token0, pair.token1), (token, Address::repeat_byte(2)));
+
+        let hop = pool(token, 500)
+            .hop(&Address::repeat_byte(2), &token, U256::from(1000))
+            .unwrap();
+        assert_eq!(hop.pool, pair.address);
+        assert!(matches!(hop.venue, Venue::UniswapV4 { fee: 500, .. }));
+
+        // native ETH is not settled by the bot, dynamic fee pools are not quoted
+        assert!(pool(Address::ZERO, 500).pair(7).is_none());
+        let dynamic = pool(token, DYNAMIC_FEE_FLAG);
+        assert!(dynamic.pair(7).is_none());
+        assert!(dynamic
+            .hop(&token, &Address::repeat_byte(2), U256::from(1000))
+            .is_none());
+    }
+
+    #[test]
+    fn classifies_pools_by_hook_flags_and_dynamic_fee() {
+        assert_eq!(
+            HookClass::classify(&Address::ZERO, 3000),
+            HookClass::NoHooks
+        );
+        assert_eq!(
+            HookClass::classify(&Address::ZERO, DYNAMIC_FEE_FLAG),
+            HookClass::SwapAltering
+        );
+
+        // afterInitialize and afterSwap only
+        let passive = address!("0x0000000000000000000000000000000000001040");
+        assert_eq!(HookClass::classify(&passive, 500), HookClass::Passive);
+
+        let before_swap = address!("0x0000000000000000000000000000000000000080");
+        assert_eq!(
+            HookClass::classify(&before_swap, 500),
+            HookClass::SwapAltering
+        );
+
+        let returns_delta = address!("0x1234000000000000000000000000000000000044");
+        assert_eq!(
+            HookClass::classify(&returns_delta, 500),
+            HookClass::SwapAltering
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use crate::error::{ExecutorError, Result};
+use alloy::{
+    primitives::{Address, Bytes, B256, I256, U256},
+    sol_types::SolCall,
+};
+use ethereum_abi::IBalancerVault::{self, BatchSwapStep, FundManagement, SwapKind};
+
+/// One swap of the path through a Balancer pool
+#[derive(Clone, Debug)]
+pub struct BalancerHop {
+    pub pool_id: B256,
+    pub token_in: Address,
+    pub token_out: Address,
+}
+
+/// Encodes Vault `batchSwap` with `GIVEN_IN` kind for a path of hops.
+/// Only the first step has an amount, the Vault uses output of the previous step for the others.
+/// Limits allow to pay at most `amount_in` of the first token and require at least
+/// `min_amount_out` of the last one
+pub fn encode_batch_swap(
+    hops: &[BalancerHop],
+    amount_in: U256,
+    min_amount_out: U256,
+    account: Address,
+    deadline: U256,
+) -> Result<Bytes> {
+    let (Some(first), Some(last)) = (hops.first(), hops.last()) else {
+        return Err(ExecutorError::invalid_path("empty balancer path"));
+    };
+
+    let mut assets: Vec<Address> = vec![];
+    let mut asset_index = |token: Address| match assets.iter().position(|a| *a == token) {
+        Some(index) => index,
+        None => {
+            assets.push(token);
+            assets.len() - 1
+        }
+    };
+
+    let swaps: Vec<BatchSwapStep> = hops
+        .iter()
+        .enumerate()
+        .map(|(i, hop)| BatchSwapStep {
+            poolId: hop.pool_id,
+            assetInIndex: U256::from(asset_index(hop.token_in)),
+            assetOutIndex: U256::from(asset_index(hop.token_out)),
+            amount: match i {
+                0 => amount_in,
+                _ => U256::ZERO,
+            },
+            userData: Bytes::new(),
+        })
+        .collect();
+
+    // positive limit is the max amount sent to the Vault, negative is the min amount received.
+    // For cycles the first and the last tokens are the same asset
+    let mut limits = vec![I256::ZERO; assets.len()];
+    let first_index = asset_index_of(&assets, &first.token_in)?;
+    let last_index = asset_index_of(&assets, &last.token_out)?;
+    limits[first_index] = to_limit(amount_in)?;
+    limits[last_index] -= to_limit(min_amount_out)?;
+
+    let call = IBalancerVault::batchSwapCall {
+        kind: SwapKind::GIVEN_IN,
+        swaps,
+        assets,
+        funds: FundManagement {
+            sender: account,
+            fromInternalBalance: false,
+            recipient: account,
+            toInternalBalance: false,
+        },
+        limits,
+        deadline,
+    };
+
+    Ok(call.abi_encode().into())
+}
+
+fn asset_index_of(assets: &[Address], token: &Address) -> Result<usize> {
+    assets
+        .iter()
+        .position(|asset| asset == token)
+        .ok_or(ExecutorError::invalid_path(format!(
+            "unknown asset {token:?}"
+        )))
+}
+
+fn to_limit(amount: U256) -> Result<I256> {
+    I256::try_from(amount)
+        .map_err(|_| ExecutorError::invalid_path(format!("amount {amount} exceeds int256")))
+}
@@ -0,0 +1,124 @@
+use crate::{
+    encode::ArbitrageTx,
+    error::{ExecutorError, Result},
+};
+use alloy::{primitives::Address, providers::RootProvider};
+use ethereum_abi::ArbBot;
+use kronos_common::RpcError;
+use kronos_config::ExecutorConfig;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::OnceCell;
+
+/// Address of the bot contract, it is required in every mode
+pub fn bot_address(config: &ExecutorConfig) -> Result<Address> {
+    let bot = config
+        .bot
+        .as_deref()
+        .ok_or(ExecutorError::config("bot is required"))?;
+    bot.parse::<Address>()
+        .map_err(|err| ExecutorError::config(format!("invalid bot address {bot}: {err}")))
+}
+
+/// Permissions of the ArbBot contract: accounts which may start its flash loans and contracts
+/// which may be called with them. Only granted permissions are cached, so the ones which
+/// the owner grants with `setExecutors` and `setTargets` later are picked up
+pub struct BotPermissions {
+    bot: ArbBot::ArbBotInstance<(), Arc<RootProvider>>,
+    owner: OnceCell<Address>,
+    executors: Mutex<HashSet<Address>>,
+    targets: Mutex<HashSet<Address>>,
+}
+
+impl BotPermissions {
+    pub fn new(provider: Arc<RootProvider>, bot: Address) -> Self {
+        Self {
+            bot: ArbBot::new(bot, provider),
+            owner: OnceCell::new(),
+            executors: Mutex::new(HashSet::new()),
+            targets: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn bot(&self) -> Address {
+        *self.bot.address()
+    }
+
+    pub async fn owner(&self) -> Result<Address> {
+        let owner = self
+            .owner
+            .get_or_try_init(|| async {
+                self.bot
+                    .owner()
+                    .call()
+                    .await
+                    .map(|owner| owner._0)
+                    .map_err(RpcError::from)
+            })
+            .await?;
+        Ok(*owner)
+    }
+
+    /// Checks that the bot would run the transaction sent by `account`: the account is
+    /// the owner or an executor and all targets are allowed. Transactions which don't
+    /// call the bot need no permissions
+    pub async fn check(&self, tx: &ArbitrageTx, account: Address) -> Result<()> {
+        if tx.to != self.bot() {
+            return Ok(());
+        }
+        if !self.is_executor(account).await? {
+            return Err(ExecutorError::permission(format!(
+                "{account} is not an executor of the bot, add it with setExecutors"
+            )));
+        }
+
+        let unknown: Vec<Address> = {
+            let targets = self.targets.lock().unwrap();
+            tx.targets
+                .iter()
+                .filter(|target| !targets.contains(*target))
+                .copied()
+                .collect()
+        };
+        let mut denied = vec![];
+        for target in unknown {
+            let allowed = self
+                .bot
+                .allowedTargets(target)
+                .call()
+                .await
+                .map_err(RpcError::from)?
+                ._0;
+            if allowed {
+                self.targets.lock().unwrap().insert(target);
+            } else {
+                denied.push(target);
+            }
+        }
+        if !denied.is_empty() {
+            return Err(ExecutorError::permission(format!(
+                "targets {denied:?} are not allowed by the bot, add them with setTargets"
+            )));
+        }
+        Ok(())
+    }
+
+    async fn is_executor(&self, account: Address) -> Result<bool> {
+        if account == self.owner().await? || self.executors.lock().unwrap().contains(&account) {
+            return Ok(true);
+        }
+        let allowed = self
+            .bot
+            .executors(account)
+            .call()
+            .await
+            .map_err(RpcError::from)?
+            ._0;
+        if allowed {
+            self.executors.lock().unwrap().insert(account);
+        }
+        Ok(allowed)
+    }
+}
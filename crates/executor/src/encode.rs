@@ -0,0 +1,440 @@
+use crate::{
+    balancer::{encode_batch_swap, BalancerHop},
+    error::{ExecutorError, Result},
+};
+use alloy::{
+    primitives::{
+        aliases::{I24, U24},
+        Address, Bytes, U256,
+    },
+    sol_types::{SolCall, SolValue},
+};
+use ethereum_abi::{ArbBot, ICurvePool, IPoolManager, IUniswapV2Pair, IV4Quoter::PoolKey, IERC20};
+use kronos_dexes::{
+    balancer::VAULT,
+    common::{Arbitrage, Hop, Venue},
+    uniswap_v4::POOL_MANAGER,
+};
+
+/// Transaction which executes the arbitrage
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArbitrageTx {
+    pub to: Address,
+    pub data: Bytes,
+    /// Contracts which the bot calls, they must be allowed by `setTargets` of the bot
+    pub targets: Vec<Address>,
+}
+
+/// Encodes the arbitrage for `account` which sends the transaction.
+/// Swaps of Balancer pools can't run inside of the Vault flash loan, the Vault is not reentrant.
+/// Cycle of Balancer pools is one `batchSwap` of the account instead: the Vault settles only
+/// net amounts, so the account receives the revenue without funds of its own.
+/// Other cycles are swapped by the bot contract: with `inventory` from the balance
+/// of the account, otherwise with a flash loan of the start token
+pub fn encode_arbitrage(
+    arbitrage: &Arbitrage,
+    bot: Address,
+    account: Address,
+    deadline: U256,
+    inventory: bool,
+) -> Result<ArbitrageTx> {
+    let balancer = |hop: &Hop| matches!(hop.venue, Venue::Balancer { .. });
+    if !arbitrage.hops.is_empty() && arbitrage.hops.iter().all(balancer) {
+        let hops: Vec<BalancerHop> = arbitrage
+            .hops
+            .iter()
+            .filter_map(|hop| match hop.venue {
+                Venue::Balancer { pool_id } => Some(BalancerHop {
+                    pool_id,
+                    token_in: hop.token_in,
+                    token_out: hop.token_out,
+                }),
+                _ => None,
+            })
+            .collect();
+        let amount_out = arbitrage.hops[arbitrage.hops.len() - 1].amount_out;
+        return Ok(ArbitrageTx {
+            to: VAULT,
+            data: encode_batch_swap(&hops, arbitrage.amount_in, amount_out, account, deadline)?,
+            targets: vec![],
+        });
+    }
+
+    let (calls, data) = match inventory {
+        true => encode_inventory_swap(arbitrage, bot)?,
+        false => encode_flash_loan(arbitrage, bot)?,
+    };
+    let mut targets = calls.targets;
+    targets.sort();
+    targets.dedup();
+    Ok(ArbitrageTx {
+        to: bot,
+        data,
+        targets,
+    })
+}
+
+/// Low level calls which the bot contract makes with the flash loan
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BotCalls {
+    pub targets: Vec<Address>,
+    pub calls: Vec<Bytes>,
+}
+
+impl BotCalls {
+    fn push(&mut self, target: Address, call: impl SolCall) {
+        self.targets.push(target);
+        self.calls.push(call.abi_encode().into());
+    }
+
+    /// `(address[] targets, bytes[] calls)` as it is decoded by `receiveFlashLoan`
+    pub fn abi_encode(&self) -> Bytes {
+        (self.targets.clone(), self.calls.clone())
+            .abi_encode_params()
+            .into()
+    }
+}
+
+/// Calls which swap `amount_in` through `hops` with exact amounts of the arbitrage.
+/// Every swap requires the output it was sized with, so the transaction reverts
+/// if the state moved. Output of Uniswap V2 pair goes directly to the next pair.
+/// Uniswap V4 swap is `unlock` of the PoolManager with
+/// `(PoolKey key, bool zeroForOne, uint amountIn, uint minAmountOut)`,
+/// which the bot swaps and settles in `unlockCallback`
+pub fn hop_calls(hops: &[Hop], amount_in: U256, bot: Address) -> Result<BotCalls> {
+    let mut calls = BotCalls::default();
+    let mut amount = amount_in;
+    for (k, hop) in hops.iter().enumerate() {
+        let next = hops.get(k + 1);
+        match &hop.venue {
+            Venue::UniswapV2 => {
+                let previous = k.checked_sub(1).map(|k| &hops[k]);
+                if !previous.is_some_and(|previous| previous.venue == Venue::UniswapV2) {
+                    calls.push(
+                        hop.token_in,
+                        IERC20::transferCall {
+                            _to: hop.pool,
+                            _value: amount,
+                        },
+                    );
+                }
+                let (amount0_out, amount1_out) = match hop.token_in < hop.token_out {
+                    true => (U256::ZERO, hop.amount_out),
+                    false => (hop.amount_out, U256::ZERO),
+                };
+                let to = match next {
+                    Some(next) if next.venue == Venue::UniswapV2 => next.pool,
+                    _ => bot,
+                };
+                calls.push(
+                    hop.pool,
+                    IUniswapV2Pair::swapCall {
+                        amount0Out: amount0_out,
+                        amount1Out: amount1_out,
+                        to,
+                        data: Bytes::new(),
+                    },
+                );
+            }
+            Venue::Curve { i, j } => {
+                calls.push(
+                    hop.token_in,
+                    IERC20::approveCall {
+                        _spender: hop.pool,
+                        _value: amount,
+                    },
+                );
+                calls.push(
+                    hop.pool,
+                    ICurvePool::exchangeCall {
+                        i: *i,
+                        j: *j,
+                        dx: amount,
+                        min_dy: hop.amount_out,
+                    },
+                );
+            }
+            Venue::Balancer { .. } => {
+                return Err(ExecutorError::invalid_path(
+                    "balancer swap inside of balancer flash loan",
+                ));
+            }
+            Venue::UniswapV4 {
+                currency0,
+                currency1,
+                fee,
+                tick_spacing,
+                hooks,
+            } => {
+                let key = PoolKey {
+                    currency0: *currency0,
+                    currency1: *currency1,
+                    fee: U24::from(*fee),
+                    tickSpacing: I24::try_from(*tick_spacing).map_err(|_| {
+                        ExecutorError::invalid_path(format!("tick spacing {tick_spacing}"))
+                    })?,
+                    hooks: *hooks,
+                };
+                let zero_for_one = hop.token_in == *currency0;
+                calls.push(
+                    POOL_MANAGER,
+                    IPoolManager::unlockCall {
+                        data: (key, zero_for_one, amount, hop.amount_out)
+                            .abi_encode_params()
+                            .into(),
+                    },
+                );
+            }
+        }
+        amount = hop.amount_out;
+    }
+
+    if calls.calls.is_empty() {
+        return Err(ExecutorError::invalid_path("arbitrage without hops"));
+    }
+    Ok(calls)
+}
+
+/// `flashLoanBalancer` of the bot contract which borrows `amount_in` of the start token
+/// and swaps it through the hops of the arbitrage
+pub fn encode_flash_loan(arbitrage: &Arbitrage, bot: Address) -> Result<(BotCalls, Bytes)> {
+    let start = arbitrage
+        .path
+        .first()
+        .ok_or(ExecutorError::invalid_path("empty path"))?
+        .0;
+    let calls = hop_calls(&arbitrage.hops, arbitrage.amount_in, bot)?;
+    let data = ArbBot::flashLoanBalancerCall {
+        tokens: vec![start],
+        amounts: vec![arbitrage.amount_in],
+        data: calls.abi_encode(),
+    }
+    .abi_encode()
+    .into();
+    Ok((calls, data))
+}
+
+/// `swapInventory` of the bot contract which takes `amount_in` of the start token from
+/// the sending account, swaps it through the hops of the arbitrage and returns the output
+pub fn encode_inventory_swap(arbitrage: &Arbitrage, bot: Address) -> Result<(BotCalls, Bytes)> {
+    let start = arbitrage
+        .path
+        .first()
+        .ok_or(ExecutorError::invalid_path("empty path"))?
+        .0;
+    let calls = hop_calls(&arbitrage.hops, arbitrage.amount_in, bot)?;
+    let data = ArbBot::swapInventoryCall {
+        token: start,
+        amountIn: arbitrage.amount_in,
+        data: calls.abi_encode(),
+    }
+    .abi_encode()
+    .into();
+    Ok((calls, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloy::primitives::{B256, I256};
+    use ethereum_abi::IBalancerVault;
+
+    const BOT: Address = Address::repeat_byte(0xb0);
+    const ACCOUNT: Address = Address::repeat_byte(0xac);
+    const A: Address = Address::repeat_byte(1);
+    const B: Address = Address::repeat_byte(2);
+    const C: Address = Address::repeat_byte(3);
+
+    fn hop(pool: u8, token_in: Address, token_out: Address, venue: Venue, out: u64) -> Hop {
+        Hop {
+            pool: Address::repeat_byte(pool),
+            token_in,
+            token_out,
+            venue,
+            amount_out: U256::from(out),
+        }
+    }
+
+    #[test]
+    fn chains_uniswap_v2_pairs() {
+        let hops = [
+            hop(10, A, B, Venue::UniswapV2, 200),
+            hop(11, B, C, Venue::UniswapV2, 300),
+            hop(12, C, A, Venue::UniswapV2, 110),
+        ];
+        let calls = hop_calls(&hops, U256::from(100), BOT).unwrap();
+        // input is transferred once, every pair sends its output to the next one
+        assert_eq!(
+            calls.targets,
+            vec![A, hops[0].pool, hops[1].pool, hops[2].pool]
+        );
+
+        let transfer = IERC20::transferCall::abi_decode(&calls.calls[0], true).unwrap();
+        assert_eq!(
+            (transfer._to, transfer._value),
+            (hops[0].pool, U256::from(100))
+        );
+
+        let swap = IUniswapV2Pair::swapCall::abi_decode(&calls.calls[1], true).unwrap();
+        assert_eq!(
+            (swap.amount0Out, swap.amount1Out),
+            (U256::ZERO, U256::from(200))
+        );
+        assert_eq!(swap.to, hops[1].pool);
+        // C -> A sells token1 for token0
+        let swap = IUniswapV2Pair::swapCall::abi_decode(&calls.calls[3], true).unwrap();
+        assert_eq!(
+            (swap.amount0Out, swap.amount1Out),
+            (U256::from(110), U256::ZERO)
+        );
+        assert_eq!(swap.to, BOT);
+    }
+
+    #[test]
+    fn exchanges_on_curve_with_exact_approvals() {
+        let hops = [
+            hop(20, A, B, Venue::Curve { i: 0, j: 1 }, 99),
+            hop(21, B, A, Venue::Curve { i: 2, j: 0 }, 101),
+        ];
+        let calls = hop_calls(&hops, U256::from(100), BOT).unwrap();
+        assert_eq!(calls.targets, vec![A, hops[0].pool, B, hops[1].pool]);
+
+        let approve = IERC20::approveCall::abi_decode(&calls.calls[2], true).unwrap();
+        assert_eq!(
+            (approve._spender, approve._value),
+            (hops[1].pool, U256::from(99))
+        );
+        let exchange = ICurvePool::exchangeCall::abi_decode(&calls.calls[3], true).unwrap();
+        assert_eq!((exchange.i, exchange.j), (2, 0));
+        assert_eq!(
+            (exchange.dx, exchange.min_dy),
+            (U256::from(99), U256::from(101))
+        );
+    }
+
+    #[test]
+    fn swaps_uniswap_v4_in_unlock_callback() {
+        let venue = Venue::UniswapV4 {
+            currency0: A,
+            currency1: B,
+            fee: 500,
+            tick_spacing: -10,
+            hooks: Address::ZERO,
+        };
+        let hops = [
+            hop(10, A, B, Venue::UniswapV2, 200),
+            hop(40, B, A, venue, 110),
+        ];
+        let calls = hop_calls(&hops, U256::from(100), BOT).unwrap();
+        // the pair sends to the bot, the bot settles the PoolManager itself
+        assert_eq!(calls.targets, vec![A, hops[0].pool, POOL_MANAGER]);
+        let swap = IUniswapV2Pair::swapCall::abi_decode(&calls.calls[1], true).unwrap();
+        assert_eq!(swap.to, BOT);
+
+        let unlock = IPoolManager::unlockCall::abi_decode(&calls.calls[2], true).unwrap();
+        let (key, zero_for_one, amount_in, min_amount_out) =
+            <(PoolKey, bool, U256, U256)>::abi_decode_params(&unlock.data, true).unwrap();
+        assert_eq!((key.currency0, key.currency1), (A, B));
+        assert_eq!((key.fee.to::<u32>(), key.tickSpacing.as_i32()), (500, -10));
+        assert!(!zero_for_one);
+        assert_eq!(
+            (amount_in, min_amount_out),
+            (U256::from(200), U256::from(110))
+        );
+    }
+
+    #[test]
+    fn flash_loan_data_is_decoded_by_the_bot() {
+        let hops = vec![
+            hop(20, A, B, Venue::Curve { i: 0, j: 1 }, 99),
+            hop(21, B, A, Venue::Curve { i: 1, j: 0 }, 101),
+        ];
+        let arbitrage = Arbitrage {
+            dex_id: 1,
+            block_number: 1,
+            amount_in: U256::from(100),
+            revenue: U256::from(1),
+            path: vec![(A, B), (B, A)],
+            hops,
+            span: tracing::Span::none(),
+        };
+        let (calls, data) = encode_flash_loan(&arbitrage, BOT).unwrap();
+        let loan = ArbBot::flashLoanBalancerCall::abi_decode(&data, true).unwrap();
+        assert_eq!(loan.tokens, vec![A]);
+        assert_eq!(loan.amounts, vec![U256::from(100)]);
+
+        let (targets, bytes) =
+            <(Vec<Address>, Vec<Bytes>)>::abi_decode_params(&loan.data, true).unwrap();
+        assert_eq!(
+            BotCalls {
+                targets,
+                calls: bytes
+            },
+            calls
+        );
+
+        assert!(hop_calls(&[], U256::from(1), BOT).is_err());
+
+        let tx = encode_arbitrage(&arbitrage, BOT, ACCOUNT, U256::MAX, false).unwrap();
+        assert_eq!((tx.to, tx.data), (BOT, data));
+        // tokens and pools, sorted once
+        let pools = [Address::repeat_byte(20), Address::repeat_byte(21)];
+        assert_eq!(tx.targets, vec![A, B, pools[0], pools[1]]);
+    }
+
+    #[test]
+    fn inventory_swap_spends_the_balance_of_the_account() {
+        let arbitrage = Arbitrage {
+            dex_id: 1,
+            block_number: 1,
+            amount_in: U256::from(100),
+            revenue: U256::from(1),
+            path: vec![(A, B), (B, A)],
+            hops: vec![
+                hop(10, A, B, Venue::UniswapV2, 200),
+                hop(11, B, A, Venue::UniswapV2, 101),
+            ],
+            span: tracing::Span::none(),
+        };
+        let tx = encode_arbitrage(&arbitrage, BOT, ACCOUNT, U256::MAX, true).unwrap();
+        assert_eq!(tx.to, BOT);
+        let swap = ArbBot::swapInventoryCall::abi_decode(&tx.data, true).unwrap();
+        assert_eq!((swap.token, swap.amountIn), (A, U256::from(100)));
+
+        let (targets, calls) =
+            <(Vec<Address>, Vec<Bytes>)>::abi_decode_params(&swap.data, true).unwrap();
+        let expected = hop_calls(&arbitrage.hops, arbitrage.amount_in, BOT).unwrap();
+        assert_eq!(BotCalls { targets, calls }, expected);
+        // no flash loan is taken
+        assert!(ArbBot::flashLoanBalancerCall::abi_decode(&tx.data, true).is_err());
+    }
+
+    #[test]
+    fn balancer_cycle_is_one_batch_swap_of_the_account() {
+        let venue = |byte| Venue::Balancer {
+            pool_id: B256::repeat_byte(byte),
+        };
+        let arbitrage = Arbitrage {
+            dex_id: 1,
+            block_number: 1,
+            amount_in: U256::from(100),
+            revenue: U256::from(5),
+            path: vec![(A, B), (B, A)],
+            hops: vec![hop(30, A, B, venue(30), 99), hop(31, B, A, venue(31), 105)],
+            span: tracing::Span::none(),
+        };
+        let tx = encode_arbitrage(&arbitrage, BOT, ACCOUNT, U256::MAX, false).unwrap();
+        assert_eq!(tx.to, VAULT);
+        assert!(tx.targets.is_empty());
+
+        let swap = IBalancerVault::batchSwapCall::abi_decode(&tx.data, true).unwrap();
+        assert_eq!(swap.assets, vec![A, B]);
+        assert_eq!(swap.funds.sender, ACCOUNT);
+        // the account pays nothing and receives at least the revenue
+        assert_eq!(swap.limits, vec![I256::try_from(-5).unwrap(), I256::ZERO]);
+
+        // balancer swap can't be a call of the flash loan
+        assert!(hop_calls(&arbitrage.hops, arbitrage.amount_in, BOT).is_err());
+    }
+}
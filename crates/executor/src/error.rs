@@ -0,0 +1,95 @@
+use kronos_common::{ErrorAction, RpcError};
+use kronos_db::DbError;
+use kronos_math::error::MathError;
+
+pub type Result<T, E = ExecutorError> = std::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExecutorError {
+    #[error(transparent)]
+    Math(#[from] MathError),
+    #[error(transparent)]
+    Db(#[from] DbError),
+    #[error(transparent)]
+    Rpc(#[from] RpcError),
+    /// Path of the arbitrage can't be encoded into a transaction
+    #[error("Invalid path: {0}")]
+    InvalidPath(String),
+    /// Keys of executor accounts can't be loaded or used
+    #[error("Wallet error: {0}")]
+    Wallet(String),
+    /// Start tokens can't be tracked or rebalanced
+    #[error("Inventory error: {0}")]
+    Inventory(String),
+    /// Alert sink is misconfigured or the alert can't be delivered
+    #[error("Alert error: {0}")]
+    Alert(String),
+    /// Bundle is not accepted by the relay
+    #[error("Relay error: {0}")]
+    Relay(String),
+    /// Transaction of the arbitrage would revert against the latest state
+    #[error("Simulation failed: {0}")]
+    Simulation(String),
+    /// Bot contract doesn't let the account or its targets run the arbitrage
+    #[error("Permission error: {0}")]
+    Permission(String),
+    /// Executor can't run with its configuration
+    #[error("Config error: {0}")]
+    Config(String),
+}
+
+impl ExecutorError {
+    pub fn invalid_path(reason: impl Into<String>) -> Self {
+        Self::InvalidPath(reason.into())
+    }
+
+    pub fn wallet(reason: impl Into<String>) -> Self {
+        Self::Wallet(reason.into())
+    }
+
+    pub fn inventory(reason: impl Into<String>) -> Self {
+        Self::Inventory(reason.into())
+    }
+
+    pub fn alert(reason: impl Into<String>) -> Self {
+        Self::Alert(reason.into())
+    }
+
+    pub fn relay(reason: impl Into<String>) -> Self {
+        Self::Relay(reason.into())
+    }
+
+    pub fn simulation(reason: impl Into<String>) -> Self {
+        Self::Simulation(reason.into())
+    }
+
+    pub fn permission(reason: impl Into<String>) -> Self {
+        Self::Permission(reason.into())
+    }
+
+    pub fn config(reason: impl Into<String>) -> Self {
+        Self::Config(reason.into())
+    }
+
+    pub fn action(&self) -> ErrorAction {
+        match self {
+            Self::Math(err) => err.action(),
+            Self::Db(err) => err.action(),
+            Self::Rpc(err) => err.action(),
+            Self::InvalidPath(_) => ErrorAction::Skip,
+            Self::Wallet(_) => ErrorAction::Abort,
+            Self::Inventory(_) => ErrorAction::Skip,
+            Self::Alert(_) => ErrorAction::Skip,
+            Self::Relay(_) => ErrorAction::Skip,
+            Self::Simulation(_) => ErrorAction::Skip,
+            Self::Permission(_) => ErrorAction::Skip,
+            Self::Config(_) => ErrorAction::Abort,
+        }
+    }
+}
+
+impl From<alloy::transports::TransportError> for ExecutorError {
+    fn from(err: alloy::transports::TransportError) -> Self {
+        Self::Rpc(err.into())
+    }
+}
@@ -0,0 +1,372 @@
+use alloy::{
+    consensus::{BlockHeader, Transaction},
+    eips::eip1559::BaseFeeParams,
+    primitives::{Address, U256},
+    providers::{Provider, RootProvider},
+};
+use futures::StreamExt;
+use kronos_config::{GasConfig, GasStrategyConfig};
+use kronos_db::tables::GasBid;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+const GWEI: f64 = 1e9;
+
+/// Pending transactions are forgotten after two slots, they are mined or dropped by then
+pub const MEMPOOL_TTL: Duration = Duration::from_secs(24);
+
+/// What the strategy knows about the transaction it prices
+#[derive(Clone, Debug)]
+pub struct GasContext<'a> {
+    /// Base fee of the block the transaction is sent for
+    pub next_base_fee: u128,
+    pub gas_limit: u64,
+    /// Profit of the arbitrage in wei before gas
+    pub expected_profit: U256,
+    pub pairs: &'a [Address],
+}
+
+impl<'a> GasContext<'a> {
+    /// Context of the transaction for the block after `header`
+    pub fn new(
+        header: &impl BlockHeader,
+        gas_limit: u64,
+        expected_profit: U256,
+        pairs: &'a [Address],
+    ) -> Self {
+        let next_base_fee = header
+            .next_block_base_fee(BaseFeeParams::ethereum())
+            .unwrap_or_default();
+        Self {
+            next_base_fee: next_base_fee as u128,
+            gas_limit,
+            expected_profit,
+            pairs,
+        }
+    }
+
+    /// The most the transaction can pay per gas without losing money
+    pub fn profit_per_gas(&self) -> u128 {
+        let per_gas = self.expected_profit / U256::from(self.gas_limit.max(1));
+        per_gas.saturating_to()
+    }
+}
+
+/// Chooses the priority fee of arbitrage transactions
+pub trait GasStrategy: Send + Sync {
+    /// Name which is recorded with executions
+    fn name(&self) -> &'static str;
+
+    /// Parameters which are recorded with executions, as JSON
+    fn params(&self) -> String;
+
+    /// Priority fee per gas in wei
+    fn tip(&self, context: &GasContext) -> u128;
+}
+
+/// The same tip for every transaction
+pub struct FixedTip {
+    pub tip: u128,
+}
+
+impl GasStrategy for FixedTip {
+    fn name(&self) -> &'static str {
+        "fixed_tip"
+    }
+
+    fn params(&self) -> String {
+        format!(r#"{{"tip":{}}}"#, self.tip)
+    }
+
+    fn tip(&self, _: &GasContext) -> u128 {
+        self.tip
+    }
+}
+
+/// Tip is a share of expected profit, so builders are paid more for better arbitrages
+pub struct ProfitShare {
+    pub profit_percent: f64,
+}
+
+impl GasStrategy for ProfitShare {
+    fn name(&self) -> &'static str {
+        "profit_share"
+    }
+
+    fn params(&self) -> String {
+        format!(r#"{{"profit_percent":{}}}"#, self.profit_percent)
+    }
+
+    fn tip(&self, context: &GasContext) -> u128 {
+        share(context.profit_per_gas(), self.profit_percent)
+    }
+}
+
+/// Highest tips of pending transactions by addresses they touch.
+/// Transaction touches its `to`, addresses of its access list and addresses in calldata,
+/// so pairs passed to routers and arbitrage contracts are found without tracing
+#[derive(Clone, Debug, Default)]
+pub struct MempoolBids {
+    bids: Arc<RwLock<HashMap<Address, (u128, Instant)>>>,
+}
+
+impl MempoolBids {
+    pub fn observe(&self, tx: &impl Transaction) {
+        self.observe_at(tx, Instant::now());
+    }
+
+    fn observe_at(&self, tx: &impl Transaction, now: Instant) {
+        let tip = tx.priority_fee_or_price();
+        let mut bids = self.bids.write().unwrap();
+        bids.retain(|_, (_, seen)| now.duration_since(*seen) < MEMPOOL_TTL);
+
+        for address in touched_addresses(tx) {
+            let bid = bids.entry(address).or_insert((tip, now));
+            if bid.0 <= tip {
+                *bid = (tip, now);
+            }
+        }
+    }
+
+    /// The highest tip of recent pending transactions touching any of `addresses`
+    pub fn highest_tip(&self, addresses: &[Address]) -> Option<u128> {
+        let now = Instant::now();
+        let bids = self.bids.read().unwrap();
+        addresses
+            .iter()
+            .filter_map(|address| bids.get(address))
+            .filter(|(_, seen)| now.duration_since(*seen) < MEMPOOL_TTL)
+            .map(|(tip, _)| *tip)
+            .max()
+    }
+
+    /// Follows pending transactions of the node, needs a pubsub connection
+    pub async fn watch(self, provider: RootProvider) {
+        let subscription = match provider.subscribe_full_pending_transactions().await {
+            Ok(subscription) => subscription,
+            Err(err) => {
+                tracing::warn!("mempool bids are not tracked: {err}");
+                return;
+            }
+        };
+        let mut stream = subscription.into_stream();
+        while let Some(tx) = stream.next().await {
+            self.observe(&tx);
+        }
+    }
+}
+
+fn touched_addresses(tx: &impl Transaction) -> Vec<Address> {
+    let mut addresses: Vec<Address> = tx.to().into_iter().collect();
+    if let Some(access_list) = tx.access_list() {
+        addresses.extend(access_list.iter().map(|item| item.address));
+    }
+    // ABI encoded address is a word with 12 zero bytes
+    let args = tx.input().get(4..).unwrap_or_default();
+    for word in args.chunks_exact(32) {
+        if word[..12].iter().all(|byte| *byte == 0) && word[12..].iter().any(|byte| *byte != 0) {
+            addresses.push(Address::from_slice(&word[12..]));
+        }
+    }
+    addresses
+}
+
+/// Outbids pending transactions which touch the same pairs, but never pays more than
+/// `max_profit_percent` of profit
+pub struct Competitive {
+    pub min_tip: u128,
+    pub outbid_percent: f64,
+    pub max_profit_percent: f64,
+    pub mempool: MempoolBids,
+}
+
+impl GasStrategy for Competitive {
+    fn name(&self) -> &'static str {
+        "competitive"
+    }
+
+    fn params(&self) -> String {
+        format!(
+            r#"{{"min_tip":{},"outbid_percent":{},"max_profit_percent":{}}}"#,
+            self.min_tip, self.outbid_percent, self.max_profit_percent
+        )
+    }
+
+    fn tip(&self, context: &GasContext) -> u128 {
+        let outbid = self
+            .mempool
+            .highest_tip(context.pairs)
+            .map(|tip| tip + share(tip, self.outbid_percent) + 1)
+            .unwrap_or_default();
+        let max_tip = share(context.profit_per_gas(), self.max_profit_percent);
+        outbid.max(self.min_tip).min(max_tip)
+    }
+}
+
+/// Prices transactions with the configured strategy and EIP-1559 fee caps
+pub struct GasPricer {
+    strategy: Box<dyn GasStrategy>,
+    base_fee_multiplier: f64,
+    mempool: MempoolBids,
+}
+
+impl GasPricer {
+    pub fn new(strategy: Box<dyn GasStrategy>, base_fee_multiplier: f64) -> Self {
+        Self {
+            strategy,
+            base_fee_multiplier,
+            mempool: MempoolBids::default(),
+        }
+    }
+
+    /// `mempool` is used by the competitive strategy and to estimate inclusion
+    pub fn from_config(config: &GasConfig, mempool: MempoolBids) -> Self {
+        let strategy: Box<dyn GasStrategy> = match config.strategy {
+            GasStrategyConfig::FixedTip { tip_gwei } => Box::new(FixedTip {
+                tip: gwei(tip_gwei),
+            }),
+            GasStrategyConfig::ProfitShare { profit_percent } => {
+                Box::new(ProfitShare { profit_percent })
+            }
+            GasStrategyConfig::Competitive {
+                min_tip_gwei,
+                outbid_percent,
+                max_profit_percent,
+            } => Box::new(Competitive {
+                min_tip: gwei(min_tip_gwei),
+                outbid_percent,
+                max_profit_percent,
+                mempool: mempool.clone(),
+            }),
+        };
+        Self {
+            strategy,
+            base_fee_multiplier: config.base_fee_multiplier,
+            mempool,
+        }
+    }
+
+    pub fn strategy(&self) -> &dyn GasStrategy {
+        self.strategy.as_ref()
+    }
+
+    pub fn mempool(&self) -> &MempoolBids {
+        &self.mempool
+    }
+
+    /// Fees of the transaction, `None` if gas at the base fee and the tip eats the profit
+    pub fn bid(&self, context: &GasContext) -> Option<GasBid> {
+        let tip = self.strategy.tip(context);
+        if context.next_base_fee + tip >= context.profit_per_gas() {
+            return None;
+        }
+
+        let max_base_fee = (context.next_base_fee as f64 * self.base_fee_multiplier) as u128;
+        Some(GasBid {
+            strategy: self.strategy.name().to_string(),
+            params: self.strategy.params(),
+            max_fee_per_gas: max_base_fee.max(context.next_base_fee) + tip,
+            max_priority_fee_per_gas: tip,
+        })
+    }
+}
+
+fn gwei(amount: f64) -> u128 {
+    (amount * GWEI) as u128
+}
+
+fn share(amount: u128, percent: f64) -> u128 {
+    (amount as f64 * percent / 100.0) as u128
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::{
+        consensus::TxEip1559,
+        primitives::{Bytes, TxKind},
+    };
+
+    const GAS: u64 = 100_000;
+
+    fn context(pairs: &[Address]) -> GasContext<'_> {
+        GasContext {
+            next_base_fee: gwei(10.0),
+            gas_limit: GAS,
+            // 100 gwei per gas
+            expected_profit: U256::from(gwei(100.0) * GAS as u128),
+            pairs,
+        }
+    }
+
+    fn pending_tx(to: Address, pair: Address, tip: u128) -> TxEip1559 {
+        let mut input = vec![0x12, 0x34, 0x56, 0x78];
+        input.extend([0u8; 12]);
+        input.extend(pair.as_slice());
+        TxEip1559 {
+            to: TxKind::Call(to),
+            max_priority_fee_per_gas: tip,
+            max_fee_per_gas: tip * 10,
+            input: Bytes::from(input),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn tip_is_share_of_profit() {
+        let strategy = ProfitShare {
+            profit_percent: 20.0,
+        };
+        assert_eq!(strategy.tip(&context(&[])), gwei(20.0));
+    }
+
+    #[test]
+    fn outbids_pending_transactions_on_the_same_pairs() {
+        let pair = Address::repeat_byte(1);
+        let mempool = MempoolBids::default();
+        mempool.observe(&pending_tx(Address::repeat_byte(9), pair, gwei(4.0)));
+        mempool.observe(&pending_tx(
+            Address::repeat_byte(9),
+            Address::repeat_byte(2),
+            gwei(60.0),
+        ));
+
+        let strategy = Competitive {
+            min_tip: gwei(1.0),
+            outbid_percent: 10.0,
+            max_profit_percent: 50.0,
+            mempool: mempool.clone(),
+        };
+        assert_eq!(strategy.tip(&context(&[pair])), gwei(4.4) + 1);
+        // no competitors
+        assert_eq!(
+            strategy.tip(&context(&[Address::repeat_byte(3)])),
+            gwei(1.0)
+        );
+        // bid is capped by share of profit
+        assert_eq!(
+            strategy.tip(&context(&[Address::repeat_byte(2)])),
+            gwei(50.0)
+        );
+
+        let stale = Instant::now() + MEMPOOL_TTL;
+        mempool.observe_at(&pending_tx(Address::ZERO, Address::ZERO, 1), stale);
+        assert_eq!(mempool.highest_tip(&[pair]), None);
+    }
+
+    #[test]
+    fn caps_fees_by_base_fee() {
+        let pricer = GasPricer::new(Box::new(FixedTip { tip: gwei(2.0) }), 2.0);
+        let bid = pricer.bid(&context(&[])).unwrap();
+        assert_eq!(bid.strategy, "fixed_tip");
+        assert_eq!(bid.max_priority_fee_per_gas, gwei(2.0));
+        assert_eq!(bid.max_fee_per_gas, gwei(22.0));
+
+        // base fee and tip are more than profit
+        let pricer = GasPricer::new(Box::new(FixedTip { tip: gwei(95.0) }), 2.0);
+        assert!(pricer.bid(&context(&[])).is_none());
+    }
+}
@@ -0,0 +1,540 @@
+use crate::{
+    error::{ExecutorError, Result},
+    nonce::{NonceManager, PendingTx},
+};
+use alloy::{
+    network::TransactionBuilder,
+    primitives::{utils::format_units, Address, Uint, U256},
+    providers::{Provider, RootProvider},
+    rpc::types::TransactionRequest,
+    sol_types::SolCall,
+};
+use chrono::Utc;
+use ethereum_abi::{swapExactTokensForTokensCall, IERC20};
+use kronos_common::RpcError;
+use kronos_config::InventoryConfig;
+use kronos_db::{GraphSnapshot, Inventory, TokenRegistry, DB};
+use kronos_math::{
+    cpmm::{path_amount_out, ArbitrageData},
+    oracle::PriceOracle,
+    HUB_TOKENS,
+};
+use kronos_metrics as metrics;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Gas of ERC20 approval of the router
+pub const APPROVE_GAS: u64 = 60_000;
+/// Gas of the router swap per pair of the route
+pub const SWAP_GAS_PER_HOP: u64 = 100_000;
+/// Rebalancing swap reverts if it is not mined in time
+pub const SWAP_DEADLINE_SECS: i64 = 300;
+
+/// Swap of `value_usd` of `sell` into `buy` which brings the account closer to targets
+#[derive(Clone, Debug, PartialEq)]
+pub struct RebalanceSwap {
+    pub sell: Address,
+    pub buy: Address,
+    pub value_usd: f64,
+}
+
+/// Swaps which restore target shares of the account, empty if every token is within
+/// `drift_percent` of its target. `values` and `targets` are in USD and percent
+pub fn plan_rebalance(
+    values: &[(Address, f64)],
+    targets: &[(Address, f64)],
+    drift_percent: f64,
+    min_usd: f64,
+) -> Vec<RebalanceSwap> {
+    let total: f64 = values.iter().map(|(_, value)| value).sum();
+    if total <= 0.0 {
+        return vec![];
+    }
+    let value = |token: &Address| {
+        values
+            .iter()
+            .find(|(held, _)| held == token)
+            .map(|(_, value)| *value)
+            .unwrap_or_default()
+    };
+
+    // positive for surplus, negative for deficit
+    let diffs: Vec<(Address, f64)> = targets
+        .iter()
+        .map(|(token, target)| (*token, value(token) - total * target / 100.0))
+        .collect();
+    let drifted = diffs
+        .iter()
+        .any(|(_, diff)| diff.abs() / total * 100.0 > drift_percent);
+    if !drifted {
+        return vec![];
+    }
+
+    let mut deficits: Vec<(Address, f64)> = diffs
+        .iter()
+        .filter(|(_, diff)| *diff < 0.0)
+        .map(|(token, diff)| (*token, -diff))
+        .collect();
+    deficits.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut swaps = vec![];
+    for (sell, mut surplus) in diffs.into_iter().filter(|(_, diff)| *diff > 0.0) {
+        for (buy, deficit) in deficits.iter_mut() {
+            let value_usd = surplus.min(*deficit);
+            if value_usd < min_usd {
+                continue;
+            }
+            swaps.push(RebalanceSwap {
+                sell,
+                buy: *buy,
+                value_usd,
+            });
+            surplus -= value_usd;
+            *deficit -= value_usd;
+        }
+    }
+    swaps
+}
+
+/// Path through one router with its output
+#[derive(Clone, Debug)]
+pub struct Route {
+    pub dex_id: i32,
+    pub path: Vec<Address>,
+    pub amount_out: U256,
+}
+
+/// The route with the best output for `amount` of `sell`: the direct pair or a path
+/// through one of `hubs`, over constant product pairs of `dex_ids`.
+/// Start tokens and hubs don't take transfer tax
+pub fn best_route(
+    snapshot: &GraphSnapshot,
+    dex_ids: &[i32],
+    hubs: &[Address],
+    sell: Address,
+    buy: Address,
+    amount: U256,
+) -> Option<Route> {
+    let mut paths = vec![vec![sell, buy]];
+    paths.extend(
+        hubs.iter()
+            .filter(|hub| **hub != sell && **hub != buy)
+            .map(|hub| vec![sell, *hub, buy]),
+    );
+
+    dex_ids
+        .iter()
+        .flat_map(|dex_id| paths.iter().map(move |path| (*dex_id, path)))
+        .filter_map(|(dex_id, path)| {
+            let data = path
+                .windows(2)
+                .map(|hop| {
+                    Some(ArbitrageData {
+                        reserves: snapshot.reserves(dex_id, &hop[0], &hop[1])?,
+                        fee: Uint::from(3),
+                        transfer_tax_bps: 0,
+                        output_tax_bps: 0,
+                    })
+                })
+                .collect::<Option<Vec<ArbitrageData>>>()?;
+            Some(Route {
+                dex_id,
+                path: path.clone(),
+                amount_out: path_amount_out(&data, amount),
+            })
+        })
+        .max_by_key(|route| route.amount_out)
+}
+
+/// Tracks start tokens of executor accounts and swaps them back to target shares
+/// through Uniswap V2 compatible routers. Account is rebalanced again only after
+/// its previous swaps are mined, balances are stale until then.
+/// Arbitrages spend the tokens through the bot, so every account approves them to it
+pub struct InventoryManager {
+    inventory: Inventory,
+    bot: Address,
+    db: DB,
+    tokens: TokenRegistry,
+    oracle: PriceOracle,
+    provider: Arc<RootProvider>,
+    nonces: Arc<NonceManager>,
+    targets: Vec<(Address, f64)>,
+    // dex id -> router
+    routers: HashMap<i32, Address>,
+    config: InventoryConfig,
+    // account -> nonce of its last rebalancing transaction
+    last_nonces: Mutex<HashMap<Address, u64>>,
+    // (account, token) approved to the bot
+    approved: Mutex<HashSet<(Address, Address)>>,
+}
+
+impl InventoryManager {
+    pub async fn from_config(
+        config: &InventoryConfig,
+        bot: Address,
+        inventory: Inventory,
+        db: DB,
+        tokens: TokenRegistry,
+        oracle: PriceOracle,
+        provider: Arc<RootProvider>,
+        nonces: Arc<NonceManager>,
+    ) -> Result<Self> {
+        let parse = |address: &str| {
+            address.parse::<Address>().map_err(|err| {
+                ExecutorError::inventory(format!("invalid address {address}: {err}"))
+            })
+        };
+
+        let mut targets = vec![];
+        for token in config.tokens.iter() {
+            targets.push((parse(&token.token)?, token.target_percent));
+        }
+        let target_sum: f64 = targets.iter().map(|(_, target)| target).sum();
+        if (target_sum - 100.0).abs() > 0.01 {
+            return Err(ExecutorError::inventory(format!(
+                "target shares sum to {target_sum}%, expected 100%"
+            )));
+        }
+        tokens
+            .ensure_tokens(&targets.iter().map(|(token, _)| *token).collect::<Vec<_>>())
+            .await?;
+
+        let mut routers = HashMap::new();
+        for (dex, router) in config.routers.iter() {
+            let dex_id = db.postgres().get_dex_id(dex).await?;
+            routers.insert(dex_id, parse(router)?);
+        }
+
+        Ok(Self {
+            inventory,
+            bot,
+            db,
+            tokens,
+            oracle,
+            provider,
+            nonces,
+            targets,
+            routers,
+            config: config.clone(),
+            last_nonces: Mutex::new(HashMap::new()),
+            approved: Mutex::new(HashSet::new()),
+        })
+    }
+
+    pub fn inventory(&self) -> &Inventory {
+        &self.inventory
+    }
+
+    /// Reads balances of start tokens of all executor accounts, a held token
+    /// which is not approved to the bot yet is approved
+    pub async fn refresh(&self) -> Result<()> {
+        for account in self.nonces.wallet().accounts() {
+            for (token, _) in self.targets.iter() {
+                let balance = IERC20::new(*token, self.provider.clone())
+                    .balanceOf(*account)
+                    .call()
+                    .await
+                    .map_err(RpcError::from)?
+                    .balance;
+                if !balance.is_zero() {
+                    self.approve_bot(*account, *token, balance).await?;
+                }
+                self.inventory.set_balance(*account, *token, balance);
+
+                let decimals = self.tokens.decimals(token).unwrap_or(18);
+                let whole = format_units(balance, decimals).unwrap_or_default();
+                metrics::INVENTORY_BALANCE
+                    .with_label_values(&[&account.to_string(), &self.tokens.ticker(token)])
+                    .set(whole.parse().unwrap_or_default());
+            }
+        }
+        Ok(())
+    }
+
+    /// Approves the token of the account to the bot, once per account and token
+    async fn approve_bot(&self, account: Address, token: Address, balance: U256) -> Result<()> {
+        if self.approved.lock().unwrap().contains(&(account, token)) {
+            return Ok(());
+        }
+        let allowance = IERC20::new(token, self.provider.clone())
+            .allowance(account, self.bot)
+            .call()
+            .await
+            .map_err(RpcError::from)?
+            ._0;
+        if allowance < balance {
+            let fees = self.provider.estimate_eip1559_fees().await?;
+            let approve = IERC20::approveCall {
+                _spender: self.bot,
+                _value: U256::MAX,
+            };
+            let request = TransactionRequest::default()
+                .with_to(token)
+                .with_input(approve.abi_encode())
+                .with_gas_limit(APPROVE_GAS)
+                .with_max_fee_per_gas(fees.max_fee_per_gas)
+                .with_max_priority_fee_per_gas(fees.max_priority_fee_per_gas);
+            let sent = self.nonces.send_from(account, request, None).await?;
+            tracing::info!(
+                "approve {} of {account} to the bot in {}",
+                self.tokens.ticker(&token),
+                sent.hash
+            );
+        }
+        self.approved.lock().unwrap().insert((account, token));
+        Ok(())
+    }
+
+    /// Sends swaps which bring every account back to target shares
+    pub async fn rebalance(&self) -> Result<()> {
+        let block_number = self.provider.get_block_number().await?;
+        let snapshot = self.db.graph().snapshot();
+
+        for account in self.nonces.wallet().accounts() {
+            if !self.is_settled(account).await? {
+                tracing::info!("previous rebalance of {account} is not mined yet");
+                continue;
+            }
+
+            let mut values = vec![];
+            for (token, _) in self.targets.iter() {
+                let balance = self.inventory.balance(account, token);
+                let value = self
+                    .oracle
+                    .amount_to_usd(block_number, token, balance)
+                    .await?;
+                values.push((*token, value));
+            }
+
+            let swaps = plan_rebalance(
+                &values,
+                &self.targets,
+                self.config.rebalance_drift_percent,
+                self.config.min_rebalance_usd,
+            );
+            for swap in swaps {
+                let (_, sell_value) = values
+                    .iter()
+                    .find(|(token, _)| *token == swap.sell)
+                    .unwrap();
+                let balance = self.inventory.balance(account, &swap.sell);
+                // 6 digits of the share are enough for rebalancing
+                let share = (swap.value_usd / sell_value * 1e6) as u64;
+                let amount = balance * U256::from(share) / U256::from(1_000_000);
+
+                match self.swap(&snapshot, *account, &swap, amount).await {
+                    Ok(sent) => {
+                        self.last_nonces
+                            .lock()
+                            .unwrap()
+                            .insert(*account, sent.nonce);
+                    }
+                    Err(err) => tracing::warn!(
+                        "failed to rebalance {} of {account}: {err}",
+                        self.tokens.format_amount(&swap.sell, amount)
+                    ),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the last rebalancing transaction of the account is mined, replaced
+    /// or cancelled, so its balances are final
+    async fn is_settled(&self, account: &Address) -> Result<bool> {
+        let last_nonce = self.last_nonces.lock().unwrap().get(account).copied();
+        let Some(last_nonce) = last_nonce else {
+            return Ok(true);
+        };
+        let mined_nonce = self
+            .provider
+            .get_transaction_count(*account)
+            .latest()
+            .await?;
+        Ok(mined_nonce > last_nonce)
+    }
+
+    async fn swap(
+        &self,
+        snapshot: &GraphSnapshot,
+        account: Address,
+        swap: &RebalanceSwap,
+        amount: U256,
+    ) -> Result<PendingTx> {
+        let dex_ids: Vec<i32> = self.routers.keys().copied().collect();
+        let mut hubs = HUB_TOKENS.to_vec();
+        hubs.extend(self.targets.iter().map(|(token, _)| *token));
+        hubs.sort();
+        hubs.dedup();
+
+        let route = best_route(snapshot, &dex_ids, &hubs, swap.sell, swap.buy, amount).ok_or(
+            ExecutorError::inventory(format!(
+                "no route from {} to {}",
+                self.tokens.ticker(&swap.sell),
+                self.tokens.ticker(&swap.buy)
+            )),
+        )?;
+        let router = self.routers[&route.dex_id];
+        let slippage = U256::from(10_000 - self.config.max_slippage_bps.min(10_000));
+        let min_amount_out = route.amount_out * slippage / U256::from(10_000);
+
+        let fees = self.provider.estimate_eip1559_fees().await?;
+        let request = |to: Address, input: Vec<u8>, gas: u64| {
+            TransactionRequest::default()
+                .with_to(to)
+                .with_input(input)
+                .with_gas_limit(gas)
+                .with_max_fee_per_gas(fees.max_fee_per_gas)
+                .with_max_priority_fee_per_gas(fees.max_priority_fee_per_gas)
+        };
+
+        let allowance = IERC20::new(swap.sell, self.provider.clone())
+            .allowance(account, router)
+            .call()
+            .await
+            .map_err(RpcError::from)?
+            ._0;
+        if allowance < amount {
+            let approve = IERC20::approveCall {
+                _spender: router,
+                _value: U256::MAX,
+            };
+            self.nonces
+                .send_from(
+                    account,
+                    request(swap.sell, approve.abi_encode(), APPROVE_GAS),
+                    None,
+                )
+                .await?;
+        }
+
+        let deadline = Utc::now().timestamp() + SWAP_DEADLINE_SECS;
+        let call = swapExactTokensForTokensCall {
+            amountIn: amount,
+            amountOutMin: min_amount_out,
+            path: route.path.clone(),
+            to: account,
+            deadline: U256::from(deadline),
+        };
+        let gas = SWAP_GAS_PER_HOP * (route.path.len() as u64 - 1);
+        let sent = self
+            .nonces
+            .send_from(account, request(router, call.abi_encode(), gas), None)
+            .await?;
+        tracing::info!(
+            "rebalance {account}: {} -> {} ({:.2} USD) in {}",
+            self.tokens.format_amount(&swap.sell, amount),
+            self.tokens.format_amount(&swap.buy, route.amount_out),
+            swap.value_usd,
+            sent.hash
+        );
+        Ok(sent)
+    }
+
+    /// Periodically refreshes balances and rebalances accounts
+    pub async fn start(self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.check_secs));
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.refresh().await {
+                tracing::warn!("failed to refresh inventory: {err}");
+                continue;
+            }
+            if let Err(err) = self.rebalance().await {
+                tracing::warn!("failed to rebalance inventory: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kronos_common::Reserves;
+    use kronos_db::{tables::Pair, TokenGraph, UpdateReservesData};
+
+    const WETH: Address = Address::repeat_byte(1);
+    const USDC: Address = Address::repeat_byte(2);
+    const DAI: Address = Address::repeat_byte(3);
+
+    #[test]
+    fn rebalances_drifted_tokens_to_targets() {
+        let targets = [(WETH, 50.0), (USDC, 25.0), (DAI, 25.0)];
+
+        // within drift
+        let values = [(WETH, 5_500.0), (USDC, 2_300.0), (DAI, 2_200.0)];
+        assert!(plan_rebalance(&values, &targets, 10.0, 100.0).is_empty());
+
+        let values = [(WETH, 8_000.0), (USDC, 1_000.0), (DAI, 1_000.0)];
+        let swaps = plan_rebalance(&values, &targets, 10.0, 100.0);
+        assert_eq!(swaps.len(), 2);
+        assert!(swaps.iter().all(|swap| swap.sell == WETH));
+        assert_eq!(
+            swaps.iter().map(|swap| swap.value_usd).sum::<f64>(),
+            3_000.0
+        );
+
+        // nothing is held
+        assert!(plan_rebalance(&[], &targets, 10.0, 100.0).is_empty());
+    }
+
+    #[test]
+    fn limits_size_by_richest_account() {
+        let (first, second) = (Address::repeat_byte(8), Address::repeat_byte(9));
+        assert!(Inventory::flash_swap().holds(&WETH));
+        assert_eq!(Inventory::flash_swap().max_amount_in(&WETH), None);
+
+        let inventory = Inventory::new(50.0);
+        assert!(!inventory.holds(&WETH));
+        inventory.set_balance(first, WETH, U256::from(1_000));
+        inventory.set_balance(second, WETH, U256::from(3_000));
+
+        assert!(inventory.holds(&WETH));
+        assert_eq!(inventory.total(&WETH), U256::from(4_000));
+        assert_eq!(inventory.max_amount_in(&WETH), Some(U256::from(1_500)));
+        // the sized amount is sent by the account which holds it
+        assert_eq!(inventory.holder(&WETH, U256::from(1_500)), Some(second));
+        assert_eq!(inventory.holder(&WETH, U256::from(3_001)), None);
+    }
+
+    #[test]
+    fn routes_through_hub_when_it_pays_more() {
+        let graph = TokenGraph::default();
+        let pairs = [(1, USDC, DAI), (1, USDC, WETH), (1, WETH, DAI)];
+        let pairs: Vec<Pair> = pairs
+            .iter()
+            .enumerate()
+            .map(|(index, (dex_id, token0, token1))| Pair {
+                address: Address::repeat_byte(10 + index as u8),
+                dex_id: *dex_id,
+                token0: *token0,
+                token1: *token1,
+            })
+            .collect();
+        graph.add_pairs(&pairs);
+
+        let reserves = |pair: &Pair, r0: u64, r1: u64| UpdateReservesData {
+            pair: pair.address,
+            token0: pair.token0,
+            token1: pair.token1,
+            reserves: Reserves(Uint::from(r0), Uint::from(r1)),
+            block_number: 1,
+        };
+        // direct pair is shallow, route through WETH is deep
+        graph.update_reserves(&[
+            reserves(&pairs[0], 1_000, 1_000),
+            reserves(&pairs[1], 1_000_000, 1_000),
+            reserves(&pairs[2], 1_000, 1_000_000),
+        ]);
+
+        let snapshot = graph.snapshot();
+        let amount = U256::from(500);
+        let route = best_route(&snapshot, &[1], &[WETH], USDC, DAI, amount).unwrap();
+        assert_eq!(route.path, [USDC, WETH, DAI]);
+
+        assert!(best_route(&snapshot, &[2], &[WETH], USDC, DAI, amount).is_none());
+    }
+}
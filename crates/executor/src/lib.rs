@@ -1,92 +1,566 @@
 use alloy::{
-    primitives::{Address, Uint},
-    providers::{Provider, RootProvider},
+    primitives::{utils::format_ether, Address, B256, U256},
+    providers::RootProvider,
+};
+use chrono::Utc;
+use error::Result;
+use gas::GasPricer;
+use kronos_common::ErrorAction;
+use kronos_config::ExecutorConfig;
+use kronos_db::{
+    tables::{Execution, ExecutionMode, ExecutionStatus, GasBid, Opportunity},
+    Inventory, TokenRegistry, DB,
 };
-use anyhow::Result;
-use kronos_db::{TokensGraphStorage, DB};
 use kronos_dexes::common::Arbitrage;
-use kronos_math::price_to_usd;
+use kronos_math::{oracle::PriceOracle, WETH};
+use kronos_metrics as metrics;
+use live::{Landed, LiveTrader};
+use nonce::NonceManager;
+use paper::{PaperOutcome, PaperTrader};
+use risk::{Breach, RiskManager, Trade, KILL_SWITCH_INTERVAL};
 use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc};
+use tracing::Instrument;
+use wallet::ExecutorWallet;
 
+pub mod balancer;
+pub mod bot;
+pub mod encode;
+pub mod error;
+pub mod gas;
+pub mod inventory;
+pub mod live;
 pub mod max_price;
+pub mod nonce;
+pub mod notifier;
+pub mod paper;
+pub mod relay;
+pub mod risk;
 pub mod triangular_swap;
+pub mod wallet;
+
+/// Events are dropped for subscribers which lag more than this
+pub const EVENTS_CAPACITY: usize = 1024;
 
+/// What the executor does, followed by alerts through `Executor::subscribe`
+#[derive(Clone, Debug)]
 pub enum ExecutorEvent {
-    ArbitrageExecuted,
+    /// Opportunity is stored, it is found even while execution is paused
+    OpportunityFound {
+        opportunity_id: i64,
+        block_number: u64,
+        revenue_usd: Option<f64>,
+    },
+    ArbitrageExecuted {
+        opportunity_id: i64,
+        mode: ExecutionMode,
+        status: ExecutionStatus,
+        profit_usd: Option<f64>,
+    },
+    /// Arbitrage broke a limit and was not executed
+    ArbitrageRejected {
+        opportunity_id: i64,
+        breach: Breach,
+    },
+    /// Circuit breaker tripped or the kill switch was engaged
+    Paused(Breach),
+    Resumed,
+}
+
+/// Sent arbitrage which holds its exposure until the transaction is mined
+#[derive(Clone, Debug)]
+struct Tracked {
+    execution_id: Option<i64>,
+    opportunity_id: i64,
+    trade: Trade,
+    revenue_usd: f64,
+    eth_usd: f64,
+}
+
+/// Services shared by the executor with the rest of the bot
+pub struct ExecutorParts {
+    pub db: DB,
+    pub tokens: TokenRegistry,
+    pub oracle: PriceOracle,
+    pub provider: Arc<RootProvider>,
+    pub nonces: Arc<NonceManager>,
+    pub gas: GasPricer,
+    pub risk: RiskManager,
+    pub inventory: Inventory,
 }
 
 pub struct Executor {
     db: DB,
-    provider: Arc<RootProvider>,
+    tokens: TokenRegistry,
+    oracle: PriceOracle,
+    nonces: Arc<NonceManager>,
+    gas: GasPricer,
+    /// Set in live mode, arbitrages are sent as transactions
+    live: Option<LiveTrader>,
+    /// Set in paper mode, arbitrages are filled on paper instead of sent
+    paper: Option<PaperTrader>,
+    risk: RiskManager,
+    events: broadcast::Sender<ExecutorEvent>,
 
-    rx: tokio::sync::mpsc::UnboundedReceiver<Arbitrage>,
+    rx: mpsc::UnboundedReceiver<Arbitrage>,
+    /// Sent arbitrages come back when their transactions are mined
+    landed_tx: mpsc::UnboundedSender<(Tracked, Landed)>,
+    landed_rx: mpsc::UnboundedReceiver<(Tracked, Landed)>,
 }
 
 impl Executor {
     pub fn new(
-        db: DB,
-        provider: Arc<RootProvider>,
-        rx: tokio::sync::mpsc::UnboundedReceiver<Arbitrage>,
-    ) -> Self {
-        Self { db, provider, rx }
+        parts: ExecutorParts,
+        config: &ExecutorConfig,
+        rx: mpsc::UnboundedReceiver<Arbitrage>,
+    ) -> Result<Self> {
+        let ExecutorParts {
+            db,
+            tokens,
+            oracle,
+            provider,
+            nonces,
+            gas,
+            risk,
+            inventory,
+        } = parts;
+        // paper mode simulates the transactions which the bot would send
+        let bot = bot::bot_address(config)?;
+        let (mut live, mut paper) = (None, None);
+        match config.mode {
+            ExecutionMode::Live => {
+                live = Some(LiveTrader::new(provider, nonces.clone(), bot, inventory))
+            }
+            ExecutionMode::Paper => {
+                paper = Some(PaperTrader::new(db.clone(), oracle.clone(), provider, bot))
+            }
+        }
+        let (landed_tx, landed_rx) = mpsc::unbounded_channel();
+        Ok(Self {
+            db,
+            tokens,
+            oracle,
+            nonces,
+            gas,
+            live,
+            paper,
+            risk,
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+            rx,
+            landed_tx,
+            landed_rx,
+        })
+    }
+
+    pub fn wallet(&self) -> &ExecutorWallet {
+        self.nonces.wallet()
+    }
+
+    pub fn nonces(&self) -> &Arc<NonceManager> {
+        &self.nonces
+    }
+
+    pub fn gas(&self) -> &GasPricer {
+        &self.gas
+    }
+
+    pub fn paper(&self) -> Option<&PaperTrader> {
+        self.paper.as_ref()
+    }
+
+    pub fn risk(&self) -> &RiskManager {
+        &self.risk
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ExecutorEvent> {
+        self.events.subscribe()
     }
 
     pub async fn start(mut self) -> Result<()> {
-        while let Some(arbitrage) = self.rx.recv().await {
-            self.process_arbitrage(arbitrage).await?;
+        if let Some(paper) = &self.paper {
+            paper.restore().await?;
+        }
+        if self.live.is_some() {
+            let pnl_usd = self
+                .db
+                .postgres()
+                .realized_pnl_usd(ExecutionMode::Live)
+                .await?;
+            metrics::REALIZED_PNL_USD.set(pnl_usd);
+        }
+        let mut kill_switch = tokio::time::interval(KILL_SWITCH_INTERVAL);
+        loop {
+            let arbitrage = tokio::select! {
+                arbitrage = self.rx.recv() => match arbitrage {
+                    Some(arbitrage) => arbitrage,
+                    None => break,
+                },
+                Some((tracked, landed)) = self.landed_rx.recv() => {
+                    self.record_landed(tracked, landed).await;
+                    continue;
+                }
+                // toggles of the kill switch are alerted even if no arbitrages are found
+                _ = kill_switch.tick() => {
+                    let paused = self.risk.paused();
+                    self.risk.sync_kill_switch();
+                    self.on_pause_change(paused);
+                    continue;
+                }
+            };
+            metrics::set_channel_depth("arbitrages", self.rx.len());
+            let block_number = arbitrage.block_number;
+            // every log of the arbitrage carries its block, dex and path, opportunity id once stored.
+            // Span continues the trace of the block started by the dex registry
+            let span = tracing::info_span!(
+                parent: &arbitrage.span,
+                "arbitrage",
+                block_number,
+                dex_id = arbitrage.dex_id,
+                path_id = %arbitrage.path_id(),
+                opportunity_id = tracing::field::Empty,
+            );
+            // arbitrage is stale after its block, so transient errors are not retried
+            let result = self
+                .process_arbitrage(arbitrage)
+                .instrument(span.clone())
+                .await;
+            let _entered = span.enter();
+            match result {
+                Ok(()) => {}
+                Err(err) if err.action() == ErrorAction::Abort => return Err(err),
+                Err(err) => tracing::warn!("skip arbitrage of block {block_number}: {err}"),
+            }
         }
         Ok(())
     }
 
     pub async fn process_arbitrage(&self, arbitrage: Arbitrage) -> Result<()> {
         let first_token = arbitrage.path[0].0;
-        let amount_in_usd = price_to_usd(
-            self.db.clone(),
-            arbitrage.dex_id,
-            &first_token,
-            arbitrage.amount_in,
-        )
-        .await?;
-
-        let revenue_usd = price_to_usd(
-            self.db.clone(),
-            arbitrage.dex_id,
-            &first_token,
-            arbitrage.revenue,
-        )
-        .await?;
-
-        self.print_path(&arbitrage.path).await?;
-
-        for tokens in arbitrage.path.iter() {
-            let pair_adr = self
-                .db
-                .pair_adr(arbitrage.dex_id, &tokens.0, &tokens.1)
+        let price = match self
+            .oracle
+            .usd_price(arbitrage.block_number, &first_token)
+            .await
+        {
+            Ok(price) => Some(price),
+            Err(err) => {
+                tracing::warn!("{err}");
+                None
+            }
+        };
+
+        self.print_path(&arbitrage.path);
+
+        let pairs: Vec<Address> = arbitrage.hops.iter().map(|hop| hop.pool).collect();
+        tracing::info!("pools: {pairs:?}");
+
+        tracing::info!(
+            "revenue: {}, amount in: {}",
+            self.tokens.format_amount(&first_token, arbitrage.revenue),
+            self.tokens.format_amount(&first_token, arbitrage.amount_in),
+        );
+        let mut revenue_usd = None;
+        let mut amount_in_usd = None;
+        if let Some(price) = price {
+            let revenue = self
+                .oracle
+                .amount_to_usd(arbitrage.block_number, &first_token, arbitrage.revenue)
                 .await?;
-            let slot = self
-                .provider
-                .get_storage_at(pair_adr, Uint::<256, 4>::from(4))
+            revenue_usd = Some(revenue);
+            let amount_in = self
+                .oracle
+                .amount_to_usd(arbitrage.block_number, &first_token, arbitrage.amount_in)
                 .await?;
-            tracing::info!("pair: {pair_adr:?} slot: {slot:?}");
+            amount_in_usd = Some(amount_in);
+            tracing::info!(
+                "revenue_usd: {revenue}, amount in: {amount_in}, price confidence: {:.2}",
+                price.confidence
+            );
+        }
+
+        let opportunity_id = self.record_opportunity(&arbitrage, revenue_usd).await?;
+        tracing::Span::current().record("opportunity_id", opportunity_id);
+        self.emit(ExecutorEvent::OpportunityFound {
+            opportunity_id,
+            block_number: arbitrage.block_number,
+            revenue_usd,
+        });
+
+        let mut tokens: Vec<Address> = arbitrage.path.iter().map(|hop| hop.0).collect();
+        tokens.sort();
+        tokens.dedup();
+        let trade = Trade {
+            block_number: arbitrage.block_number,
+            notional_usd: amount_in_usd,
+            tokens,
+            pairs,
+        };
+        let paused = self.risk.paused();
+        let checked = self.risk.check(&trade);
+        self.on_pause_change(paused);
+        if let Err(breach) = checked {
+            if !breach.pauses() {
+                metrics::RISK_BREACHES
+                    .with_label_values(&[breach.limit()])
+                    .inc();
+            }
+            tracing::info!("arbitrage {opportunity_id} is rejected: {breach}");
+            self.emit(ExecutorEvent::ArbitrageRejected {
+                opportunity_id,
+                breach,
+            });
+            return Ok(());
+        }
+
+        if let Some(paper) = &self.paper {
+            self.risk.open(&trade);
+            let fill = paper.fill(&arbitrage, opportunity_id, &self.gas).await;
+            self.risk.close(&trade);
+            let fill = fill?;
+
+            let (status, profit_usd) = match fill.outcome {
+                PaperOutcome::Filled => (ExecutionStatus::Included, Some(fill.pnl_usd)),
+                // reverted paper transaction would not be sent, so it is not a revert
+                PaperOutcome::Missed | PaperOutcome::Unprofitable | PaperOutcome::Reverted => {
+                    (ExecutionStatus::Failed, None)
+                }
+            };
+            self.record_result(opportunity_id, ExecutionMode::Paper, status, profit_usd);
         }
 
-        tracing::info!("revenue_usd: {revenue_usd}, amount in: {amount_in_usd}");
+        if let Some(live) = &self.live {
+            // unpriced trades are rejected by the risk check
+            let Some(revenue_usd) = revenue_usd else {
+                return Ok(());
+            };
+            let eth_usd = self
+                .oracle
+                .usd_price(arbitrage.block_number, &WETH)
+                .await?
+                .price;
+            let expected_profit = U256::from((revenue_usd / eth_usd * 1e18) as u128);
 
+            self.risk.open(&trade);
+            let submission = match live.submit(&arbitrage, expected_profit, &self.gas).await {
+                Ok(Some(submission)) => submission,
+                Ok(None) => {
+                    self.risk.close(&trade);
+                    tracing::info!("arbitrage {opportunity_id} is unprofitable after gas");
+                    return Ok(());
+                }
+                Err(err) => {
+                    self.risk.close(&trade);
+                    return Err(err);
+                }
+            };
+            let pending = submission.pending;
+            tracing::info!(
+                "sent {} from {} with nonce {}",
+                pending.hash,
+                pending.account,
+                pending.nonce
+            );
+
+            // the transaction is sent, so it is followed even if it can't be stored
+            let execution_id = match self
+                .record_execution(
+                    opportunity_id,
+                    arbitrage.block_number,
+                    Some(pending.hash),
+                    ExecutionStatus::Pending,
+                    Some(submission.bid),
+                )
+                .await
+            {
+                Ok(id) => Some(id),
+                Err(err) => {
+                    tracing::warn!("failed to store execution of {}: {err}", pending.hash);
+                    None
+                }
+            };
+            let tracked = Tracked {
+                execution_id,
+                opportunity_id,
+                trade,
+                revenue_usd,
+                eth_usd,
+            };
+            let provider = live.provider().clone();
+            let landed_tx = self.landed_tx.clone();
+            tokio::spawn(async move {
+                let landed = live::wait_mined(provider, pending).await;
+                // the executor is stopped
+                let _ = landed_tx.send((tracked, landed));
+            });
+        }
         Ok(())
     }
 
-    async fn print_path(&self, path: &[(Address, Address)]) -> Result<()> {
+    /// Releases exposure of the mined arbitrage and feeds circuit breakers with its result:
+    /// included transaction earns the revenue it was sized with, reverted one only pays gas
+    async fn record_landed(&self, tracked: Tracked, landed: Landed) {
+        self.risk.close(&tracked.trade);
+        let fee_eth: f64 = format_ether(landed.fee_wei).parse().unwrap_or_default();
+        let gas_usd = fee_eth * tracked.eth_usd;
+        let profit_usd = match landed.status {
+            ExecutionStatus::Included => Some(tracked.revenue_usd - gas_usd),
+            ExecutionStatus::Reverted => Some(-gas_usd),
+            ExecutionStatus::Pending | ExecutionStatus::Failed => None,
+        };
+        tracing::info!(
+            "transaction of arbitrage {} is {}",
+            tracked.opportunity_id,
+            landed.status.as_str()
+        );
+        if let Some(profit_usd) = profit_usd {
+            metrics::REALIZED_PNL_USD.add(profit_usd);
+        }
+        self.record_result(
+            tracked.opportunity_id,
+            ExecutionMode::Live,
+            landed.status,
+            profit_usd,
+        );
+
+        let Some(execution_id) = tracked.execution_id else {
+            return;
+        };
+        if let Err(err) = self
+            .db
+            .postgres()
+            .update_execution(execution_id, landed.status, landed.gas_used, profit_usd)
+            .await
+        {
+            tracing::warn!("failed to update execution {execution_id}: {err}");
+        }
+    }
+
+    /// Feeds circuit breakers with the result and notifies subscribers
+    fn record_result(
+        &self,
+        opportunity_id: i64,
+        mode: ExecutionMode,
+        status: ExecutionStatus,
+        profit_usd: Option<f64>,
+    ) {
+        if status != ExecutionStatus::Pending {
+            metrics::EXECUTIONS
+                .with_label_values(&[mode.as_str(), status.as_str()])
+                .inc();
+        }
+        let paused = self.risk.paused();
+        self.risk.record_result(status, profit_usd);
+        self.emit(ExecutorEvent::ArbitrageExecuted {
+            opportunity_id,
+            mode,
+            status,
+            profit_usd,
+        });
+        self.on_pause_change(paused);
+    }
+
+    fn on_pause_change(&self, before: Option<Breach>) {
+        match (before, self.risk.paused()) {
+            (None, Some(breach)) => {
+                metrics::RISK_BREACHES
+                    .with_label_values(&[breach.limit()])
+                    .inc();
+                metrics::EXECUTION_PAUSED.set(1);
+                self.emit(ExecutorEvent::Paused(breach));
+            }
+            (Some(_), None) => {
+                metrics::EXECUTION_PAUSED.set(0);
+                self.emit(ExecutorEvent::Resumed);
+            }
+            // the kill switch took over another circuit breaker
+            (Some(before), Some(breach)) if before != breach => {
+                metrics::RISK_BREACHES
+                    .with_label_values(&[breach.limit()])
+                    .inc();
+                self.emit(ExecutorEvent::Paused(breach));
+            }
+            _ => {}
+        }
+    }
+
+    fn emit(&self, event: ExecutorEvent) {
+        // no subscribers is not an error
+        let _ = self.events.send(event);
+    }
+
+    /// Stores opportunity and publishes it for live subscribers of the api
+    async fn record_opportunity(
+        &self,
+        arbitrage: &Arbitrage,
+        revenue_usd: Option<f64>,
+    ) -> Result<i64> {
+        let mut path: Vec<Address> = arbitrage.path.iter().map(|hop| hop.0).collect();
+        path.extend(arbitrage.path.last().map(|hop| hop.1));
+
+        let mut opportunity = Opportunity {
+            id: 0,
+            dex_id: arbitrage.dex_id,
+            block_number: arbitrage.block_number,
+            path,
+            amount_in: arbitrage.amount_in,
+            revenue: arbitrage.revenue,
+            revenue_usd,
+            created_at: Utc::now(),
+        };
+        opportunity.id = self.db.postgres().insert_opportunity(&opportunity).await?;
+
+        if let Err(err) = self.db.redis().publish_opportunity(&opportunity).await {
+            tracing::warn!("failed to publish opportunity {}: {err}", opportunity.id);
+        }
+        Ok(opportunity.id)
+    }
+
+    /// Stores the attempt to execute the opportunity with fees and gas strategy of the transaction
+    pub async fn record_execution(
+        &self,
+        opportunity_id: i64,
+        block_number: u64,
+        tx_hash: Option<B256>,
+        status: ExecutionStatus,
+        gas: Option<GasBid>,
+    ) -> Result<i64> {
+        let execution = Execution {
+            id: 0,
+            opportunity_id: Some(opportunity_id),
+            block_number,
+            tx_hash,
+            status,
+            mode: ExecutionMode::Live,
+            gas_used: None,
+            profit_usd: None,
+            gas,
+            slippage_bps: None,
+            created_at: Utc::now(),
+        };
+        let id = self
+            .db
+            .postgres()
+            .insert_execution(&execution)
+            .instrument(tracing::info_span!(
+                "record",
+                mode = "live",
+                status = status.as_str()
+            ))
+            .await?;
+        self.record_result(opportunity_id, ExecutionMode::Live, status, None);
+        Ok(id)
+    }
+
+    fn print_path(&self, path: &[(Address, Address)]) {
         let mut path_str = String::new();
         for (index, tokens) in path.iter().enumerate() {
-            path_str.push_str(&self.db.postgres().get_token_ticker(&tokens.0).await?.ticker);
+            path_str.push_str(&self.tokens.ticker(&tokens.0));
             path_str.push_str(" -> ");
-            path_str.push_str(&self.db.postgres().get_token_ticker(&tokens.1).await?.ticker);
+            path_str.push_str(&self.tokens.ticker(&tokens.1));
 
             if index != path.len() - 1 {
                 path_str.push_str(" -> ");
             }
         }
         tracing::info!("path: {path_str}");
-        Ok(())
     }
 }
@@ -0,0 +1,190 @@
+use crate::{
+    bot::BotPermissions,
+    encode::encode_arbitrage,
+    error::{ExecutorError, Result},
+    gas::{GasContext, GasPricer},
+    nonce::{NonceManager, PendingTx},
+};
+use alloy::{
+    eips::BlockNumberOrTag,
+    network::TransactionBuilder,
+    primitives::{keccak256, Address, B256, U256},
+    providers::{Provider, RootProvider},
+    rpc::types::TransactionRequest,
+};
+use kronos_db::{
+    tables::{ExecutionStatus, GasBid},
+    Inventory,
+};
+use kronos_dexes::common::Arbitrage;
+use std::{future::IntoFuture, sync::Arc, time::Duration};
+use tracing::Instrument;
+
+/// Batch swaps expire after this time from the latest block
+pub const DEADLINE_SECS: u64 = 120;
+/// Estimated gas is raised by this share, the state may change before inclusion
+pub const GAS_MARGIN_PERCENT: u64 = 20;
+/// Sent transactions are checked every few seconds until their nonce is mined
+pub const RECEIPT_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Transaction of the arbitrage which is sent and not mined yet
+#[derive(Clone, Debug)]
+pub struct Submission {
+    pub pending: PendingTx,
+    pub bid: GasBid,
+}
+
+/// What happened to the transaction when its nonce was mined
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Landed {
+    pub status: ExecutionStatus,
+    pub gas_used: Option<u64>,
+    /// Fee paid for the transaction, zero if it was not mined
+    pub fee_wei: U256,
+}
+
+/// Sends arbitrages as transactions of executor accounts. Transactions of the same path
+/// replace each other, so a newer opportunity supersedes the pending one.
+/// In inventory mode the arbitrage is paid by the account which holds its amount in
+pub struct LiveTrader {
+    provider: Arc<RootProvider>,
+    nonces: Arc<NonceManager>,
+    bot: BotPermissions,
+    inventory: Inventory,
+}
+
+impl LiveTrader {
+    pub fn new(
+        provider: Arc<RootProvider>,
+        nonces: Arc<NonceManager>,
+        bot: Address,
+        inventory: Inventory,
+    ) -> Self {
+        Self {
+            bot: BotPermissions::new(provider.clone(), bot),
+            provider,
+            nonces,
+            inventory,
+        }
+    }
+
+    pub fn provider(&self) -> &Arc<RootProvider> {
+        &self.provider
+    }
+
+    /// Encodes the arbitrage for the account which sends it, checks that the bot lets the account
+    /// and the targets run it, estimates its gas against the latest state and sends it with
+    /// the bid of the gas strategy.
+    /// `None` if the bid leaves nothing of `expected_profit` wei, the transaction is not sent
+    pub async fn submit(
+        &self,
+        arbitrage: &Arbitrage,
+        expected_profit: U256,
+        gas: &GasPricer,
+    ) -> Result<Option<Submission>> {
+        let key = keccak256(arbitrage.path_id());
+        let account = self.account_for(key, arbitrage).await?;
+        let block = self
+            .provider
+            .get_block_by_number(BlockNumberOrTag::Latest)
+            .await?
+            .ok_or(ExecutorError::invalid_path("latest block is not found"))?;
+
+        let deadline = U256::from(block.header.timestamp + DEADLINE_SECS);
+        let inventory = self.inventory.is_enabled();
+        let tx = encode_arbitrage(arbitrage, self.bot.bot(), account, deadline, inventory)?;
+        self.bot.check(&tx, account).await?;
+        let request = TransactionRequest::default()
+            .with_from(account)
+            .with_to(tx.to)
+            .with_input(tx.data);
+        // estimation executes the transaction, so an arbitrage which would revert is not sent
+        let estimate = self
+            .provider
+            .estimate_gas(request.clone())
+            .into_future()
+            .instrument(tracing::info_span!("simulate"))
+            .await
+            .map_err(|err| ExecutorError::simulation(err.to_string()))?;
+        let gas_limit = estimate + estimate * GAS_MARGIN_PERCENT / 100;
+
+        let pools: Vec<Address> = arbitrage.hops.iter().map(|hop| hop.pool).collect();
+        let context = GasContext::new(&block.header, gas_limit, expected_profit, &pools);
+        let Some(bid) = gas.bid(&context) else {
+            return Ok(None);
+        };
+        let request = request
+            .with_gas_limit(gas_limit)
+            .with_max_fee_per_gas(bid.max_fee_per_gas)
+            .with_max_priority_fee_per_gas(bid.max_priority_fee_per_gas);
+        let pending = self
+            .nonces
+            .send_or_replace(request, key)
+            .instrument(tracing::info_span!("submit", mode = "live"))
+            .await?;
+        Ok(Some(Submission { pending, bid }))
+    }
+
+    /// Account which sends the arbitrage. The replacement of a pending transaction is sent
+    /// by its account, in inventory mode it must hold the amount in as well
+    async fn account_for(&self, key: B256, arbitrage: &Arbitrage) -> Result<Address> {
+        if !self.inventory.is_enabled() {
+            return self.nonces.account_for(key).await;
+        }
+        let token = arbitrage.path[0].0;
+        let account = match self.nonces.pending_account(key).await {
+            Some(account) => account,
+            None => self.inventory.holder(&token, arbitrage.amount_in).ok_or(
+                ExecutorError::inventory(format!(
+                    "no account holds {} of {token}",
+                    arbitrage.amount_in
+                )),
+            )?,
+        };
+        if self.inventory.balance(&account, &token) < arbitrage.amount_in {
+            return Err(ExecutorError::inventory(format!(
+                "{account} holds less than {} of {token}",
+                arbitrage.amount_in
+            )));
+        }
+        Ok(account)
+    }
+}
+
+/// Waits until the nonce of the transaction is mined. Transaction which was replaced or
+/// cancelled is never mined, it is failed without fee. Errors of the node are retried
+pub async fn wait_mined(provider: Arc<RootProvider>, pending: PendingTx) -> Landed {
+    let mut interval = tokio::time::interval(RECEIPT_INTERVAL);
+    loop {
+        interval.tick().await;
+        match check_mined(&provider, &pending).await {
+            Ok(Some(landed)) => return landed,
+            Ok(None) => {}
+            Err(err) => tracing::warn!("failed to check transaction {}: {err}", pending.hash),
+        }
+    }
+}
+
+async fn check_mined(provider: &RootProvider, pending: &PendingTx) -> Result<Option<Landed>> {
+    // nonce is read before the receipt, so a mined nonce without the receipt
+    // means that another transaction used it
+    let mined_nonce = provider
+        .get_transaction_count(pending.account)
+        .latest()
+        .await?;
+    if let Some(receipt) = provider.get_transaction_receipt(pending.hash).await? {
+        return Ok(Some(Landed {
+            status: match receipt.status() {
+                true => ExecutionStatus::Included,
+                false => ExecutionStatus::Reverted,
+            },
+            gas_used: Some(receipt.gas_used),
+            fee_wei: U256::from(receipt.gas_used as u128 * receipt.effective_gas_price),
+        }));
+    }
+    Ok((mined_nonce > pending.nonce).then_some(Landed {
+        status: ExecutionStatus::Failed,
+        gas_used: None,
+        fee_wei: U256::ZERO,
+    }))
+}
@@ -0,0 +1,448 @@
+use crate::{
+    error::{ExecutorError, Result},
+    relay::BundleRelay,
+    wallet::ExecutorWallet,
+};
+use alloy::{
+    eips::eip2718::Encodable2718,
+    network::{NetworkWallet, TransactionBuilder},
+    primitives::{Address, B256, U256},
+    providers::{Provider, RootProvider},
+    rpc::types::TransactionRequest,
+};
+use kronos_common::RpcError;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::OnceCell;
+
+/// Nodes accept a replacement with at least 10% higher fees
+pub const FEE_BUMP_PERCENT: u128 = 10;
+
+/// Gas of a plain ETH transfer, used by cancellations
+pub const TRANSFER_GAS: u64 = 21_000;
+
+/// Pending transactions are checked once per slot
+pub const SYNC_INTERVAL: Duration = Duration::from_secs(12);
+
+/// Transaction of an executor account which is not mined yet
+#[derive(Clone, Debug)]
+pub struct PendingTx {
+    pub account: Address,
+    pub nonce: u64,
+    pub hash: B256,
+    /// Request which was signed, with nonce and fees
+    pub request: TransactionRequest,
+    /// Opportunity key, newer opportunity with the same key replaces the transaction
+    pub key: Option<B256>,
+    /// Block number when the transaction was sent or last replaced
+    pub sent_block: u64,
+}
+
+#[derive(Debug, Default)]
+struct AccountNonces {
+    /// Next nonce to use, `None` until it is read from the node
+    next: Option<u64>,
+    pending: BTreeMap<u64, PendingTx>,
+}
+
+impl AccountNonces {
+    /// Forgets bundles whose target block is mined without them, and the later nonces
+    /// which can't be mined after the gap. Next nonce is read from the node again
+    fn expire_bundles(&mut self, block_number: u64) -> Vec<PendingTx> {
+        let Some(nonce) = self
+            .pending
+            .values()
+            .find(|pending| pending.sent_block < block_number)
+            .map(|pending| pending.nonce)
+        else {
+            return vec![];
+        };
+        self.next = None;
+        self.pending.split_off(&nonce).into_values().collect()
+    }
+}
+
+/// Assigns nonces to transactions of executor accounts and keeps track of them until mined.
+/// Sends of one account are serialized, so concurrent sends get consecutive nonces.
+/// Nonces are seeded from the pending transaction count of the node, so restarts
+/// continue after transactions which are still in the mempool.
+/// With a relay transactions are sent as bundles for the next block, nonces of the ones
+/// which are not included in it are reused
+pub struct NonceManager {
+    provider: Arc<RootProvider>,
+    wallet: ExecutorWallet,
+    relay: Option<BundleRelay>,
+    chain_id: OnceCell<u64>,
+    accounts: Mutex<HashMap<Address, Arc<tokio::sync::Mutex<AccountNonces>>>>,
+}
+
+impl NonceManager {
+    pub fn new(provider: Arc<RootProvider>, wallet: ExecutorWallet) -> Self {
+        Self {
+            provider,
+            wallet,
+            relay: None,
+            chain_id: OnceCell::new(),
+            accounts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_relay(mut self, relay: BundleRelay) -> Self {
+        self.relay = Some(relay);
+        self
+    }
+
+    pub fn wallet(&self) -> &ExecutorWallet {
+        &self.wallet
+    }
+
+    fn account(&self, account: Address) -> Arc<tokio::sync::Mutex<AccountNonces>> {
+        self.accounts
+            .lock()
+            .unwrap()
+            .entry(account)
+            .or_default()
+            .clone()
+    }
+
+    /// Sends the transaction from the next executor account with the next nonce of the account
+    pub async fn send(&self, request: TransactionRequest, key: Option<B256>) -> Result<PendingTx> {
+        let account = self
+            .wallet
+            .next_account()
+            .ok_or(ExecutorError::wallet("no executor accounts"))?;
+        self.send_from(account, request, key).await
+    }
+
+    pub async fn send_from(
+        &self,
+        account: Address,
+        request: TransactionRequest,
+        key: Option<B256>,
+    ) -> Result<PendingTx> {
+        let state = self.account(account);
+        let mut state = state.lock().await;
+        let nonce = match state.next {
+            Some(nonce) => nonce,
+            None => {
+                self.provider
+                    .get_transaction_count(account)
+                    .pending()
+                    .await?
+            }
+        };
+
+        let pending = self
+            .sign_and_send(account, nonce, request, key)
+            .await
+            .inspect_err(|_| state.next = None)?;
+        state.next = Some(nonce + 1);
+        state.pending.insert(nonce, pending.clone());
+        Ok(pending)
+    }
+
+    /// Sends the transaction superseding an older opportunity: a pending transaction with
+    /// the same key is replaced, otherwise the transaction is sent with a new nonce
+    /// of `from` of the request or of the next account
+    pub async fn send_or_replace(
+        &self,
+        request: TransactionRequest,
+        key: B256,
+    ) -> Result<PendingTx> {
+        match (self.find(key).await, request.from) {
+            (Some(pending), _) => self.replace(&pending, request).await,
+            (None, Some(account)) => self.send_from(account, request, Some(key)).await,
+            (None, None) => self.send(request, Some(key)).await,
+        }
+    }
+
+    /// Account which sends the transaction of `key`: the account of its pending transaction,
+    /// so the replacement is encoded for the same sender, otherwise the next account
+    pub async fn account_for(&self, key: B256) -> Result<Address> {
+        match self.pending_account(key).await {
+            Some(account) => Ok(account),
+            None => self
+                .wallet
+                .next_account()
+                .ok_or(ExecutorError::wallet("no executor accounts")),
+        }
+    }
+
+    /// Account of the pending transaction of `key`
+    pub async fn pending_account(&self, key: B256) -> Option<Address> {
+        self.find(key).await.map(|pending| pending.account)
+    }
+
+    /// Replaces the pending transaction with `request`, fees are at least bumped fees of
+    /// the pending transaction
+    pub async fn replace(
+        &self,
+        pending: &PendingTx,
+        request: TransactionRequest,
+    ) -> Result<PendingTx> {
+        self.resend(pending, request, pending.key).await
+    }
+
+    /// Replaces the pending transaction with a zero self-transfer
+    pub async fn cancel(&self, pending: &PendingTx) -> Result<PendingTx> {
+        let request = TransactionRequest::default()
+            .with_to(pending.account)
+            .with_value(U256::ZERO)
+            .with_gas_limit(TRANSFER_GAS);
+        self.resend(pending, request, None).await
+    }
+
+    async fn resend(
+        &self,
+        pending: &PendingTx,
+        request: TransactionRequest,
+        key: Option<B256>,
+    ) -> Result<PendingTx> {
+        let state = self.account(pending.account);
+        let mut state = state.lock().await;
+        if !state.pending.contains_key(&pending.nonce) {
+            return Err(ExecutorError::wallet(format!(
+                "transaction with nonce {} of {} is not pending",
+                pending.nonce, pending.account
+            )));
+        }
+
+        let request = with_bumped_fees(request, &pending.request);
+        let replacement = self
+            .sign_and_send(pending.account, pending.nonce, request, key)
+            .await?;
+        tracing::info!(
+            "replaced {} with {} (nonce {})",
+            pending.hash,
+            replacement.hash,
+            pending.nonce
+        );
+        state.pending.insert(pending.nonce, replacement.clone());
+        Ok(replacement)
+    }
+
+    /// Pending transactions of all accounts
+    pub async fn pending(&self) -> Vec<PendingTx> {
+        let accounts: Vec<_> = self.accounts.lock().unwrap().values().cloned().collect();
+        let mut pending = vec![];
+        for state in accounts {
+            pending.extend(state.lock().await.pending.values().cloned());
+        }
+        pending
+    }
+
+    async fn find(&self, key: B256) -> Option<PendingTx> {
+        self.pending()
+            .await
+            .into_iter()
+            .find(|pending| pending.key == Some(key))
+    }
+
+    /// Forgets transactions which are mined, or replaced by mined ones, and returns them.
+    /// With a relay bundles which missed their block are forgotten too
+    pub async fn sync(&self, account: Address) -> Result<Vec<PendingTx>> {
+        // block is read before the nonce, so a bundle whose block is mined
+        // and whose nonce is not was not included
+        let block_number = match self.relay {
+            Some(_) => Some(self.provider.get_block_number().await?),
+            None => None,
+        };
+        let mined_nonce = self
+            .provider
+            .get_transaction_count(account)
+            .latest()
+            .await?;
+
+        let state = self.account(account);
+        let mut state = state.lock().await;
+        let unmined = state.pending.split_off(&mined_nonce);
+        let mined = std::mem::replace(&mut state.pending, unmined);
+        // transactions sent by other tools from the same account
+        if state.next.is_some_and(|next| next < mined_nonce) {
+            state.next = Some(mined_nonce);
+        }
+        if let Some(block_number) = block_number {
+            for expired in state.expire_bundles(block_number) {
+                tracing::warn!(
+                    "bundle of {} for block {} is not included, nonce {} of {account} is reused",
+                    expired.hash,
+                    expired.sent_block + 1,
+                    expired.nonce
+                );
+            }
+        }
+        Ok(mined.into_values().collect())
+    }
+
+    /// Cancels transactions which are not mined for `max_blocks` after they were sent.
+    /// Failed cancellation is logged and retried on the next sync, the rest are still cancelled
+    pub async fn cancel_stuck(&self, block_number: u64, max_blocks: u64) -> Vec<PendingTx> {
+        let mut cancelled = vec![];
+        for pending in self.pending().await {
+            // cancellations are bumped again if they are stuck too
+            if pending.sent_block + max_blocks > block_number {
+                continue;
+            }
+            tracing::warn!(
+                "transaction {} of {} is stuck since block {}",
+                pending.hash,
+                pending.account,
+                pending.sent_block
+            );
+            match self.cancel(&pending).await {
+                Ok(cancellation) => cancelled.push(cancellation),
+                Err(err) => tracing::warn!("failed to cancel {}: {err}", pending.hash),
+            }
+        }
+        cancelled
+    }
+
+    /// Periodically forgets mined transactions and cancels stuck ones
+    pub async fn start(self: Arc<Self>, stuck_after_blocks: u64) {
+        let mut interval = tokio::time::interval(SYNC_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.sync_all(stuck_after_blocks).await {
+                tracing::warn!("failed to sync nonces: {err}");
+            }
+        }
+    }
+
+    async fn sync_all(&self, stuck_after_blocks: u64) -> Result<()> {
+        for account in self.wallet.accounts() {
+            for mined in self.sync(*account).await? {
+                tracing::info!("transaction {} of {account} is mined", mined.hash);
+            }
+        }
+        let block_number = self.provider.get_block_number().await?;
+        self.cancel_stuck(block_number, stuck_after_blocks).await;
+        Ok(())
+    }
+
+    async fn sign_and_send(
+        &self,
+        account: Address,
+        nonce: u64,
+        request: TransactionRequest,
+        key: Option<B256>,
+    ) -> Result<PendingTx> {
+        let chain_id = *self
+            .chain_id
+            .get_or_try_init(|| async { self.provider.get_chain_id().await })
+            .await?;
+        let request = request
+            .with_from(account)
+            .with_nonce(nonce)
+            .with_chain_id(chain_id);
+
+        let envelope = self
+            .wallet
+            .sign_request(request.clone())
+            .await
+            .map_err(|err| ExecutorError::wallet(err.to_string()))?;
+        let raw = envelope.encoded_2718();
+        let sent_block = self.provider.get_block_number().await?;
+        match &self.relay {
+            Some(relay) => relay.send_bundle(&[raw.into()], sent_block + 1).await?,
+            None => {
+                let _ = self
+                    .provider
+                    .send_raw_transaction(&raw)
+                    .await
+                    .map_err(RpcError::from)?;
+            }
+        }
+
+        Ok(PendingTx {
+            account,
+            nonce,
+            hash: *envelope.tx_hash(),
+            request,
+            key,
+            sent_block,
+        })
+    }
+}
+
+/// Fee which is enough to replace a transaction with `fee`
+pub fn bump_fee(fee: u128) -> u128 {
+    fee + fee * FEE_BUMP_PERCENT / 100 + 1
+}
+
+/// Raises fees of the request to replace the transaction with `previous` request
+pub fn with_bumped_fees(
+    mut request: TransactionRequest,
+    previous: &TransactionRequest,
+) -> TransactionRequest {
+    let raise = |fee: Option<u128>, previous: Option<u128>| match (fee, previous) {
+        (fee, Some(previous)) => Some(fee.unwrap_or_default().max(bump_fee(previous))),
+        (fee, None) => fee,
+    };
+    request.gas_price = raise(request.gas_price, previous.gas_price);
+    request.max_fee_per_gas = raise(request.max_fee_per_gas, previous.max_fee_per_gas);
+    request.max_priority_fee_per_gas = raise(
+        request.max_priority_fee_per_gas,
+        previous.max_priority_fee_per_gas,
+    );
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending(nonce: u64, sent_block: u64) -> PendingTx {
+        PendingTx {
+            account: Address::repeat_byte(1),
+            nonce,
+            hash: B256::repeat_byte(nonce as u8),
+            request: TransactionRequest::default(),
+            key: None,
+            sent_block,
+        }
+    }
+
+    #[test]
+    fn expired_bundle_frees_its_nonce_and_later_ones() {
+        // mined nonces are already forgotten by `sync`
+        let mut state = AccountNonces {
+            next: Some(8),
+            pending: [(6, pending(6, 101)), (7, pending(7, 102))].into(),
+        };
+        // bundles for blocks 102 and 103 may still be included
+        assert!(state.expire_bundles(101).is_empty());
+        assert_eq!(state.next, Some(8));
+
+        // block 102 is mined without nonce 6, so nonce 7 can't be mined either
+        let expired: Vec<u64> = state
+            .expire_bundles(102)
+            .iter()
+            .map(|pending| pending.nonce)
+            .collect();
+        assert_eq!(expired, vec![6, 7]);
+        assert!(state.pending.is_empty());
+        assert_eq!(state.next, None);
+    }
+
+    #[test]
+    fn bumps_fees_of_replacement() {
+        let previous = TransactionRequest::default()
+            .with_max_fee_per_gas(100)
+            .with_max_priority_fee_per_gas(10);
+
+        // higher fees of the new request are kept
+        let request = TransactionRequest::default()
+            .with_max_fee_per_gas(200)
+            .with_max_priority_fee_per_gas(5);
+        let request = with_bumped_fees(request, &previous);
+        assert_eq!(request.max_fee_per_gas, Some(200));
+        assert_eq!(request.max_priority_fee_per_gas, Some(12));
+
+        let request = with_bumped_fees(TransactionRequest::default(), &previous);
+        assert_eq!(request.max_fee_per_gas, Some(111));
+        assert_eq!(request.gas_price, None);
+    }
+}
@@ -0,0 +1,650 @@
+use crate::{
+    error::{ExecutorError, Result},
+    ExecutorEvent,
+};
+use alloy::providers::{Provider, RootProvider};
+use chrono::Utc;
+use kronos_config::{AlertSinkConfig, AlertsConfig};
+use kronos_db::tables::ExecutionStatus;
+use kronos_metrics as metrics;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::{broadcast, mpsc};
+
+/// Sink is given up on after this time, so a slow sink does not hold other alerts
+pub const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    /// Never rate limited
+    Critical,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    ArbitrageExecuted,
+    TransactionReverted,
+    CircuitBreaker,
+    ExecutionResumed,
+    RpcOutage,
+    RpcRestored,
+    BlockStall,
+    LowBalance,
+}
+
+impl AlertKind {
+    pub const ALL: [Self; 8] = [
+        Self::ArbitrageExecuted,
+        Self::TransactionReverted,
+        Self::CircuitBreaker,
+        Self::ExecutionResumed,
+        Self::RpcOutage,
+        Self::RpcRestored,
+        Self::BlockStall,
+        Self::LowBalance,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ArbitrageExecuted => "arbitrage_executed",
+            Self::TransactionReverted => "transaction_reverted",
+            Self::CircuitBreaker => "circuit_breaker",
+            Self::ExecutionResumed => "execution_resumed",
+            Self::RpcOutage => "rpc_outage",
+            Self::RpcRestored => "rpc_restored",
+            Self::BlockStall => "block_stall",
+            Self::LowBalance => "low_balance",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|known| known.as_str() == kind)
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::ArbitrageExecuted | Self::ExecutionResumed | Self::RpcRestored => Severity::Info,
+            Self::TransactionReverted | Self::LowBalance => Severity::Warning,
+            Self::CircuitBreaker | Self::RpcOutage | Self::BlockStall => Severity::Critical,
+        }
+    }
+
+    /// Kind of the opposite state transition: the recovery of an outage or a pause and back.
+    /// An alert clears de-duplication of its opposite, so every transition is sent
+    pub fn opposite(&self) -> Option<Self> {
+        match self {
+            Self::CircuitBreaker => Some(Self::ExecutionResumed),
+            Self::ExecutionResumed => Some(Self::CircuitBreaker),
+            Self::RpcOutage => Some(Self::RpcRestored),
+            Self::RpcRestored => Some(Self::RpcOutage),
+            _ => None,
+        }
+    }
+
+    /// Template used if `alerts.templates` has none for the kind
+    pub fn default_template(&self) -> &'static str {
+        match self {
+            Self::ArbitrageExecuted => {
+                "Arbitrage {opportunity_id} ({mode}) is included, PnL {profit_usd} USD"
+            }
+            Self::TransactionReverted => "Transaction of arbitrage {opportunity_id} reverted",
+            Self::CircuitBreaker => "Execution is paused by {limit}: {reason}",
+            Self::ExecutionResumed => "Execution is resumed",
+            Self::RpcOutage => "Node does not answer: {error}",
+            Self::RpcRestored => "Node answers again",
+            Self::BlockStall => "No blocks for {secs}s, last block {block_number}",
+            Self::LowBalance => "Executor {account} has {balance_eth} ETH, below {min_eth} ETH",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Alert {
+    pub kind: AlertKind,
+    pub severity: Severity,
+    /// Alerts with the same key are de-duplicated, the kind by default
+    pub key: String,
+    /// Values of template placeholders
+    pub fields: BTreeMap<&'static str, String>,
+}
+
+impl Alert {
+    pub fn new(kind: AlertKind) -> Self {
+        Self {
+            kind,
+            severity: kind.severity(),
+            key: kind.as_str().to_string(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_key(mut self, key: impl Display) -> Self {
+        self.key = format!("{}:{key}", self.kind.as_str());
+        self
+    }
+
+    pub fn with_field(mut self, name: &'static str, value: impl Display) -> Self {
+        self.fields.insert(name, value.to_string());
+        self
+    }
+
+    /// Alert of the executor event, `None` if the event is not worth an alert
+    pub fn from_event(event: &ExecutorEvent) -> Option<Self> {
+        let alert = match event {
+            ExecutorEvent::ArbitrageExecuted {
+                opportunity_id,
+                mode,
+                status: ExecutionStatus::Included,
+                profit_usd,
+            } => Self::new(AlertKind::ArbitrageExecuted)
+                .with_key(opportunity_id)
+                .with_field("opportunity_id", opportunity_id)
+                .with_field("mode", mode.as_str())
+                .with_field(
+                    "profit_usd",
+                    profit_usd.map_or("unknown".to_string(), |usd| format!("{usd:.2}")),
+                ),
+            ExecutorEvent::ArbitrageExecuted {
+                opportunity_id,
+                mode,
+                status: ExecutionStatus::Reverted,
+                ..
+            } => Self::new(AlertKind::TransactionReverted)
+                .with_key(opportunity_id)
+                .with_field("opportunity_id", opportunity_id)
+                .with_field("mode", mode.as_str()),
+            ExecutorEvent::Paused(breach) => Self::new(AlertKind::CircuitBreaker)
+                .with_key(breach.limit())
+                .with_field("limit", breach.limit())
+                .with_field("reason", breach),
+            ExecutorEvent::Resumed => Self::new(AlertKind::ExecutionResumed),
+            _ => return None,
+        };
+        Some(alert)
+    }
+}
+
+/// Renders alerts with templates of `alerts.templates` or defaults of the kinds
+#[derive(Clone, Debug, Default)]
+pub struct Templates {
+    custom: HashMap<AlertKind, String>,
+}
+
+impl Templates {
+    pub fn from_config(templates: &BTreeMap<String, String>) -> Result<Self> {
+        let mut custom = HashMap::new();
+        for (kind, template) in templates.iter() {
+            let kind = AlertKind::parse(kind)
+                .ok_or_else(|| ExecutorError::alert(format!("unknown alert kind {kind}")))?;
+            custom.insert(kind, template.clone());
+        }
+        Ok(Self { custom })
+    }
+
+    /// Placeholders without a field are left as they are
+    pub fn render(&self, alert: &Alert) -> String {
+        let mut text = self
+            .custom
+            .get(&alert.kind)
+            .map(String::as_str)
+            .unwrap_or(alert.kind.default_template())
+            .to_string();
+        for (name, value) in alert.fields.iter() {
+            text = text.replace(&format!("{{{name}}}"), value);
+        }
+        text
+    }
+}
+
+/// Why the alert is not sent
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dropped {
+    Duplicate,
+    RateLimited,
+}
+
+impl Dropped {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Duplicate => "duplicate",
+            Self::RateLimited => "rate_limited",
+        }
+    }
+}
+
+/// De-duplicates alerts by key and limits the number of alerts per minute.
+/// Alert of a state transition is a duplicate only until the opposite transition is sent
+#[derive(Debug)]
+pub struct Limiter {
+    dedup: Duration,
+    max_per_minute: usize,
+    last_sent: HashMap<String, Instant>,
+    sent: VecDeque<Instant>,
+}
+
+impl Limiter {
+    pub fn new(dedup: Duration, max_per_minute: u32) -> Self {
+        Self {
+            dedup,
+            max_per_minute: max_per_minute as usize,
+            last_sent: HashMap::new(),
+            sent: VecDeque::new(),
+        }
+    }
+
+    pub fn admit(&mut self, alert: &Alert, now: Instant) -> Result<(), Dropped> {
+        let dedup = self.dedup;
+        self.last_sent
+            .retain(|_, sent_at| now.duration_since(*sent_at) < dedup);
+        if self.last_sent.contains_key(&alert.key) {
+            return Err(Dropped::Duplicate);
+        }
+
+        let minute = Duration::from_secs(60);
+        while let Some(sent_at) = self.sent.front() {
+            if now.duration_since(*sent_at) < minute {
+                break;
+            }
+            self.sent.pop_front();
+        }
+        if alert.severity < Severity::Critical && self.sent.len() >= self.max_per_minute {
+            return Err(Dropped::RateLimited);
+        }
+
+        if let Some(opposite) = alert.kind.opposite() {
+            let prefix = format!("{}:", opposite.as_str());
+            self.last_sent
+                .retain(|key, _| key != opposite.as_str() && !key.starts_with(&prefix));
+        }
+        self.last_sent.insert(alert.key.clone(), now);
+        self.sent.push_back(now);
+        Ok(())
+    }
+}
+
+/// Chat or endpoint which receives rendered alerts
+#[derive(Clone, Debug)]
+pub enum Sink {
+    Telegram { url: String, chat_id: String },
+    Slack { url: String },
+    Webhook { url: String },
+}
+
+impl Sink {
+    /// Secrets are read from the environment variables named in the config
+    pub fn from_config(config: &AlertSinkConfig) -> Result<Self> {
+        Self::from_config_with_env(config, |name| std::env::var(name).ok())
+    }
+
+    /// Secrets are looked up by `env` under the variable names of the config
+    pub fn from_config_with_env(
+        config: &AlertSinkConfig,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self> {
+        let env = |name: &str| {
+            env(name).ok_or_else(|| {
+                ExecutorError::alert(format!("environment variable {name} is not set"))
+            })
+        };
+        Ok(match config {
+            AlertSinkConfig::Telegram {
+                bot_token_env,
+                chat_id,
+                api_url,
+            } => Self::Telegram {
+                url: format!(
+                    "{}/bot{}/sendMessage",
+                    api_url.trim_end_matches('/'),
+                    env(bot_token_env)?
+                ),
+                chat_id: chat_id.clone(),
+            },
+            AlertSinkConfig::Slack { webhook_url_env } => Self::Slack {
+                url: env(webhook_url_env)?,
+            },
+            AlertSinkConfig::Webhook { url } => Self::Webhook { url: url.clone() },
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Telegram { .. } => "telegram",
+            Self::Slack { .. } => "slack",
+            Self::Webhook { .. } => "webhook",
+        }
+    }
+
+    pub async fn send(&self, client: &reqwest::Client, alert: &Alert, text: &str) -> Result<()> {
+        let request = match self {
+            Self::Telegram { url, chat_id } => client.post(url).json(&serde_json::json!({
+                "chat_id": chat_id,
+                "text": text,
+            })),
+            Self::Slack { url } => client.post(url).json(&serde_json::json!({ "text": text })),
+            Self::Webhook { url } => client.post(url).json(&serde_json::json!({
+                "kind": alert.kind,
+                "severity": alert.severity,
+                "key": alert.key,
+                "text": text,
+                "fields": alert.fields,
+                "created_at": Utc::now(),
+            })),
+        };
+        let response = request
+            .timeout(SEND_TIMEOUT)
+            .send()
+            .await
+            // url of telegram holds the bot token
+            .map_err(|err| {
+                ExecutorError::alert(format!("{}: {}", self.name(), err.without_url()))
+            })?;
+        if !response.status().is_success() {
+            return Err(ExecutorError::alert(format!(
+                "{} answered {}",
+                self.name(),
+                response.status()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Handle which queues alerts for the dispatcher, alerts are dropped if no sinks are configured
+#[derive(Clone, Debug, Default)]
+pub struct Notifier {
+    tx: Option<mpsc::UnboundedSender<Alert>>,
+}
+
+impl Notifier {
+    pub fn notify(&self, alert: Alert) {
+        if let Some(tx) = &self.tx {
+            // dispatcher lives as long as the bot
+            let _ = tx.send(alert);
+        }
+    }
+
+    /// Turns executor events into alerts
+    pub async fn follow(self, mut events: broadcast::Receiver<ExecutorEvent>) {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if let Some(alert) = Alert::from_event(&event) {
+                        self.notify(alert);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("notifier skipped {skipped} executor events")
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+
+    /// Alerts when the node stops answering and when it answers again
+    pub async fn watch_rpc(self, provider: Arc<RootProvider>, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        let mut down = false;
+        loop {
+            interval.tick().await;
+            match provider.get_block_number().await {
+                Ok(_) if down => {
+                    down = false;
+                    self.notify(Alert::new(AlertKind::RpcRestored));
+                }
+                Err(err) if !down => {
+                    down = true;
+                    self.notify(Alert::new(AlertKind::RpcOutage).with_field("error", err));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Renders queued alerts and delivers them to every sink
+pub struct AlertDispatcher {
+    sinks: Vec<Sink>,
+    templates: Templates,
+    limiter: Limiter,
+    client: reqwest::Client,
+    tx: mpsc::UnboundedSender<Alert>,
+    rx: mpsc::UnboundedReceiver<Alert>,
+}
+
+impl AlertDispatcher {
+    pub fn from_config(config: &AlertsConfig) -> Result<Self> {
+        Self::from_config_with_env(config, |name| std::env::var(name).ok())
+    }
+
+    /// Secrets of the sinks are looked up by `env`, see [`Sink::from_config_with_env`]
+    pub fn from_config_with_env(
+        config: &AlertsConfig,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self> {
+        let sinks = config
+            .sinks
+            .iter()
+            .map(|sink| Sink::from_config_with_env(sink, &env))
+            .collect::<Result<Vec<_>>>()?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        Ok(Self {
+            sinks,
+            templates: Templates::from_config(&config.templates)?,
+            limiter: Limiter::new(
+                Duration::from_secs(config.dedup_secs),
+                config.max_per_minute,
+            ),
+            client: reqwest::Client::new(),
+            tx,
+            rx,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    pub fn notifier(&self) -> Notifier {
+        Notifier {
+            tx: (!self.is_empty()).then(|| self.tx.clone()),
+        }
+    }
+
+    /// Sends the alert to all sinks, returns why it was dropped
+    pub async fn dispatch(&mut self, alert: &Alert) -> Result<(), Dropped> {
+        let kind = alert.kind.as_str();
+        if let Err(dropped) = self.limiter.admit(alert, Instant::now()) {
+            tracing::debug!("alert {} is dropped: {}", alert.key, dropped.as_str());
+            metrics::ALERTS
+                .with_label_values(&[kind, dropped.as_str()])
+                .inc();
+            return Err(dropped);
+        }
+
+        let text = self.templates.render(alert);
+        for sink in self.sinks.iter() {
+            match sink.send(&self.client, alert, &text).await {
+                Ok(()) => metrics::ALERTS.with_label_values(&[kind, "sent"]).inc(),
+                Err(err) => {
+                    metrics::ALERTS.with_label_values(&[kind, "failed"]).inc();
+                    tracing::warn!("failed to send alert {}: {err}", alert.key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn start(mut self) {
+        while let Some(alert) = self.rx.recv().await {
+            let _ = self.dispatch(&alert).await;
+        }
+    }
+}
+
+/// Notes received blocks and alerts when the block stream stalls
+#[derive(Clone, Debug)]
+pub struct BlockWatchdog {
+    last_block: Arc<Mutex<(u64, Instant)>>,
+}
+
+impl Default for BlockWatchdog {
+    fn default() -> Self {
+        Self {
+            last_block: Arc::new(Mutex::new((0, Instant::now()))),
+        }
+    }
+}
+
+impl BlockWatchdog {
+    pub fn block(&self, block_number: u64) {
+        *self.last_block.lock().unwrap() = (block_number, Instant::now());
+    }
+
+    /// Alerts once per stall, the next alert is raised after blocks come again
+    pub async fn start(self, notifier: Notifier, stall: Duration) {
+        let mut interval = tokio::time::interval((stall / 4).max(Duration::from_secs(1)));
+        let mut stalled_since = None;
+        loop {
+            interval.tick().await;
+            let (block_number, received_at) = *self.last_block.lock().unwrap();
+            if received_at.elapsed() < stall {
+                stalled_since = None;
+                continue;
+            }
+            if stalled_since == Some(received_at) {
+                continue;
+            }
+            stalled_since = Some(received_at);
+            notifier.notify(
+                Alert::new(AlertKind::BlockStall)
+                    .with_key(block_number)
+                    .with_field("block_number", block_number)
+                    .with_field("secs", received_at.elapsed().as_secs()),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::risk::Breach;
+    use kronos_db::tables::ExecutionMode;
+
+    #[test]
+    fn renders_templates_of_events() {
+        let event = ExecutorEvent::ArbitrageExecuted {
+            opportunity_id: 7,
+            mode: ExecutionMode::Paper,
+            status: ExecutionStatus::Included,
+            profit_usd: Some(12.345),
+        };
+        let alert = Alert::from_event(&event).unwrap();
+        let templates = Templates::default();
+        assert_eq!(
+            templates.render(&alert),
+            "Arbitrage 7 (paper) is included, PnL 12.35 USD"
+        );
+
+        let custom = Templates::from_config(&BTreeMap::from([(
+            "circuit_breaker".to_string(),
+            "paused: {reason} ({missing})".to_string(),
+        )]))
+        .unwrap();
+        let breaker = Alert::from_event(&ExecutorEvent::Paused(Breach::ConsecutiveReverts {
+            count: 3,
+        }))
+        .unwrap();
+        assert_eq!(breaker.key, "circuit_breaker:consecutive_reverts");
+        assert_eq!(
+            custom.render(&breaker),
+            "paused: 3 transactions reverted in a row ({missing})"
+        );
+
+        let failed = ExecutorEvent::ArbitrageExecuted {
+            status: ExecutionStatus::Failed,
+            opportunity_id: 7,
+            mode: ExecutionMode::Paper,
+            profit_usd: None,
+        };
+        assert!(Alert::from_event(&failed).is_none());
+        assert!(
+            Templates::from_config(&BTreeMap::from([("unknown".to_string(), String::new())]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn limiter_drops_duplicates_and_bursts() {
+        let mut limiter = Limiter::new(Duration::from_secs(600), 2);
+        let now = Instant::now();
+        let low = |account: u8| Alert::new(AlertKind::LowBalance).with_key(account);
+
+        assert_eq!(limiter.admit(&low(1), now), Ok(()));
+        assert_eq!(limiter.admit(&low(1), now), Err(Dropped::Duplicate));
+        assert_eq!(limiter.admit(&low(2), now), Ok(()));
+        assert_eq!(limiter.admit(&low(3), now), Err(Dropped::RateLimited));
+        // critical alerts pass the rate limit
+        assert_eq!(
+            limiter.admit(&Alert::new(AlertKind::RpcOutage), now),
+            Ok(())
+        );
+
+        let later = now + Duration::from_secs(601);
+        assert_eq!(limiter.admit(&low(1), later), Ok(()));
+    }
+
+    #[test]
+    fn recovery_lets_the_next_outage_and_pause_through() {
+        let mut limiter = Limiter::new(Duration::from_secs(600), 20);
+        let now = Instant::now();
+        let paused = || {
+            Alert::from_event(&ExecutorEvent::Paused(Breach::ConsecutiveReverts {
+                count: 3,
+            }))
+            .unwrap()
+        };
+
+        assert_eq!(
+            limiter.admit(&Alert::new(AlertKind::RpcOutage), now),
+            Ok(())
+        );
+        assert_eq!(
+            limiter.admit(&Alert::new(AlertKind::RpcOutage), now),
+            Err(Dropped::Duplicate)
+        );
+        assert_eq!(
+            limiter.admit(&Alert::new(AlertKind::RpcRestored), now),
+            Ok(())
+        );
+        assert_eq!(
+            limiter.admit(&Alert::new(AlertKind::RpcOutage), now),
+            Ok(())
+        );
+        assert_eq!(
+            limiter.admit(&Alert::new(AlertKind::RpcRestored), now),
+            Ok(())
+        );
+
+        assert_eq!(limiter.admit(&paused(), now), Ok(()));
+        assert_eq!(limiter.admit(&paused(), now), Err(Dropped::Duplicate));
+        assert_eq!(
+            limiter.admit(&Alert::new(AlertKind::ExecutionResumed), now),
+            Ok(())
+        );
+        assert_eq!(limiter.admit(&paused(), now), Ok(()));
+        assert_eq!(
+            limiter.admit(&Alert::new(AlertKind::ExecutionResumed), now),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,446 @@
+use crate::{
+    bot::BotPermissions,
+    encode::encode_arbitrage,
+    error::{ExecutorError, Result},
+    gas::{GasContext, GasPricer},
+    live::DEADLINE_SECS,
+};
+use alloy::{
+    network::TransactionBuilder,
+    primitives::{utils::format_ether, Address, Bytes, I256, U256},
+    providers::{Provider, RootProvider},
+    rpc::types::{
+        simulate::{SimBlock, SimCallResult, SimulatePayload},
+        TransactionRequest,
+    },
+    sol_types::SolCall,
+};
+use chrono::Utc;
+use ethereum_abi::IERC20;
+use kronos_db::{
+    tables::{Execution, ExecutionMode, ExecutionStatus},
+    DB,
+};
+use kronos_dexes::common::Arbitrage;
+use kronos_math::{oracle::PriceOracle, WETH};
+use kronos_metrics as metrics;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc, sync::Mutex};
+use tracing::Instrument;
+
+/// What would happen to the paper transaction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaperOutcome {
+    /// Transaction would be included with simulated output
+    Filled,
+    /// Pending transaction of a competitor on the same pairs bids more
+    Missed,
+    /// Nothing is left after simulation and gas, the transaction would not be sent
+    Unprofitable,
+    /// Transaction reverts at the block of the arbitrage, it would not be sent
+    Reverted,
+}
+
+/// Arbitrage filled on paper
+#[derive(Clone, Debug)]
+pub struct PaperFill {
+    pub token: Address,
+    pub amount_in: U256,
+    /// Output predicted by the adapter
+    pub predicted_out: U256,
+    /// Output of the transaction at the block of the arbitrage, zero if it reverts
+    pub simulated_out: U256,
+    pub outcome: PaperOutcome,
+    /// Profit minus gas in USD, 0 if not filled
+    pub pnl_usd: f64,
+}
+
+impl PaperFill {
+    pub fn slippage_bps(&self) -> i32 {
+        slippage_bps(self.predicted_out, self.simulated_out)
+    }
+}
+
+/// Predicted minus simulated output in basis points of predicted output
+pub fn slippage_bps(predicted: U256, simulated: U256) -> i32 {
+    if predicted.is_zero() {
+        return 0;
+    }
+    let bps = |amount: U256| amount * U256::from(10_000) / predicted;
+    let bps = if simulated <= predicted {
+        i64::try_from(bps(predicted - simulated)).unwrap_or(i64::MAX)
+    } else {
+        -i64::try_from(bps(simulated - predicted)).unwrap_or(i64::MAX)
+    };
+    bps.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+}
+
+/// Virtual inventory and results of paper trading, persisted in redis across restarts
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PaperLedger {
+    /// Change of balance of every traded token
+    pub inventory: HashMap<Address, I256>,
+    pub pnl_usd: f64,
+    pub filled: u64,
+    pub missed: u64,
+    pub unprofitable: u64,
+    #[serde(default)]
+    pub reverted: u64,
+    slippage_bps_sum: i64,
+}
+
+impl PaperLedger {
+    pub fn record(&mut self, fill: &PaperFill) {
+        // reverted transaction has no output to compare
+        if fill.outcome != PaperOutcome::Reverted {
+            self.slippage_bps_sum += fill.slippage_bps() as i64;
+        }
+        match fill.outcome {
+            PaperOutcome::Filled => {
+                self.filled += 1;
+                let delta = I256::from_raw(fill.simulated_out) - I256::from_raw(fill.amount_in);
+                *self.inventory.entry(fill.token).or_default() += delta;
+                self.pnl_usd += fill.pnl_usd;
+            }
+            PaperOutcome::Missed => self.missed += 1,
+            PaperOutcome::Unprofitable => self.unprofitable += 1,
+            PaperOutcome::Reverted => self.reverted += 1,
+        }
+    }
+
+    /// Average slippage of all simulated arbitrages which don't revert
+    pub fn average_slippage_bps(&self) -> f64 {
+        let count = self.filled + self.missed + self.unprofitable;
+        match count {
+            0 => 0.0,
+            count => self.slippage_bps_sum as f64 / count as f64,
+        }
+    }
+
+    /// Share of profitable arbitrages which would be outbid
+    pub fn missed_ratio(&self) -> f64 {
+        match self.filled + self.missed {
+            0 => 0.0,
+            count => self.missed as f64 / count as f64,
+        }
+    }
+}
+
+/// Executes arbitrages on paper: the transaction of the arbitrage is simulated with
+/// `eth_simulateV1` at its block and filled in the virtual ledger, fills are stored as executions with `paper` mode
+pub struct PaperTrader {
+    db: DB,
+    oracle: PriceOracle,
+    provider: Arc<RootProvider>,
+    bot: BotPermissions,
+    ledger: Mutex<PaperLedger>,
+}
+
+impl PaperTrader {
+    pub fn new(db: DB, oracle: PriceOracle, provider: Arc<RootProvider>, bot: Address) -> Self {
+        Self {
+            db,
+            oracle,
+            bot: BotPermissions::new(provider.clone(), bot),
+            provider,
+            ledger: Mutex::new(PaperLedger::default()),
+        }
+    }
+
+    pub fn ledger(&self) -> PaperLedger {
+        self.ledger.lock().unwrap().clone()
+    }
+
+    /// Continues the ledger stored before restart
+    pub async fn restore(&self) -> Result<()> {
+        let Some(ledger) = self.db.redis().paper_ledger::<PaperLedger>().await? else {
+            return Ok(());
+        };
+        metrics::PAPER_PNL_USD.set(ledger.pnl_usd);
+        *self.ledger.lock().unwrap() = ledger;
+        Ok(())
+    }
+
+    /// Runs the transaction of the arbitrage with `eth_simulateV1` on top of its block, sent by
+    /// the owner of the bot. Output is the amount in plus the change of the start token balance
+    /// of the receiver of the profit: the bot for flash loans, the owner for batch swaps.
+    /// Returns the output and the gas used, `None` if the transaction reverts
+    pub async fn simulate(
+        &self,
+        arbitrage: &Arbitrage,
+        timestamp: u64,
+    ) -> Result<Option<(U256, u64)>> {
+        let owner = self.bot.owner().await?;
+        let deadline = U256::from(timestamp + DEADLINE_SECS);
+        // the owner holds no inventory, so the arbitrage is simulated with a flash loan
+        let tx = encode_arbitrage(arbitrage, self.bot.bot(), owner, deadline, false)?;
+        self.bot.check(&tx, owner).await?;
+        let receiver = match tx.to == self.bot.bot() {
+            true => self.bot.bot(),
+            false => owner,
+        };
+
+        let call = |to: Address, input: Bytes| {
+            TransactionRequest::default()
+                .with_from(owner)
+                .with_to(to)
+                .with_input(input)
+        };
+        let balance_of = || {
+            call(
+                arbitrage.path[0].0,
+                IERC20::balanceOfCall { _owner: receiver }
+                    .abi_encode()
+                    .into(),
+            )
+        };
+        let block =
+            SimBlock::default().extend_calls([balance_of(), call(tx.to, tx.data), balance_of()]);
+        let payload = SimulatePayload {
+            block_state_calls: vec![block],
+            ..Default::default()
+        };
+        let simulated = self
+            .provider
+            .simulate(&payload)
+            .number(arbitrage.block_number)
+            .await?;
+        let calls = &simulated
+            .first()
+            .ok_or(ExecutorError::simulation("empty simulation result"))?
+            .calls;
+        simulated_output(arbitrage.amount_in, calls)
+    }
+
+    /// Simulates the arbitrage, fills it in the ledger and stores the paper execution.
+    /// Unprofitable and reverted arbitrages are not stored, the live executor would not send them
+    pub async fn fill(
+        &self,
+        arbitrage: &Arbitrage,
+        opportunity_id: i64,
+        gas: &GasPricer,
+    ) -> Result<PaperFill> {
+        let token = arbitrage.path[0].0;
+        let block = self
+            .provider
+            .get_block_by_number(arbitrage.block_number.into())
+            .await?
+            .ok_or(ExecutorError::invalid_path(format!(
+                "block {} is not found",
+                arbitrage.block_number
+            )))?;
+        let simulated = self
+            .simulate(arbitrage, block.header.timestamp)
+            .instrument(tracing::info_span!("simulate"))
+            .await?;
+
+        let mut fill = PaperFill {
+            token,
+            amount_in: arbitrage.amount_in,
+            predicted_out: arbitrage.amount_in + arbitrage.revenue,
+            simulated_out: U256::ZERO,
+            outcome: PaperOutcome::Reverted,
+            pnl_usd: 0.0,
+        };
+        let mut bid = None;
+        let mut gas_used = None;
+        if let Some((simulated_out, simulated_gas)) = simulated {
+            fill.simulated_out = simulated_out;
+            fill.outcome = PaperOutcome::Unprofitable;
+            gas_used = Some(simulated_gas);
+
+            let profit = simulated_out.saturating_sub(arbitrage.amount_in);
+            let profit_usd = self
+                .oracle
+                .amount_to_usd(arbitrage.block_number, &token, profit)
+                .await?;
+            let eth_usd = self
+                .oracle
+                .usd_price(arbitrage.block_number, &WETH)
+                .await?
+                .price;
+            let profit_wei = U256::from((profit_usd / eth_usd * 1e18) as u128);
+
+            let pools: Vec<Address> = arbitrage.hops.iter().map(|hop| hop.pool).collect();
+            let context = GasContext::new(&block.header, simulated_gas, profit_wei, &pools);
+            bid = gas.bid(&context);
+            if let Some(bid) = &bid {
+                let competitor = gas.mempool().highest_tip(&pools).unwrap_or_default();
+                if competitor > bid.max_priority_fee_per_gas {
+                    fill.outcome = PaperOutcome::Missed;
+                } else {
+                    fill.outcome = PaperOutcome::Filled;
+                    let fee = context.next_base_fee + bid.max_priority_fee_per_gas;
+                    let gas_eth: f64 = format_ether(U256::from(fee * simulated_gas as u128))
+                        .parse()
+                        .unwrap_or_default();
+                    fill.pnl_usd = profit_usd - gas_eth * eth_usd;
+                }
+            }
+        }
+
+        let ledger = {
+            let mut ledger = self.ledger.lock().unwrap();
+            ledger.record(&fill);
+            ledger.clone()
+        };
+        metrics::PAPER_PNL_USD.set(ledger.pnl_usd);
+        if let Err(err) = self.db.redis().set_paper_ledger(&ledger).await {
+            tracing::warn!("failed to store paper ledger: {err}");
+        }
+        tracing::info!(
+            "paper {:?}: pnl {:.2} USD, slippage {} bps, total pnl {:.2} USD, missed {:.0}%",
+            fill.outcome,
+            fill.pnl_usd,
+            fill.slippage_bps(),
+            ledger.pnl_usd,
+            ledger.missed_ratio() * 100.0
+        );
+
+        if matches!(fill.outcome, PaperOutcome::Filled | PaperOutcome::Missed) {
+            let execution = Execution {
+                id: 0,
+                opportunity_id: Some(opportunity_id),
+                block_number: arbitrage.block_number,
+                tx_hash: None,
+                status: match fill.outcome {
+                    PaperOutcome::Filled => ExecutionStatus::Included,
+                    _ => ExecutionStatus::Failed,
+                },
+                mode: ExecutionMode::Paper,
+                gas_used,
+                profit_usd: Some(fill.pnl_usd),
+                gas: bid,
+                slippage_bps: Some(fill.slippage_bps()),
+                created_at: Utc::now(),
+            };
+            self.db
+                .postgres()
+                .insert_execution(&execution)
+                .instrument(tracing::info_span!(
+                    "record",
+                    mode = "paper",
+                    status = execution.status.as_str()
+                ))
+                .await?;
+        }
+        Ok(fill)
+    }
+}
+
+/// Output of the arbitrage from the results of the balance call, the arbitrage
+/// and the balance call again
+fn simulated_output(amount_in: U256, calls: &[SimCallResult]) -> Result<Option<(U256, u64)>> {
+    let [before, arbitrage, after] = calls else {
+        return Err(ExecutorError::simulation(format!(
+            "expected 3 simulated calls, got {}",
+            calls.len()
+        )));
+    };
+    if !arbitrage.status {
+        let reason = arbitrage.error.as_ref().map(|err| err.message.as_str());
+        tracing::info!("paper transaction reverts: {}", reason.unwrap_or_default());
+        return Ok(None);
+    }
+    let balance = |call: &SimCallResult| {
+        IERC20::balanceOfCall::abi_decode_returns(&call.return_data, false)
+            .map(|balance| balance.balance)
+            .map_err(|err| ExecutorError::simulation(format!("invalid balance: {err}")))
+    };
+    let received = balance(after)?.saturating_sub(balance(before)?);
+    Ok(Some((amount_in + received, arbitrage.gas_used)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(outcome: PaperOutcome, simulated_out: u64, pnl_usd: f64) -> PaperFill {
+        PaperFill {
+            token: WETH,
+            amount_in: U256::from(1_000),
+            predicted_out: U256::from(1_100),
+            simulated_out: U256::from(simulated_out),
+            outcome,
+            pnl_usd,
+        }
+    }
+
+    fn call(status: bool, return_data: Vec<u8>, gas_used: u64) -> SimCallResult {
+        SimCallResult {
+            return_data: return_data.into(),
+            logs: vec![],
+            gas_used,
+            status,
+            error: None,
+        }
+    }
+
+    fn balance(amount: u64) -> SimCallResult {
+        let data = IERC20::balanceOfCall::abi_encode_returns(&(U256::from(amount),));
+        call(true, data, 30_000)
+    }
+
+    #[test]
+    fn output_is_amount_in_plus_received_balance() {
+        let calls = [balance(500), call(true, vec![], 180_000), balance(589)];
+        assert_eq!(
+            simulated_output(U256::from(1_000), &calls).unwrap(),
+            Some((U256::from(1_089), 180_000))
+        );
+    }
+
+    #[test]
+    fn reverted_arbitrage_has_no_output() {
+        let calls = [balance(500), call(false, vec![], 90_000), balance(500)];
+        assert_eq!(simulated_output(U256::from(1_000), &calls).unwrap(), None);
+    }
+
+    #[test]
+    fn measures_slippage() {
+        assert_eq!(slippage_bps(U256::from(1_000), U256::from(990)), 100);
+        assert_eq!(slippage_bps(U256::from(1_000), U256::from(1_005)), -50);
+        assert_eq!(slippage_bps(U256::ZERO, U256::from(1)), 0);
+    }
+
+    #[test]
+    fn ledger_tracks_inventory_and_pnl() {
+        let mut ledger = PaperLedger::default();
+        ledger.record(&fill(PaperOutcome::Filled, 1_089, 2.5));
+        ledger.record(&fill(PaperOutcome::Missed, 1_100, 0.0));
+        ledger.record(&fill(PaperOutcome::Unprofitable, 990, 0.0));
+        ledger.record(&fill(PaperOutcome::Reverted, 0, 0.0));
+
+        assert_eq!(ledger.inventory[&WETH], I256::try_from(89).unwrap());
+        assert_eq!(ledger.pnl_usd, 2.5);
+        assert_eq!(
+            (
+                ledger.filled,
+                ledger.missed,
+                ledger.unprofitable,
+                ledger.reverted
+            ),
+            (1, 1, 1, 1)
+        );
+        assert_eq!(ledger.missed_ratio(), 0.5);
+        // 100 bps, 0 bps and 1000 bps, the reverted fill has no slippage
+        assert_eq!(ledger.average_slippage_bps(), 1100.0 / 3.0);
+    }
+
+    #[test]
+    fn ledger_survives_restart() {
+        let mut ledger = PaperLedger::default();
+        ledger.record(&fill(PaperOutcome::Filled, 1_089, 2.5));
+        ledger.record(&fill(PaperOutcome::Missed, 1_100, 0.0));
+
+        let restored: PaperLedger =
+            serde_json::from_slice(&serde_json::to_vec(&ledger).unwrap()).unwrap();
+        assert_eq!(restored.inventory, ledger.inventory);
+        assert_eq!((restored.filled, restored.missed), (1, 1));
+        assert_eq!(restored.pnl_usd, 2.5);
+        assert_eq!(
+            restored.average_slippage_bps(),
+            ledger.average_slippage_bps()
+        );
+    }
+}
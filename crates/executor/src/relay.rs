@@ -0,0 +1,120 @@
+use crate::{
+    error::{ExecutorError, Result},
+    wallet::ReputationKey,
+};
+use alloy::primitives::{Bytes, U64};
+use std::time::Duration;
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Flashbots relay which receives transactions as bundles instead of the public mempool.
+/// Every request is signed by the reputation key, so the relay credits the searcher
+/// for its bundles
+#[derive(Clone, Debug)]
+pub struct BundleRelay {
+    url: String,
+    key: ReputationKey,
+    client: reqwest::Client,
+}
+
+impl BundleRelay {
+    pub fn new(url: &str, key: ReputationKey) -> Self {
+        Self {
+            url: url.to_string(),
+            key,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn key(&self) -> &ReputationKey {
+        &self.key
+    }
+
+    /// `eth_sendBundle` body with signed `txs` for `block_number` and its
+    /// `X-Flashbots-Signature` header
+    pub async fn bundle_request(
+        &self,
+        txs: &[Bytes],
+        block_number: u64,
+    ) -> Result<(Vec<u8>, String)> {
+        let body = serde_json::to_vec(&serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_sendBundle",
+            "params": [{
+                "txs": txs,
+                "blockNumber": U64::from(block_number),
+            }],
+        }))
+        .map_err(|err| ExecutorError::relay(err.to_string()))?;
+        let signature = self
+            .key
+            .flashbots_signature(&body)
+            .await
+            .map_err(|err| ExecutorError::wallet(err.to_string()))?;
+        Ok((body, signature))
+    }
+
+    /// Sends the bundle which is valid only in `block_number`
+    pub async fn send_bundle(&self, txs: &[Bytes], block_number: u64) -> Result<()> {
+        let (body, signature) = self.bundle_request(txs, block_number).await?;
+        let response = self
+            .client
+            .post(&self.url)
+            .header("content-type", "application/json")
+            .header("X-Flashbots-Signature", signature)
+            .body(body)
+            .timeout(SEND_TIMEOUT)
+            .send()
+            .await
+            .map_err(|err| ExecutorError::relay(err.to_string()))?;
+        if !response.status().is_success() {
+            return Err(ExecutorError::relay(format!(
+                "relay answered {}",
+                response.status()
+            )));
+        }
+
+        let answer: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|err| ExecutorError::relay(err.to_string()))?;
+        match answer.get("error") {
+            Some(error) => Err(ExecutorError::relay(error.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::ExecutorWallet;
+    use alloy::{
+        primitives::{keccak256, Address, PrimitiveSignature},
+        signers::local::PrivateKeySigner,
+    };
+
+    #[tokio::test]
+    async fn bundles_are_signed_by_the_reputation_key() {
+        let signer = PrivateKeySigner::random();
+        let key = ReputationKey::new(signer.clone(), &ExecutorWallet::new()).unwrap();
+        let relay = BundleRelay::new("http://localhost", key);
+
+        let txs = [Bytes::from_static(&[0x02, 0x01])];
+        let (body, header) = relay.bundle_request(&txs, 100).await.unwrap();
+        let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(request["method"], "eth_sendBundle");
+        assert_eq!(request["params"][0]["txs"][0], "0x0201");
+        assert_eq!(request["params"][0]["blockNumber"], "0x64");
+
+        let (address, signature) = header.split_once(':').unwrap();
+        assert_eq!(address.parse::<Address>().unwrap(), signer.address());
+        let signature: PrimitiveSignature = signature.parse().unwrap();
+        let message = format!("{:?}", keccak256(&body));
+        assert_eq!(
+            signature.recover_address_from_msg(message).unwrap(),
+            signer.address()
+        );
+    }
+}
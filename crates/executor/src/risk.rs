@@ -0,0 +1,458 @@
+use alloy::primitives::Address;
+use chrono::{DateTime, NaiveDate, Utc};
+use kronos_config::RiskConfig;
+use kronos_db::{redis::RedisDB, tables::ExecutionStatus};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+/// Kill switch sources are polled every second
+pub const KILL_SWITCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Limit which the arbitrage or the executor broke
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum Breach {
+    #[error("trade of {usd:.2} USD is above {max:.2} USD")]
+    TradeNotional { usd: f64, max: f64 },
+    #[error("trades of block {block_number} would be {usd:.2} USD, above {max:.2} USD")]
+    BlockNotional {
+        block_number: u64,
+        usd: f64,
+        max: f64,
+    },
+    #[error("exposure to token {token} would be {usd:.2} USD, above {max:.2} USD")]
+    TokenExposure { token: Address, usd: f64, max: f64 },
+    #[error("exposure to pair {pair} would be {usd:.2} USD, above {max:.2} USD")]
+    PairExposure { pair: Address, usd: f64, max: f64 },
+    #[error("start token has no USD price, trade can't be checked against limits")]
+    Unpriced,
+    #[error("daily loss {loss:.2} USD is above {max:.2} USD")]
+    DailyLoss { loss: f64, max: f64 },
+    #[error("{count} transactions reverted in a row")]
+    ConsecutiveReverts { count: u32 },
+    #[error("kill switch is engaged: {reason}")]
+    KillSwitch { reason: String },
+}
+
+impl Breach {
+    /// Circuit breakers pause execution, other breaches only reject the trade
+    pub fn pauses(&self) -> bool {
+        matches!(
+            self,
+            Self::DailyLoss { .. } | Self::ConsecutiveReverts { .. } | Self::KillSwitch { .. }
+        )
+    }
+
+    /// Label of the limit in metrics and alerts
+    pub fn limit(&self) -> &'static str {
+        match self {
+            Self::TradeNotional { .. } => "trade_notional",
+            Self::BlockNotional { .. } => "block_notional",
+            Self::TokenExposure { .. } => "token_exposure",
+            Self::PairExposure { .. } => "pair_exposure",
+            Self::Unpriced => "unpriced",
+            Self::DailyLoss { .. } => "daily_loss",
+            Self::ConsecutiveReverts { .. } => "consecutive_reverts",
+            Self::KillSwitch { .. } => "kill_switch",
+        }
+    }
+}
+
+/// What the limits know about the arbitrage
+#[derive(Clone, Debug)]
+pub struct Trade {
+    pub block_number: u64,
+    /// Amount in, `None` if the start token has no price
+    pub notional_usd: Option<f64>,
+    pub tokens: Vec<Address>,
+    pub pairs: Vec<Address>,
+}
+
+/// Engaged through the api or by the flag file. Execution is paused while it is engaged,
+/// releasing it also resumes execution paused by other circuit breakers
+#[derive(Clone, Debug, Default)]
+pub struct KillSwitch {
+    reason: Arc<RwLock<Option<String>>>,
+}
+
+impl KillSwitch {
+    pub fn engage(&self, reason: impl Into<String>) {
+        *self.reason.write().unwrap() = Some(reason.into());
+    }
+
+    pub fn release(&self) {
+        *self.reason.write().unwrap() = None;
+    }
+
+    /// Reason of the engaged kill switch
+    pub fn reason(&self) -> Option<String> {
+        self.reason.read().unwrap().clone()
+    }
+
+    /// Follows the kill switch of the api in redis and the flag file.
+    /// If redis is unreachable the last known state is kept
+    pub async fn watch(self, redis: RedisDB, file: Option<PathBuf>) {
+        let mut interval = tokio::time::interval(KILL_SWITCH_INTERVAL);
+        let mut api_reason = None;
+        loop {
+            interval.tick().await;
+            match redis.kill_switch().await {
+                Ok(kill_switch) => {
+                    api_reason =
+                        kill_switch
+                            .filter(|kill_switch| kill_switch.engaged)
+                            .map(|kill_switch| {
+                                kill_switch
+                                    .reason
+                                    .unwrap_or("engaged through api".to_string())
+                            })
+                }
+                Err(err) => tracing::warn!("failed to read kill switch: {err}"),
+            }
+            let file_reason = file
+                .as_ref()
+                .filter(|file| file.exists())
+                .map(|file| format!("{} exists", file.display()));
+
+            match api_reason.clone().or(file_reason) {
+                Some(reason) => self.engage(reason),
+                None => self.release(),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct RiskState {
+    block_number: u64,
+    block_notional_usd: f64,
+    day: Option<NaiveDate>,
+    daily_pnl_usd: f64,
+    consecutive_reverts: u32,
+    token_exposure: HashMap<Address, f64>,
+    pair_exposure: HashMap<Address, f64>,
+    paused: Option<Breach>,
+}
+
+impl RiskState {
+    fn roll_day(&mut self, now: DateTime<Utc>) {
+        let today = now.date_naive();
+        if self.day == Some(today) {
+            return;
+        }
+        self.day = Some(today);
+        self.daily_pnl_usd = 0.0;
+        if matches!(self.paused, Some(Breach::DailyLoss { .. })) {
+            self.paused = None;
+        }
+    }
+
+    fn pause(&mut self, breach: Breach) {
+        tracing::error!("execution is paused: {breach}");
+        self.paused = Some(breach);
+    }
+
+    fn resume(&mut self) {
+        tracing::warn!("execution is resumed");
+        self.paused = None;
+        self.consecutive_reverts = 0;
+    }
+}
+
+/// Checks arbitrages against limits before they are executed and trips circuit breakers
+/// on results of executions. Exposure is held by trades between `open` and `close`
+pub struct RiskManager {
+    config: RiskConfig,
+    kill_switch: KillSwitch,
+    state: Mutex<RiskState>,
+}
+
+impl RiskManager {
+    pub fn new(config: &RiskConfig, kill_switch: KillSwitch) -> Self {
+        Self {
+            config: config.clone(),
+            kill_switch,
+            state: Mutex::new(RiskState::default()),
+        }
+    }
+
+    pub fn kill_switch(&self) -> &KillSwitch {
+        &self.kill_switch
+    }
+
+    /// Circuit breaker which paused execution
+    pub fn paused(&self) -> Option<Breach> {
+        self.state.lock().unwrap().paused.clone()
+    }
+
+    /// Resumes execution paused by a circuit breaker, the kill switch stays engaged
+    pub fn resume(&self) {
+        self.state.lock().unwrap().resume();
+    }
+
+    pub fn check(&self, trade: &Trade) -> Result<(), Breach> {
+        self.check_at(trade, Utc::now())
+    }
+
+    /// Pauses execution when the kill switch is engaged and resumes it when it is released,
+    /// so toggles are seen without arbitrages
+    pub fn sync_kill_switch(&self) {
+        let mut state = self.state.lock().unwrap();
+        self.apply_kill_switch(&mut state);
+    }
+
+    fn apply_kill_switch(&self, state: &mut RiskState) {
+        match (self.kill_switch.reason(), &state.paused) {
+            (Some(reason), Some(Breach::KillSwitch { reason: paused })) if *paused == reason => {}
+            (Some(reason), _) => state.pause(Breach::KillSwitch { reason }),
+            (None, Some(Breach::KillSwitch { .. })) => state.resume(),
+            (None, _) => {}
+        }
+    }
+
+    fn check_at(&self, trade: &Trade, now: DateTime<Utc>) -> Result<(), Breach> {
+        let mut state = self.state.lock().unwrap();
+        state.roll_day(now);
+
+        self.apply_kill_switch(&mut state);
+        if let Some(breach) = &state.paused {
+            return Err(breach.clone());
+        }
+
+        let usd = trade.notional_usd.ok_or(Breach::Unpriced)?;
+        if let Some(max) = self.config.max_trade_usd.filter(|max| usd > *max) {
+            return Err(Breach::TradeNotional { usd, max });
+        }
+
+        let block_usd = match state.block_number == trade.block_number {
+            true => state.block_notional_usd + usd,
+            false => usd,
+        };
+        if let Some(max) = self.config.max_block_usd.filter(|max| block_usd > *max) {
+            return Err(Breach::BlockNotional {
+                block_number: trade.block_number,
+                usd: block_usd,
+                max,
+            });
+        }
+
+        if let Some(max) = self.config.max_token_exposure_usd {
+            for token in trade.tokens.iter() {
+                let exposure = state.token_exposure.get(token).copied().unwrap_or_default() + usd;
+                if exposure > max {
+                    return Err(Breach::TokenExposure {
+                        token: *token,
+                        usd: exposure,
+                        max,
+                    });
+                }
+            }
+        }
+        if let Some(max) = self.config.max_pair_exposure_usd {
+            for pair in trade.pairs.iter() {
+                let exposure = state.pair_exposure.get(pair).copied().unwrap_or_default() + usd;
+                if exposure > max {
+                    return Err(Breach::PairExposure {
+                        pair: *pair,
+                        usd: exposure,
+                        max,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts the checked trade in notional of its block and exposures
+    pub fn open(&self, trade: &Trade) {
+        let usd = trade.notional_usd.unwrap_or_default();
+        let mut state = self.state.lock().unwrap();
+        if state.block_number != trade.block_number {
+            state.block_number = trade.block_number;
+            state.block_notional_usd = 0.0;
+        }
+        state.block_notional_usd += usd;
+        for token in trade.tokens.iter() {
+            *state.token_exposure.entry(*token).or_default() += usd;
+        }
+        for pair in trade.pairs.iter() {
+            *state.pair_exposure.entry(*pair).or_default() += usd;
+        }
+    }
+
+    /// Releases exposure of the trade when its transaction is mined or dropped
+    pub fn close(&self, trade: &Trade) {
+        let usd = trade.notional_usd.unwrap_or_default();
+        let mut state = self.state.lock().unwrap();
+        let release = |exposure: &mut HashMap<Address, f64>, key: &Address| {
+            if let Some(value) = exposure.get_mut(key) {
+                *value -= usd;
+                if *value <= f64::EPSILON {
+                    exposure.remove(key);
+                }
+            }
+        };
+        for token in trade.tokens.iter() {
+            release(&mut state.token_exposure, token);
+        }
+        for pair in trade.pairs.iter() {
+            release(&mut state.pair_exposure, pair);
+        }
+    }
+
+    /// Counts the result of the execution, returns the circuit breaker if it is tripped
+    pub fn record_result(&self, status: ExecutionStatus, pnl_usd: Option<f64>) -> Option<Breach> {
+        self.record_result_at(status, pnl_usd, Utc::now())
+    }
+
+    fn record_result_at(
+        &self,
+        status: ExecutionStatus,
+        pnl_usd: Option<f64>,
+        now: DateTime<Utc>,
+    ) -> Option<Breach> {
+        let mut state = self.state.lock().unwrap();
+        state.roll_day(now);
+        state.daily_pnl_usd += pnl_usd.unwrap_or_default();
+        match status {
+            ExecutionStatus::Reverted => state.consecutive_reverts += 1,
+            ExecutionStatus::Included => state.consecutive_reverts = 0,
+            ExecutionStatus::Pending | ExecutionStatus::Failed => {}
+        }
+        if state.paused.is_some() {
+            return None;
+        }
+
+        let loss = -state.daily_pnl_usd;
+        let breach = match (
+            self.config.max_daily_loss_usd,
+            self.config.max_consecutive_reverts,
+        ) {
+            (Some(max), _) if loss > max => Some(Breach::DailyLoss { loss, max }),
+            (_, Some(max)) if state.consecutive_reverts >= max => {
+                Some(Breach::ConsecutiveReverts {
+                    count: state.consecutive_reverts,
+                })
+            }
+            _ => None,
+        }?;
+        state.pause(breach.clone());
+        Some(breach)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn config() -> RiskConfig {
+        RiskConfig {
+            max_trade_usd: Some(1_000.0),
+            max_block_usd: Some(1_500.0),
+            max_daily_loss_usd: Some(100.0),
+            max_consecutive_reverts: Some(2),
+            max_token_exposure_usd: Some(10_000.0),
+            max_pair_exposure_usd: Some(1_200.0),
+            kill_switch_file: None,
+        }
+    }
+
+    fn trade(block_number: u64, usd: f64, pair: u8) -> Trade {
+        Trade {
+            block_number,
+            notional_usd: Some(usd),
+            tokens: vec![Address::repeat_byte(1)],
+            pairs: vec![Address::repeat_byte(pair)],
+        }
+    }
+
+    #[test]
+    fn rejects_trades_above_limits() {
+        let risk = RiskManager::new(&config(), KillSwitch::default());
+        assert!(matches!(
+            risk.check(&trade(1, 2_000.0, 10)),
+            Err(Breach::TradeNotional { .. })
+        ));
+        let unpriced = Trade {
+            notional_usd: None,
+            ..trade(1, 0.0, 10)
+        };
+        assert_eq!(risk.check(&unpriced), Err(Breach::Unpriced));
+
+        let first = trade(1, 800.0, 10);
+        risk.check(&first).unwrap();
+        risk.open(&first);
+        // the same pair is still open
+        assert!(matches!(
+            risk.check(&trade(1, 500.0, 10)),
+            Err(Breach::PairExposure { .. })
+        ));
+        assert!(matches!(
+            risk.check(&trade(1, 800.0, 11)),
+            Err(Breach::BlockNotional { .. })
+        ));
+        // notional of the next block starts from zero
+        risk.check(&trade(2, 800.0, 11)).unwrap();
+
+        risk.close(&first);
+        risk.check(&trade(2, 500.0, 10)).unwrap();
+        assert_eq!(risk.paused(), None);
+    }
+
+    #[test]
+    fn pauses_on_daily_loss_until_next_day() {
+        let risk = RiskManager::new(&config(), KillSwitch::default());
+        let day = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap();
+
+        let included = ExecutionStatus::Included;
+        assert_eq!(risk.record_result_at(included, Some(-60.0), day), None);
+        let breach = risk.record_result_at(included, Some(-60.0), day);
+        assert!(matches!(breach, Some(Breach::DailyLoss { .. })));
+        assert!(risk.check_at(&trade(1, 10.0, 10), day).is_err());
+
+        let next_day = day + chrono::Duration::days(1);
+        risk.check_at(&trade(1, 10.0, 10), next_day).unwrap();
+    }
+
+    #[test]
+    fn kill_switch_and_reverts_pause_execution() {
+        let kill_switch = KillSwitch::default();
+        let risk = RiskManager::new(&config(), kill_switch.clone());
+
+        assert_eq!(risk.record_result(ExecutionStatus::Reverted, None), None);
+        assert_eq!(
+            risk.record_result(ExecutionStatus::Reverted, None),
+            Some(Breach::ConsecutiveReverts { count: 2 })
+        );
+        assert!(risk.check(&trade(1, 10.0, 10)).is_err());
+
+        // flipping the kill switch resumes execution
+        kill_switch.engage("incident");
+        assert_eq!(
+            risk.check(&trade(1, 10.0, 10)),
+            Err(Breach::KillSwitch {
+                reason: "incident".to_string()
+            })
+        );
+        kill_switch.release();
+        risk.check(&trade(1, 10.0, 10)).unwrap();
+        assert_eq!(risk.paused(), None);
+
+        // toggles are applied without trades
+        kill_switch.engage("maintenance");
+        risk.sync_kill_switch();
+        assert_eq!(
+            risk.paused(),
+            Some(Breach::KillSwitch {
+                reason: "maintenance".to_string()
+            })
+        );
+        kill_switch.release();
+        risk.sync_kill_switch();
+        assert_eq!(risk.paused(), None);
+    }
+}
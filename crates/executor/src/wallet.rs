@@ -0,0 +1,364 @@
+use crate::{
+    error::{ExecutorError, Result},
+    notifier::{Alert, AlertKind, Notifier},
+};
+use alloy::{
+    consensus::{TxEnvelope, TypedTransaction},
+    eips::eip2718::Decodable2718,
+    network::{Ethereum, EthereumWallet, NetworkWallet},
+    primitives::{keccak256, utils::format_ether, utils::parse_ether, Address, Bytes, U256},
+    providers::{Provider, RootProvider},
+    rpc::{client::RpcClient, types::TransactionRequest},
+    signers::{local::PrivateKeySigner, Signer},
+};
+use kronos_config::{KeystoreConfig, SignerConfig, WalletConfig};
+use kronos_metrics as metrics;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Decrypts the key of the keystore with the password from `password_env`
+pub fn load_keystore(config: &KeystoreConfig) -> Result<PrivateKeySigner> {
+    let password = std::env::var(&config.password_env)
+        .map_err(|_| ExecutorError::wallet(format!("{} is not set", config.password_env)))?;
+    decrypt_keystore(config, &password)
+}
+
+pub fn decrypt_keystore(config: &KeystoreConfig, password: &str) -> Result<PrivateKeySigner> {
+    PrivateKeySigner::decrypt_keystore(&config.path, password).map_err(|err| {
+        ExecutorError::wallet(format!(
+            "failed to decrypt {}: {err}",
+            config.path.display()
+        ))
+    })
+}
+
+/// Signer behind `eth_signTransaction` of an external service (Web3Signer, Clef, HSM proxy),
+/// the key never leaves it
+#[derive(Clone, Debug)]
+pub struct RemoteSigner {
+    address: Address,
+    client: Arc<RpcClient>,
+}
+
+impl RemoteSigner {
+    pub fn new(url: &str, address: Address) -> Result<Self> {
+        let url = url
+            .parse()
+            .map_err(|err| ExecutorError::wallet(format!("invalid signer url {url}: {err}")))?;
+        Ok(Self {
+            address,
+            client: Arc::new(RpcClient::new_http(url)),
+        })
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Signs the transaction remotely and checks that it is signed by `address`
+    pub async fn sign_transaction(
+        &self,
+        tx: TypedTransaction,
+    ) -> alloy::signers::Result<TxEnvelope> {
+        let mut request: TransactionRequest = tx.into();
+        request.from = Some(self.address);
+
+        let raw: Bytes = self
+            .client
+            .request("eth_signTransaction", (request,))
+            .await
+            .map_err(alloy::signers::Error::other)?;
+        let envelope =
+            TxEnvelope::decode_2718(&mut raw.as_ref()).map_err(alloy::signers::Error::other)?;
+
+        let signer = envelope
+            .recover_signer()
+            .map_err(alloy::signers::Error::other)?;
+        if signer != self.address {
+            return Err(alloy::signers::Error::other(format!(
+                "remote signer signed with {signer}, expected {}",
+                self.address
+            )));
+        }
+        Ok(envelope)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum AccountSigner {
+    Local(EthereumWallet),
+    Remote(RemoteSigner),
+}
+
+/// Pool of executor accounts. Transactions are sent from the accounts in turn,
+/// so concurrent sends don't wait for each other's nonces
+#[derive(Clone, Debug)]
+pub struct ExecutorWallet {
+    accounts: Arc<Vec<Address>>,
+    signers: Arc<HashMap<Address, AccountSigner>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl ExecutorWallet {
+    pub fn from_config(config: &WalletConfig) -> Result<Self> {
+        let mut wallet = Self::new();
+        for signer in config.executors.iter() {
+            match signer {
+                SignerConfig::Keystore(keystore) => wallet.add_local(load_keystore(keystore)?)?,
+                SignerConfig::Remote { url, address } => {
+                    let address = address.parse().map_err(|err| {
+                        ExecutorError::wallet(format!("invalid address {address}: {err}"))
+                    })?;
+                    wallet.add_remote(RemoteSigner::new(url, address)?)?
+                }
+            }
+        }
+        Ok(wallet)
+    }
+
+    pub fn new() -> Self {
+        Self {
+            accounts: Arc::new(vec![]),
+            signers: Arc::new(HashMap::new()),
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn add_local(&mut self, signer: PrivateKeySigner) -> Result<()> {
+        let address = signer.address();
+        self.add(address, AccountSigner::Local(EthereumWallet::from(signer)))
+    }
+
+    pub fn add_remote(&mut self, signer: RemoteSigner) -> Result<()> {
+        self.add(signer.address(), AccountSigner::Remote(signer))
+    }
+
+    fn add(&mut self, address: Address, signer: AccountSigner) -> Result<()> {
+        if self.signers.contains_key(&address) {
+            return Err(ExecutorError::wallet(format!(
+                "executor {address} is configured twice"
+            )));
+        }
+        Arc::make_mut(&mut self.accounts).push(address);
+        Arc::make_mut(&mut self.signers).insert(address, signer);
+        Ok(())
+    }
+
+    pub fn accounts(&self) -> &[Address] {
+        &self.accounts
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    /// Account for the next transaction, accounts are used round-robin
+    pub fn next_account(&self) -> Option<Address> {
+        if self.accounts.is_empty() {
+            return None;
+        }
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.accounts.len();
+        Some(self.accounts[index])
+    }
+}
+
+impl Default for ExecutorWallet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetworkWallet<Ethereum> for ExecutorWallet {
+    fn default_signer_address(&self) -> Address {
+        self.accounts.first().copied().unwrap_or_default()
+    }
+
+    fn has_signer_for(&self, address: &Address) -> bool {
+        self.signers.contains_key(address)
+    }
+
+    fn signer_addresses(&self) -> impl Iterator<Item = Address> {
+        self.accounts.iter().copied()
+    }
+
+    async fn sign_transaction_from(
+        &self,
+        sender: Address,
+        tx: TypedTransaction,
+    ) -> alloy::signers::Result<TxEnvelope> {
+        match self.signers.get(&sender) {
+            Some(AccountSigner::Local(wallet)) => {
+                NetworkWallet::<Ethereum>::sign_transaction_from(wallet, sender, tx).await
+            }
+            Some(AccountSigner::Remote(signer)) => signer.sign_transaction(tx).await,
+            None => Err(alloy::signers::Error::other(format!(
+                "{sender} is not an executor account"
+            ))),
+        }
+    }
+}
+
+/// Key of the Flashbots searcher reputation. It only signs bundle requests:
+/// it is not an executor account, can't sign transactions and should hold no funds
+#[derive(Clone, Debug)]
+pub struct ReputationKey(PrivateKeySigner);
+
+impl ReputationKey {
+    /// Loads the key and checks that it is not one of the executor accounts
+    pub fn load(config: &KeystoreConfig, executors: &ExecutorWallet) -> Result<Self> {
+        Self::new(load_keystore(config)?, executors)
+    }
+
+    pub fn new(signer: PrivateKeySigner, executors: &ExecutorWallet) -> Result<Self> {
+        if executors.has_signer_for(&signer.address()) {
+            return Err(ExecutorError::wallet(format!(
+                "reputation key {} is also an executor account",
+                signer.address()
+            )));
+        }
+        Ok(Self(signer))
+    }
+
+    pub fn address(&self) -> Address {
+        self.0.address()
+    }
+
+    /// Value of `X-Flashbots-Signature` header for the request body
+    pub async fn flashbots_signature(&self, body: &[u8]) -> alloy::signers::Result<String> {
+        let message = format!("{:?}", keccak256(body));
+        let signature = self.0.sign_message(message.as_bytes()).await?;
+        Ok(format!("{:?}:{}", self.address(), signature))
+    }
+}
+
+/// Tracks ETH balances of executor accounts, which pay for gas
+pub struct BalanceWatcher {
+    provider: Arc<RootProvider>,
+    accounts: Vec<Address>,
+    min_balance: U256,
+    interval: Duration,
+    notifier: Notifier,
+}
+
+impl BalanceWatcher {
+    pub fn new(
+        provider: Arc<RootProvider>,
+        wallet: &ExecutorWallet,
+        config: &WalletConfig,
+        notifier: Notifier,
+    ) -> Result<Self> {
+        let min_balance = parse_ether(&config.min_balance_eth.to_string()).map_err(|err| {
+            ExecutorError::wallet(format!(
+                "invalid min balance {}: {err}",
+                config.min_balance_eth
+            ))
+        })?;
+        Ok(Self {
+            provider,
+            accounts: wallet.accounts().to_vec(),
+            min_balance,
+            interval: Duration::from_secs(config.balance_check_secs),
+            notifier,
+        })
+    }
+
+    /// Returns accounts with less ETH than the minimum
+    pub async fn check(&self) -> Result<Vec<(Address, U256)>> {
+        let mut low = vec![];
+        for account in self.accounts.iter() {
+            let balance = self.provider.get_balance(*account).await?;
+            let eth = format_ether(balance);
+            metrics::EXECUTOR_BALANCE
+                .with_label_values(&[&account.to_string()])
+                .set(eth.parse().unwrap_or_default());
+
+            if balance < self.min_balance {
+                tracing::warn!("executor {account} is low on ETH: {eth}");
+                self.notifier.notify(
+                    Alert::new(AlertKind::LowBalance)
+                        .with_key(account)
+                        .with_field("account", account)
+                        .with_field("balance_eth", &eth)
+                        .with_field("min_eth", format_ether(self.min_balance)),
+                );
+                low.push((*account, balance));
+            }
+        }
+        Ok(low)
+    }
+
+    pub async fn start(self) {
+        let mut interval = tokio::time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.check().await {
+                tracing::warn!("failed to check executor balances: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uses_accounts_round_robin() {
+        let mut wallet = ExecutorWallet::new();
+        assert_eq!(wallet.next_account(), None);
+
+        let signers = [PrivateKeySigner::random(), PrivateKeySigner::random()];
+        for signer in signers.iter() {
+            wallet.add_local(signer.clone()).unwrap();
+        }
+        assert!(wallet.add_local(signers[0].clone()).is_err());
+
+        let used: Vec<_> = (0..4)
+            .map(|_| wallet.clone().next_account().unwrap())
+            .collect();
+        let (first, second) = (signers[0].address(), signers[1].address());
+        assert_eq!(used, [first, second, first, second]);
+    }
+
+    #[test]
+    fn reputation_key_is_not_executor() {
+        let signer = PrivateKeySigner::random();
+        let mut wallet = ExecutorWallet::new();
+        wallet.add_local(signer.clone()).unwrap();
+
+        assert!(ReputationKey::new(signer, &wallet).is_err());
+        assert!(ReputationKey::new(PrivateKeySigner::random(), &wallet).is_ok());
+    }
+
+    #[test]
+    fn loads_encrypted_keystore() {
+        let dir = std::env::temp_dir().join(format!("kronos-keystore-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let signer = PrivateKeySigner::random();
+        PrivateKeySigner::encrypt_keystore(
+            &dir,
+            &mut rand::thread_rng(),
+            signer.to_bytes(),
+            "secret",
+            Some("executor.json"),
+        )
+        .unwrap();
+
+        let config = KeystoreConfig {
+            path: dir.join("executor.json"),
+            password_env: "KRONOS_TEST_KEYSTORE_PASSWORD".to_string(),
+        };
+        assert!(decrypt_keystore(&config, "wrong").is_err());
+        assert_eq!(
+            decrypt_keystore(&config, "secret").unwrap().address(),
+            signer.address()
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
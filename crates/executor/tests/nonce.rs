@@ -0,0 +1,195 @@
+//! Nonce manager against a local Anvil with automine off, run with `cargo test -- --ignored`
+
+use alloy::{
+    network::TransactionBuilder,
+    node_bindings::{Anvil, AnvilInstance},
+    primitives::{Address, B256, U256},
+    providers::{ext::AnvilApi, Provider, ProviderBuilder, RootProvider},
+    rpc::types::TransactionRequest,
+    signers::local::PrivateKeySigner,
+};
+use kronos_db::tables::ExecutionStatus;
+use kronos_executor::{live::wait_mined, nonce::NonceManager, wallet::ExecutorWallet};
+use std::sync::Arc;
+
+const GWEI: u128 = 1_000_000_000;
+
+async fn setup(accounts: usize) -> (AnvilInstance, Arc<RootProvider>, ExecutorWallet) {
+    let anvil = Anvil::new().arg("--no-mining").spawn();
+    let provider: RootProvider = ProviderBuilder::default()
+        .connect(&anvil.endpoint())
+        .await
+        .unwrap();
+
+    let mut wallet = ExecutorWallet::new();
+    for key in anvil.keys().iter().take(accounts) {
+        wallet
+            .add_local(PrivateKeySigner::from_signing_key(key.clone().into()))
+            .unwrap();
+    }
+    (anvil, Arc::new(provider), wallet)
+}
+
+fn transfer(to: Address) -> TransactionRequest {
+    TransactionRequest::default()
+        .with_to(to)
+        .with_value(U256::from(1))
+        .with_gas_limit(21_000)
+        .with_max_fee_per_gas(10 * GWEI)
+        .with_max_priority_fee_per_gas(GWEI)
+}
+
+#[tokio::test]
+#[ignore = "requires anvil"]
+async fn concurrent_sends_get_consecutive_nonces() {
+    let (_anvil, provider, wallet) = setup(1).await;
+    let account = wallet.accounts()[0];
+    let nonces = Arc::new(NonceManager::new(provider.clone(), wallet));
+
+    let sends = (0..5).map(|_| {
+        let nonces = nonces.clone();
+        tokio::spawn(async move { nonces.send(transfer(Address::ZERO), None).await })
+    });
+    let mut sent: Vec<u64> = futures::future::join_all(sends)
+        .await
+        .into_iter()
+        .map(|send| send.unwrap().unwrap().nonce)
+        .collect();
+    sent.sort();
+    assert_eq!(sent, [0, 1, 2, 3, 4]);
+
+    provider.anvil_mine(Some(1), None).await.unwrap();
+    assert_eq!(nonces.sync(account).await.unwrap().len(), 5);
+    assert!(nonces.pending().await.is_empty());
+}
+
+#[tokio::test]
+#[ignore = "requires anvil"]
+async fn restart_continues_after_mempool() {
+    let (_anvil, provider, wallet) = setup(1).await;
+
+    let nonces = NonceManager::new(provider.clone(), wallet.clone());
+    nonces.send(transfer(Address::ZERO), None).await.unwrap();
+    nonces.send(transfer(Address::ZERO), None).await.unwrap();
+
+    let restarted = NonceManager::new(provider, wallet);
+    let sent = restarted.send(transfer(Address::ZERO), None).await.unwrap();
+    assert_eq!(sent.nonce, 2);
+}
+
+#[tokio::test]
+#[ignore = "requires anvil"]
+async fn newer_opportunity_replaces_pending_transaction() {
+    let (_anvil, provider, wallet) = setup(2).await;
+    let nonces = NonceManager::new(provider.clone(), wallet);
+    let key = B256::repeat_byte(1);
+
+    let first = nonces
+        .send_or_replace(transfer(Address::ZERO), key)
+        .await
+        .unwrap();
+    let second = nonces
+        .send_or_replace(transfer(Address::repeat_byte(2)), key)
+        .await
+        .unwrap();
+    assert_eq!((second.account, second.nonce), (first.account, first.nonce));
+    assert!(second.request.max_priority_fee_per_gas > first.request.max_priority_fee_per_gas);
+
+    provider.anvil_mine(Some(1), None).await.unwrap();
+    assert!(provider
+        .get_transaction_receipt(first.hash)
+        .await
+        .unwrap()
+        .is_none());
+    let receipt = provider
+        .get_transaction_receipt(second.hash)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(receipt.to, Some(Address::repeat_byte(2)));
+}
+
+#[tokio::test]
+#[ignore = "requires anvil"]
+async fn replaced_transaction_lands_as_failed() {
+    let (_anvil, provider, wallet) = setup(2).await;
+    let nonces = NonceManager::new(provider.clone(), wallet);
+    let key = B256::repeat_byte(1);
+
+    // the replacement is sent from the account which the key was encoded for
+    let account = nonces.account_for(key).await.unwrap();
+    let first = nonces
+        .send_or_replace(transfer(Address::ZERO).with_from(account), key)
+        .await
+        .unwrap();
+    assert_eq!(first.account, account);
+    assert_eq!(nonces.account_for(key).await.unwrap(), account);
+    let second = nonces
+        .send_or_replace(transfer(Address::repeat_byte(2)), key)
+        .await
+        .unwrap();
+
+    provider.anvil_mine(Some(1), None).await.unwrap();
+    let replaced = wait_mined(provider.clone(), first).await;
+    assert_eq!(replaced.status, ExecutionStatus::Failed);
+    assert!(replaced.fee_wei.is_zero());
+    let included = wait_mined(provider, second).await;
+    assert_eq!(included.status, ExecutionStatus::Included);
+    assert_eq!(included.gas_used, Some(21_000));
+}
+
+#[tokio::test]
+#[ignore = "requires anvil"]
+async fn cancels_stuck_transaction_with_self_transfer() {
+    let (_anvil, provider, wallet) = setup(1).await;
+    let account = wallet.accounts()[0];
+    let nonces = NonceManager::new(provider.clone(), wallet);
+
+    let stuck = nonces.send(transfer(Address::ZERO), None).await.unwrap();
+    assert!(nonces
+        .cancel_stuck(stuck.sent_block + 1, 2)
+        .await
+        .is_empty());
+
+    let cancelled = nonces.cancel_stuck(stuck.sent_block + 2, 2).await;
+    assert_eq!(cancelled.len(), 1);
+    assert_eq!(cancelled[0].nonce, stuck.nonce);
+
+    provider.anvil_mine(Some(1), None).await.unwrap();
+    let receipt = provider
+        .get_transaction_receipt(cancelled[0].hash)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(receipt.to, Some(account));
+    assert_eq!(
+        nonces.sync(account).await.unwrap()[0].hash,
+        cancelled[0].hash
+    );
+}
+
+#[tokio::test]
+#[ignore = "requires anvil"]
+async fn keeps_cancelling_after_failed_cancellation() {
+    let (_anvil, provider, wallet) = setup(2).await;
+    let accounts = wallet.accounts().to_vec();
+    let nonces = NonceManager::new(provider.clone(), wallet);
+
+    nonces
+        .send_from(accounts[0], transfer(Address::ZERO), None)
+        .await
+        .unwrap();
+    provider.anvil_mine(Some(1), None).await.unwrap();
+    let stuck = nonces
+        .send_from(accounts[1], transfer(Address::ZERO), None)
+        .await
+        .unwrap();
+
+    // the mined transaction is not synced yet, so its cancellation is rejected by the node
+    let cancelled = nonces.cancel_stuck(stuck.sent_block + 2, 2).await;
+    assert_eq!(cancelled.len(), 1);
+    assert_eq!(
+        (cancelled[0].account, cancelled[0].nonce),
+        (accounts[1], stuck.nonce)
+    );
+}
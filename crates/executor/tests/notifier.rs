@@ -0,0 +1,174 @@
+//! Alert sinks against a local HTTP stub
+
+use axum::{extract::Path, routing::post, Json, Router};
+use kronos_config::{AlertSinkConfig, AlertsConfig};
+use kronos_executor::{
+    notifier::{Alert, AlertDispatcher, AlertKind, Dropped, Sink},
+    risk::Breach,
+    ExecutorEvent,
+};
+use serde_json::Value;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Serves telegram, slack and webhook endpoints, received requests are sent to the channel
+async fn stub() -> (String, mpsc::UnboundedReceiver<(String, Value)>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let telegram = tx.clone();
+    let slack = tx.clone();
+    let app = Router::new()
+        .route(
+            "/{bot}/sendMessage",
+            post(
+                move |Path(bot): Path<String>, Json(body): Json<Value>| async move {
+                    telegram.send((bot, body)).unwrap();
+                },
+            ),
+        )
+        .route(
+            "/slack",
+            post(move |Json(body): Json<Value>| async move {
+                slack.send(("slack".to_string(), body)).unwrap();
+            }),
+        )
+        .route(
+            "/webhook",
+            post(move |Json(body): Json<Value>| async move {
+                tx.send(("webhook".to_string(), body)).unwrap();
+            }),
+        );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (url, rx)
+}
+
+/// Environment with the secrets of the sinks in [`config`]
+fn env(url: &str) -> impl Fn(&str) -> Option<String> {
+    let slack = format!("{url}/slack");
+    move |name| match name {
+        "TEST_TELEGRAM_TOKEN" => Some("secret".to_string()),
+        "TEST_SLACK_URL" => Some(slack.clone()),
+        _ => None,
+    }
+}
+
+fn config(url: &str) -> AlertsConfig {
+    AlertsConfig {
+        sinks: vec![
+            AlertSinkConfig::Telegram {
+                bot_token_env: "TEST_TELEGRAM_TOKEN".to_string(),
+                chat_id: "42".to_string(),
+                api_url: url.to_string(),
+            },
+            AlertSinkConfig::Slack {
+                webhook_url_env: "TEST_SLACK_URL".to_string(),
+            },
+            AlertSinkConfig::Webhook {
+                url: format!("{url}/webhook"),
+            },
+        ],
+        ..Default::default()
+    }
+}
+
+async fn received(rx: &mut mpsc::UnboundedReceiver<(String, Value)>) -> Vec<(String, Value)> {
+    let mut requests = vec![];
+    while let Ok(Some(request)) = tokio::time::timeout(Duration::from_millis(200), rx.recv()).await
+    {
+        requests.push(request);
+    }
+    requests.sort_by(|a, b| a.0.cmp(&b.0));
+    requests
+}
+
+#[tokio::test]
+async fn delivers_alerts_to_all_sinks_once() {
+    let (url, mut rx) = stub().await;
+    let mut dispatcher = AlertDispatcher::from_config_with_env(&config(&url), env(&url)).unwrap();
+
+    let event = ExecutorEvent::Paused(Breach::DailyLoss {
+        loss: 1_200.0,
+        max: 1_000.0,
+    });
+    let alert = Alert::from_event(&event).unwrap();
+    dispatcher.dispatch(&alert).await.unwrap();
+
+    let requests = received(&mut rx).await;
+    let text = "Execution is paused by daily_loss: daily loss 1200.00 USD is above 1000.00 USD";
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[0].0, "botsecret");
+    assert_eq!(requests[0].1["chat_id"], "42");
+    assert_eq!(requests[0].1["text"], text);
+    assert_eq!(requests[1].0, "slack");
+    assert_eq!(requests[1].1["text"], text);
+    assert_eq!(requests[2].0, "webhook");
+    assert_eq!(requests[2].1["kind"], "circuit_breaker");
+    assert_eq!(requests[2].1["severity"], "critical");
+    assert_eq!(requests[2].1["fields"]["limit"], "daily_loss");
+
+    // the same breaker is not repeated
+    assert_eq!(dispatcher.dispatch(&alert).await, Err(Dropped::Duplicate));
+    assert!(received(&mut rx).await.is_empty());
+}
+
+#[tokio::test]
+async fn notifier_queues_alerts_for_dispatcher() {
+    let (url, mut rx) = stub().await;
+    let config = AlertsConfig {
+        sinks: vec![AlertSinkConfig::Webhook {
+            url: format!("{url}/webhook"),
+        }],
+        templates: [("low_balance".to_string(), "{account} is low".to_string())].into(),
+        ..Default::default()
+    };
+
+    let dispatcher = AlertDispatcher::from_config(&config).unwrap();
+    let notifier = dispatcher.notifier();
+    tokio::spawn(dispatcher.start());
+
+    notifier.notify(
+        Alert::new(AlertKind::LowBalance)
+            .with_key("executor")
+            .with_field("account", "executor"),
+    );
+    let requests = received(&mut rx).await;
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].1["text"], "executor is low");
+}
+
+#[tokio::test]
+async fn send_errors_do_not_leak_the_bot_token() {
+    // nothing listens on the port of the dropped listener
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+
+    let sink = Sink::from_config_with_env(
+        &AlertSinkConfig::Telegram {
+            bot_token_env: "TEST_TELEGRAM_TOKEN".to_string(),
+            chat_id: "42".to_string(),
+            api_url: url,
+        },
+        |_| Some("secret-token".to_string()),
+    )
+    .unwrap();
+    let err = sink
+        .send(
+            &reqwest::Client::new(),
+            &Alert::new(AlertKind::RpcOutage),
+            "down",
+        )
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("telegram"));
+    assert!(!err.to_string().contains("secret-token"));
+
+    let missing = Sink::from_config_with_env(
+        &AlertSinkConfig::Slack {
+            webhook_url_env: "TEST_SLACK_URL".to_string(),
+        },
+        |_| None,
+    );
+    assert!(missing.is_err());
+}
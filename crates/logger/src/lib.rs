@@ -1,12 +1,125 @@
+use anyhow::Result;
+use kronos_config::{LoggingConfig, OtlpConfig};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    trace::{Sampler, SdkTracerProvider},
+    Resource,
+};
 use tracing::Level;
-use tracing_subscriber::FmtSubscriber;
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+    layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer, Registry,
+};
 
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Flushes buffered logs to the file and pending spans to the collector when dropped,
+/// must live until the process exits
+#[must_use = "logs written after the guard is dropped are lost"]
+pub struct LoggerGuard {
+    _file: Option<WorkerGuard>,
+    tracer: Option<SdkTracerProvider>,
+}
+
+impl Drop for LoggerGuard {
+    fn drop(&mut self) {
+        if let Some(tracer) = self.tracer.take() {
+            if let Err(err) = tracer.shutdown() {
+                eprintln!("failed to export remaining spans: {err}");
+            }
+        }
+    }
+}
+
+/// Text logs of the level to stdout, for tools which have no logging config
 pub fn init_logger(max_level: Level) {
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(max_level)
-        //.with_writer(rolling::daily("logs", "processed_tx.log"))
-        //.with_ansi(false)
-        .finish();
+    let config = LoggingConfig {
+        filter: max_level.to_string().to_lowercase(),
+        ..Default::default()
+    };
+    // nothing is written to files, so the guard holds nothing
+    let _guard = init(&config).expect("setting default subscriber failed");
+}
+
+/// Logs to stdout and, if `dir` is set, to daily rolling files through a non-blocking writer.
+/// With `otlp` spans are also exported to the collector in batches
+pub fn init(config: &LoggingConfig) -> Result<LoggerGuard> {
+    let filter = filter(config)?;
+
+    let mut layers: Vec<BoxedLayer> = vec![format(config.json, std::io::stdout, true)];
+    let mut guard = None;
+    if let Some(dir) = &config.dir {
+        let mut appender = RollingFileAppender::builder()
+            .rotation(Rotation::DAILY)
+            .filename_prefix(&config.file_prefix);
+        if let Some(max_files) = config.max_files {
+            appender = appender.max_log_files(max_files);
+        }
+        let (writer, file_guard) = tracing_appender::non_blocking(appender.build(dir)?);
+        layers.push(format(config.json, writer, false));
+        guard = Some(file_guard);
+    }
+    let mut tracer = None;
+    if let Some(otlp) = &config.otlp {
+        let provider = tracer_provider(otlp)?;
+        let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("kronos"));
+        layers.push(layer.boxed());
+        tracer = Some(provider);
+    }
+
+    tracing_subscriber::registry()
+        .with(layers.with_filter(filter))
+        .try_init()?;
+    Ok(LoggerGuard {
+        _file: guard,
+        tracer,
+    })
+}
+
+/// Root spans are sampled by `sample_ratio`, children follow their root,
+/// so a block is exported as a whole trace or not at all
+fn tracer_provider(config: &OtlpConfig) -> Result<SdkTracerProvider> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&config.endpoint)
+        .build()?;
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            config.sample_ratio,
+        ))))
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build(),
+        )
+        .build())
+}
+
+/// `RUST_LOG` if it is set, otherwise directives of the config
+fn filter(config: &LoggingConfig) -> Result<EnvFilter> {
+    match std::env::var(EnvFilter::DEFAULT_ENV) {
+        Ok(directives) => Ok(EnvFilter::try_new(directives)?),
+        Err(_) => Ok(EnvFilter::try_new(&config.filter)?),
+    }
+}
 
-    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+fn format<W>(json: bool, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+    match json {
+        // every line carries fields of its spans, so one block or opportunity can be grepped
+        true => layer
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+        false => layer.with_ansi(ansi).boxed(),
+    }
 }
@@ -0,0 +1,62 @@
+//! Spans exported to a local OTLP/HTTP receiver
+
+use axum::{body::Bytes, http::HeaderMap, routing::post, Router};
+use kronos_config::{LoggingConfig, OtlpConfig};
+use std::{sync::mpsc, time::Duration};
+
+/// Serves the traces endpoint on its own runtime, received requests are sent to the channel.
+/// The exporter blocks on its requests, so the test itself runs outside of tokio
+fn receiver() -> (String, mpsc::Receiver<(HeaderMap, Bytes)>) {
+    let (tx, rx) = mpsc::channel();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+    listener.set_nonblocking(true).unwrap();
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async move {
+            let app = Router::new().route(
+                "/v1/traces",
+                post(move |headers: HeaderMap, body: Bytes| async move {
+                    tx.send((headers, body)).unwrap();
+                }),
+            );
+            let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+            axum::serve(listener, app).await.unwrap();
+        });
+    });
+    (url, rx)
+}
+
+fn contains(body: &[u8], text: &str) -> bool {
+    body.windows(text.len())
+        .any(|window| window == text.as_bytes())
+}
+
+#[test]
+fn exports_spans_to_the_collector() {
+    let (endpoint, rx) = receiver();
+    let config = LoggingConfig {
+        otlp: Some(OtlpConfig {
+            endpoint,
+            service_name: "kronos-test".to_string(),
+            sample_ratio: 1.0,
+        }),
+        ..Default::default()
+    };
+    let guard = kronos_logger::init(&config).unwrap();
+    {
+        let block = tracing::info_span!("block", block_number = 7u64);
+        let _entered = block.enter();
+        tracing::info_span!("process_block", dex = "uniswap_v2").in_scope(|| {
+            tracing::info!("processed");
+        });
+    }
+    // pending spans are exported when the guard is dropped
+    drop(guard);
+
+    let (headers, body) = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(headers["content-type"], "application/x-protobuf");
+    assert!(contains(&body, "kronos-test"));
+    assert!(contains(&body, "block"));
+    assert!(contains(&body, "process_block"));
+}
@@ -0,0 +1,557 @@
+//! Uniswap V3 concentrated liquidity math, ported from `TickMath.sol`, `SqrtPriceMath.sol`
+//! and `SwapMath.sol` with the same rounding. Uniswap V4 pools use the same math
+use alloy::primitives::{U256, U512};
+use std::collections::BTreeMap;
+
+pub const MIN_TICK: i32 = -887272;
+pub const MAX_TICK: i32 = 887272;
+
+pub const MIN_SQRT_RATIO: U256 = U256::from_limbs([4295128739, 0, 0, 0]);
+pub const MAX_SQRT_RATIO: U256 =
+    U256::from_limbs([6743328256752651558, 17280870778742802505, 4294805859, 0]);
+
+// Fee is in hundredths of a bip
+const FEE_DENOMINATOR: u32 = 1_000_000;
+const Q96: U256 = U256::from_limbs([0, 1 << 32, 0, 0]);
+
+// `sqrt(1.0001^-2^i) * 2^128` for every bit of the tick
+const TICK_RATIOS: [u128; 19] = [
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x9aa508b5b7a84e1c677de54f3e99bc9,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe98,
+    0x48a170391f7dc42444e8fa2,
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TickInfo {
+    pub liquidity_gross: u128,
+    pub liquidity_net: i128,
+}
+
+/// State of the pool which is required for swaps: current price and initialized ticks
+#[derive(Clone, Debug)]
+pub struct ConcentratedPool {
+    pub sqrt_price_x96: U256,
+    pub tick: i32,
+    pub liquidity: u128,
+    /// Swap fee in hundredths of a bip
+    pub fee: u32,
+    /// Protocol fee of V4 pools in hundredths of a bip: lower 12 bits for zero for one swaps,
+    /// upper 12 bits for one for zero swaps
+    pub protocol_fee: u32,
+    pub tick_spacing: i32,
+    pub ticks: BTreeMap<i32, TickInfo>,
+}
+
+impl ConcentratedPool {
+    pub fn new(sqrt_price_x96: U256, tick: i32, fee: u32, tick_spacing: i32) -> Self {
+        Self {
+            sqrt_price_x96,
+            tick,
+            liquidity: 0,
+            fee,
+            protocol_fee: 0,
+            tick_spacing,
+            ticks: BTreeMap::new(),
+        }
+    }
+
+    /// Adds (or removes with negative `delta`) liquidity of the position
+    pub fn modify_liquidity(&mut self, tick_lower: i32, tick_upper: i32, delta: i128) {
+        if delta == 0 {
+            return;
+        }
+        self.update_tick(tick_lower, delta, false);
+        self.update_tick(tick_upper, delta, true);
+
+        if tick_lower <= self.tick && self.tick < tick_upper {
+            self.liquidity = self.liquidity.saturating_add_signed(delta);
+        }
+    }
+
+    fn update_tick(&mut self, tick: i32, delta: i128, upper: bool) {
+        let info = self.ticks.entry(tick).or_default();
+        info.liquidity_gross = info.liquidity_gross.saturating_add_signed(delta);
+        info.liquidity_net = match upper {
+            true => info.liquidity_net.saturating_sub(delta),
+            false => info.liquidity_net.saturating_add(delta),
+        };
+        if info.liquidity_gross == 0 {
+            self.ticks.remove(&tick);
+        }
+    }
+
+    /// Fee of the swap direction with the protocol fee, as `ProtocolFeeLibrary.calculateSwapFee`.
+    /// Protocol fee is taken from the fee amount, so the output depends only on the sum
+    pub fn swap_fee(&self, zero_for_one: bool) -> u32 {
+        let protocol_fee = match zero_for_one {
+            true => self.protocol_fee & 0xfff,
+            false => self.protocol_fee >> 12,
+        };
+        match protocol_fee {
+            0 => self.fee,
+            _ => protocol_fee + self.fee - protocol_fee * self.fee / FEE_DENOMINATOR,
+        }
+    }
+
+    // Next initialized tick in the direction of the swap, bounded by min and max ticks
+    fn next_initialized_tick(&self, zero_for_one: bool) -> (i32, bool) {
+        let next = match zero_for_one {
+            true => self.ticks.range(..=self.tick).next_back(),
+            false => self.ticks.range(self.tick + 1..).next(),
+        };
+        match (next, zero_for_one) {
+            (Some((tick, _)), _) => (*tick, true),
+            (None, true) => (MIN_TICK, false),
+            (None, false) => (MAX_TICK, false),
+        }
+    }
+
+    /// Amount out for exact `amount_in`, `None` if the pool does not have enough liquidity
+    pub fn swap_exact_in(&self, zero_for_one: bool, amount_in: U256) -> Option<U256> {
+        let mut pool = self.clone();
+        let amount_out = pool.swap(zero_for_one, amount_in)?;
+        Some(amount_out)
+    }
+
+    /// Executes the swap on the state and returns amount out
+    pub fn swap(&mut self, zero_for_one: bool, amount_in: U256) -> Option<U256> {
+        let sqrt_price_limit = match zero_for_one {
+            true => MIN_SQRT_RATIO + U256::from(1),
+            false => MAX_SQRT_RATIO - U256::from(1),
+        };
+
+        let fee = self.swap_fee(zero_for_one);
+        let mut amount_remaining = amount_in;
+        let mut amount_out = U256::ZERO;
+        while !amount_remaining.is_zero() && self.sqrt_price_x96 != sqrt_price_limit {
+            let sqrt_price_start = self.sqrt_price_x96;
+            let (tick_next, initialized) = self.next_initialized_tick(zero_for_one);
+            let tick_next = tick_next.clamp(MIN_TICK, MAX_TICK);
+            let sqrt_price_next = get_sqrt_ratio_at_tick(tick_next)?;
+
+            let target = match zero_for_one {
+                true => sqrt_price_next.max(sqrt_price_limit),
+                false => sqrt_price_next.min(sqrt_price_limit),
+            };
+            let step = compute_swap_step(
+                self.sqrt_price_x96,
+                target,
+                self.liquidity,
+                amount_remaining,
+                fee,
+            )?;
+            self.sqrt_price_x96 = step.sqrt_price_next;
+            amount_remaining = amount_remaining.checked_sub(step.amount_in + step.fee_amount)?;
+            amount_out += step.amount_out;
+
+            if self.sqrt_price_x96 == sqrt_price_next {
+                if initialized {
+                    let net = self.ticks[&tick_next].liquidity_net;
+                    let net = match zero_for_one {
+                        true => -net,
+                        false => net,
+                    };
+                    self.liquidity = self.liquidity.checked_add_signed(net)?;
+                }
+                self.tick = match zero_for_one {
+                    true => tick_next - 1,
+                    false => tick_next,
+                };
+            } else if self.sqrt_price_x96 != sqrt_price_start {
+                self.tick = get_tick_at_sqrt_ratio(self.sqrt_price_x96)?;
+            }
+        }
+
+        match amount_remaining.is_zero() {
+            true => Some(amount_out),
+            false => None,
+        }
+    }
+}
+
+/// `sqrt(1.0001^tick) * 2^96`
+pub fn get_sqrt_ratio_at_tick(tick: i32) -> Option<U256> {
+    let abs_tick = tick.unsigned_abs();
+    if abs_tick > MAX_TICK as u32 {
+        return None;
+    }
+
+    let mut ratio = match abs_tick & 1 {
+        0 => U256::from(1) << 128usize,
+        _ => U256::from(0xfffcb933bd6fad37aa2d162d1a594001u128),
+    };
+    for (i, multiplier) in TICK_RATIOS.iter().enumerate() {
+        if abs_tick & (2 << i) != 0 {
+            ratio = (ratio * U256::from(*multiplier)) >> 128usize;
+        }
+    }
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+
+    let round_up = !(ratio & U256::from(u32::MAX)).is_zero();
+    Some((ratio >> 32usize) + U256::from(round_up as u8))
+}
+
+/// The greatest tick whose sqrt ratio is less than or equal to `sqrt_price_x96`
+pub fn get_tick_at_sqrt_ratio(sqrt_price_x96: U256) -> Option<i32> {
+    if sqrt_price_x96 < MIN_SQRT_RATIO || sqrt_price_x96 >= MAX_SQRT_RATIO {
+        return None;
+    }
+
+    let (mut low, mut high) = (MIN_TICK, MAX_TICK);
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        match get_sqrt_ratio_at_tick(mid)? <= sqrt_price_x96 {
+            true => low = mid,
+            false => high = mid - 1,
+        }
+    }
+    Some(low)
+}
+
+/// Result of the swap within one tick range
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapStep {
+    pub sqrt_price_next: U256,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    pub fee_amount: U256,
+}
+
+/// `SwapMath.computeSwapStep` for exact input
+pub fn compute_swap_step(
+    sqrt_price_current: U256,
+    sqrt_price_target: U256,
+    liquidity: u128,
+    amount_remaining: U256,
+    fee: u32,
+) -> Option<SwapStep> {
+    let zero_for_one = sqrt_price_current >= sqrt_price_target;
+    let liquidity = U256::from(liquidity);
+    let fee_complement = U256::from(FEE_DENOMINATOR - fee);
+
+    let amount_remaining_less_fee = mul_div(
+        amount_remaining,
+        fee_complement,
+        U256::from(FEE_DENOMINATOR),
+    )?;
+    let amount_in_to_target = match zero_for_one {
+        true => get_amount0_delta(sqrt_price_target, sqrt_price_current, liquidity, true)?,
+        false => get_amount1_delta(sqrt_price_current, sqrt_price_target, liquidity, true)?,
+    };
+
+    let sqrt_price_next = match amount_remaining_less_fee >= amount_in_to_target {
+        true => sqrt_price_target,
+        false => get_next_sqrt_price_from_input(
+            sqrt_price_current,
+            liquidity,
+            amount_remaining_less_fee,
+            zero_for_one,
+        )?,
+    };
+    let max = sqrt_price_next == sqrt_price_target;
+
+    let (amount_in, amount_out) = match zero_for_one {
+        true => (
+            match max {
+                true => amount_in_to_target,
+                false => get_amount0_delta(sqrt_price_next, sqrt_price_current, liquidity, true)?,
+            },
+            get_amount1_delta(sqrt_price_next, sqrt_price_current, liquidity, false)?,
+        ),
+        false => (
+            match max {
+                true => amount_in_to_target,
+                false => get_amount1_delta(sqrt_price_current, sqrt_price_next, liquidity, true)?,
+            },
+            get_amount0_delta(sqrt_price_current, sqrt_price_next, liquidity, false)?,
+        ),
+    };
+
+    let fee_amount = match max {
+        true => mul_div_rounding_up(amount_in, U256::from(fee), fee_complement)?,
+        false => amount_remaining - amount_in,
+    };
+
+    Some(SwapStep {
+        sqrt_price_next,
+        amount_in,
+        amount_out,
+        fee_amount,
+    })
+}
+
+/// Price after `amount_in` of token0 (`zero_for_one`) or token1 is added
+pub fn get_next_sqrt_price_from_input(
+    sqrt_price: U256,
+    liquidity: U256,
+    amount_in: U256,
+    zero_for_one: bool,
+) -> Option<U256> {
+    if sqrt_price.is_zero() || liquidity.is_zero() {
+        return None;
+    }
+    if amount_in.is_zero() {
+        return Some(sqrt_price);
+    }
+
+    match zero_for_one {
+        true => {
+            // price is rounded up, so it does not move further than it should
+            let numerator = liquidity << 96usize;
+            if let Some(product) = amount_in.checked_mul(sqrt_price) {
+                if let Some(denominator) = numerator.checked_add(product) {
+                    return mul_div_rounding_up(numerator, sqrt_price, denominator);
+                }
+            }
+            Some(div_rounding_up(
+                numerator,
+                numerator / sqrt_price + amount_in,
+            ))
+        }
+        false => {
+            let quotient = match amount_in < U256::from(1) << 160usize {
+                true => (amount_in << 96usize) / liquidity,
+                false => mul_div(amount_in, Q96, liquidity)?,
+            };
+            sqrt_price.checked_add(quotient)
+        }
+    }
+}
+
+/// Amount of token0 between two prices
+pub fn get_amount0_delta(
+    sqrt_price_a: U256,
+    sqrt_price_b: U256,
+    liquidity: U256,
+    round_up: bool,
+) -> Option<U256> {
+    let (sqrt_price_a, sqrt_price_b) = match sqrt_price_a > sqrt_price_b {
+        true => (sqrt_price_b, sqrt_price_a),
+        false => (sqrt_price_a, sqrt_price_b),
+    };
+    if sqrt_price_a.is_zero() {
+        return None;
+    }
+
+    let numerator1 = liquidity << 96usize;
+    let numerator2 = sqrt_price_b - sqrt_price_a;
+    match round_up {
+        true => Some(div_rounding_up(
+            mul_div_rounding_up(numerator1, numerator2, sqrt_price_b)?,
+            sqrt_price_a,
+        )),
+        false => Some(mul_div(numerator1, numerator2, sqrt_price_b)? / sqrt_price_a),
+    }
+}
+
+/// Amount of token1 between two prices
+pub fn get_amount1_delta(
+    sqrt_price_a: U256,
+    sqrt_price_b: U256,
+    liquidity: U256,
+    round_up: bool,
+) -> Option<U256> {
+    let (sqrt_price_a, sqrt_price_b) = match sqrt_price_a > sqrt_price_b {
+        true => (sqrt_price_b, sqrt_price_a),
+        false => (sqrt_price_a, sqrt_price_b),
+    };
+
+    match round_up {
+        true => mul_div_rounding_up(liquidity, sqrt_price_b - sqrt_price_a, Q96),
+        false => mul_div(liquidity, sqrt_price_b - sqrt_price_a, Q96),
+    }
+}
+
+/// `a * b / denominator` with 512 bit intermediate product, `None` on overflow
+pub fn mul_div(a: U256, b: U256, denominator: U256) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    let product: U512 = a.widening_mul(b);
+    let result = product / U512::from(denominator);
+    U256::checked_from_limbs_slice(&result.as_limbs()[..4])
+        .filter(|_| result >> 256usize == U512::ZERO)
+}
+
+pub fn mul_div_rounding_up(a: U256, b: U256, denominator: U256) -> Option<U256> {
+    let result = mul_div(a, b, denominator)?;
+    match a.mul_mod(b, denominator).is_zero() {
+        true => Some(result),
+        false => result.checked_add(U256::from(1)),
+    }
+}
+
+fn div_rounding_up(a: U256, b: U256) -> U256 {
+    let round_up = !(a % b).is_zero();
+    a / b + U256::from(round_up as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Expected values of price math are from Uniswap V3 core tests. Swaps are checked against
+    // exact real solutions of `sqrt(1.0001^tick)` ranges computed with mpmath, rounded down:
+    // every step rounds in favour of the pool, so the output may be a few wei less
+    const TOLERANCE: u64 = 5;
+
+    fn assert_rounds_down(actual: U256, exact: &str) {
+        let exact = u(exact);
+        assert!(actual <= exact, "{actual} > {exact}");
+        assert!(exact - actual <= U256::from(TOLERANCE), "{actual} != {exact}");
+    }
+
+    fn u(value: &str) -> U256 {
+        value.parse().unwrap()
+    }
+
+    fn e18(value: u128) -> u128 {
+        value * 1_000_000_000_000_000_000
+    }
+
+    fn pool() -> ConcentratedPool {
+        let mut pool = ConcentratedPool::new(Q96, 0, 3000, 60);
+        pool.modify_liquidity(-600, 600, e18(1000) as i128);
+        pool.modify_liquidity(-120, 120, e18(500) as i128);
+        pool.modify_liquidity(600, 1200, e18(2000) as i128);
+        pool
+    }
+
+    #[test]
+    fn sqrt_ratio_at_tick_matches_tick_math() {
+        assert_eq!(get_sqrt_ratio_at_tick(0), Some(Q96));
+        assert_eq!(get_sqrt_ratio_at_tick(MIN_TICK), Some(MIN_SQRT_RATIO));
+        assert_eq!(get_sqrt_ratio_at_tick(MAX_TICK), Some(MAX_SQRT_RATIO));
+        assert_eq!(get_sqrt_ratio_at_tick(MAX_TICK + 1), None);
+
+        for tick in [-54321, -1, 1, 12345, 200_000] {
+            let sqrt_price = get_sqrt_ratio_at_tick(tick).unwrap();
+            assert_eq!(get_tick_at_sqrt_ratio(sqrt_price), Some(tick));
+            assert_eq!(
+                get_tick_at_sqrt_ratio(sqrt_price - U256::from(1)),
+                Some(tick - 1)
+            );
+        }
+    }
+
+    #[test]
+    fn sqrt_price_math_rounds_like_the_pool() {
+        let liquidity = U256::from(e18(1));
+        assert_eq!(
+            get_next_sqrt_price_from_input(Q96, liquidity, U256::from(e18(1) / 10), false),
+            Some(u("87150978765690771352898345369"))
+        );
+        assert_eq!(
+            get_next_sqrt_price_from_input(Q96, liquidity, U256::from(e18(1) / 10), true),
+            Some(u("72025602285694852357767227579"))
+        );
+
+        // price 1.21
+        let sqrt_price = u("87150978765690771352898345369");
+        assert_eq!(
+            get_amount0_delta(Q96, sqrt_price, liquidity, true),
+            Some(u("90909090909090910"))
+        );
+        assert_eq!(
+            get_amount0_delta(Q96, sqrt_price, liquidity, false),
+            Some(u("90909090909090909"))
+        );
+        assert_eq!(
+            get_amount1_delta(Q96, sqrt_price, liquidity, true),
+            Some(u("100000000000000000"))
+        );
+        assert_eq!(
+            get_amount1_delta(Q96, sqrt_price, liquidity, false),
+            Some(u("99999999999999999"))
+        );
+    }
+
+    #[test]
+    fn swap_step_is_capped_at_the_target_price() {
+        // price 1.01
+        let target = u("79623317895830914510639640423");
+        let step = compute_swap_step(Q96, target, e18(2), U256::from(e18(1)), 600).unwrap();
+        assert_eq!(
+            step,
+            SwapStep {
+                sqrt_price_next: target,
+                amount_in: u("9975124224178055"),
+                amount_out: u("9925619580021728"),
+                fee_amount: u("5988667735148"),
+            }
+        );
+    }
+
+    #[test]
+    fn swap_crosses_initialized_ticks() {
+        let vectors = [
+            (true, e18(1), "996337767497203525", -14, e18(1500)),
+            (true, e18(20), "19640111211818621447", -336, e18(1000)),
+            (true, e18(29), "28240921039840734571", -512, e18(1000)),
+            (false, e18(40), "38569513506663719612", 662, e18(2000)),
+            (false, e18(90), "84127026233339553697", 1138, e18(2000)),
+        ];
+        for (zero_for_one, amount_in, amount_out, tick, liquidity) in vectors {
+            let mut pool = pool();
+            let actual = pool.swap(zero_for_one, U256::from(amount_in)).unwrap();
+            assert_rounds_down(actual, amount_out);
+            assert_eq!(pool.tick, tick, "{zero_for_one} {amount_in}");
+            assert_eq!(pool.liquidity, liquidity);
+        }
+    }
+
+    #[test]
+    fn swap_takes_the_protocol_fee() {
+        let mut pool = pool();
+        // 0.1% for zero for one, 0.05% for one for zero
+        pool.protocol_fee = 1000 | 500 << 12;
+        assert_eq!(pool.swap_fee(true), 3997);
+        assert_eq!(pool.swap_fee(false), 3499);
+
+        let amount_out = pool.swap_exact_in(true, U256::from(e18(20))).unwrap();
+        assert_rounds_down(amount_out, "19620829278208809872");
+        let amount_out = pool.swap_exact_in(false, U256::from(e18(40))).unwrap();
+        assert_rounds_down(amount_out, "38550832185898169383");
+    }
+
+    #[test]
+    fn rejects_swaps_beyond_liquidity() {
+        assert_eq!(pool().swap_exact_in(true, U256::from(e18(50))), None);
+    }
+
+    #[test]
+    fn modify_liquidity_updates_ticks_and_active_liquidity() {
+        let mut pool = pool();
+        assert_eq!(pool.liquidity, e18(1500));
+
+        pool.modify_liquidity(-120, 120, -(e18(500) as i128));
+        assert_eq!(pool.liquidity, e18(1000));
+        assert!(!pool.ticks.contains_key(&-120));
+
+        // position above the current tick does not change active liquidity
+        pool.modify_liquidity(1200, 1800, e18(10) as i128);
+        assert_eq!(pool.liquidity, e18(1000));
+        assert_eq!(
+            pool.ticks[&1200].liquidity_net,
+            -(e18(2000) as i128) + e18(10) as i128
+        );
+    }
+}
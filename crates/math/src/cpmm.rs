@@ -1,60 +1,31 @@
 use alloy::primitives::{Address, Uint};
-use anyhow::Result;
 // use dex_common::{DexError, Reserves, DEX};
-use kronos_common::{DexError, Reserves};
-use kronos_db::{PricesStorage, TokensGraphStorage, DB};
+use kronos_common::Reserves;
+use kronos_db::TokenRegistry;
 
 #[derive(Clone, Debug)]
 pub struct ArbitrageData {
     pub reserves: Reserves,
     pub fee: Uint<112, 2>,
+    // transfer tax of the input token in basis points, taken when the input reaches the pair
+    pub transfer_tax_bps: u16,
+    // transfer tax of the output token, taken when the pair sends the output
+    pub output_tax_bps: u16,
 }
 
-pub async fn find_triangular_arbitrage(
-    start_tokens: &[Address],
-    db: DB,
-    dex_id: i32,
-) -> Result<Vec<Vec<(Address, Address)>>> {
-    let mut paths = vec![];
-
-    for token0 in start_tokens {
-        let adjacent_for_token0 = db.adjacent_tokens(dex_id, token0).await?;
-
-        for token1 in adjacent_for_token0.iter() {
-            'iter: for token2 in db.adjacent_tokens(dex_id, token1).await?.iter() {
-                if adjacent_for_token0.contains(token2) {
-                    if token0 == token1 || token0 == token2 || token1 == token2 {
-                        continue;
-                    }
-
-                    let mut reserves = Vec::<Reserves>::new();
-
-                    let tokens = vec![(*token0, *token1), (*token1, *token2), (*token2, *token0)];
-                    for (t0, t1) in tokens.iter() {
-                        let token_reserves = match db.reserves(dex_id, t0, t1).await {
-                            Ok(reserves) => reserves,
-                            Err(err) => {
-                                if let Some(DexError::BlockRpcLimitExceed) =
-                                    err.downcast_ref::<DexError>()
-                                {
-                                    continue 'iter;
-                                }
-                                return Err(err);
-                            }
-                        };
-                        reserves.push(token_reserves);
-                    }
-
-                    let fee = Uint::from(3);
-                    if arbitrage_exists(fee, &reserves) {
-                        paths.push(tokens);
-                    }
-                }
-            }
-        }
-    }
-
-    Ok(paths)
+/// Transfer taxes `(input, output)` of every hop in basis points. Output of the hop is sent
+/// directly to the next pair, so only the first input and outputs are separate transfers
+pub fn path_taxes(tokens: &TokenRegistry, path: &[(Address, Address)]) -> Vec<(u16, u16)> {
+    path.iter()
+        .enumerate()
+        .map(|(index, (token_in, token_out))| {
+            let input = match index {
+                0 => tokens.transfer_tax_bps(token_in).unwrap_or(0),
+                _ => 0,
+            };
+            (input, tokens.transfer_tax_bps(token_out).unwrap_or(0))
+        })
+        .collect()
 }
 
 pub fn arbitrage_exists(fee: Uint<112, 2>, reserves: &[Reserves]) -> bool {
@@ -67,61 +38,157 @@ pub fn arbitrage_exists(fee: Uint<112, 2>, reserves: &[Reserves]) -> bool {
     log_sum > 0.0
 }
 
-// p = (1 - fee) * r_i/r_j - price of `j` in terms of `i`
+// p = (1 - fee) * r_out/r_in - marginal amount of `out` for one `in`,
+// reserves are in order (in, out) as in `calculate_dy`
 pub fn price_log(fee: Uint<112, 2>, reserves: &Reserves) -> f64 {
     let base = Uint::from(1000);
-    reserves.0.saturating_mul(base - fee).approx_log2()
-        - reserves.1.saturating_mul(base).approx_log2()
+    reserves.1.saturating_mul(base - fee).approx_log2()
+        - reserves.0.saturating_mul(base).approx_log2()
 }
 
 // dy = y - k / (x + 0.997 * dx)
 // dy = y - 1000* k / (1000x + 997*dx)
+// dx is reduced by transfer tax of the input token before it reaches the pair,
+// dy by transfer tax of the output token after it leaves the pair
 pub fn calculate_dy(data: &ArbitrageData, amount_in: Uint<256, 4>) -> Uint<256, 4> {
-    let base = Uint::from(1000);
+    let amount_in = after_tax(amount_in, data.transfer_tax_bps);
 
+    let base = Uint::from(1000);
     let new_reserve0 = Uint::<256, 4>::from(data.reserves.0).saturating_mul(base)
         + amount_in * (base - Uint::<256, 4>::from(data.fee));
 
     let k_last = Uint::<256, 4>::from(data.reserves.0) * Uint::<256, 4>::from(data.reserves.1);
 
-    Uint::<256, 4>::from(data.reserves.1) - (k_last * Uint::from(base) / new_reserve0)
+    let amount_out =
+        Uint::<256, 4>::from(data.reserves.1) - (k_last * Uint::from(base) / new_reserve0);
+    after_tax(amount_out, data.output_tax_bps)
 }
 
-// Profit: (optimal_amount_in, max_profit)
-type Profit = (Uint<256, 4>, Uint<256, 4>);
+fn after_tax(amount: Uint<256, 4>, tax_bps: u16) -> Uint<256, 4> {
+    let bps = Uint::<256, 4>::from(10_000);
+    amount * (bps - Uint::from(tax_bps)) / bps
+}
 
-pub fn find_profit(data: &[ArbitrageData]) -> Option<Profit> {
-    let mut amount_in = Uint::<256, 4>::from(1);
+/// Output of the path for `amount_in` of the first token
+pub fn path_amount_out(data: &[ArbitrageData], amount_in: Uint<256, 4>) -> Uint<256, 4> {
+    data.iter()
+        .fold(amount_in, |amount, hop| calculate_dy(hop, amount))
+}
 
-    let mut best_amount_in = None;
-    let mut best_profit = None;
+fn optimal_amount_in_bin_search(
+    _pair_reserves: &[(Uint<112, 2>, Uint<112, 2>)],
+) -> Option<Uint<256, 4>> {
+    let mut _amount_in = Uint::<256, 4>::from(1);
+    None
+}
 
-    for _ in 0..100 {
-        let mut amount_out = amount_in;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cycles::optimal_amount_in;
+
+    // amount in and revenue of the cycle by the sizing of all dexes
+    fn size(
+        data: &[ArbitrageData],
+        max_amount_in: Option<Uint<256, 4>>,
+    ) -> Option<(Uint<256, 4>, Uint<256, 4>)> {
+        optimal_amount_in(
+            |amount_in| Some(path_amount_out(data, amount_in)),
+            max_amount_in,
+        )
+    }
 
-        for d in data {
-            amount_out = calculate_dy(d, amount_in);
+    fn hop(reserve_in: u128, reserve_out: u128) -> ArbitrageData {
+        ArbitrageData {
+            reserves: Reserves(Uint::from(reserve_in), Uint::from(reserve_out)),
+            fee: Uint::from(3),
+            transfer_tax_bps: 0,
+            output_tax_bps: 0,
         }
+    }
 
-        if amount_out > amount_in {
-            let profit = amount_out - amount_in;
-            if best_profit.is_none() || profit > best_profit.unwrap() {
-                best_amount_in = Some(amount_in);
-                best_profit = Some(profit);
-            }
-        }
+    #[test]
+    fn taxes_input_and_output_transfers() {
+        let amount_in = Uint::from(1_000_000u64);
+        let untaxed = calculate_dy(&hop(1 << 60, 1 << 60), amount_in);
+
+        let input_taxed = ArbitrageData {
+            transfer_tax_bps: 500,
+            ..hop(1 << 60, 1 << 60)
+        };
+        assert_eq!(
+            calculate_dy(&input_taxed, amount_in),
+            calculate_dy(&hop(1 << 60, 1 << 60), Uint::from(950_000u64))
+        );
+
+        let output_taxed = ArbitrageData {
+            output_tax_bps: 500,
+            ..hop(1 << 60, 1 << 60)
+        };
+        assert_eq!(
+            calculate_dy(&output_taxed, amount_in),
+            untaxed * Uint::from(9_500) / Uint::from(10_000)
+        );
+    }
 
-        amount_in *= Uint::from(2);
+    #[test]
+    fn profit_is_measured_over_the_whole_cycle() {
+        // the last pool pays 2x for the first token
+        let data = [
+            hop(1_000_000_000_000, 1_000_000_000_000),
+            hop(1_000_000_000_000, 1_000_000_000_000),
+            hop(1_000_000_000_000, 2_000_000_000_000),
+        ];
+        let (amount_in, profit) = size(&data, None).unwrap();
+        assert_eq!(profit, path_amount_out(&data, amount_in) - amount_in);
+        // the single hop of the first pool loses the fee
+        assert!(calculate_dy(&data[0], amount_in) < amount_in);
+
+        // balanced cycle only pays fees
+        let data = [
+            hop(1_000_000_000_000, 1_000_000_000_000),
+            hop(1_000_000_000_000, 1_000_000_000_000),
+        ];
+        assert!(size(&data, None).is_none());
     }
 
-    best_amount_in.zip(best_profit)
-}
+    #[test]
+    fn revenue_is_output_minus_amount_in_with_and_without_cap() {
+        let data = [
+            hop(1_000_000_000_000, 1_000_000_000_000),
+            hop(1_000_000_000_000, 2_000_000_000_000),
+        ];
+        let (optimal_amount_in, profit) = size(&data, None).unwrap();
+        assert_eq!(
+            profit,
+            path_amount_out(&data, optimal_amount_in) - optimal_amount_in
+        );
+        assert_eq!(
+            size(&data, Some(optimal_amount_in * Uint::from(2))),
+            Some((optimal_amount_in, profit))
+        );
+
+        let cap = optimal_amount_in / Uint::from(4);
+        let (amount_in, revenue) = size(&data, Some(cap)).unwrap();
+        assert_eq!(amount_in, cap);
+        assert_eq!(revenue, path_amount_out(&data, cap) - cap);
+        assert!(revenue < profit);
+
+        assert_eq!(size(&data, Some(Uint::ZERO)), None);
+    }
 
-fn optimal_amount_in_bin_search(
-    _pair_reserves: &[(Uint<112, 2>, Uint<112, 2>)],
-) -> Option<Uint<256, 4>> {
-    let mut _amount_in = Uint::<256, 4>::from(1);
-    None
+    #[test]
+    fn taxes_make_the_cycle_unprofitable() {
+        // 10% edge of the cycle is eaten by 5% tax on both transfers of the taxed token
+        let mut data = [
+            hop(1_000_000_000_000, 1_000_000_000_000),
+            hop(1_000_000_000_000, 1_100_000_000_000),
+        ];
+        assert!(size(&data, None).is_some());
+        data[0].output_tax_bps = 500;
+        data[1].transfer_tax_bps = 500;
+        assert!(size(&data, None).is_none());
+    }
 }
 
 // mod tests {
@@ -0,0 +1,490 @@
+use crate::cpmm::arbitrage_exists;
+use alloy::primitives::{Address, Uint, U256};
+use hashbrown::HashMap;
+use kronos_common::Reserves;
+use kronos_db::{graph::Topology, GraphSnapshot};
+use rayon::prelude::*;
+
+/// Hops `(token_in, edge)` of the cycle from its start token
+pub type Path = Vec<(u32, u32)>;
+
+// Amounts are doubled up to this number of times while the cycle is sized
+const SIZING_STEPS: usize = 100;
+
+/// Tokens of the cycle, `edges[i]` is the pool of `tokens[i]` and `tokens[i + 1]`.
+/// Cycle of two tokens goes through two pools of the same pair, of three tokens through three pools
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cycle {
+    len: u8,
+    tokens: [u32; 3],
+    edges: [u32; 3],
+}
+
+impl Cycle {
+    pub fn tokens(&self) -> &[u32] {
+        &self.tokens[..self.len as usize]
+    }
+
+    pub fn edges(&self) -> &[u32] {
+        &self.edges[..self.len as usize]
+    }
+
+    /// Forward and reverse paths from the first token
+    pub fn directions(&self) -> [Path; 2] {
+        let n = self.len as usize;
+        let forward = (0..n).map(|k| (self.tokens[k], self.edges[k])).collect();
+        let reverse = (0..n)
+            .map(|k| (self.tokens[(n - k) % n], self.edges[n - 1 - k]))
+            .collect();
+        [forward, reverse]
+    }
+}
+
+/// Every rotation is the same cycle, but sized in its start token
+fn rotations(path: Path) -> impl Iterator<Item = Path> {
+    (0..path.len()).map(move |start| {
+        let mut rotated = path.clone();
+        rotated.rotate_left(start);
+        rotated
+    })
+}
+
+/// `CycleIndex` keeps all cycles of the dex and the mapping
+/// from every edge of `TokenGraph` to cycles which contain it.
+/// It is built once and extended when new pairs appear in the graph,
+/// so on a block only cycles of changed pools are scored.
+/// Cycle never goes through one pool twice
+#[derive(Debug)]
+pub struct CycleIndex {
+    dex_id: i32,
+    cycles: Vec<Cycle>,
+    by_edge: HashMap<u32, Vec<u32>>,
+    // number of topology edges which are already indexed
+    indexed_edges: usize,
+}
+
+impl CycleIndex {
+    pub fn new(dex_id: i32) -> Self {
+        Self {
+            dex_id,
+            cycles: vec![],
+            by_edge: HashMap::new(),
+            indexed_edges: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cycles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cycles.is_empty()
+    }
+
+    /// Indexes cycles of edges which were added to topology since the last call.
+    /// Edges are append-only, so a cycle is added when its newest edge is indexed
+    pub fn sync(&mut self, topology: &Topology) {
+        let edges = topology.edges();
+        for (edge, data) in edges.iter().enumerate().skip(self.indexed_edges) {
+            if data.dex_id != self.dex_id {
+                continue;
+            }
+            let (a, b) = (data.token0, data.token1);
+            let edge = edge as u32;
+            let pool = |edge: u32| edges[edge as usize].pair;
+
+            // other pools of the same pair
+            for ba in topology.edges_between(self.dex_id, a, b) {
+                if *ba >= edge || pool(*ba) == data.pair {
+                    continue;
+                }
+                self.insert(Cycle {
+                    len: 2,
+                    tokens: [a, b, 0],
+                    edges: [edge, *ba, 0],
+                });
+            }
+
+            // walk over the token with less pairs
+            let (a, b) = match topology.degree(a) <= topology.degree(b) {
+                true => (a, b),
+                false => (b, a),
+            };
+            for ca in topology.token_edges(self.dex_id, a) {
+                let c = topology.other(ca, a);
+                if ca >= edge || c == b || pool(ca) == data.pair {
+                    continue;
+                }
+                for bc in topology.edges_between(self.dex_id, b, c) {
+                    let bc = *bc;
+                    if bc >= edge || pool(bc) == data.pair || pool(bc) == pool(ca) {
+                        continue;
+                    }
+                    self.insert(Cycle {
+                        len: 3,
+                        tokens: [a, b, c],
+                        edges: [edge, bc, ca],
+                    });
+                }
+            }
+        }
+        self.indexed_edges = edges.len();
+    }
+
+    fn insert(&mut self, cycle: Cycle) {
+        let id = self.cycles.len() as u32;
+        for edge in cycle.edges() {
+            self.by_edge.entry(*edge).or_default().push(id);
+        }
+        self.cycles.push(cycle);
+    }
+
+    /// Cycles which contain any of `changed` pools. Cycles are copied out,
+    /// so the index is not borrowed while they are scored
+    pub fn affected(&self, changed: &[Address], topology: &Topology) -> Vec<Cycle> {
+        let mut affected: Vec<u32> = changed
+            .iter()
+            .flat_map(|pool| topology.pool_edges(pool))
+            .filter_map(|edge| self.by_edge.get(edge))
+            .flatten()
+            .copied()
+            .collect();
+        // pool may be changed several times per block and cycles share pools
+        affected.sort_unstable();
+        affected.dedup();
+
+        affected
+            .into_iter()
+            .map(|id| self.cycles[id as usize])
+            .collect()
+    }
+}
+
+/// Scores constant product `cycles` in both directions by reserves of the snapshot.
+/// Every profitable cycle is returned once per start token, scoring is done in parallel on rayon pool
+pub fn profitable_paths<F>(
+    cycles: Vec<Cycle>,
+    snapshot: &GraphSnapshot,
+    is_tradable: F,
+) -> Vec<Path>
+where
+    F: Fn(&Address) -> bool + Sync,
+{
+    cycles
+        .into_par_iter()
+        .flat_map_iter(|cycle| score_cycle(&cycle, snapshot, &is_tradable))
+        .collect()
+}
+
+fn score_cycle<F>(cycle: &Cycle, snapshot: &GraphSnapshot, is_tradable: &F) -> Vec<Path>
+where
+    F: Fn(&Address) -> bool,
+{
+    if !tradable(cycle, &snapshot.topology, is_tradable) {
+        return vec![];
+    }
+    let fee = Uint::from(3);
+
+    cycle
+        .directions()
+        .into_iter()
+        .filter(|path| {
+            path.iter()
+                .map(|(token_in, edge)| snapshot.edge_reserves(*edge, *token_in))
+                .collect::<Option<Vec<Reserves>>>()
+                .is_some_and(|reserves| arbitrage_exists(fee, &reserves))
+        })
+        .flat_map(rotations)
+        .collect()
+}
+
+/// All paths of `cycles` with tradable tokens, both directions from every start token.
+/// It is used for pools without reserves in the graph, which are sized by quotes of the pool
+pub fn cycle_paths<F>(cycles: Vec<Cycle>, topology: &Topology, is_tradable: F) -> Vec<Path>
+where
+    F: Fn(&Address) -> bool,
+{
+    cycles
+        .into_iter()
+        .filter(|cycle| tradable(cycle, topology, &is_tradable))
+        .flat_map(|cycle| cycle.directions())
+        .flat_map(rotations)
+        .collect()
+}
+
+fn tradable<F>(cycle: &Cycle, topology: &Topology, is_tradable: &F) -> bool
+where
+    F: Fn(&Address) -> bool,
+{
+    cycle
+        .tokens()
+        .iter()
+        .all(|token| is_tradable(&topology.token(*token)))
+}
+
+/// Amount in with the highest profit of the cycle and the profit: `(amount_in, profit)`.
+/// `amount_out` is output of the whole cycle, `None` if any pool would revert.
+/// Amount is doubled from 1 wei while the profit grows, the search stops
+/// once the profit falls or pools can't take the amount.
+/// Amount in is at most `max_amount_in` if it is set. Cycles of every dex are sized by it
+pub fn optimal_amount_in<F>(amount_out: F, max_amount_in: Option<U256>) -> Option<(U256, U256)>
+where
+    F: Fn(U256) -> Option<U256>,
+{
+    let profit = |amount_in: U256| {
+        amount_out(amount_in)?
+            .checked_sub(amount_in)
+            .filter(|profit| !profit.is_zero())
+    };
+
+    let mut best: Option<(U256, U256)> = None;
+    let mut amount_in = U256::from(1);
+    for _ in 0..SIZING_STEPS {
+        if max_amount_in.is_some_and(|max| amount_in > max) {
+            break;
+        }
+        match (profit(amount_in), best) {
+            (Some(profit), Some((_, best_profit))) if profit <= best_profit => break,
+            (Some(profit), _) => best = Some((amount_in, profit)),
+            // profit is concave, so it doesn't come back after it was positive
+            (None, Some(_)) => break,
+            (None, None) => {}
+        }
+        amount_in <<= 1;
+    }
+
+    // the cap may be closer to the optimum than the last doubled amount
+    if let (Some(max), Some((_, best_profit))) = (max_amount_in, best) {
+        if let Some(capped) = profit(max).filter(|capped| *capped > best_profit) {
+            best = Some((max, capped));
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kronos_db::{tables::Pair, TokenGraph, UpdateReservesData};
+
+    const DEX: i32 = 1;
+    const A: Address = Address::repeat_byte(1);
+    const B: Address = Address::repeat_byte(2);
+    const C: Address = Address::repeat_byte(3);
+    const D: Address = Address::repeat_byte(4);
+
+    fn pair(address: u8, dex_id: i32, token0: Address, token1: Address) -> Pair {
+        Pair {
+            address: Address::repeat_byte(address),
+            dex_id,
+            token0,
+            token1,
+        }
+    }
+
+    fn update(pair: &Pair, reserve0: u64, reserve1: u64) -> UpdateReservesData {
+        UpdateReservesData {
+            pair: pair.address,
+            token0: pair.token0,
+            token1: pair.token1,
+            reserves: Reserves(Uint::from(reserve0), Uint::from(reserve1)),
+            block_number: 1,
+        }
+    }
+
+    // input tokens of the path
+    fn tokens(paths: &[Path], topology: &Topology) -> Vec<Vec<Address>> {
+        paths
+            .iter()
+            .map(|path| {
+                path.iter()
+                    .map(|(token, _)| topology.token(*token))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn indexes_new_triangles_incrementally() {
+        let graph = TokenGraph::default();
+        let mut index = CycleIndex::new(DEX);
+        graph.add_pairs(&[
+            pair(10, DEX, A, B),
+            pair(11, DEX, B, C),
+            pair(12, DEX, C, D),
+            // other dex closes the cycle, but it is not indexed
+            pair(13, DEX + 1, A, C),
+        ]);
+        index.sync(&graph.snapshot().topology);
+        assert!(index.is_empty());
+
+        graph.add_pairs(&[pair(14, DEX, C, A)]);
+        index.sync(&graph.snapshot().topology);
+        assert_eq!(index.len(), 1);
+
+        // edge shared by two triangles
+        graph.add_pairs(&[pair(15, DEX, D, B)]);
+        index.sync(&graph.snapshot().topology);
+        assert_eq!(index.len(), 2);
+        // nothing changed
+        index.sync(&graph.snapshot().topology);
+        assert_eq!(index.len(), 2);
+
+        let topology = graph.snapshot().topology.clone();
+        let pool = |byte| Address::repeat_byte(byte);
+        assert_eq!(index.affected(&[pool(10)], &topology).len(), 1);
+        assert_eq!(index.affected(&[pool(11), pool(11)], &topology).len(), 2);
+        assert_eq!(index.affected(&[pool(10), pool(12)], &topology).len(), 2);
+        assert!(index.affected(&[pool(13), pool(99)], &topology).is_empty());
+    }
+
+    #[test]
+    fn scores_profitable_direction_from_every_start_token() {
+        let graph = TokenGraph::default();
+        let pairs = [
+            pair(10, DEX, A, B),
+            pair(11, DEX, B, C),
+            pair(12, DEX, C, A),
+        ];
+        graph.add_pairs(&pairs);
+        let mut index = CycleIndex::new(DEX);
+        index.sync(&graph.snapshot().topology);
+
+        // B is cheap in the first pair, so only A -> B -> C -> A pays
+        graph.update_reserves(&[
+            update(&pairs[0], 1_000_000, 2_000_000),
+            update(&pairs[1], 1_000_000, 1_000_000),
+            update(&pairs[2], 1_000_000, 1_000_000),
+        ]);
+        let snapshot = graph.snapshot();
+        let triangles = index.affected(&[pairs[0].address], &snapshot.topology);
+
+        let paths = profitable_paths(triangles.clone(), &snapshot, |_| true);
+        let paths = tokens(&paths, &snapshot.topology);
+        assert_eq!(paths.len(), 3);
+        assert!(paths.contains(&vec![A, B, C]));
+        assert!(paths.contains(&vec![B, C, A]));
+        assert!(paths.contains(&vec![C, A, B]));
+
+        // cycle with a token which is not tradable is skipped
+        assert!(profitable_paths(triangles.clone(), &snapshot, |token| *token != C).is_empty());
+
+        // fees eat the gap of balanced pairs
+        graph.update_reserves(&[update(&pairs[0], 1_000_000, 1_001_000)]);
+        assert!(profitable_paths(triangles, &graph.snapshot(), |_| true).is_empty());
+    }
+
+    #[test]
+    fn skips_triangles_without_reserves() {
+        let graph = TokenGraph::default();
+        let pairs = [
+            pair(10, DEX, A, B),
+            pair(11, DEX, B, C),
+            pair(12, DEX, C, A),
+        ];
+        graph.add_pairs(&pairs);
+        graph.update_reserves(&[update(&pairs[0], 1_000, 2_000)]);
+        let mut index = CycleIndex::new(DEX);
+        index.sync(&graph.snapshot().topology);
+
+        let snapshot = graph.snapshot();
+        let triangles = index.affected(&[pairs[0].address], &snapshot.topology);
+        assert_eq!(triangles.len(), 1);
+        assert!(profitable_paths(triangles, &snapshot, |_| true).is_empty());
+    }
+
+    #[test]
+    fn indexes_cycles_over_parallel_pools() {
+        let graph = TokenGraph::default();
+        let mut index = CycleIndex::new(DEX);
+        // pool of three tokens has an edge per pair, but it is not a cycle itself
+        graph.add_pairs(&[
+            pair(10, DEX, A, B),
+            pair(10, DEX, B, C),
+            pair(10, DEX, A, C),
+        ]);
+        index.sync(&graph.snapshot().topology);
+        assert!(index.is_empty());
+
+        // second pool of A and B makes a two token cycle with the first,
+        // triangle over other edges of the first pool would trade in it twice
+        graph.add_pairs(&[pair(11, DEX, A, B)]);
+        index.sync(&graph.snapshot().topology);
+        assert_eq!(index.len(), 1);
+
+        // second pool of B and C makes another two token cycle and
+        // the only triangle through three different pools
+        graph.add_pairs(&[pair(12, DEX, B, C)]);
+        index.sync(&graph.snapshot().topology);
+        assert_eq!(index.len(), 3);
+
+        let topology = graph.snapshot().topology.clone();
+        let cycles = index.affected(&[Address::repeat_byte(11)], &topology);
+        assert_eq!(cycles.len(), 2);
+        let two = cycles
+            .iter()
+            .find(|cycle| cycle.tokens().len() == 2)
+            .unwrap();
+        let paths = cycle_paths(vec![*two], &topology, |_| true);
+        // both directions from both tokens
+        assert_eq!(paths.len(), 4);
+        for path in paths.iter() {
+            assert_ne!(path[0].1, path[1].1);
+            assert_ne!(path[0].0, path[1].0);
+        }
+
+        let triangle = cycles
+            .iter()
+            .find(|cycle| cycle.tokens().len() == 3)
+            .unwrap();
+        let mut pools: Vec<u8> = triangle
+            .edges()
+            .iter()
+            .map(|edge| topology.edges()[*edge as usize].pair.0[0])
+            .collect();
+        pools.sort();
+        assert_eq!(pools, vec![10, 11, 12]);
+        assert_eq!(cycle_paths(vec![*triangle], &topology, |_| true).len(), 6);
+    }
+
+    #[test]
+    fn reverse_direction_walks_the_same_edges_back() {
+        let cycle = Cycle {
+            len: 3,
+            tokens: [0, 1, 2],
+            edges: [10, 12, 20],
+        };
+        let [forward, reverse] = cycle.directions();
+        assert_eq!(forward, vec![(0, 10), (1, 12), (2, 20)]);
+        assert_eq!(reverse, vec![(0, 20), (2, 12), (1, 10)]);
+    }
+
+    #[test]
+    fn sizes_amount_in_to_the_highest_profit() {
+        // output of the cycle with optimum near 2^19, small amounts don't pay the fixed cost
+        let amount_out = |amount_in: U256| {
+            let x = amount_in.to::<u128>() as f64;
+            let out = x + 2.0 * (x * 1_048_576.0).sqrt() - x * 1.5 - 100_000.0;
+            Some(U256::from(out.max(0.0) as u128))
+        };
+        let (amount_in, profit) = optimal_amount_in(amount_out, None).unwrap();
+        assert!(amount_in >= U256::from(1 << 18) && amount_in <= U256::from(1 << 22));
+        assert_eq!(Some(amount_in + profit), amount_out(amount_in));
+
+        // the cap is paid in full while profit still grows
+        let capped = U256::from(100_000);
+        let (amount_in, _) = optimal_amount_in(amount_out, Some(capped)).unwrap();
+        assert_eq!(amount_in, capped);
+
+        // pools which revert for large amounts stop the search
+        let limited = |amount_in: U256| {
+            (amount_in < U256::from(1 << 16))
+                .then(|| amount_out(amount_in))
+                .flatten()
+        };
+        let (amount_in, _) = optimal_amount_in(limited, None).unwrap();
+        assert!(amount_in < U256::from(1 << 16));
+
+        assert!(optimal_amount_in(Some, None).is_none());
+    }
+}
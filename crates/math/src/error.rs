@@ -0,0 +1,24 @@
+use alloy::primitives::Address;
+use kronos_common::ErrorAction;
+use kronos_db::DbError;
+
+pub type Result<T, E = MathError> = std::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MathError {
+    #[error("Not found liquid route to stable for {0:?}")]
+    NoRoute(Address),
+    #[error("Unknown decimals for {0:?}")]
+    UnknownDecimals(Address),
+    #[error(transparent)]
+    Db(#[from] DbError),
+}
+
+impl MathError {
+    pub fn action(&self) -> ErrorAction {
+        match self {
+            Self::NoRoute(_) | Self::UnknownDecimals(_) => ErrorAction::Skip,
+            Self::Db(err) => err.action(),
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use crate::{
+    error::{MathError, Result},
+    HUB_TOKENS, STABLE_COINS,
+};
+use alloy::primitives::{Address, Uint};
+use kronos_common::Reserves;
+use kronos_config::OracleConfig;
+use kronos_db::{PricesStorage, TokenRegistry, TokensGraphStorage, DB};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+// Total route liquidity (in USD) at which liquidity stops reducing confidence
+const CONFIDENT_LIQUIDITY_USD: f64 = 1_000_000.0;
+
+/// USD price of one whole token
+#[derive(Clone, Copy, Debug)]
+pub struct UsdPrice {
+    pub price: f64,
+    /// 0.0..=1.0, depends on routes liquidity and agreement between routes
+    pub confidence: f64,
+}
+
+// Price of the token observed in the single pool
+#[derive(Clone, Copy, Debug)]
+struct Quote {
+    price: f64,
+    liquidity_usd: f64,
+}
+
+#[derive(Default)]
+struct BlockCache {
+    block_number: u64,
+    prices: HashMap<Address, Option<UsdPrice>>,
+    /// Prices of hubs over stable routes only, shared by hub routes of all tokens
+    hubs: HashMap<Address, Option<UsdPrice>>,
+}
+
+impl BlockCache {
+    /// Cache of the block, prices of the previous block are dropped
+    fn at(&mut self, block_number: u64) -> &mut Self {
+        if self.block_number != block_number {
+            self.block_number = block_number;
+            self.prices.clear();
+            self.hubs.clear();
+        }
+        self
+    }
+}
+
+/// `PriceOracle` prices tokens in USD over routes `token -> stable`
+/// and `token -> hub -> stable` on every known dex.
+/// Quotes of all routes above liquidity floor are averaged by liquidity.
+/// Results are cached until the next block
+#[derive(Clone)]
+pub struct PriceOracle {
+    db: DB,
+    tokens: TokenRegistry,
+    dex_ids: Vec<i32>,
+    config: OracleConfig,
+    cache: Arc<Mutex<BlockCache>>,
+}
+
+impl PriceOracle {
+    pub async fn new(db: DB, tokens: TokenRegistry, config: OracleConfig) -> Result<Self> {
+        let dex_ids = db
+            .postgres()
+            .select_dexes()
+            .await?
+            .iter()
+            .map(|dex| dex.id)
+            .collect();
+
+        // decimals of stables and hubs are required for every route
+        tokens.ensure_tokens(&STABLE_COINS).await?;
+        tokens.ensure_tokens(&HUB_TOKENS).await?;
+
+        Ok(Self {
+            db,
+            tokens,
+            dex_ids,
+            config,
+            cache: Arc::new(Mutex::new(BlockCache::default())),
+        })
+    }
+
+    pub async fn usd_price(&self, block_number: u64, token: &Address) -> Result<UsdPrice> {
+        if let Some(cached) = self.cached(block_number, token) {
+            return cached.ok_or(MathError::NoRoute(*token));
+        }
+
+        let price = self.compute_price(block_number, token).await?;
+        self.cache
+            .lock()
+            .unwrap()
+            .at(block_number)
+            .prices
+            .insert(*token, price);
+
+        price.ok_or(MathError::NoRoute(*token))
+    }
+
+    /// Converts raw `amount` of token into USD
+    pub async fn amount_to_usd(
+        &self,
+        block_number: u64,
+        token: &Address,
+        amount: Uint<256, 4>,
+    ) -> Result<f64> {
+        let price = self.usd_price(block_number, token).await?;
+        Ok(self.whole_amount(token, amount)? * price.price)
+    }
+
+    fn cached(&self, block_number: u64, token: &Address) -> Option<Option<UsdPrice>> {
+        let mut cache = self.cache.lock().unwrap();
+        cache.at(block_number).prices.get(token).copied()
+    }
+
+    async fn compute_price(&self, block_number: u64, token: &Address) -> Result<Option<UsdPrice>> {
+        if STABLE_COINS.contains(token) {
+            return Ok(Some(UsdPrice {
+                price: 1.0,
+                confidence: 1.0,
+            }));
+        }
+
+        let mut quotes = self.stable_quotes(token).await?;
+
+        if !HUB_TOKENS.contains(token) {
+            for hub in HUB_TOKENS.iter() {
+                let Some(hub_price) = self.hub_price(block_number, hub).await? else {
+                    continue;
+                };
+
+                for quote in self.pool_quotes(token, hub).await? {
+                    quotes.push(Quote {
+                        price: quote.price * hub_price.price,
+                        liquidity_usd: quote.liquidity_usd * hub_price.price,
+                    });
+                }
+            }
+        }
+
+        quotes.retain(|quote| quote.liquidity_usd >= self.config.min_liquidity_usd);
+        Ok(aggregate(&quotes))
+    }
+
+    async fn hub_price(&self, block_number: u64, hub: &Address) -> Result<Option<UsdPrice>> {
+        let cached = self
+            .cache
+            .lock()
+            .unwrap()
+            .at(block_number)
+            .hubs
+            .get(hub)
+            .copied();
+        if let Some(price) = cached {
+            return Ok(price);
+        }
+
+        let mut quotes = self.stable_quotes(hub).await?;
+        quotes.retain(|quote| quote.liquidity_usd >= self.config.min_liquidity_usd);
+        let price = aggregate(&quotes);
+        self.cache
+            .lock()
+            .unwrap()
+            .at(block_number)
+            .hubs
+            .insert(*hub, price);
+        Ok(price)
+    }
+
+    async fn stable_quotes(&self, token: &Address) -> Result<Vec<Quote>> {
+        let mut quotes = vec![];
+        for stable in STABLE_COINS.iter() {
+            quotes.extend(self.pool_quotes(token, stable).await?);
+        }
+        Ok(quotes)
+    }
+
+    /// Price of `token` in `quote_token` on every dex which has pool with both tokens.
+    /// Liquidity is the value of both reserves in `quote_token` units
+    async fn pool_quotes(&self, token: &Address, quote_token: &Address) -> Result<Vec<Quote>> {
+        let mut quotes = vec![];
+
+        for dex_id in self.dex_ids.iter() {
+            if !self
+                .db
+                .adjacent_tokens(*dex_id, token)
+                .await?
+                .contains(quote_token)
+            {
+                continue;
+            }
+            // reserves may be not cached yet, such pool is skipped
+            let Ok(Reserves(r_token, r_quote)) =
+                self.db.reserves(*dex_id, token, quote_token).await
+            else {
+                continue;
+            };
+
+            let r_token = self.whole_amount(token, Uint::from(r_token))?;
+            let r_quote = self.whole_amount(quote_token, Uint::from(r_quote))?;
+            if r_token == 0.0 {
+                continue;
+            }
+
+            quotes.push(Quote {
+                price: r_quote / r_token,
+                liquidity_usd: 2.0 * r_quote,
+            });
+        }
+
+        Ok(quotes)
+    }
+
+    fn whole_amount(&self, token: &Address, amount: Uint<256, 4>) -> Result<f64> {
+        let decimals = self
+            .tokens
+            .decimals(token)
+            .ok_or(MathError::UnknownDecimals(*token))?;
+        Ok(f64::from(amount) / 10f64.powi(decimals as i32))
+    }
+}
+
+// Liquidity weighted average of quotes.
+// Confidence = liquidity score * (1 - weighted mean relative deviation)
+fn aggregate(quotes: &[Quote]) -> Option<UsdPrice> {
+    let total_liquidity: f64 = quotes.iter().map(|q| q.liquidity_usd).sum();
+    if quotes.is_empty() || total_liquidity <= 0.0 {
+        return None;
+    }
+
+    let price = quotes
+        .iter()
+        .map(|q| q.price * q.liquidity_usd)
+        .sum::<f64>()
+        / total_liquidity;
+
+    let deviation = quotes
+        .iter()
+        .map(|q| (q.price - price).abs() / price * q.liquidity_usd)
+        .sum::<f64>()
+        / total_liquidity;
+
+    let liquidity_score = (total_liquidity / CONFIDENT_LIQUIDITY_USD).min(1.0);
+    let confidence = liquidity_score * (1.0 - deviation.min(1.0));
+
+    Some(UsdPrice { price, confidence })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(price: f64, liquidity_usd: f64) -> Quote {
+        Quote {
+            price,
+            liquidity_usd,
+        }
+    }
+
+    #[test]
+    fn no_quotes_have_no_price() {
+        assert!(aggregate(&[]).is_none());
+        assert!(aggregate(&[quote(1.0, 0.0)]).is_none());
+    }
+
+    #[test]
+    fn price_is_weighted_by_liquidity() {
+        let price = aggregate(&[quote(2_000.0, 3_000_000.0), quote(1_000.0, 1_000_000.0)]).unwrap();
+        assert_eq!(price.price, 1_750.0);
+        // deviations 250 / 1750 and 750 / 1750 weighted 3:1
+        let deviation = (3.0 * 250.0 + 750.0) / 1_750.0 / 4.0;
+        assert!((price.confidence - (1.0 - deviation)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn confidence_grows_with_liquidity_and_agreement() {
+        let deep = aggregate(&[quote(1.0, 600_000.0), quote(1.0, 600_000.0)]).unwrap();
+        assert_eq!(deep.price, 1.0);
+        assert_eq!(deep.confidence, 1.0);
+
+        let shallow = aggregate(&[quote(1.0, 100_000.0), quote(1.0, 150_000.0)]).unwrap();
+        assert_eq!(shallow.confidence, 0.25);
+
+        // quote far from the mean cannot make confidence negative
+        let outlier = aggregate(&[quote(1.0, 1_000_000.0), quote(100.0, 1_000_000.0)]).unwrap();
+        assert!(outlier.confidence >= 0.0);
+        assert!(outlier.confidence < deep.confidence);
+    }
+}
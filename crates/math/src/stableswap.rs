@@ -0,0 +1,339 @@
+//! Curve StableSwap invariant, ported from the Vyper pools with the same integer rounding.
+//! Legacy template is `StableSwap3Pool.vy`, factory template covers plain and meta pools
+use alloy::primitives::U256;
+
+pub const PRECISION: U256 = U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0]);
+pub const FEE_DENOMINATOR: U256 = U256::from_limbs([10_000_000_000, 0, 0, 0]);
+
+const MAX_ITERATIONS: usize = 255;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Template {
+    /// 3pool era pools: `A` is stored without precision, fee is taken after rates are removed.
+    /// Newton iterations return the last value if they do not converge
+    Legacy,
+    /// Factory plain and meta pools: `A_PRECISION = 100`, fee is taken from scaled `dy`.
+    /// Newton iterations revert if they do not converge
+    Factory,
+}
+
+impl Template {
+    pub fn a_precision(&self) -> U256 {
+        match self {
+            Template::Legacy => U256::from(1),
+            Template::Factory => U256::from(100),
+        }
+    }
+}
+
+/// Linear ramp of `A` as it is stored in the pool, values are in units of `A_PRECISION`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AmpRamp {
+    pub initial_a: U256,
+    pub future_a: U256,
+    pub initial_time: u64,
+    pub future_time: u64,
+}
+
+impl AmpRamp {
+    /// `_A()` of the pool at the block timestamp
+    pub fn a(&self, timestamp: u64) -> U256 {
+        if timestamp >= self.future_time {
+            return self.future_a;
+        }
+
+        let elapsed = U256::from(timestamp.saturating_sub(self.initial_time));
+        let duration = U256::from(self.future_time - self.initial_time);
+        match self.future_a > self.initial_a {
+            true => self.initial_a + (self.future_a - self.initial_a) * elapsed / duration,
+            false => self.initial_a - (self.initial_a - self.future_a) * elapsed / duration,
+        }
+    }
+}
+
+/// State of the pool which is required for `get_dy`
+#[derive(Clone, Debug)]
+pub struct StableSwapPool {
+    pub template: Template,
+    pub balances: Vec<U256>,
+    /// `10^(36 - decimals)` for plain coins, virtual price of base pool for LP coin of metapool
+    pub rates: Vec<U256>,
+    /// `A * A_PRECISION`
+    pub amp: U256,
+    pub fee: U256,
+}
+
+impl StableSwapPool {
+    pub fn n_coins(&self) -> usize {
+        self.balances.len()
+    }
+
+    fn xp(&self) -> Vec<U256> {
+        self.rates
+            .iter()
+            .zip(self.balances.iter())
+            .map(|(rate, balance)| rate * balance / PRECISION)
+            .collect()
+    }
+
+    /// Amount of `j` coin received for `dx` of `i` coin, `None` if the pool would revert
+    pub fn get_dy(&self, i: usize, j: usize, dx: U256) -> Option<U256> {
+        let n = self.n_coins();
+        if i == j || i >= n || j >= n {
+            return None;
+        }
+
+        let xp = self.xp();
+        let x = xp[i] + dx * self.rates[i] / PRECISION;
+        let y = get_y(i, j, x, &xp, self.amp, self.template)?;
+        let dy = xp[j].checked_sub(y)?.checked_sub(U256::from(1))?;
+
+        match self.template {
+            Template::Legacy => {
+                let dy = dy * PRECISION / self.rates[j];
+                let fee = self.fee * dy / FEE_DENOMINATOR;
+                Some(dy - fee)
+            }
+            Template::Factory => {
+                let fee = self.fee * dy / FEE_DENOMINATOR;
+                Some((dy - fee) * PRECISION / self.rates[j])
+            }
+        }
+    }
+}
+
+/// StableSwap invariant `D` for normalized balances
+pub fn get_d(xp: &[U256], amp: U256, template: Template) -> Option<U256> {
+    let n = U256::from(xp.len());
+    let a_precision = template.a_precision();
+
+    let s: U256 = xp.iter().sum();
+    if s.is_zero() {
+        return Some(U256::ZERO);
+    }
+
+    let mut d = s;
+    let ann = amp * n;
+    for _ in 0..MAX_ITERATIONS {
+        let mut d_p = d;
+        for x in xp {
+            d_p = d_p * d / x.checked_mul(n).filter(|x| !x.is_zero())?;
+        }
+        let d_prev = d;
+        d = (ann * s / a_precision + d_p * n) * d
+            / ((ann.checked_sub(a_precision)?) * d / a_precision + (n + U256::from(1)) * d_p);
+
+        if converged(d, d_prev) {
+            return Some(d);
+        }
+    }
+
+    match template {
+        Template::Legacy => Some(d),
+        Template::Factory => None,
+    }
+}
+
+/// New normalized balance of `j` coin if balance of `i` coin becomes `x`
+pub fn get_y(
+    i: usize,
+    j: usize,
+    x: U256,
+    xp: &[U256],
+    amp: U256,
+    template: Template,
+) -> Option<U256> {
+    let n = U256::from(xp.len());
+    let a_precision = template.a_precision();
+
+    let d = get_d(xp, amp, template)?;
+    let ann = amp * n;
+
+    let mut c = d;
+    let mut s = U256::ZERO;
+    for (k, balance) in xp.iter().enumerate() {
+        let x = match k {
+            k if k == i => x,
+            k if k != j => *balance,
+            _ => continue,
+        };
+        s += x;
+        c = c * d / x.checked_mul(n).filter(|x| !x.is_zero())?;
+    }
+    c = c * d * a_precision / (ann * n);
+    let b = s + d * a_precision / ann;
+
+    let mut y = d;
+    for _ in 0..MAX_ITERATIONS {
+        let y_prev = y;
+        y = (y * y + c) / (U256::from(2) * y + b).checked_sub(d)?;
+
+        if converged(y, y_prev) {
+            return Some(y);
+        }
+    }
+
+    match template {
+        Template::Legacy => Some(y),
+        Template::Factory => None,
+    }
+}
+
+// Equality with the precision of 1
+fn converged(value: U256, prev: U256) -> bool {
+    match value > prev {
+        true => value - prev <= U256::from(1),
+        false => prev - value <= U256::from(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Expected values are computed by `get_D`, `get_y` and `get_dy` of `StableSwap3Pool.vy`
+    // and of the factory plain and meta templates, executed line by line with unbounded
+    // integers and floor division as in Vyper, so the port must match them exactly.
+    // On-chain `get_dy` of live pools is checked by `tests/onchain.rs`
+
+    fn u(value: &str) -> U256 {
+        value.parse().unwrap()
+    }
+
+    fn three_pool() -> StableSwapPool {
+        StableSwapPool {
+            template: Template::Legacy,
+            balances: vec![
+                u("52345678123456789012345678"),
+                u("61234567891234"),
+                u("48765432109876"),
+            ],
+            rates: vec![
+                u("1000000000000000000"),
+                u("1000000000000000000000000000000"),
+                u("1000000000000000000000000000000"),
+            ],
+            amp: U256::from(2000),
+            fee: U256::from(1_000_000),
+        }
+    }
+
+    fn metapool() -> StableSwapPool {
+        StableSwapPool {
+            template: Template::Factory,
+            balances: vec![
+                u("12345678000000000000000123"),
+                u("11111111000000000000000456"),
+            ],
+            rates: vec![u("1000000000000000000"), u("1032123456789012345")],
+            amp: U256::from(200 * 100),
+            fee: U256::from(4_000_000),
+        }
+    }
+
+    #[test]
+    fn three_pool_invariant_matches_vyper() {
+        let pool = three_pool();
+        assert_eq!(
+            get_d(&pool.xp(), pool.amp, pool.template),
+            Some(u("162345303872901789853094581"))
+        );
+    }
+
+    #[test]
+    fn three_pool_get_dy_matches_vyper() {
+        let pool = three_pool();
+        let vectors = [
+            (0, 1, "1000000000000000000000000", "999967310762"),
+            (1, 2, "250000000000", "249945777542"),
+            (2, 0, "5000000000000", "4999423553382457381350767"),
+            (0, 2, "1000000000000000000", "999862"),
+        ];
+        for (i, j, dx, dy) in vectors {
+            assert_eq!(pool.get_dy(i, j, u(dx)), Some(u(dy)));
+        }
+    }
+
+    #[test]
+    fn imbalanced_legacy_pool_get_dy_matches_vyper() {
+        let pool = StableSwapPool {
+            balances: vec![
+                u("900000000000000000000000000"),
+                u("1000000000000"),
+                u("50000000000000"),
+            ],
+            amp: U256::from(500),
+            fee: U256::from(4_000_000),
+            ..three_pool()
+        };
+        assert_eq!(
+            pool.get_dy(1, 0, u("100000000000")),
+            Some(u("14354698358617048966709988"))
+        );
+        assert_eq!(
+            pool.get_dy(0, 1, u("100000000000000000000000")),
+            Some(u("641199206"))
+        );
+    }
+
+    #[test]
+    fn metapool_get_dy_matches_vyper() {
+        let pool = metapool();
+        assert_eq!(
+            get_d(&pool.xp(), pool.amp, pool.template),
+            Some(u("23813635725604566182425416"))
+        );
+        assert_eq!(
+            pool.get_dy(0, 1, u("100000000000000000000000")),
+            Some(u("96809192117162043583507"))
+        );
+        assert_eq!(
+            pool.get_dy(1, 0, u("2500000000000000000000000")),
+            Some(u("2577380073560081103617168"))
+        );
+    }
+
+    #[test]
+    fn factory_plain_pool_get_dy_matches_vyper() {
+        let pool = StableSwapPool {
+            template: Template::Factory,
+            balances: vec![u("3000000000000"), u("2900000000000000000000000")],
+            rates: vec![
+                u("1000000000000000000000000000000"),
+                u("1000000000000000000"),
+            ],
+            amp: U256::from(15_050),
+            fee: U256::from(1_000_000),
+        };
+        assert_eq!(
+            pool.get_dy(0, 1, u("1000000000")),
+            Some(u("999673928733591399374"))
+        );
+    }
+
+    #[test]
+    fn amp_ramps_linearly_in_both_directions() {
+        let ramp = AmpRamp {
+            initial_a: U256::from(1000),
+            future_a: U256::from(2000),
+            initial_time: 100,
+            future_time: 200,
+        };
+        assert_eq!(ramp.a(150), U256::from(1500));
+        assert_eq!(ramp.a(300), U256::from(2000));
+
+        let ramp = AmpRamp {
+            initial_a: U256::from(2000),
+            future_a: U256::from(1000),
+            ..ramp
+        };
+        assert_eq!(ramp.a(175), U256::from(1250));
+    }
+
+    #[test]
+    fn rejects_same_and_unknown_coins() {
+        let pool = three_pool();
+        assert_eq!(pool.get_dy(0, 0, U256::from(1)), None);
+        assert_eq!(pool.get_dy(0, 3, U256::from(1)), None);
+    }
+}
@@ -0,0 +1,460 @@
+//! Balancer V2 weighted math with the same fixed-point rounding as `FixedPoint.sol`,
+//! `LogExpMath.sol` and `WeightedMath.sol`
+use alloy::primitives::U256;
+
+pub const ONE: U256 = U256::from_limbs([1_000_000_000_000_000_000, 0, 0, 0]);
+
+// Swap can not take more than 30% of balance in
+const MAX_IN_RATIO: U256 = U256::from_limbs([300_000_000_000_000_000, 0, 0, 0]);
+const MAX_POW_RELATIVE_ERROR: U256 = U256::from_limbs([10_000, 0, 0, 0]);
+
+/// Weighted pool with balances as they are stored in the vault
+#[derive(Clone, Debug)]
+pub struct WeightedPool {
+    pub balances: Vec<U256>,
+    /// Normalized weights, sum is `ONE`
+    pub weights: Vec<U256>,
+    /// `10^(18 - decimals)` of every token
+    pub scaling_factors: Vec<U256>,
+    pub swap_fee: U256,
+}
+
+impl WeightedPool {
+    /// `onSwap` with `GIVEN_IN` kind: amount of `j` token out for `amount_in` of `i` token.
+    /// `None` if the pool would revert
+    pub fn swap_given_in(&self, i: usize, j: usize, amount_in: U256) -> Option<U256> {
+        let n = self.balances.len();
+        if i == j || i >= n || j >= n {
+            return None;
+        }
+
+        let amount_in = amount_in.checked_sub(mul_up(amount_in, self.swap_fee))?;
+        let amount_out = calc_out_given_in(
+            self.balances[i] * self.scaling_factors[i],
+            self.weights[i],
+            self.balances[j] * self.scaling_factors[j],
+            self.weights[j],
+            amount_in * self.scaling_factors[i],
+        )?;
+
+        Some(amount_out / self.scaling_factors[j])
+    }
+}
+
+/// `WeightedMath._calcOutGivenIn` for upscaled balances
+pub fn calc_out_given_in(
+    balance_in: U256,
+    weight_in: U256,
+    balance_out: U256,
+    weight_out: U256,
+    amount_in: U256,
+) -> Option<U256> {
+    if amount_in > mul_down(balance_in, MAX_IN_RATIO) || weight_out.is_zero() {
+        return None;
+    }
+
+    let denominator = balance_in + amount_in;
+    let base = div_up(balance_in, denominator);
+    let exponent = div_down(weight_in, weight_out);
+    let power = pow_up(base, exponent)?;
+
+    Some(mul_down(balance_out, complement(power)))
+}
+
+pub fn mul_down(a: U256, b: U256) -> U256 {
+    a * b / ONE
+}
+
+pub fn mul_up(a: U256, b: U256) -> U256 {
+    let product = a * b;
+    match product.is_zero() {
+        true => U256::ZERO,
+        false => (product - U256::from(1)) / ONE + U256::from(1),
+    }
+}
+
+pub fn div_down(a: U256, b: U256) -> U256 {
+    a * ONE / b
+}
+
+pub fn div_up(a: U256, b: U256) -> U256 {
+    match a.is_zero() {
+        true => U256::ZERO,
+        false => (a * ONE - U256::from(1)) / b + U256::from(1),
+    }
+}
+
+pub fn complement(x: U256) -> U256 {
+    match x < ONE {
+        true => ONE - x,
+        false => U256::ZERO,
+    }
+}
+
+/// `x^y` rounded up, exponents 1, 2 and 4 are computed exactly
+pub fn pow_up(x: U256, y: U256) -> Option<U256> {
+    if y == ONE {
+        Some(x)
+    } else if y == ONE * U256::from(2) {
+        Some(mul_up(x, x))
+    } else if y == ONE * U256::from(4) {
+        let square = mul_up(x, x);
+        Some(mul_up(square, square))
+    } else {
+        let raw = log_exp::pow(x, y)?;
+        let max_error = mul_up(raw, MAX_POW_RELATIVE_ERROR) + U256::from(1);
+        Some(raw + max_error)
+    }
+}
+
+/// Port of `LogExpMath.sol`. Signed division truncates toward zero as in Solidity
+pub mod log_exp {
+    use alloy::primitives::{I256, U256};
+
+    const fn int(value: u128) -> I256 {
+        I256::from_raw(U256::from_limbs([value as u64, (value >> 64) as u64, 0, 0]))
+    }
+
+    const ONE_18: I256 = int(1_000_000_000_000_000_000);
+    const ONE_20: I256 = int(100_000_000_000_000_000_000);
+    const ONE_36: I256 = int(1_000_000_000_000_000_000_000_000_000_000_000_000);
+
+    const MAX_NATURAL_EXPONENT: I256 = int(130_000_000_000_000_000_000);
+    // absolute value of `MIN_NATURAL_EXPONENT = -41e18`
+    const MIN_NATURAL_EXPONENT_ABS: I256 = int(41_000_000_000_000_000_000);
+
+    const LN_36_LOWER_BOUND: I256 = int(900_000_000_000_000_000);
+    const LN_36_UPPER_BOUND: I256 = int(1_100_000_000_000_000_000);
+
+    // 18 decimal constants
+    const X0: I256 = int(128_000_000_000_000_000_000);
+    const A0: I256 = I256::from_raw(U256::from_limbs([
+        171843153341448192,
+        17670479068478958691,
+        114249481722274167,
+        0,
+    ]));
+    const X1: I256 = int(64_000_000_000_000_000_000);
+    const A1: I256 = int(6235149080811616882910000000);
+
+    // 20 decimal constants, (x_n, e^x_n)
+    const TERMS: [(I256, I256); 10] = [
+        (
+            int(3200000000000000000000),
+            int(7896296018268069516100000000000000),
+        ),
+        (
+            int(1600000000000000000000),
+            int(888611052050787263676000000),
+        ),
+        (int(800000000000000000000), int(298095798704172827474000)),
+        (int(400000000000000000000), int(5459815003314423907810)),
+        (int(200000000000000000000), int(738905609893065022723)),
+        (int(100000000000000000000), int(271828182845904523536)),
+        (int(50000000000000000000), int(164872127070012814685)),
+        (int(25000000000000000000), int(128402541668774148407)),
+        (int(12500000000000000000), int(113314845306682631683)),
+        (int(6250000000000000000), int(106449445891785942956)),
+    ];
+    // `exp` uses terms up to x9, `ln` up to x11
+    const EXP_TERMS: usize = 8;
+
+    /// `x^y` with 18 decimals, `None` if out of bounds
+    pub fn pow(x: U256, y: U256) -> Option<U256> {
+        if y.is_zero() {
+            return Some(ONE_18.into_raw());
+        }
+        if x.is_zero() {
+            return Some(U256::ZERO);
+        }
+        let mild_exponent_bound = (U256::from(1) << 254) / ONE_20.into_raw();
+        if x.bit(255) || y >= mild_exponent_bound {
+            return None;
+        }
+
+        let x = I256::from_raw(x);
+        let y = I256::from_raw(y);
+
+        let logx_times_y = if LN_36_LOWER_BOUND < x && x < LN_36_UPPER_BOUND {
+            let ln_36_x = ln_36(x);
+            (ln_36_x / ONE_18) * y + ((ln_36_x % ONE_18) * y) / ONE_18
+        } else {
+            ln(x) * y
+        } / ONE_18;
+
+        if logx_times_y < -MIN_NATURAL_EXPONENT_ABS || logx_times_y > MAX_NATURAL_EXPONENT {
+            return None;
+        }
+        Some(exp(logx_times_y)?.into_raw())
+    }
+
+    /// `e^x` with 18 decimals
+    pub fn exp(x: I256) -> Option<I256> {
+        if x < -MIN_NATURAL_EXPONENT_ABS || x > MAX_NATURAL_EXPONENT {
+            return None;
+        }
+        if x.is_negative() {
+            return Some((ONE_18 * ONE_18) / exp(-x)?);
+        }
+
+        let mut x = x;
+        let first_an = if x >= X0 {
+            x -= X0;
+            A0
+        } else if x >= X1 {
+            x -= X1;
+            A1
+        } else {
+            I256::ONE
+        };
+
+        // 20 decimals from here
+        x *= int(100);
+
+        let mut product = ONE_20;
+        for (x_n, a_n) in TERMS.iter().take(EXP_TERMS) {
+            if x >= *x_n {
+                x -= *x_n;
+                product = (product * *a_n) / ONE_20;
+            }
+        }
+
+        // Taylor series up to 12th term
+        let mut series_sum = ONE_20;
+        let mut term = x;
+        series_sum += term;
+        for k in 2..=12u128 {
+            term = ((term * x) / ONE_20) / int(k);
+            series_sum += term;
+        }
+
+        Some((((product * series_sum) / ONE_20) * first_an) / int(100))
+    }
+
+    /// Natural logarithm with 18 decimals
+    pub fn ln(a: I256) -> I256 {
+        if a < ONE_18 {
+            return -ln((ONE_18 * ONE_18) / a);
+        }
+
+        let mut a = a;
+        let mut sum = I256::ZERO;
+        if a >= A0 * ONE_18 {
+            a /= A0;
+            sum += X0;
+        }
+        if a >= A1 * ONE_18 {
+            a /= A1;
+            sum += X1;
+        }
+
+        // 20 decimals from here
+        sum *= int(100);
+        a *= int(100);
+
+        for (x_n, a_n) in TERMS.iter() {
+            if a >= *a_n {
+                a = (a * ONE_20) / *a_n;
+                sum += *x_n;
+            }
+        }
+
+        // ln(a) = 2 * (z + z^3 / 3 + z^5 / 5 + ...), z = (a - 1) / (a + 1)
+        let z = ((a - ONE_20) * ONE_20) / (a + ONE_20);
+        let z_squared = (z * z) / ONE_20;
+
+        let mut num = z;
+        let mut series_sum = num;
+        for k in [3u128, 5, 7, 9, 11] {
+            num = (num * z_squared) / ONE_20;
+            series_sum += num / int(k);
+        }
+        series_sum *= int(2);
+
+        (sum + series_sum) / int(100)
+    }
+
+    // Natural logarithm with 36 decimals for `x` close to one
+    fn ln_36(x: I256) -> I256 {
+        let x = x * ONE_18;
+
+        let z = ((x - ONE_36) * ONE_36) / (x + ONE_36);
+        let z_squared = (z * z) / ONE_36;
+
+        let mut num = z;
+        let mut series_sum = num;
+        for k in [3u128, 5, 7, 9, 11, 13, 15] {
+            num = (num * z_squared) / ONE_36;
+            series_sum += num / int(k);
+        }
+
+        series_sum * int(2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::I256;
+
+    // Expected values are exact real values of the formulas for the same inputs
+    // (mpmath with 60 digits), rounded down. `LogExpMath` is within its documented relative
+    // error of them, swaps round in favor of the pool within the error bound of `pow_up`.
+    // Exact equality with on-chain `queryBatchSwap` is checked by `tests/onchain.rs`
+
+    fn u(value: &str) -> U256 {
+        value.parse().unwrap()
+    }
+
+    fn e18(value: u128) -> U256 {
+        U256::from(value) * ONE
+    }
+
+    fn abs_diff(a: U256, b: U256) -> U256 {
+        match a > b {
+            true => a - b,
+            false => b - a,
+        }
+    }
+
+    fn assert_relative(actual: U256, expected: &str) {
+        let expected = u(expected);
+        let tolerance = mul_up(expected, MAX_POW_RELATIVE_ERROR) + U256::from(1);
+        assert!(
+            abs_diff(actual, expected) <= tolerance,
+            "{actual} is not close to {expected}"
+        );
+    }
+
+    // output never exceeds the exact value and is less by at most the `pow_up` error
+    // of the balance out and one unit of the token
+    fn assert_rounds_down(actual: Option<U256>, exact: &str, balance_out: U256) {
+        let (actual, exact) = (actual.unwrap(), u(exact));
+        let tolerance = mul_up(balance_out, MAX_POW_RELATIVE_ERROR * U256::from(2)) + U256::from(1);
+        assert!(actual <= exact, "{actual} is above {exact}");
+        assert!(
+            exact - actual <= tolerance,
+            "{actual} is too far below {exact}"
+        );
+    }
+
+    #[test]
+    fn log_exp_is_within_its_relative_error() {
+        assert_relative(
+            log_exp::exp(I256::from_raw(ONE)).unwrap().into_raw(),
+            "2718281828459045235",
+        );
+        assert_relative(
+            log_exp::exp(-I256::from_raw(u("3500000000000000000")))
+                .unwrap()
+                .into_raw(),
+            "30197383422318500",
+        );
+        assert_relative(
+            log_exp::ln(I256::from_raw(e18(5))).into_raw(),
+            "1609437912434100374",
+        );
+    }
+
+    #[test]
+    fn pow_is_within_its_relative_error_and_pow_up_rounds_up() {
+        let vectors = [
+            (
+                "2000000000000000000",
+                "500000000000000000",
+                "1414213562373095048",
+            ),
+            (
+                "950000000000000000",
+                "4000000000000000000",
+                "814506249999999847",
+            ),
+            (
+                "950000000000000000",
+                "250000000000000000",
+                "987258544901433794",
+            ),
+        ];
+        for (x, y, expected) in vectors {
+            assert_relative(log_exp::pow(u(x), u(y)).unwrap(), expected);
+            // rounded up power is never below the exact one
+            assert!(pow_up(u(x), u(y)).unwrap() >= u(expected), "{x}^{y}");
+        }
+    }
+
+    #[test]
+    fn swap_in_80_20_pool_rounds_down() {
+        let pool = WeightedPool {
+            balances: vec![
+                e18(25_000_000) + U256::from(123),
+                e18(8_500) + U256::from(456),
+            ],
+            weights: vec![u("800000000000000000"), u("200000000000000000")],
+            scaling_factors: vec![U256::from(1), U256::from(1)],
+            swap_fee: u("10000000000000000"),
+        };
+        assert_rounds_down(
+            pool.swap_given_in(1, 0, e18(10)),
+            "7274117405583836893871",
+            pool.balances[0],
+        );
+        assert_rounds_down(
+            pool.swap_given_in(0, 1, e18(100_000)),
+            "133317548154925344377",
+            pool.balances[1],
+        );
+    }
+
+    #[test]
+    fn swap_in_50_50_pool_scales_decimals() {
+        let pool = WeightedPool {
+            balances: vec![e18(1_000), U256::from(2_500_000_000_000u128)],
+            weights: vec![u("500000000000000000"), u("500000000000000000")],
+            scaling_factors: vec![U256::from(1), U256::from(10).pow(U256::from(12))],
+            swap_fee: u("3000000000000000"),
+        };
+        assert_rounds_down(
+            pool.swap_given_in(0, 1, e18(1)),
+            "2490017452",
+            pool.balances[1],
+        );
+    }
+
+    #[test]
+    fn swap_in_three_token_pool_rounds_down() {
+        let pool = WeightedPool {
+            balances: vec![
+                U256::from(12_000_000_000u128),
+                e18(2_000),
+                U256::from(3_600_000_000_000u128),
+            ],
+            weights: vec![
+                u("333333333333333333"),
+                u("333333333333333333"),
+                u("333333333333333334"),
+            ],
+            scaling_factors: vec![
+                U256::from(10).pow(U256::from(10)),
+                U256::from(1),
+                U256::from(10).pow(U256::from(12)),
+            ],
+            swap_fee: u("2500000000000000"),
+        };
+        assert_rounds_down(
+            pool.swap_given_in(0, 2, U256::from(100_000_000)),
+            "29678299138",
+            pool.balances[2],
+        );
+    }
+
+    #[test]
+    fn rejects_amounts_over_max_in_ratio() {
+        let pool = WeightedPool {
+            balances: vec![e18(100), e18(100)],
+            weights: vec![u("500000000000000000"), u("500000000000000000")],
+            scaling_factors: vec![U256::from(1), U256::from(1)],
+            swap_fee: U256::ZERO,
+        };
+        assert_eq!(pool.swap_given_in(0, 1, e18(31)), None);
+    }
+}
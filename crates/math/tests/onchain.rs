@@ -0,0 +1,249 @@
+//! Pool math against on-chain quotes of pinned blocks,
+//! run with `RPC_URL=<archive node> cargo test -p kronos-math -- --ignored`
+
+use alloy::{
+    eips::BlockId,
+    primitives::{
+        address,
+        aliases::{I24, U24},
+        b256, keccak256, Address, B256, U256,
+    },
+    providers::{ProviderBuilder, RootProvider},
+    sol_types::SolValue,
+};
+use ethereum_abi::{
+    IBalancerVault::{self, BatchSwapStep, FundManagement, SwapKind},
+    ICurvePool, IStateView,
+    IV4Quoter::{self, PoolKey, QuoteExactSingleParams},
+    IWeightedPool,
+};
+use kronos_math::{
+    concentrated::{ConcentratedPool, TickInfo},
+    stableswap::{StableSwapPool, Template},
+    weighted::WeightedPool,
+};
+
+const BLOCK: u64 = 19_000_000;
+
+// DAI, USDC, USDT
+const THREE_POOL: Address = address!("0xbEbc44782C7dB0a1A60Cb6fe97d0b483032FF1C7");
+
+async fn provider() -> RootProvider {
+    let url = std::env::var("RPC_URL").expect("RPC_URL of an archive node");
+    ProviderBuilder::default().connect(&url).await.unwrap()
+}
+
+fn units(amount: u64, decimals: u8) -> U256 {
+    U256::from(amount) * U256::from(10).pow(U256::from(decimals))
+}
+
+#[tokio::test]
+#[ignore = "requires archive node"]
+async fn curve_get_dy_matches_three_pool() {
+    let block = BlockId::number(BLOCK);
+    let pool = ICurvePool::new(THREE_POOL, provider().await);
+
+    let mut balances = vec![];
+    for i in 0..3 {
+        let balance = pool.balances(U256::from(i)).block(block).call().await;
+        balances.push(balance.unwrap()._0);
+    }
+    let state = StableSwapPool {
+        template: Template::Legacy,
+        balances,
+        rates: vec![units(1, 18), units(1, 30), units(1, 30)],
+        amp: pool.A().block(block).call().await.unwrap()._0,
+        fee: pool.fee().block(block).call().await.unwrap()._0,
+    };
+
+    let vectors = [
+        (0, 1, units(1_000, 18)),
+        (1, 2, units(1_000_000, 6)),
+        (2, 0, units(10, 6)),
+        (0, 2, units(50_000_000, 18)),
+    ];
+    for (i, j, dx) in vectors {
+        let onchain = pool
+            .get_dy(i as i128, j as i128, dx)
+            .block(block)
+            .call()
+            .await
+            .unwrap()
+            ._0;
+        assert_eq!(state.get_dy(i, j, dx), Some(onchain), "{i} -> {j}");
+    }
+}
+
+// BAL/WETH 80/20
+const BAL_WETH_POOL_ID: B256 =
+    b256!("0x5c6ee304399dbdb9c8ef030ab642b10820db8f56000200000000000000000014");
+const BALANCER_VAULT: Address = address!("0xBA12222222228d8Ba445958a75a0704d566BF2C8");
+
+#[tokio::test]
+#[ignore = "requires archive node"]
+async fn balancer_swap_given_in_matches_vault_query() {
+    let block = BlockId::number(BLOCK);
+    let provider = provider().await;
+    let vault = IBalancerVault::new(BALANCER_VAULT, &provider);
+    let pool = IWeightedPool::new(Address::from_slice(&BAL_WETH_POOL_ID[..20]), &provider);
+
+    let tokens = vault
+        .getPoolTokens(BAL_WETH_POOL_ID)
+        .block(block)
+        .call()
+        .await
+        .unwrap();
+    let state = WeightedPool {
+        balances: tokens.balances,
+        weights: pool
+            .getNormalizedWeights()
+            .block(block)
+            .call()
+            .await
+            .unwrap()
+            ._0,
+        // BAL and WETH have 18 decimals
+        scaling_factors: vec![U256::from(1); 2],
+        swap_fee: pool
+            .getSwapFeePercentage()
+            .block(block)
+            .call()
+            .await
+            .unwrap()
+            ._0,
+    };
+
+    let funds = FundManagement {
+        sender: Address::ZERO,
+        fromInternalBalance: false,
+        recipient: Address::ZERO,
+        toInternalBalance: false,
+    };
+    let vectors = [
+        (0, 1, units(1, 18)),
+        (1, 0, units(10, 18)),
+        (0, 1, units(100_000, 18)),
+    ];
+    for (i, j, amount) in vectors {
+        let step = BatchSwapStep {
+            poolId: BAL_WETH_POOL_ID,
+            assetInIndex: U256::from(i),
+            assetOutIndex: U256::from(j),
+            amount,
+            userData: Default::default(),
+        };
+        let deltas = vault
+            .queryBatchSwap(
+                SwapKind::GIVEN_IN,
+                vec![step],
+                tokens.tokens.clone(),
+                funds.clone(),
+            )
+            .block(block)
+            .call()
+            .await
+            .unwrap()
+            .assetDeltas;
+        let onchain = U256::try_from(-deltas[j]).unwrap();
+        assert_eq!(
+            state.swap_given_in(i, j, amount),
+            Some(onchain),
+            "{i} -> {j}"
+        );
+    }
+}
+
+// Uniswap V4 is deployed after `BLOCK`
+const V4_BLOCK: u64 = 22_000_000;
+const STATE_VIEW: Address = address!("0x7fFE42C4a5DEeA5b0feC41C94C136Cf115597227");
+const V4_QUOTER: Address = address!("0x52F0E24D1c21C8A0cB1e5a5dD6198556BD9E1203");
+const USDC: Address = address!("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+// Initialized ticks are read this many tick spacings around the price,
+// swaps of the vectors stay within them
+const TICK_RANGE: i32 = 100;
+
+#[tokio::test]
+#[ignore = "requires archive node"]
+async fn uniswap_v4_swap_matches_quoter() {
+    let block = BlockId::number(V4_BLOCK);
+    let provider = provider().await;
+    let state_view = IStateView::new(STATE_VIEW, &provider);
+    let quoter = IV4Quoter::new(V4_QUOTER, &provider);
+
+    // ETH/USDC 0.05% without hooks
+    let tick_spacing = 10;
+    let key = PoolKey {
+        currency0: Address::ZERO,
+        currency1: USDC,
+        fee: U24::from(500),
+        tickSpacing: I24::try_from(tick_spacing).unwrap(),
+        hooks: Address::ZERO,
+    };
+    let pool_id = keccak256(key.abi_encode());
+
+    let slot0 = state_view
+        .getSlot0(pool_id)
+        .block(block)
+        .call()
+        .await
+        .unwrap();
+    let mut state = ConcentratedPool::new(
+        U256::from(slot0.sqrtPriceX96),
+        slot0.tick.as_i32(),
+        slot0.lpFee.to(),
+        tick_spacing,
+    );
+    state.protocol_fee = slot0.protocolFee.to();
+    state.liquidity = state_view
+        .getLiquidity(pool_id)
+        .block(block)
+        .call()
+        .await
+        .unwrap()
+        .liquidity;
+
+    let center = state.tick.div_euclid(tick_spacing) * tick_spacing;
+    for k in -TICK_RANGE..=TICK_RANGE {
+        let tick = center + k * tick_spacing;
+        let info = state_view
+            .getTickLiquidity(pool_id, I24::try_from(tick).unwrap())
+            .block(block)
+            .call()
+            .await
+            .unwrap();
+        if info.liquidityGross > 0 {
+            let info = TickInfo {
+                liquidity_gross: info.liquidityGross,
+                liquidity_net: info.liquidityNet,
+            };
+            state.ticks.insert(tick, info);
+        }
+    }
+
+    let vectors = [
+        (true, units(1, 17)),
+        (true, units(100, 18)),
+        (false, units(1_000, 6)),
+        (false, units(1_000_000, 6)),
+    ];
+    for (zero_for_one, amount_in) in vectors {
+        let params = QuoteExactSingleParams {
+            poolKey: key.clone(),
+            zeroForOne: zero_for_one,
+            exactAmount: amount_in.to(),
+            hookData: Default::default(),
+        };
+        let onchain = quoter
+            .quoteExactInputSingle(params)
+            .block(block)
+            .call()
+            .await
+            .unwrap()
+            .amountOut;
+        assert_eq!(
+            state.swap_exact_in(zero_for_one, amount_in),
+            Some(onchain),
+            "{zero_for_one} {amount_in}"
+        );
+    }
+}
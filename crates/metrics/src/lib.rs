@@ -0,0 +1,221 @@
+use prometheus::{
+    exponential_buckets, Encoder, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramTimer,
+    HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::sync::LazyLock;
+
+pub mod rpc;
+pub mod server;
+
+pub use prometheus;
+pub use rpc::RpcMetricsLayer;
+pub use server::serve;
+
+/// All metrics of the bot, exported on `/metrics`
+pub static REGISTRY: LazyLock<Registry> = LazyLock::new(|| {
+    Registry::new_custom(Some("kronos".to_string()), None).expect("valid registry prefix")
+});
+
+// Blocks
+
+pub static BLOCKS_PROCESSED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(IntCounterVec::new(
+        Opts::new("blocks_processed_total", "Blocks processed by dex adapter"),
+        &["dex", "status"],
+    ))
+});
+
+/// Seconds from block timestamp till the end of block processing by the adapter
+pub static DETECTION_LATENCY: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register(HistogramVec::new(
+        HistogramOpts::new(
+            "block_detection_seconds",
+            "Latency from block timestamp to found arbitrages",
+        )
+        .buckets(exponential_buckets(0.05, 2.0, 10).unwrap()),
+        &["dex"],
+    ))
+});
+
+// RPC
+
+pub static RPC_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(IntCounterVec::new(
+        Opts::new("rpc_requests_total", "JSON-RPC requests by method"),
+        &["method"],
+    ))
+});
+
+pub static RPC_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(IntCounterVec::new(
+        Opts::new("rpc_errors_total", "Failed JSON-RPC requests by method"),
+        &["method"],
+    ))
+});
+
+pub static RPC_RATE_LIMITED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register(IntCounter::new(
+        "rpc_rate_limited_total",
+        "Requests rejected by rate limit of the node",
+    ))
+});
+
+pub static RPC_LATENCY: LazyLock<Histogram> = LazyLock::new(|| {
+    register(Histogram::with_opts(
+        HistogramOpts::new("rpc_request_seconds", "Latency of JSON-RPC requests")
+            .buckets(exponential_buckets(0.005, 2.0, 12).unwrap()),
+    ))
+});
+
+// Arbitrages
+
+/// Cycles scored by the adapter on updated pairs
+pub static CANDIDATES_FOUND: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(IntCounterVec::new(
+        Opts::new("candidates_found_total", "Cycles scored as profitable"),
+        &["dex"],
+    ))
+});
+
+/// Candidates with positive profit after optimal amount is found
+pub static CANDIDATES_SIZED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(IntCounterVec::new(
+        Opts::new("candidates_sized_total", "Cycles with profitable amount in"),
+        &["dex"],
+    ))
+});
+
+pub static EXECUTIONS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "executions_total",
+            "Executed arbitrages by mode and outcome: included, reverted or failed",
+        ),
+        &["mode", "outcome"],
+    ))
+});
+
+pub static RISK_BREACHES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(IntCounterVec::new(
+        Opts::new(
+            "risk_breaches_total",
+            "Arbitrages rejected and pauses by risk limit",
+        ),
+        &["limit"],
+    ))
+});
+
+pub static EXECUTION_PAUSED: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(IntGauge::new(
+        "execution_paused",
+        "1 while execution is paused by a circuit breaker or the kill switch",
+    ))
+});
+
+pub static REALIZED_PNL_USD: LazyLock<Gauge> = LazyLock::new(|| {
+    register(Gauge::new(
+        "realized_pnl_usd",
+        "Profit of included transactions minus gas, in USD",
+    ))
+});
+
+pub static PAPER_PNL_USD: LazyLock<Gauge> = LazyLock::new(|| {
+    register(Gauge::new(
+        "paper_pnl_usd",
+        "Profit of paper fills minus estimated gas, in USD",
+    ))
+});
+
+pub static EXECUTOR_BALANCE: LazyLock<GaugeVec> = LazyLock::new(|| {
+    register(GaugeVec::new(
+        Opts::new("executor_balance_eth", "ETH balance of executor accounts"),
+        &["account"],
+    ))
+});
+
+pub static INVENTORY_BALANCE: LazyLock<GaugeVec> = LazyLock::new(|| {
+    register(GaugeVec::new(
+        Opts::new(
+            "inventory_balance",
+            "Balance of start tokens held by executor accounts, in whole tokens",
+        ),
+        &["account", "token"],
+    ))
+});
+
+pub static ALERTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(IntCounterVec::new(
+        Opts::new("alerts_total", "Alerts by kind and delivery result"),
+        &["kind", "result"],
+    ))
+});
+
+// Storage and channels
+
+pub static DB_LATENCY: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register(HistogramVec::new(
+        HistogramOpts::new("db_query_seconds", "Latency of redis and postgres queries")
+            .buckets(exponential_buckets(0.0005, 2.0, 12).unwrap()),
+        &["backend", "op"],
+    ))
+});
+
+pub static CHANNEL_DEPTH: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register(IntGaugeVec::new(
+        Opts::new("channel_depth", "Messages waiting in the channel"),
+        &["channel"],
+    ))
+});
+
+/// Registers all metrics, so they are exported before the first update
+pub fn init() {
+    LazyLock::force(&BLOCKS_PROCESSED);
+    LazyLock::force(&DETECTION_LATENCY);
+    LazyLock::force(&RPC_REQUESTS);
+    LazyLock::force(&RPC_ERRORS);
+    LazyLock::force(&RPC_RATE_LIMITED);
+    LazyLock::force(&RPC_LATENCY);
+    LazyLock::force(&CANDIDATES_FOUND);
+    LazyLock::force(&CANDIDATES_SIZED);
+    LazyLock::force(&EXECUTIONS);
+    LazyLock::force(&RISK_BREACHES);
+    LazyLock::force(&EXECUTION_PAUSED);
+    LazyLock::force(&REALIZED_PNL_USD);
+    LazyLock::force(&PAPER_PNL_USD);
+    LazyLock::force(&EXECUTOR_BALANCE);
+    LazyLock::force(&ALERTS);
+    LazyLock::force(&INVENTORY_BALANCE);
+    LazyLock::force(&DB_LATENCY);
+    LazyLock::force(&CHANNEL_DEPTH);
+}
+
+/// Starts timer which records latency of the query on drop
+pub fn db_timer(backend: &str, op: &str) -> HistogramTimer {
+    DB_LATENCY.with_label_values(&[backend, op]).start_timer()
+}
+
+pub fn set_channel_depth(channel: &str, depth: usize) {
+    CHANNEL_DEPTH
+        .with_label_values(&[channel])
+        .set(depth as i64);
+}
+
+/// Prometheus text exposition of all registered metrics
+pub fn encode() -> String {
+    let mut buffer = vec![];
+    if let Err(err) = TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer) {
+        tracing::warn!("failed to encode metrics: {err}");
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+fn register<M>(metric: prometheus::Result<M>) -> M
+where
+    M: prometheus::core::Collector + Clone + 'static,
+{
+    let metric = metric.expect("valid metric options");
+    REGISTRY
+        .register(Box::new(metric.clone()))
+        .expect("metric is registered once");
+    metric
+}
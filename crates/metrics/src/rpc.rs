@@ -0,0 +1,100 @@
+use crate::{RPC_ERRORS, RPC_LATENCY, RPC_RATE_LIMITED, RPC_REQUESTS};
+use alloy::{
+    rpc::json_rpc::{RequestPacket, ResponsePacket},
+    transports::{TransportError, TransportErrorKind, TransportFut},
+};
+use kronos_common::RATE_LIMIT_CODES;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+/// Transport layer which counts requests by method, errors and rate limit hits.
+///
+/// ```ignore
+/// let client = ClientBuilder::default().layer(RpcMetricsLayer).connect(url).await?;
+/// let provider = ProviderBuilder::default().on_client(client);
+/// ```
+///
+/// Layered transport can't be used for subscriptions, pubsub provider is kept separately
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RpcMetricsLayer;
+
+impl<S> Layer<S> for RpcMetricsLayer {
+    type Service = RpcMetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RpcMetricsService { inner }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RpcMetricsService<S> {
+    inner: S,
+}
+
+impl<S> Service<RequestPacket> for RpcMetricsService<S>
+where
+    S: Service<
+            RequestPacket,
+            Response = ResponsePacket,
+            Error = TransportError,
+            Future = TransportFut<'static>,
+        > + Send
+        + 'static,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let methods: Vec<String> = match &request {
+            RequestPacket::Single(request) => vec![request.method().to_string()],
+            RequestPacket::Batch(requests) => requests
+                .iter()
+                .map(|request| request.method().to_string())
+                .collect(),
+        };
+        for method in methods.iter() {
+            RPC_REQUESTS.with_label_values(&[method]).inc();
+        }
+
+        let timer = RPC_LATENCY.start_timer();
+        let future = self.inner.call(request);
+        Box::pin(async move {
+            let response = future.await;
+            timer.observe_duration();
+
+            match &response {
+                Ok(packet) => {
+                    for error in packet.iter_errors() {
+                        if RATE_LIMIT_CODES.contains(&error.code) {
+                            RPC_RATE_LIMITED.inc();
+                        }
+                    }
+                    if packet.is_error() {
+                        record_errors(&methods);
+                    }
+                }
+                Err(err) => {
+                    if let TransportError::Transport(TransportErrorKind::HttpError(http)) = err {
+                        if http.is_rate_limit_err() {
+                            RPC_RATE_LIMITED.inc();
+                        }
+                    }
+                    record_errors(&methods);
+                }
+            }
+
+            response
+        })
+    }
+}
+
+fn record_errors(methods: &[String]) {
+    for method in methods {
+        RPC_ERRORS.with_label_values(&[method]).inc();
+    }
+}
@@ -0,0 +1,20 @@
+use axum::{http::header, response::IntoResponse, routing::get, Router};
+use std::io;
+use tokio::net::TcpListener;
+
+/// Serves `/metrics` in prometheus text format until the process exits
+pub async fn serve(listen: &str) -> io::Result<()> {
+    crate::init();
+    let app = Router::new().route("/metrics", get(metrics));
+
+    let listener = TcpListener::bind(listen).await?;
+    tracing::info!("📈 Metrics are served on http://{listen}/metrics");
+    axum::serve(listener, app).await
+}
+
+async fn metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+        crate::encode(),
+    )
+}